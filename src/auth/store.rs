@@ -0,0 +1,308 @@
+//! This module defines where cached access tokens are kept: the OS credential store, or, if
+//! the user opts in, plain files.
+
+use crate::compute::AccessToken;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Persists access tokens between runs, keyed by project.
+pub trait TokenStore {
+    /// Returns the stored token for a key, or `None` if there is none or it can't be read.
+    fn load(&self, key: &str) -> Option<AccessToken>;
+
+    /// Stores a token under a key, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to store the token under, e.g. the project ID.
+    /// * `token` - The token to store.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the token was stored.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the token couldn't be stored.
+    fn save(&self, key: &str, token: &AccessToken) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Stores tokens as plain JSON files readable only by the current user, one per key.
+///
+/// This is only used when the user opts in, as anyone with access to the account's files can
+/// read the tokens.
+pub struct FileTokenStore {
+    /// The directory holding the token files.
+    dir: PathBuf,
+}
+
+impl FileTokenStore {
+    /// Creates a new `FileTokenStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to store tokens in, e.g. `~/.bcls/token-cache`. It is created
+    ///   on first use.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the path of the file for a key.
+    fn path(&self, key: &str) -> PathBuf {
+        // Project IDs are safe file names, but guard against path separators anyway
+        self.dir
+            .join(format!("{}.json", key.replace(['/', '\\'], "_")))
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, key: &str) -> Option<AccessToken> {
+        let contents = std::fs::read_to_string(self.path(key)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn save(&self, key: &str, token: &AccessToken) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        write_private(&self.path(key), &serde_json::to_vec(token)?)
+    }
+}
+
+/// Writes a file that only the current user can read, replacing it if it exists.
+pub(crate) fn write_private(
+    path: &Path,
+    contents: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Write to a temporary file and rename it so readers never see a partial file, and
+    // concurrent runs never write to the same temporary file
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&tmp)?.write_all(contents)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Stores tokens in the OS credential store: the login keychain on macOS, the Credential
+/// Locker on Windows and the Secret Service (e.g. GNOME Keyring) elsewhere.
+///
+/// The stores are driven through their command line tools (`security`, PowerShell and
+/// `secret-tool`), and tokens are passed to them on stdin so they never appear in the process
+/// list.
+pub struct KeychainTokenStore {
+    /// The service name the tokens are filed under.
+    service: String,
+}
+
+impl KeychainTokenStore {
+    /// Creates a new `KeychainTokenStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - The service name to file tokens under, e.g. `bcls`. The key is used as
+    ///   the account name.
+    pub fn new(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+        }
+    }
+
+    /// Whether the command line tool for this OS's credential store is installed.
+    pub fn is_available() -> bool {
+        crate::version::find_on_path(tool_for(std::env::consts::OS)).is_some()
+    }
+}
+
+impl TokenStore for KeychainTokenStore {
+    fn load(&self, key: &str) -> Option<AccessToken> {
+        let (program, args) = lookup_command(std::env::consts::OS, &self.service, key);
+        let output = Command::new(program)
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let secret = STANDARD
+            .decode(String::from_utf8(output.stdout).ok()?.trim())
+            .ok()?;
+        serde_json::from_slice(&secret).ok()
+    }
+
+    fn save(&self, key: &str, token: &AccessToken) -> Result<(), Box<dyn std::error::Error>> {
+        // Base64 keeps the JSON clear of the tools' quoting rules
+        let secret = STANDARD.encode(serde_json::to_vec(token)?);
+        let (program, args, stdin) =
+            store_command(std::env::consts::OS, &self.service, key, &secret);
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        child
+            .stdin
+            .take()
+            .ok_or("Failed to open stdin")?
+            .write_all(stdin.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Loads the WinRT credential locker class in PowerShell.
+const POWERSHELL_VAULT: &str = "[void][Windows.Security.Credentials.PasswordVault,\
+    Windows.Security.Credentials,ContentType=WindowsRuntime]; \
+    $v = New-Object Windows.Security.Credentials.PasswordVault";
+
+/// Returns the credential store tool for an OS, as named by `std::env::consts::OS`.
+fn tool_for(os: &str) -> &'static str {
+    match os {
+        "macos" => "security",
+        "windows" => "powershell",
+        _ => "secret-tool",
+    }
+}
+
+/// Builds the command that prints the secret stored for a service and account.
+fn lookup_command(os: &str, service: &str, account: &str) -> (&'static str, Vec<String>) {
+    let args = match os {
+        "macos" => vec![
+            "find-generic-password".to_string(),
+            "-s".to_string(),
+            service.to_string(),
+            "-a".to_string(),
+            account.to_string(),
+            "-w".to_string(),
+        ],
+        "windows" => vec![
+            "-NoProfile".to_string(),
+            "-NonInteractive".to_string(),
+            "-Command".to_string(),
+            format!(
+                "{}; $c = $v.Retrieve('{}', '{}'); $c.RetrievePassword(); $c.Password",
+                POWERSHELL_VAULT, service, account
+            ),
+        ],
+        _ => vec![
+            "lookup".to_string(),
+            "service".to_string(),
+            service.to_string(),
+            "account".to_string(),
+            account.to_string(),
+        ],
+    };
+    (tool_for(os), args)
+}
+
+/// Builds the command that stores a secret for a service and account, together with the
+/// input to feed it on stdin.
+fn store_command(
+    os: &str,
+    service: &str,
+    account: &str,
+    secret: &str,
+) -> (&'static str, Vec<String>, String) {
+    match os {
+        // In interactive mode security reads its command from stdin rather than argv
+        "macos" => (
+            "security",
+            vec!["-i".to_string()],
+            format!(
+                "add-generic-password -U -s {} -a {} -w {}\n",
+                service, account, secret
+            ),
+        ),
+        "windows" => (
+            "powershell",
+            vec![
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-Command".to_string(),
+                format!(
+                    "{}; $v.Add((New-Object Windows.Security.Credentials.PasswordCredential(\
+                     '{}', '{}', [Console]::In.ReadToEnd().Trim())))",
+                    POWERSHELL_VAULT, service, account
+                ),
+            ],
+            secret.to_string(),
+        ),
+        _ => (
+            "secret-tool",
+            vec![
+                "store".to_string(),
+                format!("--label={} access token ({})", service, account),
+                "service".to_string(),
+                service.to_string(),
+                "account".to_string(),
+                account.to_string(),
+            ],
+            secret.to_string(),
+        ),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keychain_commands() {
+        let (program, args) = lookup_command("linux", "bcls", "my-project");
+        assert_eq!(program, "secret-tool");
+        assert_eq!(
+            args,
+            vec!["lookup", "service", "bcls", "account", "my-project"]
+        );
+
+        // The secret is passed on stdin, never as an argument
+        let (program, args, stdin) = store_command("macos", "bcls", "my-project", "c2VjcmV0");
+        assert_eq!(program, "security");
+        assert_eq!(args, vec!["-i"]);
+        assert_eq!(
+            stdin,
+            "add-generic-password -U -s bcls -a my-project -w c2VjcmV0\n"
+        );
+        for os in ["linux", "windows"] {
+            let (_, args, stdin) = store_command(os, "bcls", "my-project", "c2VjcmV0");
+            assert!(args.iter().all(|arg| !arg.contains("c2VjcmV0")));
+            assert_eq!(stdin, "c2VjcmV0");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_store_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("bcls-test-{}-store", std::process::id()));
+        let store = FileTokenStore::new(&dir);
+        let token = AccessToken {
+            token: "ya29.token".to_string(),
+            expires_at: None,
+        };
+
+        store.save("p", &token).unwrap();
+        assert_eq!(store.load("p").unwrap().token, "ya29.token");
+        assert!(store.load("other").is_none());
+        let mode = std::fs::metadata(dir.join("p.json"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! This module provides an on-disk cache for instance listings, so that repeated invocations
+//! within a few minutes don't have to page through the whole fleet again.
+
+use crate::compute::{ApiVersion, Instance};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Represents the `[cache]` section of the config file.
+///
+/// ```toml
+/// [cache]
+/// ttl_secs = 300  # 0 disables the cache
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// How long a cached listing is used for, in seconds. Defaults to 5 minutes.
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { ttl_secs: 300 }
+    }
+}
+
+/// A cached listing as stored on disk.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// When the listing was fetched.
+    fetched_at: DateTime<Utc>,
+    /// The API version the listing was fetched with, as beta listings have extra fields.
+    api_version: ApiVersion,
+    /// The instances.
+    instances: Vec<Instance>,
+}
+
+/// Caches instance listings per project in `<dir>/<project>.json`.
+pub struct InstanceCache {
+    /// The directory holding the cached listings.
+    dir: PathBuf,
+    /// How long a cached listing is used for.
+    ttl: Duration,
+}
+
+impl InstanceCache {
+    /// Creates a new `InstanceCache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to cache listings in, e.g. `~/.bcls/cache`. It is created on
+    ///   first use.
+    /// * `config` - The cache settings.
+    pub fn new(dir: impl Into<PathBuf>, config: &CacheConfig) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Duration::seconds(config.ttl_secs.try_into().unwrap_or(i64::MAX)),
+        }
+    }
+
+    /// Returns the path of the cached listing for a project.
+    fn path(&self, project: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.json", project.replace(['/', '\\'], "_")))
+    }
+
+    /// Returns the cached listing for a project if it is younger than the TTL and was fetched
+    /// with the same API version. Missing or unreadable cache files are treated as a miss.
+    pub fn load(&self, project: &str, api_version: ApiVersion) -> Option<Vec<Instance>> {
+        let contents = std::fs::read_to_string(self.path(project)).ok()?;
+        let entry = serde_json::from_str::<Entry>(&contents).ok()?;
+        (entry.api_version == api_version && Utc::now() - entry.fetched_at < self.ttl)
+            .then_some(entry.instances)
+    }
+
+    /// Caches a listing for a project, replacing any previous one.
+    pub fn save(
+        &self,
+        project: &str,
+        api_version: ApiVersion,
+        instances: &[Instance],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let entry = Entry {
+            fetched_at: Utc::now(),
+            api_version,
+            instances: instances.to_vec(),
+        };
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file and rename it so concurrent runs never see a partial listing
+        let path = self.path(project);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Removes the cached listing for a project, e.g. after changing one of its instances.
+    pub fn invalidate(&self, project: &str) -> Result<(), Box<dyn std::error::Error>> {
+        remove_if_exists(&self.path(project))
+    }
+
+    /// Removes all cached listings.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of listings removed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the cache directory can't be read or a
+    ///   listing can't be removed.
+    pub fn clear(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                remove_if_exists(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Removes a file, ignoring that it doesn't exist.
+fn remove_if_exists(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instances() -> Vec<Instance> {
+        vec![Instance {
+            name: "store-lb-1".to_string(),
+            zone: "us-east1-b".to_string(),
+            labels: Some([("team".to_string(), "storage".to_string())].into()),
+            ..Default::default()
+        }]
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bcls-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = temp_dir("cache");
+        let cache = InstanceCache::new(&dir, &CacheConfig::default());
+        assert!(cache.load("p", ApiVersion::V1).is_none());
+
+        cache.save("p", ApiVersion::V1, &instances()).unwrap();
+        let cached = cache.load("p", ApiVersion::V1).unwrap();
+        assert_eq!(cached[0].name, "store-lb-1");
+        assert_eq!(cached[0].labels, instances()[0].labels);
+
+        // Listings from another API version or project are not used
+        assert!(cache.load("p", ApiVersion::Beta).is_none());
+        assert!(cache.load("other", ApiVersion::V1).is_none());
+
+        cache.invalidate("p").unwrap();
+        assert!(cache.load("p", ApiVersion::V1).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_expires() {
+        let dir = temp_dir("cache-ttl");
+        let cache = InstanceCache::new(&dir, &CacheConfig { ttl_secs: 0 });
+        cache.save("p", ApiVersion::V1, &instances()).unwrap();
+        assert!(cache.load("p", ApiVersion::V1).is_none());
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.clear().unwrap(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,754 @@
+//! This module provides `Client`, the entry point for using bcls as a library. It wires up
+//! HTTP, retries and authentication the way the `bcls` command does, and hands out the API
+//! clients of a project.
+//!
+//! ```no_run
+//! use bcls::query::{InstanceFilter, Status};
+//!
+//! let client = bcls::Client::builder().project("acme-prd").build()?;
+//! let filter = InstanceFilter::default()
+//!     .label("team", "payments")
+//!     .status(Status::Running);
+//! for instance in client.list_instances(&filter)? {
+//!     println!("{} {}", instance.name, instance.zone);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::auth;
+use crate::cache::{CacheConfig, InstanceCache};
+use crate::cloud_logging::{CloudLogging, CloudLoggingConfig};
+use crate::compute::{
+    self, ApiVersion, Compute, ComputeConfig, Instance, ListStrategy, ResultSet, DEFAULT_JOBS,
+};
+use crate::config::{AuthConfig, AuthMethod, FileConfig, Habitat};
+use crate::container::{Container, ContainerConfig};
+use crate::dns::{Dns, DnsConfig};
+use crate::http::{
+    EtagStore, Http, HttpOptions, RateLimiter, RetryPolicy, RetryingHttp, TraceLog, Transport,
+};
+use crate::monitoring::{Monitoring, MonitoringConfig};
+use crate::osconfig::{OsConfig, OsConfigConfig};
+use crate::query::{InstanceFilter, InstanceSet, NamePattern};
+use crate::source::{GcsObject, InstanceSource, SnapshotSource, Source};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The HTTP client API calls are made with: `Http`, retrying transient failures.
+pub type HttpClient = RetryingHttp<Http>;
+
+/// A token source chosen at runtime from the auth options.
+pub type BoxedTokenSource = Box<dyn compute::TokenSource + Send + Sync>;
+
+/// The token source of an impersonated service account.
+type ImpersonatingSource = auth::ImpersonatingTokenSource<BoxedTokenSource, HttpClient>;
+
+/// Which instances `Client::list` returns: those matching a filter and a name pattern, up to a
+/// limit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceQuery {
+    /// The filter, e.g. `InstanceFilter::default()` for all instances.
+    pub filter: InstanceFilter,
+    /// The pattern the names must match. `None` matches every name.
+    pub name_pattern: Option<NamePattern>,
+    /// Listing stops once this many matching instances have been found.
+    pub limit: Option<usize>,
+    /// How the cached listing of the project is used, if the client has an instance cache.
+    pub cache: CacheMode,
+}
+
+impl InstanceQuery {
+    /// Returns the filter expression the API applies. A glob can also be applied by the API,
+    /// but not together with the other filters.
+    fn expression(&self) -> Result<Option<String>, String> {
+        Ok(self
+            .filter
+            .expression()?
+            .or_else(|| self.name_pattern.as_ref().and_then(NamePattern::expression)))
+    }
+
+    /// Whether an instance matches the filter and the name pattern.
+    fn matches(&self, instance: &Instance) -> bool {
+        self.name_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches(&instance.name))
+            && self.filter.matches(instance)
+    }
+}
+
+/// How `Client::list` uses the cached listing of a project.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Use a cached listing if there is a fresh one, and cache a new one otherwise.
+    #[default]
+    Use,
+    /// List the instances again, and cache the new listing, e.g. for `--refresh`.
+    Refresh,
+    /// Neither read nor write the cache, e.g. for `--no-cache`.
+    Off,
+}
+
+/// Makes API calls to one project, authenticated and retried as configured.
+///
+/// Create one with `Client::builder`.
+pub struct Client {
+    project: String,
+    http: Http,
+    auth: AuthConfig,
+    impersonate_service_account: Option<String>,
+    token_cache_dir: Option<PathBuf>,
+    retry: RetryPolicy,
+    trace: Option<Arc<TraceLog>>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    api_version: ApiVersion,
+    page_size: Option<u32>,
+    strict: bool,
+    list_strategy: ListStrategy,
+    jobs: usize,
+    source: Source,
+    instance_cache: Option<InstanceCache>,
+    /// The token source shared by the API clients when impersonating a service account.
+    impersonating: Mutex<Option<Arc<ImpersonatingSource>>>,
+}
+
+/// Configures a `Client`. Only the project is required; everything else defaults to what the
+/// `bcls` command does without a config file.
+#[derive(Debug, Default)]
+pub struct ClientBuilder {
+    project: Option<String>,
+    transport: Transport,
+    http: HttpOptions,
+    auth: AuthConfig,
+    impersonate_service_account: Option<String>,
+    token_cache_dir: Option<PathBuf>,
+    etag_cache_dir: Option<PathBuf>,
+    retry: RetryPolicy,
+    trace: Option<Arc<TraceLog>>,
+    api_version: ApiVersion,
+    page_size: Option<u32>,
+    strict: bool,
+    list_strategy: ListStrategy,
+    jobs: Option<usize>,
+    source: Source,
+    instance_cache_dir: Option<PathBuf>,
+    cache: CacheConfig,
+}
+
+impl ClientBuilder {
+    /// Sets the Google Cloud project ID.
+    pub fn project(mut self, project: &str) -> Self {
+        self.project = Some(project.to_string());
+        self
+    }
+
+    /// Sets the Compute Engine API version. Defaults to `v1`.
+    pub fn api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// Sets how many resources to request per page when listing. Defaults to the API's own
+    /// page size.
+    pub fn page_size(mut self, page_size: Option<u32>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sets whether a resource that can't be parsed fails a listing. Defaults to skipping it
+    /// with a warning.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets how instances are listed. Defaults to one aggregated listing of all zones.
+    pub fn list_strategy(mut self, list_strategy: ListStrategy) -> Self {
+        self.list_strategy = list_strategy;
+        self
+    }
+
+    /// Sets how many requests are made at once where a listing is split up, e.g. the zones
+    /// of `ListStrategy::PerZone`. Defaults to `compute::DEFAULT_JOBS`.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Sets how the API is reached. Defaults to connecting directly.
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Sets the HTTP timeouts, proxy, certificates and request rate. Defaults to
+    /// `HttpOptions::default()`.
+    pub fn http(mut self, http: HttpOptions) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Sets how access tokens are obtained. Defaults to the `auto` method.
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Sets a service account to impersonate with the caller's own credentials.
+    pub fn impersonate_service_account(mut self, service_account: Option<String>) -> Self {
+        self.impersonate_service_account = service_account;
+        self
+    }
+
+    /// Sets the directory tokens are cached in when the OS credential store is unavailable
+    /// and `plaintext_token_cache` is enabled. Without it, tokens aren't cached in files.
+    pub fn token_cache_dir(mut self, dir: PathBuf) -> Self {
+        self.token_cache_dir = Some(dir);
+        self
+    }
+
+    /// Sets the directory GET responses are kept in for conditional requests, if `etag_cache`
+    /// is enabled in the HTTP options. Without it, responses aren't kept.
+    pub fn etag_cache_dir(mut self, dir: PathBuf) -> Self {
+        self.etag_cache_dir = Some(dir);
+        self
+    }
+
+    /// Sets how transiently failing requests are retried.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets where to record a summary of each API request.
+    pub fn trace(mut self, trace: Option<Arc<TraceLog>>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Sets where instances are listed from. Defaults to the Compute API.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Sets the directory instance listings are cached in for `list`. Without it, listings
+    /// aren't cached.
+    pub fn instance_cache_dir(mut self, dir: PathBuf) -> Self {
+        self.instance_cache_dir = Some(dir);
+        self
+    }
+
+    /// Sets how long cached instance listings are used for.
+    pub fn cache(mut self, cache: CacheConfig) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Takes the settings of a habitat from the config file, as the `bcls` command does.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The config file, for the HTTP, auth, retry, trace, page size, strict, list
+    ///   strategy, jobs and cache options.
+    /// * `habitat` - The habitat, for the project, transport, API version, impersonation,
+    ///   request rate and source.
+    pub fn habitat(self, config: &FileConfig, habitat: &Habitat) -> Self {
+        ClientBuilder {
+            project: Some(habitat.project.clone()),
+            transport: habitat.transport.clone(),
+            http: HttpOptions {
+                requests_per_second: habitat
+                    .requests_per_second
+                    .or(config.http.requests_per_second),
+                ..config.http.clone()
+            },
+            auth: config.auth.clone(),
+            impersonate_service_account: habitat.impersonate_service_account.clone(),
+            retry: config.retry.clone(),
+            trace: config.trace.clone(),
+            api_version: habitat.api_version.unwrap_or_default(),
+            page_size: config.page_size,
+            strict: config.strict,
+            list_strategy: config.list_strategy,
+            jobs: config.jobs.or(self.jobs),
+            source: habitat.source.clone(),
+            cache: config.cache.clone(),
+            ..self
+        }
+    }
+
+    /// Creates the client.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Client)` - The client.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if no project was set, or the proxy or
+    ///   transport can't be set up.
+    pub fn build(self) -> Result<Client, Box<dyn std::error::Error>> {
+        let project = self
+            .project
+            .ok_or("A project must be set to build a client")?;
+        self.http.validate()?;
+        let mut http = Http::with_options(&self.http, &self.transport)
+            .map_err(|e| format!("Failed to set up the HTTP client for {}: {}", project, e))?;
+        if let (true, Some(dir)) = (self.http.etag_cache, self.etag_cache_dir) {
+            http = http.with_etag_store(Arc::new(EtagStore::new(dir)));
+        }
+        let rate_limit = self
+            .http
+            .requests_per_second
+            .map(|rate| RateLimiter::shared(&project, rate, self.http.burst));
+        Ok(Client {
+            project,
+            http,
+            auth: self.auth,
+            impersonate_service_account: self.impersonate_service_account,
+            token_cache_dir: self.token_cache_dir,
+            retry: self.retry,
+            trace: self.trace,
+            rate_limit,
+            api_version: self.api_version,
+            page_size: self.page_size,
+            strict: self.strict,
+            list_strategy: self.list_strategy,
+            jobs: self.jobs.unwrap_or(DEFAULT_JOBS),
+            source: self.source,
+            instance_cache: self
+                .instance_cache_dir
+                .map(|dir| InstanceCache::new(dir, &self.cache)),
+            impersonating: Mutex::new(None),
+        })
+    }
+}
+
+impl Client {
+    /// Starts configuring a client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Returns the project the client calls.
+    pub fn project(&self) -> &str {
+        &self.project
+    }
+
+    /// Returns the HTTP client for API calls, which retries, traces and rate-limits requests
+    /// as configured.
+    pub fn http(&self) -> HttpClient {
+        let client = retrying(self.http.clone(), &self.retry, &self.trace);
+        match &self.rate_limit {
+            Some(rate_limit) => client.with_rate_limit(rate_limit.clone()),
+            None => client,
+        }
+    }
+
+    /// Creates the token source for the project according to the auth options,
+    /// impersonating the service account if one is set.
+    pub fn token_source(&self) -> Result<BoxedTokenSource, Box<dyn std::error::Error>> {
+        let Some(service_account) = &self.impersonate_service_account else {
+            return self.caller_token_source();
+        };
+        // The caller's own tokens are cached. All API clients share one impersonating token
+        // source, so an impersonated token is fetched once and reused until it nearly expires.
+        let mut impersonating = self
+            .impersonating
+            .lock()
+            .expect("no thread panics holding the lock");
+        let source = match impersonating.as_ref() {
+            Some(source) => source.clone(),
+            None => {
+                let source = Arc::new(auth::ImpersonatingTokenSource::new(
+                    self.caller_token_source()?,
+                    self.http(),
+                    service_account,
+                ));
+                *impersonating = Some(source.clone());
+                source
+            }
+        };
+        Ok(Box::new(source))
+    }
+
+    /// Creates a Compute Engine API client for the project.
+    pub fn compute(
+        &self,
+    ) -> Result<Compute<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(Compute::new(ComputeConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+            api_version: self.api_version,
+            page_size: self.page_size,
+            strict: self.strict,
+            list_strategy: self.list_strategy,
+            jobs: self.jobs,
+        }))
+    }
+
+    /// Creates a GKE API client for the project.
+    pub fn container(
+        &self,
+    ) -> Result<Container<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(Container::new(ContainerConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+        }))
+    }
+
+    /// Creates a Cloud DNS API client for the project.
+    pub fn dns(&self) -> Result<Dns<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(Dns::new(DnsConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+            page_size: self.page_size,
+        }))
+    }
+
+    /// Creates a Cloud Monitoring API client for the project.
+    pub fn monitoring(
+        &self,
+    ) -> Result<Monitoring<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(Monitoring::new(MonitoringConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+            page_size: self.page_size,
+        }))
+    }
+
+    /// Creates a Cloud Logging API client for the project.
+    pub fn cloud_logging(
+        &self,
+    ) -> Result<CloudLogging<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(CloudLogging::new(CloudLoggingConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+        }))
+    }
+
+    /// Creates an OS Config API client for the project.
+    pub fn osconfig(
+        &self,
+    ) -> Result<OsConfig<HttpClient, BoxedTokenSource>, Box<dyn std::error::Error>> {
+        Ok(OsConfig::new(OsConfigConfig {
+            project: self.project.clone(),
+            token_source: self.token_source()?,
+            client: self.http(),
+        }))
+    }
+
+    /// Lists the instances of the project matching a filter from its source, letting the source
+    /// apply as much of it as it can.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter, e.g. `InstanceFilter::default()` for all instances.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The matching instances, with warnings about zones that
+    ///   couldn't be listed and instances that couldn't be parsed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails.
+    pub fn list_instances(
+        &self,
+        filter: &InstanceFilter,
+    ) -> Result<ResultSet<Instance>, Box<dyn std::error::Error>> {
+        let mut listing = self
+            .instance_source()?
+            .list_instances(filter.expression()?.as_deref())?;
+        listing.items.retain(|instance| filter.matches(instance));
+        Ok(listing)
+    }
+
+    /// Returns the source the instances of the project are listed from: the Compute API, or
+    /// a snapshot in Cloud Storage.
+    pub fn instance_source(&self) -> Result<Box<dyn InstanceSource>, Box<dyn std::error::Error>> {
+        match &self.source {
+            Source::Api => Ok(Box::new(self.compute()?)),
+            Source::Snapshot(url) => Ok(Box::new(SnapshotSource::new(
+                &self.project,
+                GcsObject::parse(url)?,
+                self.http(),
+                self.token_source()?,
+                self.api_version,
+            ))),
+        }
+    }
+
+    /// Lists the instances matching a query, as the `bcls` command does.
+    ///
+    /// A fresh cached listing of the project is used if the query allows it. Otherwise the
+    /// instances are listed from the source, letting it apply as much of the filter as it
+    /// can, and the listing is cached if it is the whole project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The matching instances, with warnings about what is
+    ///   missing from the listing.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if listing failed.
+    pub fn list(
+        &self,
+        query: &InstanceQuery,
+    ) -> Result<ResultSet<Instance>, Box<dyn std::error::Error>> {
+        self.list_streaming(query, &mut |_| Ok(()))
+    }
+
+    /// Lists the instances matching a query as for `list`, passing them to `on_page` as each
+    /// page arrives, so they can be shown before the whole listing has.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Which instances to list.
+    /// * `on_page` - Called with the matching instances of each page. Listing stops if it
+    ///   fails, e.g. because stdout was closed, and its error is returned as it is.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The matching instances, as for `list`.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if listing or `on_page` failed.
+    pub fn list_streaming(
+        &self,
+        query: &InstanceQuery,
+        on_page: &mut dyn FnMut(&[Instance]) -> std::io::Result<()>,
+    ) -> Result<ResultSet<Instance>, Box<dyn std::error::Error>> {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let cache = self
+            .instance_cache
+            .as_ref()
+            .filter(|_| query.cache != CacheMode::Off);
+        let cached = cache
+            .filter(|_| query.cache == CacheMode::Use)
+            .and_then(|cache| cache.load(&self.project, self.api_version));
+        if let Some(instances) = cached {
+            // The whole listing is at hand, so labels and statuses are looked up in an index
+            // rather than checked on every instance
+            let set = InstanceSet::new(instances);
+            let selection = set.matching(&query.filter, query.name_pattern.as_ref());
+            let mut instances = set.into_selected(&selection);
+            instances.truncate(limit);
+            on_page(&instances)?;
+            return Ok(ResultSet::new(instances));
+        }
+
+        // A filtered listing is not the whole project, so it isn't cached
+        let expression = query.expression()?;
+        let cache = cache.filter(|_| expression.is_none());
+        let source = self.instance_source()?;
+        let mut pages = source.list_instance_pages(expression.as_deref())?;
+        let mut listed = ResultSet::default();
+        let mut instances = vec![];
+        // Stop fetching pages once there are enough matches
+        while instances.len() < limit {
+            let Some(page) = pages.next() else {
+                break;
+            };
+            let page = page?;
+            let matching = page
+                .items
+                .iter()
+                .filter(|inst| query.matches(inst))
+                .cloned();
+            let matching = matching.take(limit - instances.len()).collect::<Vec<_>>();
+            on_page(&matching)?;
+            instances.extend(matching);
+            // The whole listing is only kept if it can be cached
+            if cache.is_some() {
+                listed.items.extend(page.items);
+            }
+            listed.warnings.extend(page.warnings);
+        }
+        // A listing cut short by the limit isn't the whole project, and an incomplete one
+        // should be listed again next time, so neither is cached
+        let complete = instances.len() < limit && listed.warnings.is_empty();
+        if let (Some(cache), true) = (cache, complete) {
+            if let Err(e) = cache.save(&self.project, self.api_version, &listed.items) {
+                tracing::warn!(error = %e, "failed to cache instances");
+            }
+        }
+        Ok(ResultSet {
+            items: instances,
+            warnings: listed.warnings,
+        })
+    }
+
+    /// Finds an instance of the project by name in its source.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Instance))` - The instance, if it exists.
+    /// * `Ok(None)` - If no instance has that name.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails.
+    pub fn find_instance(
+        &self,
+        name: &str,
+    ) -> Result<Option<Instance>, Box<dyn std::error::Error>> {
+        self.instance_source()?.find_instance(name)
+    }
+
+    /// Chooses the token source of the caller according to the auth options.
+    fn caller_token_source(&self) -> Result<BoxedTokenSource, Box<dyn std::error::Error>> {
+        let service_account = || -> Result<BoxedTokenSource, Box<dyn std::error::Error>> {
+            let path = self
+                .auth
+                .service_account_key
+                .as_ref()
+                .ok_or("auth.service_account_key must be set to use a service account")?;
+            let key = auth::ServiceAccountKey::from_file(path)?;
+            Ok(Box::new(auth::ServiceAccountTokenSource::new(
+                key,
+                self.http.clone(),
+            )?))
+        };
+        // Off GCE the metadata server may not answer at all, so don't wait long for it
+        let metadata =
+            || auth::MetadataTokenSource::new(Http::with_timeout(Duration::from_secs(2)));
+        // Cache tokens so gcloud is only spawned when the cached token is about to expire.
+        // Tokens go to the OS credential store, or to plain files only if the user opted in.
+        let cached = |source: BoxedTokenSource| -> BoxedTokenSource {
+            let mut stores: Vec<Box<dyn auth::TokenStore + Send + Sync>> = vec![];
+            if auth::KeychainTokenStore::is_available() {
+                stores.push(Box::new(auth::KeychainTokenStore::new("bcls")));
+            }
+            if let (true, Some(dir)) = (self.auth.plaintext_token_cache, &self.token_cache_dir) {
+                stores.push(Box::new(auth::FileTokenStore::new(dir.clone())));
+            }
+            Box::new(auth::CachingTokenSource::new(
+                source,
+                self.http.clone(),
+                stores,
+            ))
+        };
+
+        Ok(match self.auth.method {
+            AuthMethod::ServiceAccount => service_account()?,
+            AuthMethod::Auto if self.auth.service_account_key.is_some() => service_account()?,
+            AuthMethod::Metadata => Box::new(metadata()),
+            AuthMethod::Gcloud => cached(Box::new(compute::GcloudTokenSource)),
+            AuthMethod::Auto => cached(Box::new(auth::ChainTokenSource::new(vec![
+                Box::new(metadata()),
+                Box::new(compute::GcloudTokenSource),
+            ]))),
+        })
+    }
+}
+
+/// Wraps a client to retry as configured, and to trace requests if a trace log is given.
+///
+/// # Arguments
+///
+/// * `http` - The client to wrap.
+/// * `retry` - How transiently failing requests are retried.
+/// * `trace` - Where to record a summary of each request, if anywhere.
+pub fn retrying(http: Http, retry: &RetryPolicy, trace: &Option<Arc<TraceLog>>) -> HttpClient {
+    let client = RetryingHttp::new(http, retry.clone());
+    match trace {
+        Some(trace) => client.with_trace(trace.clone()),
+        None => client,
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_requires_project() {
+        let error = Client::builder().build().err().unwrap();
+        assert_eq!(error.to_string(), "A project must be set to build a client");
+
+        let client = Client::builder()
+            .project("acme-stg")
+            .api_version(ApiVersion::Beta)
+            .build()
+            .unwrap();
+        assert_eq!(client.project(), "acme-stg");
+        assert_eq!(client.api_version, ApiVersion::Beta);
+    }
+
+    fn instance(name: &str, status: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            status: status.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_list_from_cache() {
+        let dir = std::env::temp_dir().join(format!("bcls-client-cache-{}", std::process::id()));
+        let cache = InstanceCache::new(&dir, &CacheConfig::default());
+        let instances = [
+            instance("db-1", "RUNNING"),
+            instance("web-1", "TERMINATED"),
+            instance("web-2", "RUNNING"),
+            instance("web-3", "RUNNING"),
+        ];
+        cache
+            .save("acme-prd", ApiVersion::default(), &instances)
+            .unwrap();
+        let client = Client::builder()
+            .project("acme-prd")
+            .instance_cache_dir(dir.clone())
+            .build()
+            .unwrap();
+
+        // The cached listing is filtered and limited without calling the API
+        let query = InstanceQuery {
+            filter: InstanceFilter::default().status(crate::query::Status::Running),
+            name_pattern: Some(NamePattern::plain("web")),
+            limit: Some(1),
+            cache: CacheMode::Use,
+        };
+        let mut pages = vec![];
+        let listed = client
+            .list_streaming(&query, &mut |page| {
+                pages.push(page.len());
+                Ok(())
+            })
+            .unwrap();
+        let names = listed.items.iter().map(|inst| inst.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), vec!["web-2"]);
+        assert!(listed.warnings.is_empty());
+        assert_eq!(pages, vec![1]);
+
+        // An error from `on_page` is returned as it is
+        let error = client
+            .list_streaming(&query, &mut |_| {
+                Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+            })
+            .unwrap_err();
+        assert!(error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe));
+
+        cache.invalidate("acme-prd").unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_expression() {
+        let glob = InstanceQuery {
+            name_pattern: Some(NamePattern::plain("web-*")),
+            ..Default::default()
+        };
+        assert_eq!(
+            glob.expression(),
+            Ok(Some("name eq \"web-.*\"".to_string()))
+        );
+        assert!(glob.matches(&instance("web-1", "RUNNING")));
+        assert!(!glob.matches(&instance("db-1", "RUNNING")));
+
+        // The other filters take precedence, and the glob is only matched locally
+        let filtered = InstanceQuery {
+            filter: InstanceFilter::default().status(crate::query::Status::Running),
+            ..glob
+        };
+        assert_eq!(filtered.expression(), filtered.filter.expression());
+        assert!(!filtered.matches(&instance("web-1", "TERMINATED")));
+    }
+}
@@ -0,0 +1,229 @@
+//! This module provides a client for the Cloud Logging API, reading the Admin Activity audit
+//! logs of instances, i.e. who started, stopped or changed an instance and when.
+//!
+//! It is named after the API to tell it apart from `logging`, which writes bcls's own
+//! diagnostic log.
+
+mod entries;
+mod filter;
+
+use crate::compute::TokenSource;
+use crate::http;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+pub use entries::AuditEntry;
+pub use filter::LogFilter;
+
+/// The root of all Cloud Logging API URLs.
+const BASE_URL: &str = "https://logging.googleapis.com/v2";
+
+/// The most entries the API returns per page.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Configuration for the `CloudLogging` service.
+pub struct CloudLoggingConfig<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The HTTP client implementation.
+    pub client: H,
+    /// The token source.
+    pub token_source: T,
+}
+
+/// A client for the Cloud Logging API of a project.
+pub struct CloudLogging<H: http::HttpClient, T: TokenSource> {
+    config: CloudLoggingConfig<H, T>,
+}
+
+impl<H: http::HttpClient, T: TokenSource> CloudLogging<H, T> {
+    /// Creates a new `CloudLogging` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the `CloudLogging` service.
+    pub fn new(config: CloudLoggingConfig<H, T>) -> Self {
+        Self { config }
+    }
+
+    /// Returns the filter selecting the Admin Activity audit log entries of an instance. It
+    /// matches the instance by name in any zone, so it also finds deleted instances.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the instance.
+    /// * `since` - The earliest time of the entries.
+    pub fn instance_filter(&self, name: &str, since: DateTime<Utc>) -> LogFilter {
+        LogFilter::new()
+            .eq(
+                "logName",
+                &format!(
+                    "projects/{}/logs/cloudaudit.googleapis.com%2Factivity",
+                    self.config.project
+                ),
+            )
+            .eq("resource.type", "gce_instance")
+            .matches(
+                "protoPayload.resourceName",
+                &format!("/instances/{}$", regex::escape(name)),
+            )
+            .since(since)
+    }
+
+    /// Lists the recent admin activity on an instance, the most recent first.
+    ///
+    /// Long-running calls, such as stopping an instance, are logged twice: when they are made
+    /// and when they complete. Completions are only kept if they report an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the instance.
+    /// * `since` - How far back to look.
+    /// * `limit` - The most entries to return.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<AuditEntry>)` - The entries.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn instance_history(
+        &self,
+        name: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        let filter = self.instance_filter(name, since);
+        self.list_entries(&filter, limit, |entry| {
+            !entry.completion || entry.error.is_some()
+        })
+    }
+
+    /// Fetches log entries matching a filter, the most recent first, until enough are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter of the entries.
+    /// * `limit` - The most entries to return.
+    /// * `keep` - Whether to keep a parsed entry.
+    fn list_entries(
+        &self,
+        filter: &LogFilter,
+        limit: usize,
+        keep: impl Fn(&AuditEntry) -> bool,
+    ) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/logging/docs/reference/v2/rest/v2/entries/list>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = format!("{}/entries:list", BASE_URL);
+        let mut entries = vec![];
+        let mut page_token: Option<String> = None;
+        while entries.len() < limit {
+            let mut body = json!({
+                "resourceNames": [format!("projects/{}", self.config.project)],
+                "filter": filter.to_string(),
+                "orderBy": "timestamp desc",
+                "pageSize": limit.clamp(1, MAX_PAGE_SIZE),
+            });
+            if let Some(page_token) = &page_token {
+                body["pageToken"] = page_token.as_str().into();
+            }
+            let resp = self.config.client.post(&token, &url, &body)?;
+            for entry in resp["entries"].as_array().into_iter().flatten() {
+                let entry = AuditEntry::try_from(entry.clone())?;
+                if keep(&entry) {
+                    entries.push(entry);
+                }
+            }
+            // Pages can be empty while the API is still searching, so follow the token
+            match resp["nextPageToken"]
+                .as_str()
+                .filter(|token| !token.is_empty())
+            {
+                Some(token) => page_token = Some(token.to_string()),
+                None => break,
+            }
+        }
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::MockHttpClient;
+    use serde_json::Value as JsonValue;
+
+    fn cloud_logging(mock_http: MockHttpClient) -> CloudLogging<MockHttpClient, MockTokenSource> {
+        CloudLogging::new(CloudLoggingConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+        })
+    }
+
+    fn entry(method: &str, operation: JsonValue) -> JsonValue {
+        json!({
+            "operation": operation,
+            "protoPayload": {"methodName": format!("v1.compute.instances.{}", method)}
+        })
+    }
+
+    #[test]
+    fn test_instance_history() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+        mock_http
+            .expect_post()
+            .withf(|token, url, body| {
+                token == "mock_token"
+                    && url == "https://logging.googleapis.com/v2/entries:list"
+                    && body["resourceNames"] == json!(["projects/test-project"])
+                    && body["orderBy"] == "timestamp desc"
+                    && body["pageSize"] == 2
+                    && body["filter"]
+                        .as_str()
+                        .unwrap()
+                        .contains(r#"protoPayload.resourceName =~ "/instances/web\\.1$""#)
+                    && body.get("pageToken").is_none()
+            })
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| {
+                Ok(json!({
+                    "entries": [
+                        entry("stop", json!({"last": true})),
+                        entry("stop", json!({"first": true})),
+                    ],
+                    "nextPageToken": "page2"
+                }))
+            });
+        mock_http
+            .expect_post()
+            .withf(|_, _, body| body["pageToken"] == "page2")
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| {
+                Ok(json!({
+                    "entries": [
+                        entry("setLabels", json!({"first": true, "last": true})),
+                        entry("insert", json!({"first": true})),
+                    ],
+                    "nextPageToken": "page3"
+                }))
+            });
+
+        let since = "2024-03-01T12:00:00Z".parse().unwrap();
+        let entries = cloud_logging(mock_http)
+            .instance_history("web.1", since, 2)
+            .unwrap();
+
+        // The completion of the stop is dropped, and the third page is never fetched
+        let methods = entries
+            .iter()
+            .map(|entry| entry.method.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(methods, vec!["stop", "setLabels"]);
+    }
+}
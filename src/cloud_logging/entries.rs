@@ -0,0 +1,109 @@
+//! This module defines the `AuditEntry` struct, which represents an entry of the Cloud Audit
+//! Logs, and provides a `TryFrom` implementation for creating it from a log entry's JSON data.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents an audit log entry, recording a call to a Google Cloud API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditEntry {
+    /// When the call was made.
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The method called, without its service and version, e.g. `stop` or `setLabels`.
+    pub method: String,
+    /// The email of the user or service account that made the call.
+    pub principal: Option<String>,
+    /// The IP address the call came from.
+    pub caller_ip: Option<String>,
+    /// The error message of a failed call.
+    pub error: Option<String>,
+    /// Whether the entry records the completion of a long-running operation rather than the
+    /// call that started it.
+    pub completion: bool,
+}
+
+impl TryFrom<JsonValue> for AuditEntry {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `AuditEntry` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the log entry, with an `AuditLog` payload.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AuditEntry)` - The created `AuditEntry` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the entry has no method name, e.g. because it is
+    ///   not an audit log entry.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |pointer: &str| json.pointer(pointer).and_then(JsonValue::as_str);
+        let bool_field =
+            |pointer: &str| json.pointer(pointer).and_then(JsonValue::as_bool) == Some(true);
+
+        // e.g. v1.compute.instances.stop
+        let method = str_field("/protoPayload/methodName")
+            .ok_or("Missing or invalid 'protoPayload.methodName' field")?;
+        let method = method.rsplit('.').next().unwrap_or(method).to_string();
+
+        Ok(AuditEntry {
+            timestamp: str_field("/timestamp")
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc)),
+            method,
+            principal: str_field("/protoPayload/authenticationInfo/principalEmail")
+                .map(str::to_string),
+            caller_ip: str_field("/protoPayload/requestMetadata/callerIp").map(str::to_string),
+            error: str_field("/protoPayload/status/message")
+                .filter(|message| !message.is_empty())
+                .map(str::to_string),
+            completion: bool_field("/operation/last") && !bool_field("/operation/first"),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_audit_entry_from_json() {
+        let json = json!({
+            "timestamp": "2024-03-01T12:00:00.123456Z",
+            "operation": {"id": "operation-1", "first": true},
+            "protoPayload": {
+                "@type": "type.googleapis.com/google.cloud.audit.AuditLog",
+                "methodName": "v1.compute.instances.stop",
+                "authenticationInfo": {"principalEmail": "alice@example.com"},
+                "requestMetadata": {"callerIp": "203.0.113.7"},
+                "resourceName": "projects/p/zones/us-east1-b/instances/web-1"
+            }
+        });
+
+        let entry = AuditEntry::try_from(json).unwrap();
+
+        assert!(entry.timestamp.is_some());
+        assert_eq!(entry.method, "stop");
+        assert_eq!(entry.principal, Some("alice@example.com".to_string()));
+        assert_eq!(entry.caller_ip, Some("203.0.113.7".to_string()));
+        assert_eq!(entry.error, None);
+        assert!(!entry.completion);
+
+        let done = json!({
+            "operation": {"id": "operation-1", "last": true},
+            "protoPayload": {
+                "methodName": "beta.compute.instances.setMachineType",
+                "status": {"code": 9, "message": "Instance is running"}
+            }
+        });
+        let done = AuditEntry::try_from(done).unwrap();
+        assert_eq!(done.method, "setMachineType");
+        assert_eq!(done.error, Some("Instance is running".to_string()));
+        assert!(done.completion);
+
+        assert!(AuditEntry::try_from(json!({"textPayload": "hello"})).is_err());
+    }
+}
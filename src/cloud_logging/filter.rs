@@ -0,0 +1,87 @@
+//! This module builds filters in the Cloud Logging query language, e.g.
+//! `resource.type = "gce_instance" AND timestamp >= "2024-03-01T00:00:00Z"`, quoting values so
+//! that names and patterns can't change the meaning of the filter.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+
+/// A filter of log entries: comparisons of fields with values, all of which must hold.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogFilter {
+    /// The comparisons, each a complete expression.
+    terms: Vec<String>,
+}
+
+impl LogFilter {
+    /// Creates a filter matching every entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a field to equal a value.
+    pub fn eq(self, field: &str, value: &str) -> Self {
+        self.term(field, "=", value)
+    }
+
+    /// Requires a field to match a regular expression, in RE2 syntax.
+    pub fn matches(self, field: &str, pattern: &str) -> Self {
+        self.term(field, "=~", pattern)
+    }
+
+    /// Requires entries to be logged at or after a time.
+    pub fn since(self, time: DateTime<Utc>) -> Self {
+        self.term(
+            "timestamp",
+            ">=",
+            &time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+    }
+
+    /// Appends a comparison of a field with a quoted value.
+    fn term(mut self, field: &str, operator: &str, value: &str) -> Self {
+        self.terms
+            .push(format!("{} {} {}", field, operator, quote(value)));
+        self
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.terms.join(" AND "))
+    }
+}
+
+/// Quotes a value as a string literal, escaping backslashes and double quotes.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let since = "2024-03-01T12:00:00Z".parse().unwrap();
+        let filter = LogFilter::new()
+            .eq(
+                "logName",
+                "projects/p/logs/cloudaudit.googleapis.com%2Factivity",
+            )
+            .matches("protoPayload.resourceName", "/instances/web-1$")
+            .since(since);
+        assert_eq!(
+            filter.to_string(),
+            "logName = \"projects/p/logs/cloudaudit.googleapis.com%2Factivity\" \
+             AND protoPayload.resourceName =~ \"/instances/web-1$\" \
+             AND timestamp >= \"2024-03-01T12:00:00Z\""
+        );
+        assert_eq!(LogFilter::new().to_string(), "");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
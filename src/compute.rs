@@ -1,14 +1,117 @@
 //! This module provides an interface for interacting with the Google Compute Engine API.
 //! It defines the `Compute` struct for making API calls and related helper functions.
 
+mod addresses;
+mod aggregated;
+mod disks;
+mod images;
+mod instance_detail;
+mod instance_groups;
+mod labels;
+mod machine_types;
+mod metadata;
+mod networks;
+mod operations;
+mod parallel;
 mod records;
+mod reservations;
+mod resource_policies;
+mod serial;
+mod snapshots;
+mod urls;
 
 use std::vec;
 
 use crate::http;
-use serde_json::{Map, Value};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
 
-pub use records::Instance;
+pub use addresses::Address;
+pub use aggregated::{
+    AggregatedList, ListWarning, RecordError, ScopeWarning, ScopedList, Warning, WarningData,
+};
+pub use disks::Disk;
+pub use images::{Image, InstanceTemplate};
+pub use instance_detail::{InstanceDetail, Scheduling, ServiceAccount};
+pub use instance_groups::{Autoscaler, InstanceGroupManager, ManagedInstance, MigLocation};
+pub use labels::{validate_key as validate_label_key, Label, LabelChange, Labels};
+pub use machine_types::MachineType;
+pub use metadata::{Metadata, MetadataItem};
+pub use networks::{Network, SecondaryRange, Subnetwork};
+pub use operations::Operation;
+pub use parallel::{map_bounded, DEFAULT_JOBS};
+pub use records::{AttachedDisk, BetaFields, Instance, NetworkInterface, ReservationAffinity};
+pub use reservations::Reservation;
+pub use resource_policies::{PolicyKind, ResourcePolicy};
+pub use serial::SerialOutput;
+pub use snapshots::Snapshot;
+pub use urls::{ComputeUrl, Endpoint};
+
+/// The Compute Engine API version to call.
+///
+/// Some fields (e.g. certain scheduling options) are only returned by the beta API.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    /// The stable `compute/v1` API.
+    #[default]
+    V1,
+    /// The `compute/beta` API.
+    Beta,
+}
+
+impl ApiVersion {
+    /// Returns the version as used in API URLs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::Beta => "beta",
+        }
+    }
+}
+
+/// How instances are listed.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListStrategy {
+    /// One `instances.aggregatedList` over all zones, a page at a time, so the first instances
+    /// can be shown while later pages are still to come.
+    #[default]
+    Aggregated,
+    /// Lists the zones, then each zone that isn't down with `instances.list`, as many zones at
+    /// once as `jobs` allows. This is faster for very large projects, but nothing is shown
+    /// until every zone is listed.
+    PerZone,
+}
+
+/// An access token together with its expiry time, if known.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AccessToken {
+    /// The bearer token.
+    pub token: String,
+    /// When the token expires. `None` if the token source doesn't report it.
+    pub expires_at: Option<DateTime<Utc>>,
+}
 
 /// A trait for fetching authentication tokens.
 pub trait TokenSource {
@@ -23,6 +126,26 @@ pub trait TokenSource {
     /// * `Ok(String)` - The authentication token on success.
     /// * `Err(Box<dyn std::error::Error>)` - An error if token retrieval fails.
     fn get_token(&self, project: &str) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// Retrieves an authentication token together with its expiry time.
+    ///
+    /// The default implementation wraps `get_token` and reports no expiry. Token sources that
+    /// know when their tokens expire should override it so that callers can cache tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - The ID of the Google Cloud project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AccessToken)` - The authentication token on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if token retrieval fails.
+    fn get_access_token(&self, project: &str) -> Result<AccessToken, Box<dyn std::error::Error>> {
+        Ok(AccessToken {
+            token: self.get_token(project)?,
+            expires_at: None,
+        })
+    }
 }
 
 /// Retrieves authentication tokens using the `gcloud` command-line tool.
@@ -41,7 +164,23 @@ impl TokenSource for GcloudTokenSource {
     /// * `Err(Box<dyn std::error::Error>)` - An error if the `gcloud` command fails
     ///   or if there's an issue processing the output.
     fn get_token(&self, project: &str) -> Result<String, Box<dyn std::error::Error>> {
-        println!("fetching token for project: {:?}", project);
+        Ok(self.get_access_token(project)?.token)
+    }
+
+    /// Executes the `gcloud` command with `--format=json` to obtain an access token and its
+    /// expiry time.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - The Google Cloud project ID.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AccessToken)` - The access token on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the `gcloud` command fails
+    ///   or if there's an issue processing the output.
+    fn get_access_token(&self, project: &str) -> Result<AccessToken, Box<dyn std::error::Error>> {
+        tracing::info!(project, "fetching access token from gcloud");
         let output = std::process::Command::new("gcloud")
             .args([
                 "auth",
@@ -49,12 +188,12 @@ impl TokenSource for GcloudTokenSource {
                 "print-access-token",
                 "--project",
                 project,
+                "--format=json",
             ])
             .output()?;
 
         if output.status.success() {
-            let token = String::from_utf8(output.stdout)?.trim().to_string();
-            Ok(token)
+            parse_gcloud_token(&String::from_utf8(output.stdout)?)
         } else {
             let err = String::from_utf8(output.stderr)?;
             Err(err.into())
@@ -62,12 +201,96 @@ impl TokenSource for GcloudTokenSource {
     }
 }
 
+/// Parses the output of `gcloud auth application-default print-access-token --format=json`.
+///
+/// The JSON object carries the token and its expiry. Plain-text output (a bare token, as printed
+/// without `--format=json`) is also accepted, in which case the expiry is unknown.
+///
+/// # Arguments
+///
+/// * `output` - The standard output of the `gcloud` command.
+///
+/// # Returns
+///
+/// * `Ok(AccessToken)` - The parsed token.
+/// * `Err(Box<dyn std::error::Error>)` - An error if the output contains no token.
+fn parse_gcloud_token(output: &str) -> Result<AccessToken, Box<dyn std::error::Error>> {
+    let output = output.trim();
+    let Ok(json) = serde_json::from_str::<Value>(output) else {
+        if output.is_empty() {
+            return Err("gcloud printed no token".into());
+        }
+        return Ok(AccessToken {
+            token: output.to_string(),
+            expires_at: None,
+        });
+    };
+
+    let token = ["token", "access_token"]
+        .iter()
+        .find_map(|key| json[key].as_str())
+        .ok_or("No token in gcloud output")?
+        .to_string();
+    let expires_at = json["token_expiry"]
+        .as_str()
+        .and_then(|expiry| {
+            DateTime::parse_from_rfc3339(expiry)
+                .map(|t| t.with_timezone(&Utc))
+                .ok()
+                // gcloud may omit the offset, in which case the time is in UTC
+                .or_else(|| {
+                    chrono::NaiveDateTime::parse_from_str(expiry, "%Y-%m-%dT%H:%M:%S%.f")
+                        .map(|t| t.and_utc())
+                        .ok()
+                })
+        })
+        .or_else(|| {
+            json["expires_in"]
+                .as_i64()
+                .map(|secs| Utc::now() + chrono::Duration::seconds(secs))
+        });
+
+    Ok(AccessToken { token, expires_at })
+}
+
+/// Forwards to the boxed token source, so the token source can be chosen at runtime.
+impl<T: TokenSource + ?Sized> TokenSource for Box<T> {
+    fn get_token(&self, project: &str) -> Result<String, Box<dyn std::error::Error>> {
+        (**self).get_token(project)
+    }
+
+    fn get_access_token(&self, project: &str) -> Result<AccessToken, Box<dyn std::error::Error>> {
+        (**self).get_access_token(project)
+    }
+}
+
+/// Forwards to the shared token source, so that clients can share one token source and its
+/// tokens.
+impl<T: TokenSource + ?Sized> TokenSource for std::sync::Arc<T> {
+    fn get_token(&self, project: &str) -> Result<String, Box<dyn std::error::Error>> {
+        (**self).get_token(project)
+    }
+
+    fn get_access_token(&self, project: &str) -> Result<AccessToken, Box<dyn std::error::Error>> {
+        (**self).get_access_token(project)
+    }
+}
+
 /// A mock token source for testing purposes.
 pub struct MockTokenSource {
     /// The mock token to return.
     mock_token: String,
 }
 
+impl MockTokenSource {
+    /// Creates a new `MockTokenSource` that always returns the given token.
+    pub fn new(mock_token: &str) -> Self {
+        Self {
+            mock_token: mock_token.to_string(),
+        }
+    }
+}
+
 impl TokenSource for MockTokenSource {
     /// Returns the configured mock token.
     ///
@@ -83,36 +306,164 @@ impl TokenSource for MockTokenSource {
     }
 }
 
-/// An iterator that handles paginating through all the instances in a project.
-/// Each call to `next` fetches a page of instances from the API as vectors of `Instance` structs.
-struct InstancesPageIterator<'a, H: http::HttpClient, T: TokenSource> {
+/// Parses one element of an aggregated list into a record, given the API version it came from.
+type ParseFn<R> = fn(&Value, ApiVersion) -> Result<R, Box<dyn std::error::Error>>;
+
+/// A page of records from an aggregated list, or the error that ended the listing.
+pub type Page<R> = Result<ResultSet<R>, Box<dyn std::error::Error>>;
+
+/// Records from a listing, along with warnings about the parts of it that are missing, so that
+/// a zone that can't be listed doesn't hide the rest of the project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultSet<R> {
+    /// The records.
+    pub items: Vec<R>,
+    /// What is missing from `items` and why, in the order it was found.
+    pub warnings: Vec<ListWarning>,
+}
+
+impl<R> Default for ResultSet<R> {
+    fn default() -> Self {
+        ResultSet {
+            items: vec![],
+            warnings: vec![],
+        }
+    }
+}
+
+impl<R> ResultSet<R> {
+    /// Creates a listing without warnings.
+    pub fn new(items: Vec<R>) -> Self {
+        ResultSet {
+            items,
+            warnings: vec![],
+        }
+    }
+
+    /// Collects pages into a single listing.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet)` - The records and warnings of every page.
+    /// * `Err(Box<dyn std::error::Error>)` - The error that ended the pages.
+    pub fn from_pages(
+        pages: impl Iterator<Item = Page<R>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut listing = ResultSet::default();
+        for page in pages {
+            let page = page?;
+            listing.items.extend(page.items);
+            listing.warnings.extend(page.warnings);
+        }
+        Ok(listing)
+    }
+}
+
+impl<R> IntoIterator for ResultSet<R> {
+    type Item = R;
+    type IntoIter = vec::IntoIter<R>;
+
+    /// Iterates over the records, dropping the warnings.
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+/// A record from a listing, or the error that ended the listing.
+pub type Listed<R> = Result<R, Box<dyn std::error::Error>>;
+
+/// Flattens pages of records into the records themselves, fetching each page only when the
+/// records before it have been consumed.
+///
+/// # Arguments
+///
+/// * `pages` - The pages, e.g. from `Compute::list_instance_pages`.
+///
+/// # Returns
+///
+/// An iterator over the records. An error ending the pages is yielded after the records
+/// before it, and ends the iteration. The warnings of the pages are dropped.
+pub fn flatten_pages<R>(pages: impl Iterator<Item = Page<R>>) -> impl Iterator<Item = Listed<R>> {
+    pages.flat_map(|page| {
+        let (records, error) = match page {
+            Ok(listing) => (listing.items, None),
+            Err(e) => (vec![], Some(e)),
+        };
+        records.into_iter().map(Ok).chain(error.map(Err))
+    })
+}
+
+/// Makes an error sendable to another thread. HTTP errors are kept as they are, so callers can
+/// still tell e.g. permission errors apart; others are kept as their message.
+fn sendable(e: Box<dyn std::error::Error>) -> Box<dyn std::error::Error + Send + Sync> {
+    match e.downcast::<http::HttpError>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
+
+/// An iterator that handles paginating through an aggregated list of resources (instances,
+/// disks, ...) in a project, or through the list of one zone.
+/// Each call to `next` fetches a page of resources from the API as a vector of records.
+struct AggregatedPageIterator<'a, H: http::HttpClient, T: TokenSource, R> {
     config: &'a ComputeConfig<H, T>,
+    /// The resource collection, e.g. `instances`. This is both the URL path segment and the
+    /// key of the list within each scope of the response.
+    resource: &'static str,
+    /// Converts each element of the list into a record.
+    parse: ParseFn<R>,
+    /// A filter expression applied by the server, e.g. `labels.env = "prod"`.
+    filter: Option<String>,
+    /// The zone to list instead of the whole project. Its pages are parsed as aggregated
+    /// lists with a single scope.
+    zone: Option<String>,
     page_token: Option<String>,
     auth_token: String,
     finished: bool,
 }
 
-/// Implementation of the `InstanceIterator` struct.
-impl<'a, H: http::HttpClient, T: TokenSource> InstancesPageIterator<'a, H, T> {
-    fn new(config: &'a ComputeConfig<H, T>, auth_token: String) -> Self {
+/// Implementation of the `AggregatedPageIterator` struct.
+impl<'a, H: http::HttpClient, T: TokenSource, R> AggregatedPageIterator<'a, H, T, R> {
+    fn new(
+        config: &'a ComputeConfig<H, T>,
+        auth_token: String,
+        resource: &'static str,
+        parse: ParseFn<R>,
+    ) -> Self {
         Self {
             config,
+            resource,
+            parse,
+            filter: None,
+            zone: None,
             page_token: None,
             auth_token,
             finished: false,
         }
     }
+
+    /// Only lists resources matching a filter expression.
+    fn with_filter(mut self, filter: Option<&str>) -> Self {
+        self.filter = filter.map(str::to_string);
+        self
+    }
+
+    /// Only lists the resources of one zone.
+    fn in_zone(mut self, zone: &str) -> Self {
+        self.zone = Some(zone.to_string());
+        self
+    }
 }
 
-/// Implementation of the `Iterator` trait for `InstanceIterator`.
-impl<H: http::HttpClient, T: TokenSource> Iterator for InstancesPageIterator<'_, H, T> {
-    type Item = Result<Vec<records::Instance>, Box<dyn std::error::Error>>;
+/// Implementation of the `Iterator` trait for `AggregatedPageIterator`.
+impl<H: http::HttpClient, T: TokenSource, R> Iterator for AggregatedPageIterator<'_, H, T, R> {
+    type Item = Page<R>;
 
-    /// Fetches the next page of instances from the API.
+    /// Fetches the next page of resources from the API.
     /// If there are no more pages, returns `None`.
     /// If an error occurs, returns an error result wrapped in `Some` and terminates the iteration by setting `finished`
     /// to `true`.
-    /// If the next page is successfully fetched, returns a vector of instances wrapped in `Some`.
+    /// If the next page is successfully fetched, returns a vector of records wrapped in `Some`.
     fn next(&mut self) -> Option<Self::Item> {
         if self.finished {
             return None;
@@ -120,16 +471,24 @@ impl<H: http::HttpClient, T: TokenSource> Iterator for InstancesPageIterator<'_,
 
         // Construct the URL
         // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/aggregatedList#http-request>
-        let url = match &self.page_token {
-            Some(token) => format!(
-                "https://compute.googleapis.com/compute/v1/projects/{}/aggregated/instances?pageToken={}",
-                self.config.project, token
-            ),
-            None => format!(
-                "https://compute.googleapis.com/compute/v1/projects/{}/aggregated/instances",
-                self.config.project
-            ),
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/list#http-request>
+        let endpoint = match &self.zone {
+            Some(zone) => Endpoint::Zonal {
+                zone: zone.clone(),
+                resource: self.resource.to_string(),
+            },
+            None => Endpoint::Aggregated(self.resource.to_string()),
         };
+        let url = self
+            .config
+            .url(endpoint)
+            .optional_param("filter", self.filter.as_deref())
+            .optional_param("maxResults", self.config.page_size)
+            .optional_param("pageToken", self.page_token.as_deref())
+            // Without it, an unreachable zone fails the whole aggregated list instead of being
+            // reported in `unreachables` and the scope warnings
+            .optional_param("returnPartialSuccess", self.zone.is_none().then_some(true))
+            .to_string();
 
         // Make the HTTP request
         let resp = match self.config.client.get(&self.auth_token, &url) {
@@ -141,53 +500,77 @@ impl<H: http::HttpClient, T: TokenSource> Iterator for InstancesPageIterator<'_,
         };
 
         // Parse the response
-        let json_response = match resp["items"].as_object() {
-            Some(instances_json) => instances_json,
-            None => {
+        let list = match &self.zone {
+            Some(zone) => {
+                AggregatedList::from_scoped(&format!("zones/{}", zone), self.resource, resp)
+            }
+            None => AggregatedList::try_from(resp),
+        };
+        let list = match list {
+            Ok(list) => list,
+            Err(e) => {
                 self.finished = true;
-                return Some(Err("No items in response".into()));
+                return Some(Err(e));
             }
         };
 
-        // Convert the json response to a list of instance structs
-        let mut error = false;
-        let instance_list = json_response
-            .iter() // Iterate over the zones
-            // Convert the instances in each zone to a list of instances
-            .flat_map(|(_, value)| {
-                let object = value
-                    .as_object()
-                    .expect("Expected JSON object but got something else");
-                object_to_instance_list(object)
-            })
-            // Filter out any errors that occurred during parsing
-            // Print any errors and set the error flag to true replace the error with None which will filter it out
-            .filter_map(|result| match result {
-                Ok(instance) => Some(instance),
-                Err(e) => {
-                    println!("error: {:?}", e);
-                    error = true;
-                    None
-                }
+        // Convert the resources of each scope (zone or region) to records, logging any that
+        // can't be parsed along with where they are in the response. They are skipped unless
+        // the listing is strict
+        let (record_list, errors): (Vec<_>, Vec<_>) = list
+            .parse(self.resource, |json| {
+                (self.parse)(json, self.config.api_version)
             })
+            .into_iter()
+            .partition(Result::is_ok);
+        let record_list = record_list.into_iter().flatten().collect::<Vec<_>>();
+        let errors = errors
+            .into_iter()
+            .filter_map(Result::err)
             .collect::<Vec<_>>();
+        for e in &errors {
+            tracing::debug!(resource = self.resource, error = %e, "failed to parse record");
+        }
 
         // Check for errors
-        if error {
+        if let (true, Some(first)) = (self.config.strict, errors.first()) {
             self.finished = true;
-            return Some(Err("Error parsing instances".into()));
+            let more = match errors.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            return Some(Err(format!(
+                "Error parsing {}: {}{}",
+                self.resource, first, more
+            )
+            .into()));
+        }
+
+        // The API's own warnings about scopes come first, e.g. zones that couldn't be reached,
+        // then the records skipped
+        let scope_warnings = list.warnings();
+        for warning in scope_warnings
+            .iter()
+            .filter(|warning| warning.is_unreachable())
+        {
+            tracing::debug!(resource = self.resource, scope = %warning.scope, "scope unreachable");
         }
+        let warnings = scope_warnings
+            .into_iter()
+            .map(ListWarning::from)
+            .chain(errors.into_iter().map(ListWarning::from))
+            .collect();
 
         // Check for a next page token
-        self.page_token = resp["nextPageToken"]
-            .as_str()
-            .map(|token| token.to_string())
-            .or_else(|| {
-                self.finished = true;
-                None
-            });
+        self.page_token = list.next_page_token.or_else(|| {
+            self.finished = true;
+            None
+        });
 
-        Some(Ok(instance_list))
+        Some(Ok(ResultSet {
+            items: record_list,
+            warnings,
+        }))
     }
 }
 
@@ -199,6 +582,26 @@ pub struct ComputeConfig<H: http::HttpClient, T: TokenSource> {
     pub client: H,
     /// The token source.
     pub token_source: T,
+    /// The API version to call.
+    pub api_version: ApiVersion,
+    /// How many resources to request per page of aggregated lists, up to 500. `None` leaves
+    /// it to the API, which returns up to 500.
+    pub page_size: Option<u32>,
+    /// Whether a resource that can't be parsed fails the listing. Otherwise it is logged and
+    /// skipped, so that one odd resource doesn't hide all the others.
+    pub strict: bool,
+    /// How instances are listed.
+    pub list_strategy: ListStrategy,
+    /// How many zones are listed at once with `ListStrategy::PerZone`.
+    pub jobs: usize,
+}
+
+impl<H: http::HttpClient, T: TokenSource> ComputeConfig<H, T> {
+    /// Returns the URL of an endpoint in the project, e.g.
+    /// `https://compute.googleapis.com/compute/v1/projects/my-project/zones`.
+    fn url(&self, endpoint: Endpoint) -> ComputeUrl {
+        ComputeUrl::new(self.api_version, &self.project, endpoint)
+    }
 }
 
 /// Provides an interface for interacting with the Google Compute Engine API.
@@ -221,203 +624,2326 @@ impl<H: http::HttpClient, T: TokenSource> Compute<H, T> {
         Self { config }
     }
 
-    /// Lists available zones in the project (currently unused).
-    #[allow(dead_code)]
+    /// Returns the Google Cloud project ID this service talks to.
+    pub fn project(&self) -> &str {
+        &self.config.project
+    }
+
+    /// Lists the zones of the project, e.g. to list instances one zone at a time.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - The names of the zones, e.g. `us-east1-b`.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
     pub fn list_zones(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let url = format!(
-            "https://compute.googleapis.com/compute/v1/projects/{}/zones",
-            self.config.project
-        );
+        let zones = self.list_zone_statuses()?;
+        Ok(zones.into_iter().map(|(name, _)| name).collect())
+    }
 
-        println!("url: {:?}", url);
+    /// Lists the zones of the project with their status, `UP` or `DOWN`.
+    fn list_zone_statuses(&self) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
         let token = self.config.token_source.get_token(&self.config.project)?;
-        let resp = self.config.client.get(&token, &url)?;
-        let zones = resp["items"]
-            .as_array()
-            .ok_or("No items in response")?
-            .iter()
-            .map(|item| item["name"].as_str().unwrap().to_string())
-            .collect::<Vec<String>>();
+        let mut zones = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let url = self
+                .config
+                .url(Endpoint::Zones)
+                .optional_param("maxResults", self.config.page_size)
+                .optional_param("pageToken", page_token.as_deref())
+                .to_string();
+            let resp = self.config.client.get(&token, &url)?;
+            for item in resp["items"].as_array().into_iter().flatten() {
+                let name = item["name"]
+                    .as_str()
+                    .ok_or("Missing or invalid 'name' field of zone")?;
+                let status = item["status"].as_str().unwrap_or_default();
+                zones.push((name.to_string(), status.to_string()));
+            }
+            match resp["nextPageToken"].as_str() {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(zones),
+            }
+        }
+    }
 
-        Ok(zones)
+    /// Makes a cheap authenticated API call, listing at most one zone, to check that the
+    /// project is reachable with the current credentials.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the call succeeded.
+    /// * `Err(Box<dyn std::error::Error>)` - The error of the token source or the API.
+    pub fn check_access(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let url = self
+            .config
+            .url(Endpoint::Zones)
+            .param("maxResults", 1)
+            .to_string();
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        self.config.client.get(&token, &url)?;
+        Ok(())
     }
 
     /// Lists instances in the specified project
     /// # Returns
     ///
-    /// * `Ok(Vec<Instance>)` - A vector of `Instance` structs representing the matching instances.
+    /// * `Ok(ResultSet<Instance>)` - The instances, with warnings about zones the API reported
+    ///   problems with and instances that couldn't be parsed and were skipped.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_instances(
+        &self,
+    ) -> Result<ResultSet<records::Instance>, Box<dyn std::error::Error>>
+    where
+        H: Sync,
+        T: Sync,
+    {
+        self.list_instances(None)
+    }
+
+    /// Lists the instances in the project matching a filter expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Compute Engine filter expression, e.g. `labels.env = "prod"`, as built by
+    ///   `query::InstanceFilter::expression`. `None` lists every instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The matching instances, with warnings as for
+    ///   `list_all_instances`.
     /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
     ///   issue parsing the response.
-    pub fn list_all_instances(&self) -> Result<Vec<records::Instance>, Box<dyn std::error::Error>> {
+    pub fn list_instances(
+        &self,
+        filter: Option<&str>,
+    ) -> Result<ResultSet<records::Instance>, Box<dyn std::error::Error>>
+    where
+        H: Sync,
+        T: Sync,
+    {
+        ResultSet::from_pages(self.list_instance_pages(filter)?)
+    }
+
+    /// Lists the instances in the project matching a filter expression one at a time. Pages are
+    /// fetched as the iterator reaches them, so a consumer can show the first instances while
+    /// later pages are still to come, and stop early without fetching the rest.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Compute Engine filter expression, as for `list_instances`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(impl Iterator)` - An iterator over the instances. The iteration ends after the
+    ///   first error.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if no token could be fetched.
+    pub fn instances_iter(
+        &self,
+        filter: Option<&str>,
+    ) -> Result<impl Iterator<Item = Listed<records::Instance>> + '_, Box<dyn std::error::Error>>
+    where
+        H: Sync,
+        T: Sync,
+    {
+        Ok(flatten_pages(self.list_instance_pages(filter)?))
+    }
+
+    /// Lists the instances in the project matching a filter expression a page at a time, so
+    /// they can be shown before the whole listing has arrived.
+    ///
+    /// With `ListStrategy::PerZone` each page holds the instances of one zone, and all of them
+    /// are fetched before the first is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Compute Engine filter expression, as for `list_instances`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(impl Iterator)` - An iterator over the pages of instances. Each page is fetched
+    ///   when the iterator reaches it, and the iteration ends after the first error.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if no token could be fetched.
+    pub fn list_instance_pages(
+        &self,
+        filter: Option<&str>,
+    ) -> Result<impl Iterator<Item = Page<records::Instance>> + '_, Box<dyn std::error::Error>>
+    where
+        H: Sync,
+        T: Sync,
+    {
         // Fetch the auth token
         let auth_token = self.config.token_source.get_token(&self.config.project)?;
 
         // Create an iterator over the instances. This will handle pagination.
-        let iter = InstancesPageIterator::new(&self.config, auth_token);
+        let pages: Box<dyn Iterator<Item = Page<records::Instance>>> = match self
+            .config
+            .list_strategy
+        {
+            ListStrategy::Aggregated => Box::new(
+                AggregatedPageIterator::new(&self.config, auth_token, "instances", parse_instance)
+                    .with_filter(filter),
+            ),
+            ListStrategy::PerZone => Box::new(self.list_zone_pages(auth_token, filter).into_iter()),
+        };
+        Ok(pages)
+    }
 
-        // Collect the instances from the iterator returning either a vector of vectors of instances
-        // or an error if one occurred during the iteration.
-        let instances = iter.collect::<Result<Vec<_>, _>>()?;
+    /// Lists the instances of every zone that isn't down, `jobs` zones at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_token` - The token to list with.
+    /// * `filter` - A Compute Engine filter expression, as for `list_instances`.
+    ///
+    /// # Returns
+    ///
+    /// A page per zone, in the order the API lists the zones. A zone that is down or can't be
+    /// listed is reported as a warning of its page, as an aggregated listing reports zones it
+    /// can't reach, so the other zones are still listed. If the zones can't be listed, the
+    /// only page is that error.
+    fn list_zone_pages(
+        &self,
+        auth_token: String,
+        filter: Option<&str>,
+    ) -> Vec<Page<records::Instance>>
+    where
+        H: Sync,
+        T: Sync,
+    {
+        let zones = match self.list_zone_statuses() {
+            Ok(zones) => zones,
+            Err(e) => return vec![Err(e)],
+        };
+        let listings = map_bounded(&zones, self.config.jobs, |(zone, status)| {
+            if status == "DOWN" {
+                return Ok(ResultSet::default());
+            }
+            let pages = AggregatedPageIterator::new(
+                &self.config,
+                auth_token.clone(),
+                "instances",
+                parse_instance,
+            )
+            .with_filter(filter)
+            .in_zone(zone);
+            ResultSet::from_pages(pages).map_err(sendable)
+        });
+        zones
+            .iter()
+            .zip(listings)
+            .map(|((zone, status), listing)| {
+                let scope = format!("zones/{}", zone);
+                let warning = match listing {
+                    Ok(listing) if status != "DOWN" => return Ok(listing),
+                    Ok(_) => ScopeWarning::down(&scope),
+                    Err(e) => ScopeWarning::failed(&scope, e.as_ref()),
+                };
+                Ok(ResultSet {
+                    items: vec![],
+                    warnings: vec![warning.into()],
+                })
+            })
+            .collect()
+    }
 
-        // Flatten the vector of vectors into a single vector iterator and collect it into a vector of instances.
-        Ok(instances.into_iter().flatten().collect())
+    /// Fetches every page of an aggregated list and flattens the records into a single vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource collection, e.g. `disks`.
+    /// * `parse` - Converts each element of the list into a record.
+    fn list_all<R>(
+        &self,
+        resource: &'static str,
+        parse: ParseFn<R>,
+    ) -> Result<Vec<R>, Box<dyn std::error::Error>> {
+        let auth_token = self.config.token_source.get_token(&self.config.project)?;
+        let iter = AggregatedPageIterator::new(&self.config, auth_token, resource, parse);
+        let pages = iter.collect::<Result<Vec<_>, _>>()?;
+        Ok(pages.into_iter().flatten().collect())
     }
-}
 
-/// Converts a JSON object representing a group of instances within a zone
-/// into a vector of `Result<Instance, Box<dyn std::error::Error>>`.
-///
-/// This function takes a JSON object, extracts the "instances" array if present,
-/// and attempts to convert each element of the array into an `Instance` struct.
-/// Any errors encountered during the conversion are returned as part of the vector.
-///
-/// # Arguments
-///
-/// * `object` - The JSON object representing a group of instances within a zone.
-///
-/// # Returns
-///
-/// A vector of `Result<Instance, Box<dyn std::error::Error>>`. Each element
-/// represents either a successfully parsed `Instance` or an error encountered
-/// during parsing.
-fn object_to_instance_list(
-    object: &Map<String, Value>,
-) -> Vec<Result<Instance, Box<dyn std::error::Error>>> {
-    object
-        .get("instances")
-        .and_then(|value| value.as_array())
-        .unwrap_or(&vec![])
-        .iter()
-        .map(|instance| Instance::try_from(instance.clone()))
-        .collect()
-}
+    /// Lists persistent disks in all zones and regions of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Disk>)` - A vector of `Disk` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_disks(&self) -> Result<Vec<Disk>, Box<dyn std::error::Error>> {
+        self.list_all("disks", |json, _| Disk::try_from(json.clone()))
+    }
 
-// Tests
+    /// Lists reserved static IP addresses in all regions of the project, including the
+    /// global ones.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Address>)` - A vector of `Address` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_addresses(&self) -> Result<Vec<Address>, Box<dyn std::error::Error>> {
+        // The aggregated list also has a `global` scope with the global addresses
+        self.list_all("addresses", |json, _| Address::try_from(json.clone()))
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::http::MockHttpClient;
-    use mockall::predicate;
-    use serde_json::json;
+    /// Lists the VPC networks of the project. Networks are global resources.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Network>)` - A vector of `Network` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_networks(&self) -> Result<Vec<Network>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/networks/list>
+        self.list_global("networks", |json| Network::try_from(json.clone()))
+    }
 
-    #[test]
-    fn test_list_zones() {
-        let mut mock_http = MockHttpClient::new();
+    /// Lists subnets in all regions of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Subnetwork>)` - A vector of `Subnetwork` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_subnetworks(&self) -> Result<Vec<Subnetwork>, Box<dyn std::error::Error>> {
+        self.list_all("subnetworks", |json, _| Subnetwork::try_from(json.clone()))
+    }
 
-        // Set up expectations
-        let expected_token = "mock_token".to_string();
-        let expected_result = vec!["zone1".to_string(), "zone2".to_string()];
-        mock_http
-            .expect_get()
-            .with(
-                predicate::eq(expected_token.clone()),
-                predicate::eq(
-                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones",
-                ),
-            )
-            .return_once(move |_, _| Ok(json!({"items": [{"name": "zone1"}, {"name": "zone2"}]})));
+    /// Lists capacity reservations in all zones of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Reservation>)` - A vector of `Reservation` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_reservations(&self) -> Result<Vec<Reservation>, Box<dyn std::error::Error>> {
+        self.list_all("reservations", |json, _| {
+            Reservation::try_from(json.clone())
+        })
+    }
 
-        // Create a Compute instance with the mock HttpTrait
-        let config = ComputeConfig {
-            project: "test-project".to_string(),
-            client: mock_http,
-            token_source: MockTokenSource {
-                mock_token: expected_token,
+    /// Lists resource policies, such as instance and snapshot schedules, in all regions of
+    /// the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ResourcePolicy>)` - A vector of `ResourcePolicy` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_resource_policies(
+        &self,
+    ) -> Result<Vec<ResourcePolicy>, Box<dyn std::error::Error>> {
+        self.list_all("resourcePolicies", |json, _| {
+            ResourcePolicy::try_from(json.clone())
+        })
+    }
+
+    /// Lists managed instance groups in all zones and regions of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<InstanceGroupManager>)` - A vector of `InstanceGroupManager` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_instance_group_managers(
+        &self,
+    ) -> Result<Vec<InstanceGroupManager>, Box<dyn std::error::Error>> {
+        self.list_all("instanceGroupManagers", |json, _| {
+            InstanceGroupManager::try_from(json.clone())
+        })
+    }
+
+    /// Lists autoscalers in all zones and regions of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Autoscaler>)` - A vector of `Autoscaler` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_autoscalers(&self) -> Result<Vec<Autoscaler>, Box<dyn std::error::Error>> {
+        self.list_all("autoscalers", |json, _| Autoscaler::try_from(json.clone()))
+    }
+
+    /// Lists the instances of a managed instance group with their current action and health.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - The zone or region of the group.
+    /// * `name` - The name of the group.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ManagedInstance>)` - The instances of the group.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_managed_instances(
+        &self,
+        location: &MigLocation,
+        name: &str,
+    ) -> Result<Vec<ManagedInstance>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instanceGroupManagers/listManagedInstances>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let mut instances = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let url = self
+                .config
+                .url(Endpoint::GroupManagerMethod {
+                    location: location.clone(),
+                    name: name.to_string(),
+                    method: "listManagedInstances".to_string(),
+                })
+                .optional_param("maxResults", self.config.page_size)
+                .optional_param("pageToken", page_token.as_deref())
+                .to_string();
+            let resp = self.config.client.post(&token, &url, &json!({}))?;
+            for item in resp["managedInstances"].as_array().into_iter().flatten() {
+                instances.push(ManagedInstance::try_from(item.clone())?);
+            }
+            match resp["nextPageToken"].as_str() {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(instances),
+            }
+        }
+    }
+
+    /// Lists the disk snapshots of the project. Snapshots are global resources, so they are
+    /// listed page by page from the global collection rather than an aggregated list.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Snapshot>)` - A vector of `Snapshot` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_snapshots(&self) -> Result<Vec<Snapshot>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/snapshots/list>
+        self.list_global("snapshots", |json| Snapshot::try_from(json.clone()))
+    }
+
+    /// Lists the custom images of the project, including deprecated ones. Public images,
+    /// e.g. of `debian-cloud`, belong to other projects and aren't listed.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Image>)` - A vector of `Image` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_images(&self) -> Result<Vec<Image>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/images/list>
+        self.list_global("images", |json| Image::try_from(json.clone()))
+    }
+
+    /// Lists the global instance templates of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<InstanceTemplate>)` - A vector of `InstanceTemplate` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_instance_templates(
+        &self,
+    ) -> Result<Vec<InstanceTemplate>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instanceTemplates/list>
+        self.list_global("instanceTemplates", |json| {
+            InstanceTemplate::try_from(json.clone())
+        })
+    }
+
+    /// Fetches every page of a global collection and parses its items into records.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource collection, e.g. `snapshots`.
+    /// * `parse` - Converts each item of the list into a record.
+    fn list_global<R>(
+        &self,
+        resource: &str,
+        parse: fn(&Value) -> Result<R, Box<dyn std::error::Error>>,
+    ) -> Result<Vec<R>, Box<dyn std::error::Error>> {
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let mut records = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let url = self
+                .config
+                .url(Endpoint::Global(resource.to_string()))
+                .optional_param("maxResults", self.config.page_size)
+                .optional_param("pageToken", page_token.as_deref())
+                .to_string();
+            let resp = self.config.client.get(&token, &url)?;
+            // An empty list has no items at all
+            for item in resp["items"].as_array().into_iter().flatten() {
+                records.push(parse(item)?);
+            }
+            match resp["nextPageToken"].as_str() {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(records),
+            }
+        }
+    }
+
+    /// Fetches the details of a machine type.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone to look the machine type up in.
+    /// * `name` - The name of the machine type, e.g. `n2-standard-16`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(MachineType)` - The machine type.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_machine_type(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<MachineType, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/machineTypes/get>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self
+            .config
+            .url(Endpoint::MachineType {
+                zone: zone.to_string(),
+                name: name.to_string(),
+            })
+            .to_string();
+        MachineType::try_from(self.config.client.get(&token, &url)?)
+    }
+
+    /// Fetches the full details of an instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance, e.g. from `find_instance`.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstanceDetail)` - The instance details.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_instance(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<InstanceDetail, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/get>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self
+            .config
+            .url(Endpoint::Instance {
+                zone: zone.to_string(),
+                name: name.to_string(),
+            })
+            .to_string();
+        let json = self.config.client.get(&token, &url)?;
+        let mut detail = InstanceDetail::try_from(json.clone())?;
+        if self.config.api_version == ApiVersion::Beta {
+            detail.instance.beta = Some(BetaFields::from(&json));
+        }
+        Ok(detail)
+    }
+
+    /// Starts a stopped instance and waits for the operation to finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The finished operation.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn start_instance(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        self.instance_action(zone, name, "start")
+    }
+
+    /// Stops a running instance and waits for the operation to finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The finished operation.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn stop_instance(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        self.instance_action(zone, name, "stop")
+    }
+
+    /// Hard-resets an instance and waits for the operation to finish.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The finished operation.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn reset_instance(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        self.instance_action(zone, name, "reset")
+    }
+
+    /// POSTs an action (`start`, `stop`, `reset`) to an instance and waits for the resulting
+    /// zone operation to finish.
+    fn instance_action(
+        &self,
+        zone: &str,
+        name: &str,
+        action: &str,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/stop>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self
+            .config
+            .url(Endpoint::InstanceMethod {
+                zone: zone.to_string(),
+                name: name.to_string(),
+                method: action.to_string(),
+            })
+            // The API acts on a request ID only once, so the request can be retried safely
+            .param("requestId", http::new_request_id())
+            .to_string();
+        let resp = self.config.client.post(&token, &url, &json!({}))?;
+        self.wait_for_operation(&token, Operation::try_from(resp)?)
+    }
+
+    /// Fetches the custom metadata of an instance, with its fingerprint.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance, e.g. from `find_instance`.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Metadata)` - The metadata.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_metadata(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<Metadata, Box<dyn std::error::Error>> {
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        self.fetch_metadata(&token, zone, name)
+    }
+
+    /// Sets metadata entries of an instance, keeping the others, and waits for the operation
+    /// to finish.
+    ///
+    /// The API only accepts a change along with the fingerprint of the metadata it replaces.
+    /// If another change lands in between, the metadata is read again and the change retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    /// * `items` - The entries to set.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Operation))` - The finished operation.
+    /// * `Ok(None)` - If the instance already had these entries, so nothing was changed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn set_metadata(
+        &self,
+        zone: &str,
+        name: &str,
+        items: &[MetadataItem],
+    ) -> Result<Option<Operation>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/setMetadata>
+        self.update_instance(zone, name, "setMetadata", |instance| {
+            let mut metadata =
+                Metadata::try_from(instance.get("metadata").cloned().unwrap_or_default())?;
+            Ok(metadata.set(items).then(|| metadata.to_json()))
+        })
+    }
+
+    /// Fetches output of an instance's serial port.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance, e.g. from `find_instance`.
+    /// * `name` - The name of the instance.
+    /// * `port` - The port, from 1 to 4. Port 1 is the console.
+    /// * `start` - The position to read from, e.g. the `next` of the previous chunk. `None`
+    ///   reads all the output that is kept.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SerialOutput)` - The output from `start` up to now.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_serial_output(
+        &self,
+        zone: &str,
+        name: &str,
+        port: u8,
+        start: Option<u64>,
+    ) -> Result<SerialOutput, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/getSerialPortOutput>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self
+            .config
+            .url(Endpoint::InstanceMethod {
+                zone: zone.to_string(),
+                name: name.to_string(),
+                method: "serialPort".to_string(),
+            })
+            .param("port", port)
+            .optional_param("start", start)
+            .to_string();
+        SerialOutput::try_from(self.config.client.get(&token, &url)?)
+    }
+
+    /// Fetches the labels of an instance, with their fingerprint.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance, e.g. from `find_instance`.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Labels)` - The labels.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_labels(&self, zone: &str, name: &str) -> Result<Labels, Box<dyn std::error::Error>> {
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self
+            .config
+            .url(Endpoint::Instance {
+                zone: zone.to_string(),
+                name: name.to_string(),
+            })
+            .to_string();
+        Labels::try_from(&self.config.client.get(&token, &url)?)
+    }
+
+    /// Sets and removes labels of an instance, keeping the others, and waits for the operation
+    /// to finish. Like `set_metadata`, the change is retried if the labels change meanwhile.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    /// * `set` - The labels to set.
+    /// * `remove` - The keys of the labels to remove.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Operation))` - The finished operation.
+    /// * `Ok(None)` - If the labels already were as asked, so nothing was changed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn set_labels(
+        &self,
+        zone: &str,
+        name: &str,
+        set: &[Label],
+        remove: &[String],
+    ) -> Result<Option<Operation>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/instances/setLabels>
+        self.update_instance(zone, name, "setLabels", |instance| {
+            let mut labels = Labels::try_from(instance)?;
+            let changes = labels.changes(set, remove);
+            labels.apply(&changes);
+            Ok((!changes.is_empty()).then(|| labels.to_json()))
+        })
+    }
+
+    /// Reads an instance, builds a request body from it and POSTs it to an instance method
+    /// that requires a fingerprint, such as `setMetadata`, then waits for the operation.
+    ///
+    /// If the fingerprint is stale because another change landed in between, the instance is
+    /// read again and the body rebuilt, up to three times.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    /// * `method` - The method, e.g. `setLabels`.
+    /// * `body` - Builds the body from the instance, or returns `None` if there's nothing to
+    ///   change.
+    fn update_instance(
+        &self,
+        zone: &str,
+        name: &str,
+        method: &str,
+        body: impl Fn(&Value) -> Result<Option<Value>, Box<dyn std::error::Error>>,
+    ) -> Result<Option<Operation>, Box<dyn std::error::Error>> {
+        const ATTEMPTS: usize = 3;
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let instance_url = self
+            .config
+            .url(Endpoint::Instance {
+                zone: zone.to_string(),
+                name: name.to_string(),
+            })
+            .to_string();
+        let mut attempt = 1;
+        loop {
+            let Some(body) = body(&self.config.client.get(&token, &instance_url)?)? else {
+                return Ok(None);
+            };
+            // Each change gets its own request ID, which retries of it reuse
+            let method_url = self
+                .config
+                .url(Endpoint::InstanceMethod {
+                    zone: zone.to_string(),
+                    name: name.to_string(),
+                    method: method.to_string(),
+                })
+                .param("requestId", http::new_request_id())
+                .to_string();
+            match self.config.client.post(&token, &method_url, &body) {
+                Ok(resp) => {
+                    return self
+                        .wait_for_operation(&token, Operation::try_from(resp)?)
+                        .map(Some)
+                }
+                Err(e)
+                    if attempt < ATTEMPTS
+                        && e.downcast_ref::<http::HttpError>()
+                            .is_some_and(http::HttpError::is_precondition_failed) =>
+                {
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// GETs an instance and reads its metadata.
+    fn fetch_metadata(
+        &self,
+        token: &str,
+        zone: &str,
+        name: &str,
+    ) -> Result<Metadata, Box<dyn std::error::Error>> {
+        let url = self
+            .config
+            .url(Endpoint::Instance {
+                zone: zone.to_string(),
+                name: name.to_string(),
+            })
+            .to_string();
+        let json = self.config.client.get(token, &url)?;
+        Metadata::try_from(json.get("metadata").cloned().unwrap_or_default())
+    }
+
+    /// Lists the operations of the project in all zones and regions, including the global
+    /// ones. Compute Engine keeps finished operations for a limited time.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Operation>)` - A vector of `Operation` structs.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_all_operations(&self) -> Result<Vec<Operation>, Box<dyn std::error::Error>> {
+        self.list_all("operations", |json, _| Operation::try_from(json.clone()))
+    }
+
+    /// Finds an operation by name, in any zone or region or among the global operations.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the operation, e.g. `operation-1712345678901-abcdef`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Operation))` - The operation, if it exists.
+    /// * `Ok(None)` - If no operation has that name.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails.
+    pub fn find_operation(
+        &self,
+        name: &str,
+    ) -> Result<Option<Operation>, Box<dyn std::error::Error>> {
+        let auth_token = self.config.token_source.get_token(&self.config.project)?;
+        let filter = crate::query::Filter::eq("name", name).expression()?;
+        let parse: ParseFn<Operation> = |json, _| Operation::try_from(json.clone());
+        for page in AggregatedPageIterator::new(&self.config, auth_token, "operations", parse)
+            .with_filter(Some(&filter))
+        {
+            if let Some(operation) = page?.into_iter().find(|op| op.name == name) {
+                return Ok(Some(operation));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits for an operation to finish, e.g. one found with `find_operation`.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation to wait for.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The finished operation.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    pub fn wait_operation(
+        &self,
+        operation: Operation,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        self.wait_for_operation(&token, operation)
+    }
+
+    /// Polls a zone, region or global operation until it is done.
+    ///
+    /// Uses the operation's `wait` method, which returns when the operation is done or after
+    /// about two minutes, whichever comes first.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token for authentication.
+    /// * `operation` - The operation as returned by the request that started it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The finished operation.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails or the operation
+    ///   reports an error.
+    fn wait_for_operation(
+        &self,
+        token: &str,
+        mut operation: Operation,
+    ) -> Result<Operation, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/reference/rest/v1/zoneOperations/wait>
+        while !operation.is_done() {
+            tracing::info!(operation = %operation.name, "waiting for operation");
+            let name = operation.name.clone();
+            let endpoint = match (&operation.zone, &operation.region) {
+                (Some(zone), _) => Endpoint::OperationWait {
+                    zone: zone.clone(),
+                    name,
+                },
+                (None, Some(region)) => Endpoint::RegionOperationWait {
+                    region: region.clone(),
+                    name,
+                },
+                (None, None) => Endpoint::GlobalOperationWait { name },
+            };
+            let url = self.config.url(endpoint).to_string();
+            let resp = self.config.client.post(token, &url, &json!({}))?;
+            operation = Operation::try_from(resp)?;
+        }
+
+        if !operation.errors.is_empty() {
+            return Err(format!(
+                "Operation {} failed: {}",
+                operation.name,
+                operation.errors.join("; ")
+            )
+            .into());
+        }
+        Ok(operation)
+    }
+
+    /// Finds an instance by its exact name.
+    ///
+    /// Pages are fetched only until the instance is found.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Instance))` - The instance, if it exists.
+    /// * `Ok(None)` - If no instance has that name.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn find_instance(
+        &self,
+        name: &str,
+    ) -> Result<Option<Instance>, Box<dyn std::error::Error>> {
+        let auth_token = self.config.token_source.get_token(&self.config.project)?;
+        for page in
+            AggregatedPageIterator::new(&self.config, auth_token, "instances", parse_instance)
+        {
+            if let Some(instance) = page?.into_iter().find(|i| i.name == name) {
+                return Ok(Some(instance));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Returns the last segment of a resource URL, e.g. `us-east1-b` for
+/// `projects/p/zones/us-east1-b`, or the whole text if it has no `/`.
+pub(crate) fn last_segment(url: &str) -> String {
+    url.rsplit('/').next().unwrap_or(url).to_string()
+}
+
+/// Parses an instance. Beta-only fields are only parsed when the listing came from the beta API.
+pub(crate) fn parse_instance(
+    json: &Value,
+    api_version: ApiVersion,
+) -> Result<Instance, Box<dyn std::error::Error>> {
+    let mut instance = Instance::try_from(json.clone())?;
+    if api_version == ApiVersion::Beta {
+        instance.beta = Some(records::BetaFields::from(json));
+    }
+    Ok(instance)
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{MockHttpClient, ReplayHttp};
+    use mockall::predicate;
+
+    #[test]
+    fn test_last_segment() {
+        assert_eq!(last_segment("projects/p/zones/us-east1-b"), "us-east1-b");
+        assert_eq!(last_segment("us-east1-b"), "us-east1-b");
+        assert_eq!(last_segment(""), "");
+    }
+
+    #[test]
+    fn test_parse_gcloud_token() {
+        let token =
+            parse_gcloud_token(r#"{"token": "ya29.abc", "token_expiry": "2030-01-02T03:04:05Z"}"#)
+                .unwrap();
+        assert_eq!(token.token, "ya29.abc");
+        assert_eq!(
+            token.expires_at,
+            Some("2030-01-02T03:04:05Z".parse::<DateTime<Utc>>().unwrap())
+        );
+
+        let token = parse_gcloud_token(
+            r#"{"access_token": "ya29.def", "token_expiry": "2030-01-02T03:04:05.123456"}"#,
+        )
+        .unwrap();
+        assert_eq!(token.token, "ya29.def");
+        assert!(token.expires_at.is_some());
+
+        let token = parse_gcloud_token("ya29.plain\n").unwrap();
+        assert_eq!(token.token, "ya29.plain");
+        assert_eq!(token.expires_at, None);
+
+        assert!(parse_gcloud_token("").is_err());
+    }
+
+    #[test]
+    fn test_list_zones() {
+        let mut mock_http = MockHttpClient::new();
+
+        // Set up expectations
+        let expected_token = "mock_token".to_string();
+        let expected_result = vec!["zone1".to_string(), "zone2".to_string()];
+        mock_http
+            .expect_get()
+            .with(
+                predicate::eq(expected_token.clone()),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones",
+                ),
+            )
+            .return_once(move |_, _| Ok(json!({"items": [{"name": "zone1"}, {"name": "zone2"}]})));
+
+        // Create a Compute instance with the mock HttpTrait
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: expected_token,
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.list_zones();
+        let result = result.unwrap();
+
+        // Assert that the function returned the expected result
+        assert_eq!(result, expected_result);
+    }
+
+    #[test]
+    fn test_list_instances() {
+        let mut mock_http = MockHttpClient::new();
+
+        // Set up expectations
+        mock_http
+            .expect_get()
+            .withf(|_, url| url.contains("/aggregated/instances?returnPartialSuccess=true"))
+            .return_once(move |_, _| {
+                Ok(json!({
+                    "items": {
+                        "zone1": {
+                            "instances": [
+                                {
+                                    "name": "instance1",
+                                    "networkInterfaces": [
+                                        {
+                                            "networkIP": "127.0.0.1",
+                                        },
+                                    ],
+                                    "zone": "zone1",
+                                    "machineType": "machine-type1",
+                                    "cpuPlatform": "cpu-platform1",
+                                    "status": "status1",
+                                    "labels": {
+                                        "key1": "value1",
+                                        "key2": "value2",
+                                    },
+                                },
+                                {
+                                    "name": "instance2",
+                                    "networkInterfaces": [
+                                        {
+                                            "networkIP": "127.0.0.2",
+                                        },
+                                    ],
+                                    "zone": "zone1",
+                                    "machineType": "machine-type2",
+                                    "cpuPlatform": "cpu-platform2",
+                                    "status": "status2",
+                                    "labels": {
+                                        "key3": "value3",
+                                        "key4": "value4",
+                                    },
+                                },
+                            ],
+                        },
+                        "zone2": {
+                            "instances": [
+                                {
+                                    "name": "instance3",
+                                    "networkInterfaces": [
+                                        {
+                                            "networkIP": "127.0.0.3",
+                                        },
+                                    ],
+                                    "zone": "zone2",
+                                    "machineType": "machine-type3",
+                                    "cpuPlatform": "cpu-platform3",
+                                    "status": "status3",
+                                    "labels": {
+                                        "key5": "value5",
+                                        "key6": "value6",
+                                    },
+                                },
+                            ],
+                        },
+                    },
+                }))
+            });
+
+        // Create a Compute instance with the mock HttpTrait
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.list_all_instances();
+        let result = result.unwrap().items;
+
+        // Assert that the function returned the expected result
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].name, "instance1");
+        assert_eq!(result[1].name, "instance2");
+        assert_eq!(result[2].name, "instance3");
+        assert!(result[0].beta.is_none());
+    }
+
+    #[test]
+    fn test_list_instances_with_filter() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        // The filter is sent with every page
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let filter = "filter=%28labels.env%20%3D%20%22prod%22%29";
+        let first_url = format!(
+            "{}/aggregated/instances?{}&returnPartialSuccess=true",
+            base, filter
+        );
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == first_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": {}, "nextPageToken": "page2"})));
+        let second_url = format!(
+            "{}/aggregated/instances?{}&pageToken=page2&returnPartialSuccess=true",
+            base, filter
+        );
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == second_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": {}})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c
+            .list_instances(Some(r#"(labels.env = "prod")"#))
+            .unwrap()
+            .items;
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_list_instances_per_zone() {
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let instance = move |name: &str, zone: &str| {
+            json!({
+                "name": name,
+                "networkInterfaces": [{"networkIP": "10.0.0.1"}],
+                "zone": format!("{}/zones/{}", base, zone),
+                "machineType": "e2-medium",
+                "cpuPlatform": "Intel Broadwell",
+                "status": "RUNNING",
+            })
+        };
+        let config = |zones: &'static [&'static str]| {
+            let mut mock_http = MockHttpClient::new();
+            let zones_url = format!("{}/zones", base);
+            mock_http
+                .expect_get()
+                .withf(move |_, url| url == zones_url)
+                .times(1)
+                .returning(move |_, _| {
+                    let items = zones
+                        .iter()
+                        .map(|zone| match zone.ends_with("-z") {
+                            true => json!({"name": zone, "status": "DOWN"}),
+                            false => json!({"name": zone, "status": "UP"}),
+                        })
+                        .collect::<Vec<_>>();
+                    Ok(json!({"items": items}))
+                });
+            // The zones are listed concurrently, so in no particular order
+            mock_http
+                .expect_get()
+                .withf(|_, url| {
+                    url.contains("/zones/us-east1-b/instances") && !url.contains("pageToken")
+                })
+                .times(1)
+                .returning(move |_, _| {
+                    Ok(json!({"items": [instance("web-1", "us-east1-b")], "nextPageToken": "p2"}))
+                });
+            mock_http
+                .expect_get()
+                .withf(|_, url| {
+                    url.contains("/zones/us-east1-b/instances") && url.ends_with("pageToken=p2")
+                })
+                .times(1)
+                .returning(move |_, _| Ok(json!({"items": [instance("web-2", "us-east1-b")]})));
+            mock_http
+                .expect_get()
+                .withf(|_, url| {
+                    // Per-zone lists fail on their own, so partial success is not asked for
+                    url.contains("/zones/us-east1-c/instances")
+                        && !url.contains("returnPartialSuccess")
+                })
+                .times(1)
+                .returning(|_, _| Ok(json!({"kind": "compute#instanceList"})));
+            if zones.contains(&"europe-west1-d") {
+                mock_http
+                    .expect_get()
+                    .withf(|_, url| url.contains("/zones/europe-west1-d/instances"))
+                    .times(1)
+                    .returning(|_, _| {
+                        Err(Box::new(http::HttpError {
+                            status: Some(403),
+                            message: "Permission denied".to_string(),
+                            api_status: None,
+                            client_request_id: "c-1".to_string(),
+                            server_request_id: None,
+                            retry_after: None,
+                        }))
+                    });
+            }
+            ComputeConfig {
+                project: "test-project".to_string(),
+                client: mock_http,
+                token_source: MockTokenSource {
+                    mock_token: "mock_token".to_string(),
+                },
+                api_version: ApiVersion::V1,
+                page_size: None,
+                strict: false,
+                list_strategy: ListStrategy::PerZone,
+                jobs: DEFAULT_JOBS,
+            }
+        };
+
+        let c = Compute::new(config(&["us-east1-b", "us-east1-c"]));
+        let names = c
+            .list_instances(Some(r#"(labels.env = "prod")"#))
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|instance| (instance.name, instance.zone))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("web-1".to_string(), "us-east1-b".to_string()),
+                ("web-2".to_string(), "us-east1-b".to_string()),
+            ]
+        );
+
+        // A zone that can't be listed is reported and the others are still listed, and a zone
+        // that is down isn't asked for its instances at all
+        let c = Compute::new(config(&[
+            "us-east1-b",
+            "europe-west1-d",
+            "us-east1-z",
+            "us-east1-c",
+        ]));
+        let listed = c.list_all_instances().unwrap();
+        assert_eq!(listed.items.len(), 2);
+        let warnings = listed
+            .warnings
+            .iter()
+            .map(|warning| match warning {
+                ListWarning::Scope(warning) => (warning.scope.as_str(), warning.code.as_str()),
+                ListWarning::Skipped(_) => panic!("unexpected {}", warning),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            [
+                ("zones/europe-west1-d", "FAILED"),
+                ("zones/us-east1-z", "DOWN")
+            ]
+        );
+        assert!(listed.warnings[0]
+            .to_string()
+            .starts_with("zone europe-west1-d: Permission denied"));
+    }
+
+    /// Compares the listing strategies against a simulated project of 24 zones with 250
+    /// instances each, whose requests take 40ms plus 0.2ms per instance returned. Run it with
+    /// `cargo test --release bench_list_strategies -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_list_strategies() {
+        const ZONES: usize = 24;
+        const PER_ZONE: usize = 250;
+        const PAGE_SIZE: usize = 500;
+
+        // Not a mockall mock, which would only answer one request at a time
+        struct SimulatedProject;
+
+        impl SimulatedProject {
+            fn respond(instances: impl Iterator<Item = usize>) -> Vec<(String, Value)> {
+                instances
+                    .map(|i| {
+                        let zone = format!("zone-{}", i / PER_ZONE);
+                        let instance = json!({
+                            "name": format!("vm-{}", i),
+                            "networkInterfaces": [{"networkIP": "10.0.0.1"}],
+                            "zone": zone,
+                            "machineType": "e2-medium",
+                            "cpuPlatform": "Intel Broadwell",
+                            "status": "RUNNING",
+                        });
+                        (zone, instance)
+                    })
+                    .collect()
+            }
+
+            fn wait(instances: usize) {
+                let latency = std::time::Duration::from_millis(40)
+                    + std::time::Duration::from_micros(200) * instances as u32;
+                std::thread::sleep(latency);
+            }
+        }
+
+        impl http::HttpClient for SimulatedProject {
+            fn get(&self, _: &str, url: &str) -> Result<Value, Box<dyn std::error::Error>> {
+                if let Some((_, zone)) = url.split_once("/zones/zone-") {
+                    let zone = zone.split('/').next().unwrap().parse::<usize>()?;
+                    let instances = Self::respond(zone * PER_ZONE..(zone + 1) * PER_ZONE);
+                    Self::wait(instances.len());
+                    let items = instances.into_iter().map(|(_, instance)| instance);
+                    return Ok(json!({"items": items.collect::<Vec<_>>()}));
+                }
+                if url.contains("/zones") {
+                    Self::wait(0);
+                    let zones = (0..ZONES)
+                        .map(|zone| json!({"name": format!("zone-{}", zone), "status": "UP"}));
+                    return Ok(json!({"items": zones.collect::<Vec<_>>()}));
+                }
+                let page = match url.split_once("pageToken=") {
+                    Some((_, page)) => page.parse::<usize>()?,
+                    None => 0,
+                };
+                let end = ((page + 1) * PAGE_SIZE).min(ZONES * PER_ZONE);
+                let instances = Self::respond(page * PAGE_SIZE..end);
+                Self::wait(instances.len());
+                let mut items = serde_json::Map::new();
+                for (zone, instance) in instances {
+                    let scope = items
+                        .entry(format!("zones/{}", zone))
+                        .or_insert_with(|| json!({"instances": []}));
+                    scope["instances"].as_array_mut().unwrap().push(instance);
+                }
+                let mut response = json!({"items": items});
+                if end < ZONES * PER_ZONE {
+                    response["nextPageToken"] = json!((page + 1).to_string());
+                }
+                Ok(response)
+            }
+
+            fn post(
+                &self,
+                _: &str,
+                _: &str,
+                _: &Value,
+            ) -> Result<Value, Box<dyn std::error::Error>> {
+                Err("unexpected request".into())
+            }
+
+            fn get_metadata(&self, _: &str) -> Result<Value, Box<dyn std::error::Error>> {
+                Err("unexpected request".into())
+            }
+
+            fn post_json(&self, _: &str, _: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+                Err("unexpected request".into())
+            }
+
+            fn post_form(&self, _: &str, _: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+                Err("unexpected request".into())
+            }
+        }
+
+        let mut durations = vec![];
+        for list_strategy in [ListStrategy::Aggregated, ListStrategy::PerZone] {
+            let c = Compute::new(ComputeConfig {
+                project: "test-project".to_string(),
+                client: SimulatedProject,
+                token_source: MockTokenSource {
+                    mock_token: "mock_token".to_string(),
+                },
+                api_version: ApiVersion::V1,
+                page_size: Some(PAGE_SIZE as u32),
+                strict: false,
+                list_strategy,
+                jobs: DEFAULT_JOBS,
+            });
+            let start = std::time::Instant::now();
+            let listed = c.list_all_instances().unwrap();
+            let duration = start.elapsed();
+            assert_eq!(listed.items.len(), ZONES * PER_ZONE);
+            assert!(listed.warnings.is_empty());
+            durations.push(duration);
+        }
+        assert!(
+            durations[1] < durations[0],
+            "per-zone took {:?}, aggregated {:?}",
+            durations[1],
+            durations[0]
+        );
+    }
+
+    #[test]
+    fn test_list_instances_with_page_size() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        // The page size is sent with every page
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let first_url = format!(
+            "{}/aggregated/instances?maxResults=100&returnPartialSuccess=true",
+            base
+        );
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == first_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": {}, "nextPageToken": "page2"})));
+        let second_url = format!(
+            "{}/aggregated/instances?maxResults=100&pageToken=page2&returnPartialSuccess=true",
+            base
+        );
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == second_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": {}})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: Some(100),
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        assert!(c.list_all_instances().unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_list_instances_from_fixture() {
+        // Two pages of a recorded aggregated list, with zones that have no instances
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/aggregated_instances.json"
+        );
+        let replay = ReplayHttp::from_file(std::path::Path::new(path)).unwrap();
+
+        let config = ComputeConfig {
+            project: "acme-stg".to_string(),
+            client: replay.clone(),
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let instances = c.list_all_instances().unwrap().items;
+
+        assert_eq!(
+            instances
+                .iter()
+                .map(|instance| (instance.name.as_str(), instance.zone.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("store-lb-1", "us-east1-b"),
+                ("store-lb-2", "us-east1-b"),
+                ("batch-worker-7", "europe-west1-d"),
+            ]
+        );
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[test]
+    fn test_list_instances_malformed_response() {
+        let config = |response: Value, strict: bool| {
+            let mut mock_http = MockHttpClient::new();
+            mock_http.expect_get().return_once(move |_, _| Ok(response));
+            ComputeConfig {
+                project: "test-project".to_string(),
+                client: mock_http,
+                token_source: MockTokenSource {
+                    mock_token: "mock_token".to_string(),
+                },
+                api_version: ApiVersion::V1,
+                page_size: None,
+                strict,
+                list_strategy: ListStrategy::Aggregated,
+                jobs: DEFAULT_JOBS,
+            }
+        };
+
+        // A scope that isn't an object is an error rather than a panic
+        let c = Compute::new(config(
+            json!({"items": {"zones/us-east1-b": "oops"}}),
+            false,
+        ));
+        let error = c.list_all_instances().unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid aggregated list response: invalid type"),
+            "{}",
+            error
+        );
+
+        // A terminated instance without an IP or CPU platform is listed, and one without a
+        // machine type is skipped unless the listing is strict. Both it and the API's warnings
+        // about other scopes are reported
+        let response = json!({
+            "items": {
+                "zones/us-east1-a": {
+                    "warning": {
+                        "code": "NO_RESULTS_ON_PAGE",
+                        "message": "There are no results for scope 'zones/us-east1-a' on this page.",
+                    },
+                },
+                "zones/us-east1-c": {
+                    "warning": {
+                        "code": "RESOURCE_NOT_FOUND_WARNING",
+                        "message": "Zone is being turned down.",
+                    },
+                },
+                "zones/us-east1-b": {
+                    "instances": [
+                        {"name": "web-1", "zone": "zones/us-east1-b"},
+                        {
+                            "name": "web-2",
+                            "zone": "zones/us-east1-b",
+                            "machineType": "e2-small",
+                            "status": "TERMINATED",
+                            "networkInterfaces": [{"name": "nic0"}],
+                        },
+                    ],
+                },
+            },
+        });
+        let listed = Compute::new(config(response.clone(), false))
+            .list_all_instances()
+            .unwrap();
+        assert_eq!(listed.items.len(), 1);
+        assert_eq!(listed.items[0].name, "web-2");
+        assert_eq!(listed.items[0].primary_ip(), None);
+        assert_eq!(
+            listed
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "zone us-east1-c: Zone is being turned down.",
+                "zone us-east1-b: skipped web-1: missing field `machineType`",
+            ]
+        );
+
+        // Errors name the instance and where it is in the response
+        let c = Compute::new(config(response, true));
+        assert_eq!(
+            c.list_all_instances().unwrap_err().to_string(),
+            "Error parsing instances: items[\"zones/us-east1-b\"].instances[0] (web-1): \
+             missing field `machineType`"
+        );
+    }
+
+    #[test]
+    fn test_list_snapshots() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let first_url = format!("{}/global/snapshots", base);
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == first_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(json!({
+                    "items": [{"name": "snap-1", "diskSizeGb": "10"}],
+                    "nextPageToken": "page2"
+                }))
+            });
+        let second_url = format!("{}/global/snapshots?pageToken=page2", base);
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == second_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": [{"name": "snap-2", "diskSizeGb": "20"}]})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let snapshots = c.list_all_snapshots().unwrap();
+        assert_eq!(
+            snapshots
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["snap-1", "snap-2"]
+        );
+    }
+
+    #[test]
+    fn test_list_managed_instances() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_post()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/instanceGroupManagers/web/listManagedInstances",
+                ),
+                predicate::always(),
+            )
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(json!({"managedInstances": [{
+                    "instance": "projects/test-project/zones/zone1/instances/web-1",
+                    "instanceStatus": "RUNNING",
+                    "currentAction": "NONE"
+                }]}))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let instances = c
+            .list_managed_instances(&MigLocation::Zone("zone1".to_string()), "web")
+            .unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].name, "web-1");
+    }
+
+    #[test]
+    fn test_get_instance() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .withf(|_, url| {
+                url == "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/instances/instance1"
+            })
+            .times(1)
+            .returning(|_, _| {
+                Ok(json!({
+                    "name": "instance1",
+                    "zone": "zone1",
+                    "machineType": "machine-type1",
+                    "cpuPlatform": "cpu-platform1",
+                    "status": "RUNNING",
+                    "metadata": {"items": [{"key": "ssh-keys", "value": "..."}]},
+                }))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
             },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
         };
         let c = Compute::new(config);
-        let result = c.list_zones();
-        let result = result.unwrap();
-
-        // Assert that the function returned the expected result
-        assert_eq!(result, expected_result);
+        let detail = c.get_instance("zone1", "instance1").unwrap();
+        assert_eq!(detail.instance.name, "instance1");
+        assert_eq!(detail.metadata_keys, vec!["ssh-keys"]);
     }
 
     #[test]
-    fn test_list_instances() {
+    fn test_find_instance_stops_at_match() {
         let mut mock_http = MockHttpClient::new();
 
-        // Set up expectations
-        mock_http.expect_get().return_once(move |_, _| {
+        // Only the first page should be requested, even though it has a next page token
+        mock_http.expect_get().times(1).returning(|_, _| {
             Ok(json!({
                 "items": {
                     "zone1": {
                         "instances": [
                             {
                                 "name": "instance1",
-                                "networkInterfaces": [
-                                    {
-                                        "networkIP": "127.0.0.1",
-                                    },
-                                ],
                                 "zone": "zone1",
                                 "machineType": "machine-type1",
                                 "cpuPlatform": "cpu-platform1",
                                 "status": "status1",
-                                "labels": {
-                                    "key1": "value1",
-                                    "key2": "value2",
-                                },
-                            },
-                            {
-                                "name": "instance2",
-                                "networkInterfaces": [
-                                    {
-                                        "networkIP": "127.0.0.2",
-                                    },
-                                ],
-                                "zone": "zone1",
-                                "machineType": "machine-type2",
-                                "cpuPlatform": "cpu-platform2",
-                                "status": "status2",
-                                "labels": {
-                                    "key3": "value3",
-                                    "key4": "value4",
-                                },
                             },
                         ],
                     },
-                    "zone2": {
-                        "instances": [
-                            {
-                                "name": "instance3",
-                                "networkInterfaces": [
-                                    {
-                                        "networkIP": "127.0.0.3",
-                                    },
-                                ],
-                                "zone": "zone2",
-                                "machineType": "machine-type3",
-                                "cpuPlatform": "cpu-platform3",
-                                "status": "status3",
-                                "labels": {
-                                    "key5": "value5",
-                                    "key6": "value6",
+                },
+                "nextPageToken": "page2",
+            }))
+        });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.find_instance("instance1").unwrap();
+
+        assert_eq!(result.unwrap().name, "instance1");
+    }
+
+    #[test]
+    fn test_instances_iter_is_lazy() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        let page = |name: &str, next: Option<&str>| {
+            json!({
+                "items": {"zone1": {"instances": [{
+                    "name": name,
+                    "zone": "zone1",
+                    "machineType": "machine-type1",
+                    "cpuPlatform": "cpu-platform1",
+                    "status": "RUNNING",
+                }]}},
+                "nextPageToken": next,
+            })
+        };
+        let first = page("instance1", Some("page2"));
+        mock_http
+            .expect_get()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(move |_, _| Ok(first.clone()));
+        mock_http
+            .expect_get()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err("quota exceeded".into()));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+
+        // The first instance arrives before the second page is requested
+        let mut instances = c.instances_iter(None).unwrap();
+        assert_eq!(instances.next().unwrap().unwrap().name, "instance1");
+        // The error ends the iteration
+        assert!(instances.next().unwrap().is_err());
+        assert!(instances.next().is_none());
+    }
+
+    #[test]
+    fn test_list_disks() {
+        let mut mock_http = MockHttpClient::new();
+
+        mock_http
+            .expect_get()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/aggregated/disks?returnPartialSuccess=true",
+                ),
+            )
+            .return_once(move |_, _| {
+                Ok(json!({
+                    "items": {
+                        "zones/zone1": {
+                            "disks": [
+                                {
+                                    "name": "disk1",
+                                    "sizeGb": "10",
+                                    "type": "projects/p/zones/zone1/diskTypes/pd-ssd",
+                                    "zone": "projects/p/zones/zone1",
                                 },
-                            },
-                        ],
+                            ],
+                        },
+                        "regions/region1": {
+                            "disks": [
+                                {
+                                    "name": "disk2",
+                                    "sizeGb": "20",
+                                    "type": "projects/p/regions/region1/diskTypes/pd-ssd",
+                                    "region": "projects/p/regions/region1",
+                                },
+                            ],
+                        },
+                        "zones/zone2": {
+                            "warning": {"code": "NO_RESULTS_ON_PAGE"},
+                        },
                     },
-                },
+                }))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.list_all_disks().unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].name, "disk2");
+        assert_eq!(result[1].name, "disk1");
+    }
+
+    #[test]
+    fn test_list_addresses_includes_global() {
+        let mut mock_http = MockHttpClient::new();
+
+        mock_http
+            .expect_get()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/aggregated/addresses?returnPartialSuccess=true",
+                ),
+            )
+            .return_once(move |_, _| {
+                Ok(json!({
+                    "items": {
+                        "global": {
+                            "addresses": [
+                                {"name": "lb-ip", "address": "34.1.1.1", "status": "IN_USE"},
+                            ],
+                        },
+                        "regions/region1": {
+                            "addresses": [
+                                {
+                                    "name": "nat-ip",
+                                    "address": "34.2.2.2",
+                                    "status": "RESERVED",
+                                    "region": "projects/p/regions/region1",
+                                },
+                            ],
+                        },
+                    },
+                }))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.list_all_addresses().unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].region, None);
+        assert_eq!(result[1].region, Some("region1".to_string()));
+    }
+
+    #[test]
+    fn test_stop_instance_waits_for_operation() {
+        let mut mock_http = MockHttpClient::new();
+
+        mock_http
+            .expect_post()
+            .withf(|_, url, _| {
+                // With a request ID, so the POST can be retried safely
+                url.starts_with(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/instances/instance1/stop?requestId=",
+                )
+            })
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(json!({"name": "op-1", "status": "RUNNING", "zone": "projects/test-project/zones/zone1"}))
+            });
+        mock_http
+            .expect_post()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/operations/op-1/wait",
+                ),
+                predicate::always(),
+            )
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(json!({"name": "op-1", "status": "DONE", "zone": "projects/test-project/zones/zone1"}))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let operation = c.stop_instance("zone1", "instance1").unwrap();
+
+        assert!(operation.is_done());
+    }
+
+    #[test]
+    fn test_wait_for_global_operation() {
+        let mut mock_http = MockHttpClient::new();
+
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let list_url = format!(
+            "{}/aggregated/operations?filter=name%20%3D%20%22op-2%22&returnPartialSuccess=true",
+            base
+        );
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == list_url)
+            .times(1)
+            .returning(|_, _| {
+                Ok(json!({
+                    "items": {
+                        "global": {"operations": [{"name": "op-2", "status": "RUNNING"}]},
+                        "zones/zone1": {"warning": {"code": "NO_RESULTS_ON_PAGE"}},
+                    }
+                }))
+            });
+        let wait_url = format!("{}/global/operations/op-2/wait", base);
+        mock_http
+            .expect_post()
+            .withf(move |_, url, _| url == wait_url)
+            .times(1)
+            .returning(|_, _, _| Ok(json!({"name": "op-2", "status": "DONE"})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let operation = c.find_operation("op-2").unwrap().unwrap();
+        assert_eq!(operation.zone, None);
+
+        assert!(c.wait_operation(operation).unwrap().is_done());
+    }
+
+    #[test]
+    fn test_set_metadata_retries_stale_fingerprint() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        let base = "https://compute.googleapis.com/compute/v1/projects/test-project";
+        let instance_url = format!("{}/zones/zone1/instances/web-1", base);
+        let set_url = format!("{}/setMetadata?requestId=", instance_url);
+        let request_ids = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        for fingerprint in ["f1", "f2"] {
+            let get_url = instance_url.clone();
+            mock_http
+                .expect_get()
+                .withf(move |_, url| url == get_url)
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(move |_, _| {
+                    Ok(json!({"metadata": {
+                        "fingerprint": fingerprint,
+                        "items": [{"key": "maintenance", "value": "false"}],
+                    }}))
+                });
+            let post_url = set_url.clone();
+            let request_ids = request_ids.clone();
+            mock_http
+                .expect_post()
+                .withf(move |_, url, body| {
+                    url.starts_with(&post_url) && body["fingerprint"] == fingerprint
+                })
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(move |_, url, body| {
+                    assert_eq!(body["items"][0]["value"], "true");
+                    request_ids.lock().unwrap().push(url.to_string());
+                    match fingerprint {
+                        // Another change landed after the first read
+                        "f1" => Err(Box::new(http::HttpError {
+                            status: Some(412),
+                            message: "Supplied fingerprint does not match".to_string(),
+                            api_status: None,
+                            client_request_id: "c-1".to_string(),
+                            server_request_id: None,
+                            retry_after: None,
+                        })),
+                        _ => Ok(json!({"name": "op-1", "status": "DONE"})),
+                    }
+                });
+        }
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let items = ["maintenance=true".parse().unwrap()];
+        let operation = c.set_metadata("zone1", "web-1", &items).unwrap().unwrap();
+
+        assert_eq!(operation.name, "op-1");
+        // The second change isn't the first one sent again, so it has its own request ID
+        let request_ids = request_ids.lock().unwrap();
+        assert_ne!(request_ids[0], request_ids[1]);
+    }
+
+    #[test]
+    fn test_get_serial_output_from_offset() {
+        let mut mock_http = MockHttpClient::new();
+
+        mock_http
+            .expect_get()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/instances/web-1/serialPort?port=2&start=2048",
+                ),
+            )
+            .times(1)
+            .returning(|_, _| Ok(json!({"contents": "login: ", "start": "2048", "next": "2055"})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let output = c
+            .get_serial_output("zone1", "web-1", 2, Some(2048))
+            .unwrap();
+
+        assert_eq!(output.contents, "login: ");
+        assert_eq!(output.next, 2055);
+    }
+
+    #[test]
+    fn test_set_labels() {
+        let mut mock_http = MockHttpClient::new();
+
+        let instance_url = "https://compute.googleapis.com/compute/v1/projects/test-project/zones/zone1/instances/web-1";
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == instance_url)
+            .times(2)
+            .returning(|_, _| {
+                Ok(json!({"labelFingerprint": "f1", "labels": {"cell": "c1", "team": "web"}}))
+            });
+        // Only the change that does something is sent
+        mock_http
+            .expect_post()
+            .withf(move |_, url, body| {
+                url.starts_with(&format!("{}/setLabels?requestId=", instance_url))
+                    && *body == json!({"labelFingerprint": "f1", "labels": {"cell": "c3"}})
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(json!({"name": "op-1", "status": "DONE"})));
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let set = ["cell=c3".parse().unwrap()];
+        let remove = ["team".to_string()];
+
+        assert!(c
+            .set_labels("zone1", "web-1", &set, &remove)
+            .unwrap()
+            .is_some());
+        assert!(c
+            .set_labels("zone1", "web-1", &[], &["owner".to_string()])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_failed_operation_is_an_error() {
+        let mut mock_http = MockHttpClient::new();
+
+        mock_http.expect_post().times(1).returning(|_, _, _| {
+            Ok(json!({
+                "name": "op-1",
+                "status": "DONE",
+                "error": {"errors": [{"message": "Instance is not running"}]},
             }))
         });
 
-        // Create a Compute instance with the mock HttpTrait
         let config = ComputeConfig {
             project: "test-project".to_string(),
             client: mock_http,
             token_source: MockTokenSource {
                 mock_token: "mock_token".to_string(),
             },
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
         };
         let c = Compute::new(config);
-        let result = c.list_all_instances();
-        let result = result.unwrap();
+        let err = c.reset_instance("zone1", "instance1").unwrap_err();
 
-        // Assert that the function returned the expected result
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0].name, "instance1");
-        assert_eq!(result[1].name, "instance2");
-        assert_eq!(result[2].name, "instance3");
+        assert_eq!(
+            err.to_string(),
+            "Operation op-1 failed: Instance is not running"
+        );
+    }
+
+    #[test]
+    fn test_list_instances_beta() {
+        let mut mock_http = MockHttpClient::new();
+
+        // Set up expectations
+        mock_http
+            .expect_get()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://compute.googleapis.com/compute/beta/projects/test-project/aggregated/instances?returnPartialSuccess=true",
+                ),
+            )
+            .return_once(move |_, _| {
+                Ok(json!({
+                    "items": {
+                        "zone1": {
+                            "instances": [
+                                {
+                                    "name": "instance1",
+                                    "zone": "zone1",
+                                    "machineType": "machine-type1",
+                                    "cpuPlatform": "cpu-platform1",
+                                    "status": "status1",
+                                    "scheduling": {
+                                        "hostErrorTimeoutSeconds": 90,
+                                        "gracefulShutdown": {"enabled": true},
+                                    },
+                                },
+                            ],
+                        },
+                    },
+                }))
+            });
+
+        let config = ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource {
+                mock_token: "mock_token".to_string(),
+            },
+            api_version: ApiVersion::Beta,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        };
+        let c = Compute::new(config);
+        let result = c.list_all_instances().unwrap().items;
+
+        assert_eq!(
+            result[0].beta,
+            Some(BetaFields {
+                host_error_timeout_seconds: Some(90),
+                graceful_shutdown: Some(true),
+            })
+        );
     }
 }
@@ -0,0 +1,123 @@
+//! This module defines the `Address` struct, which represents a reserved static IP address,
+//! and provides a `TryFrom` implementation for creating an `Address` from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a reserved static IP address, regional or global.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Address {
+    /// The name of the address.
+    pub name: String,
+    /// The IP address itself.
+    pub address: String,
+    /// Whether the address is `INTERNAL` or `EXTERNAL`.
+    pub address_type: String,
+    /// The status of the address: `RESERVED` while unused, `IN_USE` once a resource uses it.
+    pub status: String,
+    /// The region of the address, or `None` for a global address.
+    pub region: Option<String>,
+    /// The resources using the address, as `KIND/NAME`, e.g. `instances/web-1`.
+    pub users: Vec<String>,
+}
+
+impl Address {
+    /// Returns whether the address is reserved without any resource using it.
+    pub fn is_unused(&self) -> bool {
+        self.status == "RESERVED"
+    }
+}
+
+impl TryFrom<JsonValue> for Address {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `Address` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the address data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Address)` - The created `Address` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+
+        let name = str_field("name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let address = str_field("address")
+            .ok_or("Missing or invalid 'address' field")?
+            .to_string();
+
+        Ok(Address {
+            name,
+            address,
+            // The API leaves out the type of external addresses
+            address_type: str_field("addressType").unwrap_or("EXTERNAL").to_string(),
+            status: str_field("status").unwrap_or_default().to_string(),
+            region: str_field("region").map(last_segment),
+            users: json
+                .get("users")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .map(|url| {
+                    // A user URL ends in .../{kind}/{name}
+                    let mut segments = url.rsplit('/');
+                    match (segments.next(), segments.next()) {
+                        (Some(name), Some(kind)) => format!("{}/{}", kind, name),
+                        _ => url.to_string(),
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_address_from_json() {
+        let json = json!({
+            "name": "web-ip",
+            "address": "34.1.2.3",
+            "status": "IN_USE",
+            "region": "https://www.googleapis.com/compute/v1/projects/p/regions/us-east1",
+            "users": ["https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/instances/web-1"]
+        });
+
+        let address = Address::try_from(json).unwrap();
+
+        assert_eq!(address.name, "web-ip");
+        assert_eq!(address.address, "34.1.2.3");
+        assert_eq!(address.address_type, "EXTERNAL");
+        assert_eq!(address.region, Some("us-east1".to_string()));
+        assert_eq!(address.users, vec!["instances/web-1"]);
+        assert!(!address.is_unused());
+    }
+
+    #[test]
+    fn test_global_internal_address_from_json() {
+        let json = json!({
+            "name": "psa-range",
+            "address": "10.8.0.0",
+            "addressType": "INTERNAL",
+            "status": "RESERVED"
+        });
+
+        let address = Address::try_from(json).unwrap();
+
+        assert_eq!(address.address_type, "INTERNAL");
+        assert_eq!(address.region, None);
+        assert!(address.users.is_empty());
+        assert!(address.is_unused());
+    }
+}
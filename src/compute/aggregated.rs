@@ -0,0 +1,476 @@
+//! This module defines the envelope of an aggregated list response, e.g. from
+//! `instances.aggregatedList`: the resources grouped by scope (zone or region), the warnings
+//! of scopes without resources or that couldn't be reached, and the token of the next page.
+//! <https://cloud.google.com/compute/docs/reference/rest/v1/instances/aggregatedList#response-body>
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// A page of an aggregated list.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedList {
+    /// The resources of each scope, keyed by scope, e.g. `zones/us-east1-b`.
+    pub items: BTreeMap<String, ScopedList>,
+    /// The token of the next page, if there is one.
+    pub next_page_token: Option<String>,
+    /// Scopes that couldn't be listed, e.g. `zones/europe-west4-a`.
+    #[serde(default)]
+    pub unreachables: Vec<String>,
+    /// A warning about the whole response, e.g. listing the scopes that couldn't be reached.
+    pub warning: Option<Warning>,
+}
+
+/// The resources of one scope of an aggregated list.
+#[derive(Debug, Default, Deserialize)]
+pub struct ScopedList {
+    /// Why the scope has no resources on this page, e.g. `NO_RESULTS_ON_PAGE`.
+    pub warning: Option<Warning>,
+    /// The resources, keyed by collection, e.g. `instances`.
+    #[serde(flatten)]
+    pub resources: BTreeMap<String, Vec<JsonValue>>,
+}
+
+/// A warning the API attaches to a scope or response.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Warning {
+    /// The warning code, e.g. `NO_RESULTS_ON_PAGE` or `UNREACHABLE`.
+    pub code: String,
+    /// A human-readable description.
+    #[serde(default)]
+    pub message: String,
+    /// Details, e.g. `scope` with the zone the warning is about.
+    #[serde(default)]
+    pub data: Vec<WarningData>,
+}
+
+/// A detail of a `Warning`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WarningData {
+    pub key: String,
+    pub value: String,
+}
+
+impl TryFrom<JsonValue> for AggregatedList {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `AggregatedList` from a response body.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the response.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AggregatedList)` - The created `AggregatedList` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the response isn't an aggregated list.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        AggregatedList::deserialize(json)
+            .map_err(|e| format!("Invalid aggregated list response: {}", e).into())
+    }
+}
+
+impl AggregatedList {
+    /// Wraps a page of a zonal or regional list, e.g. from `instances.list`, as a page of an
+    /// aggregated list with a single scope, so both are parsed the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope of the list, e.g. `zones/us-east1-b`.
+    /// * `resource` - The collection, e.g. `instances`.
+    /// * `json` - The `JsonValue` containing the response.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(AggregatedList)` - The page, with the list's items and warning under the scope.
+    /// * `Err(Box<dyn Error>)` - An error if the response isn't a list.
+    pub fn from_scoped(
+        scope: &str,
+        resource: &str,
+        json: JsonValue,
+    ) -> Result<Self, Box<dyn Error>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct List {
+            #[serde(default)]
+            items: Vec<JsonValue>,
+            next_page_token: Option<String>,
+            warning: Option<Warning>,
+        }
+        let list = List::deserialize(json).map_err(|e| format!("Invalid list response: {}", e))?;
+        let scoped = ScopedList {
+            warning: list.warning,
+            resources: BTreeMap::from([(resource.to_string(), list.items)]),
+        };
+        Ok(AggregatedList {
+            items: BTreeMap::from([(scope.to_string(), scoped)]),
+            next_page_token: list.next_page_token,
+            ..AggregatedList::default()
+        })
+    }
+
+    /// Parses the resources of a collection in every scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The collection, e.g. `instances`.
+    /// * `parse` - Converts a resource into a record.
+    ///
+    /// # Returns
+    ///
+    /// The records, or for resources that can't be parsed, an error naming where the resource
+    /// is in the response.
+    pub fn parse<R>(
+        &self,
+        resource: &str,
+        parse: impl Fn(&JsonValue) -> Result<R, Box<dyn Error>>,
+    ) -> Vec<Result<R, RecordError>> {
+        self.items
+            .iter()
+            .flat_map(|(scope, list)| {
+                list.resources
+                    .get(resource)
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                    .map(move |(i, json)| (scope, i, json))
+            })
+            .map(|(scope, i, json)| {
+                parse(json).map_err(|e| RecordError {
+                    scope: scope.clone(),
+                    resource: resource.to_string(),
+                    index: i,
+                    name: json["name"].as_str().map(str::to_string),
+                    error: e.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the warnings the API attached to scopes, e.g. that a zone couldn't be reached,
+    /// from the scopes themselves, the response's `warning` and its `unreachables`. Each
+    /// unreachable scope is reported once. `NO_RESULTS_ON_PAGE`, which only says a scope has
+    /// nothing on this page, is left out.
+    pub fn warnings(&self) -> Vec<ScopeWarning> {
+        let scoped = self
+            .items
+            .iter()
+            .filter_map(|(scope, list)| Some((scope.as_str(), list.warning.as_ref()?)));
+        // A response-level warning names its scopes in its data, if it is about any
+        let response = self.warning.iter().flat_map(|warning| {
+            warning
+                .data
+                .iter()
+                .filter(|data| data.key == "scope")
+                .map(move |data| (data.value.as_str(), warning))
+        });
+        let mut warnings = scoped
+            .chain(response)
+            .filter(|(_, warning)| warning.code != "NO_RESULTS_ON_PAGE")
+            .map(|(scope, warning)| ScopeWarning::new(scope, warning))
+            .collect::<Vec<_>>();
+        for scope in &self.unreachables {
+            warnings.push(ScopeWarning {
+                scope: scope.clone(),
+                code: UNREACHABLE.to_string(),
+                message: String::new(),
+            });
+        }
+
+        let mut unreachable = std::collections::HashSet::new();
+        warnings.retain(|warning| {
+            !warning.is_unreachable() || unreachable.insert(warning.scope.clone())
+        });
+        warnings
+    }
+}
+
+/// The warning code of a scope that couldn't be reached.
+const UNREACHABLE: &str = "UNREACHABLE";
+
+/// The warning code of a zone that is down.
+const DOWN: &str = "DOWN";
+
+/// The warning code of a scope that was listed on its own and failed.
+const FAILED: &str = "FAILED";
+
+/// A warning the API gave about a scope of an aggregated list, so that the listing may be
+/// missing its resources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeWarning {
+    /// The scope, e.g. `zones/europe-west4-a`.
+    pub scope: String,
+    /// The warning code, e.g. `UNREACHABLE`.
+    pub code: String,
+    /// The API's description of the warning, which may be empty.
+    pub message: String,
+}
+
+impl ScopeWarning {
+    /// Creates a `ScopeWarning` from a warning of the API.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope the warning is attached to. The scope named in the warning's data
+    ///   takes precedence.
+    /// * `warning` - The warning.
+    fn new(scope: &str, warning: &Warning) -> Self {
+        let scope = warning
+            .data
+            .iter()
+            .find(|data| data.key == "scope")
+            .map_or(scope, |data| data.value.as_str());
+        ScopeWarning {
+            scope: scope.to_string(),
+            code: warning.code.clone(),
+            message: warning.message.clone(),
+        }
+    }
+
+    /// Creates a `ScopeWarning` for a zone that is down, so it wasn't listed.
+    pub(crate) fn down(scope: &str) -> Self {
+        ScopeWarning {
+            scope: scope.to_string(),
+            code: DOWN.to_string(),
+            message: "down".to_string(),
+        }
+    }
+
+    /// Creates a `ScopeWarning` for a scope that was listed on its own and failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The scope, e.g. `zones/europe-west4-a`.
+    /// * `error` - Why it couldn't be listed, e.g. an `HttpError`.
+    pub(crate) fn failed(scope: &str, error: &(dyn std::error::Error + 'static)) -> Self {
+        ScopeWarning {
+            scope: scope.to_string(),
+            code: FAILED.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Whether the scope couldn't be reached, so none of its resources were listed.
+    pub fn is_unreachable(&self) -> bool {
+        self.code == UNREACHABLE
+    }
+}
+
+impl std::fmt::Display for ScopeWarning {
+    /// Formats the warning as a notice, e.g. `zone europe-west4-a unreachable`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.is_unreachable(), self.message.is_empty()) {
+            (true, _) => write!(f, "{} unreachable", describe_scope(&self.scope)),
+            (false, true) => write!(f, "{}: {}", describe_scope(&self.scope), self.code),
+            (false, false) => write!(f, "{}: {}", describe_scope(&self.scope), self.message),
+        }
+    }
+}
+
+/// Describes a scope for people, e.g. `zone europe-west4-a` for `zones/europe-west4-a`.
+fn describe_scope(scope: &str) -> String {
+    match scope.split_once('/') {
+        Some(("zones", zone)) => format!("zone {}", zone),
+        Some(("regions", region)) => format!("region {}", region),
+        _ => scope.to_string(),
+    }
+}
+
+/// A resource of an aggregated list that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordError {
+    /// The scope the resource is in, e.g. `zones/us-east1-b`.
+    pub scope: String,
+    /// The collection, e.g. `instances`.
+    pub resource: String,
+    /// The position of the resource in its scope.
+    pub index: usize,
+    /// The name of the resource, if it has one.
+    pub name: Option<String>,
+    /// Why it couldn't be parsed.
+    pub error: String,
+}
+
+impl std::fmt::Display for RecordError {
+    /// Formats the error with where the resource is in the response, e.g.
+    /// `items["zones/us-east1-b"].instances[3] (web-1): missing field `zone``.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "items[{:?}].{}[{}]",
+            self.scope, self.resource, self.index
+        )?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+/// Part of a listing that is missing from its results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListWarning {
+    /// The API reported a problem with a scope, e.g. that it couldn't be reached.
+    Scope(ScopeWarning),
+    /// A resource couldn't be parsed and was skipped.
+    Skipped(RecordError),
+}
+
+impl From<ScopeWarning> for ListWarning {
+    fn from(warning: ScopeWarning) -> Self {
+        ListWarning::Scope(warning)
+    }
+}
+
+impl From<RecordError> for ListWarning {
+    fn from(e: RecordError) -> Self {
+        ListWarning::Skipped(e)
+    }
+}
+
+impl std::fmt::Display for ListWarning {
+    /// Formats the warning as a notice, e.g. `zone europe-west4-a unreachable` or
+    /// `zone us-east1-b: skipped web-1: missing field `machineType``.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListWarning::Scope(warning) => write!(f, "{}", warning),
+            ListWarning::Skipped(e) => {
+                write!(f, "{}: skipped ", describe_scope(&e.scope))?;
+                match &e.name {
+                    Some(name) => write!(f, "{}", name)?,
+                    None => write!(f, "{}[{}]", e.resource, e.index)?,
+                }
+                write!(f, ": {}", e.error)
+            }
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_aggregated_list_from_json() {
+        let list = AggregatedList::try_from(json!({
+            "kind": "compute#diskAggregatedList",
+            "items": {
+                "zones/us-east1-b": {"disks": [{"name": "d1"}, {"name": "d2", "sizeGb": 1}]},
+                "zones/us-east1-c": {
+                    "warning": {
+                        "code": "NO_RESULTS_ON_PAGE",
+                        "message": "There are no results for scope 'zones/us-east1-c' on this page.",
+                        "data": [{"key": "scope", "value": "zones/us-east1-c"}],
+                    },
+                },
+            },
+            "nextPageToken": "page2",
+        }))
+        .unwrap();
+
+        assert_eq!(list.next_page_token.as_deref(), Some("page2"));
+        let warning = list.items["zones/us-east1-c"].warning.as_ref().unwrap();
+        assert_eq!(warning.code, "NO_RESULTS_ON_PAGE");
+        assert_eq!(warning.data[0].value, "zones/us-east1-c");
+
+        let sizes = list.parse("disks", |json| {
+            json["sizeGb"]
+                .as_u64()
+                .ok_or_else(|| "missing field `sizeGb`".into())
+        });
+        let error = sizes[0].clone().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"items["zones/us-east1-b"].disks[0] (d1): missing field `sizeGb`"#
+        );
+        assert_eq!(
+            ListWarning::from(error).to_string(),
+            "zone us-east1-b: skipped d1: missing field `sizeGb`"
+        );
+        assert_eq!(sizes[1], Ok(1));
+        assert!(list.warnings().is_empty());
+        assert!(list.parse("instances", |_| Ok(())).is_empty());
+
+        assert!(AggregatedList::try_from(json!({"items": {"zones/a": "oops"}})).is_err());
+        assert_eq!(
+            AggregatedList::try_from(json!({})).unwrap_err().to_string(),
+            "Invalid aggregated list response: missing field `items`"
+        );
+    }
+
+    #[test]
+    fn test_aggregated_list_from_scoped() {
+        let list = AggregatedList::from_scoped(
+            "zones/us-east1-b",
+            "instances",
+            json!({
+                "kind": "compute#instanceList",
+                "items": [{"name": "web-1"}, {"id": "2"}],
+                "nextPageToken": "page2",
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(list.next_page_token.as_deref(), Some("page2"));
+        let names = list.parse("instances", |json| {
+            json["name"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "missing field `name`".into())
+        });
+        assert_eq!(names[0], Ok("web-1".to_string()));
+        assert_eq!(
+            names[1].clone().unwrap_err().to_string(),
+            r#"items["zones/us-east1-b"].instances[1]: missing field `name`"#
+        );
+
+        // An empty zone has no items at all
+        let empty =
+            AggregatedList::from_scoped("zones/us-east1-c", "instances", json!({})).unwrap();
+        assert!(empty.parse("instances", |_| Ok(())).is_empty());
+        assert!(empty.next_page_token.is_none());
+        assert!(AggregatedList::from_scoped("zones/a", "instances", json!([])).is_err());
+    }
+
+    #[test]
+    fn test_scope_warnings() {
+        let list = AggregatedList::try_from(json!({
+            "items": {
+                "zones/europe-west4-a": {
+                    "warning": {
+                        "code": "UNREACHABLE",
+                        "message": "The resource 'zones/europe-west4-a' is unreachable.",
+                        "data": [{"key": "scope", "value": "zones/europe-west4-a"}],
+                    },
+                },
+                "regions/us-east1": {
+                    "warning": {"code": "DEPRECATED_RESOURCE_USED", "message": "Old region."},
+                },
+                "zones/us-east1-b": {"instances": []},
+            },
+            "unreachables": ["zones/europe-west4-a", "zones/asia-east1-c"],
+            "warning": {
+                "code": "UNREACHABLE",
+                "message": "Some scopes were unreachable.",
+                "data": [{"key": "scope", "value": "zones/asia-east1-c"}],
+            },
+        }))
+        .unwrap();
+
+        let warnings = list.warnings();
+        assert_eq!(
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "region us-east1: Old region.",
+                "zone europe-west4-a unreachable",
+                "zone asia-east1-c unreachable",
+            ]
+        );
+        assert!(!warnings[0].is_unreachable());
+        assert!(warnings[1].is_unreachable());
+    }
+}
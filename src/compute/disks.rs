@@ -0,0 +1,127 @@
+//! This module defines the `Disk` struct, which represents a Google Compute Engine persistent
+//! disk, and provides a `TryFrom` implementation for creating a `Disk` from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine persistent disk.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Disk {
+    /// The name of the disk.
+    pub name: String,
+    /// The size of the disk in GB.
+    pub size_gb: u64,
+    /// The disk type, e.g. `pd-balanced`.
+    pub disk_type: String,
+    /// The zone of a zonal disk.
+    pub zone: Option<String>,
+    /// The region of a regional disk.
+    pub region: Option<String>,
+    /// The zones a regional disk is replicated to.
+    pub replica_zones: Vec<String>,
+    /// The names of the instances the disk is attached to.
+    pub users: Vec<String>,
+    /// The status of the disk, e.g. `READY`.
+    pub status: String,
+    /// The names of the resource policies attached to the disk, e.g. snapshot schedules.
+    pub resource_policies: Vec<String>,
+}
+
+impl TryFrom<JsonValue> for Disk {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Disk` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the disk data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Disk)` - The created `Disk` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        let url_list = |key: &str| {
+            json.get(key)
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .map(last_segment)
+                .collect::<Vec<_>>()
+        };
+
+        let name = str_field("name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        // int64 fields are encoded as strings in the API
+        let size_gb = str_field("sizeGb")
+            .and_then(|size| size.parse().ok())
+            .ok_or("Missing or invalid 'sizeGb' field")?;
+        let disk_type = str_field("type")
+            .map(last_segment)
+            .ok_or("Missing or invalid 'type' field")?;
+
+        Ok(Disk {
+            name,
+            size_gb,
+            disk_type,
+            zone: str_field("zone").map(last_segment),
+            region: str_field("region").map(last_segment),
+            replica_zones: url_list("replicaZones"),
+            users: url_list("users"),
+            status: str_field("status").unwrap_or_default().to_string(),
+            resource_policies: url_list("resourcePolicies"),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_disk_from_json() {
+        let json = json!({
+            "name": "data-1",
+            "sizeGb": "500",
+            "type": "projects/p/zones/us-east1-b/diskTypes/pd-ssd",
+            "zone": "projects/p/zones/us-east1-b",
+            "users": ["projects/p/zones/us-east1-b/instances/instance-1"],
+            "status": "READY",
+            "resourcePolicies": ["projects/p/regions/us-east1/resourcePolicies/daily-backup"]
+        });
+
+        let disk = Disk::try_from(json).unwrap();
+
+        assert_eq!(disk.name, "data-1");
+        assert_eq!(disk.size_gb, 500);
+        assert_eq!(disk.disk_type, "pd-ssd");
+        assert_eq!(disk.zone, Some("us-east1-b".to_string()));
+        assert_eq!(disk.region, None);
+        assert_eq!(disk.users, vec!["instance-1"]);
+        assert_eq!(disk.status, "READY");
+        assert_eq!(disk.resource_policies, vec!["daily-backup"]);
+    }
+
+    #[test]
+    fn test_regional_disk_from_json() {
+        let json = json!({
+            "name": "shared",
+            "sizeGb": "200",
+            "type": "projects/p/regions/us-east1/diskTypes/pd-balanced",
+            "region": "projects/p/regions/us-east1",
+            "replicaZones": ["projects/p/zones/us-east1-b", "projects/p/zones/us-east1-c"],
+        });
+
+        let disk = Disk::try_from(json).unwrap();
+
+        assert_eq!(disk.region, Some("us-east1".to_string()));
+        assert_eq!(disk.replica_zones, vec!["us-east1-b", "us-east1-c"]);
+        assert!(disk.users.is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+//! This module defines the `Image` and `InstanceTemplate` structs, which represent a Google
+//! Compute Engine custom image and instance template, and provides `TryFrom` implementations
+//! for creating them from JSON data.
+
+use super::last_segment;
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine custom image.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Image {
+    /// The name of the image.
+    pub name: String,
+    /// The image family the image belongs to, if any.
+    pub family: Option<String>,
+    /// When the image was created.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+    /// The name of the disk the image was created from, if it was created from a disk.
+    pub source_disk: Option<String>,
+    /// The size of the image's disk in GB.
+    pub disk_size_gb: Option<u64>,
+    /// The status of the image, e.g. `READY`.
+    pub status: String,
+    /// The deprecation state of the image, e.g. `DEPRECATED` or `OBSOLETE`, or `None` if it
+    /// is not deprecated.
+    pub deprecation: Option<String>,
+    /// The image that replaces a deprecated image, as `PROJECT/IMAGE`.
+    pub replacement: Option<String>,
+}
+
+impl TryFrom<JsonValue> for Image {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `Image` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the image data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Image)` - The created `Image` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |pointer: &str| json.pointer(pointer).and_then(JsonValue::as_str);
+
+        let name = str_field("/name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+
+        Ok(Image {
+            name,
+            family: str_field("/family").map(str::to_string),
+            creation_timestamp: parse_timestamp(str_field("/creationTimestamp")),
+            source_disk: str_field("/sourceDisk").map(last_segment),
+            // int64 fields are encoded as strings in the API
+            disk_size_gb: str_field("/diskSizeGb").and_then(|size| size.parse().ok()),
+            status: str_field("/status").unwrap_or_default().to_string(),
+            // An image that was deprecated and then restored to ACTIVE isn't deprecated
+            deprecation: str_field("/deprecated/state")
+                .filter(|state| *state != "ACTIVE")
+                .map(str::to_string),
+            replacement: str_field("/deprecated/replacement").map(image_path),
+        })
+    }
+}
+
+/// Represents a Google Compute Engine instance template.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceTemplate {
+    /// The name of the template.
+    pub name: String,
+    /// The machine type of the instances created from the template, e.g. `n2-standard-4`.
+    pub machine_type: String,
+    /// The image the boot disk is created from, as `PROJECT/IMAGE` or
+    /// `PROJECT/family/FAMILY`, if the boot disk is created from an image.
+    pub source_image: Option<String>,
+    /// When the template was created.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<JsonValue> for InstanceTemplate {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `InstanceTemplate` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the template data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstanceTemplate)` - The created `InstanceTemplate` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |pointer: &str| json.pointer(pointer).and_then(JsonValue::as_str);
+
+        let name = str_field("/name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let machine_type = str_field("/properties/machineType")
+            .ok_or("Missing or invalid 'properties.machineType' field")?
+            .to_string();
+        let source_image = json
+            .pointer("/properties/disks")
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .find(|disk| disk.get("boot").and_then(JsonValue::as_bool) == Some(true))
+            .and_then(|disk| disk.pointer("/initializeParams/sourceImage"))
+            .and_then(JsonValue::as_str)
+            .map(image_path);
+
+        Ok(InstanceTemplate {
+            name,
+            machine_type,
+            source_image,
+            creation_timestamp: parse_timestamp(str_field("/creationTimestamp")),
+        })
+    }
+}
+
+/// Parses an RFC3339 timestamp of the API, if it is set and valid.
+fn parse_timestamp(timestamp: Option<&str>) -> Option<DateTime<Utc>> {
+    timestamp
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// Shortens the URL of an image to `PROJECT/IMAGE`, or `PROJECT/family/FAMILY` for the latest
+/// image of a family, e.g. `debian-cloud/family/debian-12`. The project is kept because images
+/// are often shared from other projects. URLs of another form are returned as they are.
+fn image_path(url: &str) -> String {
+    let path = url.split_once("projects/").map_or(url, |(_, path)| path);
+    match path.split_once("/global/images/") {
+        Some((project, image)) => format!("{}/{}", project, image),
+        None => url.to_string(),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_image_from_json() {
+        let json = json!({
+            "name": "base-v3",
+            "family": "base",
+            "creationTimestamp": "2024-03-01T04:00:00.000-08:00",
+            "sourceDisk": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/disks/builder",
+            "diskSizeGb": "20",
+            "status": "READY",
+            "deprecated": {
+                "state": "DEPRECATED",
+                "replacement": "https://www.googleapis.com/compute/v1/projects/p/global/images/base-v4"
+            }
+        });
+
+        let image = Image::try_from(json).unwrap();
+
+        assert_eq!(image.name, "base-v3");
+        assert_eq!(image.family, Some("base".to_string()));
+        assert_eq!(
+            image.creation_timestamp,
+            Some("2024-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap())
+        );
+        assert_eq!(image.source_disk, Some("builder".to_string()));
+        assert_eq!(image.disk_size_gb, Some(20));
+        assert_eq!(image.status, "READY");
+        assert_eq!(image.deprecation, Some("DEPRECATED".to_string()));
+        assert_eq!(image.replacement, Some("p/base-v4".to_string()));
+
+        let active = json!({"name": "base-v4", "deprecated": {"state": "ACTIVE"}});
+        assert_eq!(Image::try_from(active).unwrap().deprecation, None);
+        assert!(Image::try_from(json!({"family": "base"})).is_err());
+    }
+
+    #[test]
+    fn test_instance_template_from_json() {
+        let json = json!({
+            "name": "web-v7",
+            "creationTimestamp": "2024-03-01T12:00:00Z",
+            "properties": {
+                "machineType": "n2-standard-4",
+                "disks": [
+                    {"boot": false, "initializeParams": {"sourceImage": "global/images/data"}},
+                    {
+                        "boot": true,
+                        "initializeParams": {
+                            "sourceImage": "projects/debian-cloud/global/images/family/debian-12"
+                        }
+                    }
+                ]
+            }
+        });
+
+        let template = InstanceTemplate::try_from(json).unwrap();
+
+        assert_eq!(template.name, "web-v7");
+        assert_eq!(template.machine_type, "n2-standard-4");
+        assert_eq!(
+            template.source_image,
+            Some("debian-cloud/family/debian-12".to_string())
+        );
+        assert!(template.creation_timestamp.is_some());
+        assert!(InstanceTemplate::try_from(json!({"name": "t"})).is_err());
+    }
+
+    #[test]
+    fn test_image_path() {
+        assert_eq!(
+            image_path("https://www.googleapis.com/compute/v1/projects/p/global/images/base-v4"),
+            "p/base-v4"
+        );
+        assert_eq!(image_path("global/images/data"), "global/images/data");
+    }
+}
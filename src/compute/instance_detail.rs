@@ -0,0 +1,196 @@
+//! This module defines the `InstanceDetail` struct, the full view of a single instance as
+//! returned by `instances.get`, and provides a `TryFrom` implementation for creating it from
+//! JSON data.
+
+use super::records::Instance;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// A service account attached to an instance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceAccount {
+    /// The email address of the service account.
+    pub email: String,
+    /// The OAuth scopes granted to the instance, e.g.
+    /// `https://www.googleapis.com/auth/cloud-platform`.
+    pub scopes: Vec<String>,
+}
+
+/// The scheduling options of an instance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scheduling {
+    /// Whether the instance is preemptible (including spot instances).
+    pub preemptible: bool,
+    /// The provisioning model, `STANDARD` or `SPOT`, if reported.
+    pub provisioning_model: Option<String>,
+    /// What happens on host maintenance: `MIGRATE` or `TERMINATE`.
+    pub on_host_maintenance: Option<String>,
+    /// Whether the instance is restarted after being terminated by the system.
+    pub automatic_restart: Option<bool>,
+}
+
+impl Scheduling {
+    /// Whether the instance is a spot instance.
+    pub fn is_spot(&self) -> bool {
+        self.provisioning_model.as_deref() == Some("SPOT")
+    }
+}
+
+impl From<&JsonValue> for Scheduling {
+    /// Creates `Scheduling` from an instance's `scheduling` object. All fields are optional.
+    fn from(json: &JsonValue) -> Self {
+        Scheduling {
+            preemptible: json
+                .get("preemptible")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false),
+            provisioning_model: json
+                .get("provisioningModel")
+                .and_then(JsonValue::as_str)
+                .map(|model| model.to_string()),
+            on_host_maintenance: json
+                .get("onHostMaintenance")
+                .and_then(JsonValue::as_str)
+                .map(|policy| policy.to_string()),
+            automatic_restart: json.get("automaticRestart").and_then(JsonValue::as_bool),
+        }
+    }
+}
+
+/// The full details of an instance, for `bcls <env> describe`.
+#[derive(Debug, Clone, Default)]
+pub struct InstanceDetail {
+    /// The fields shared with listings: disks, network interfaces, labels, creation time, ...
+    pub instance: Instance,
+    /// The service accounts attached to the instance.
+    pub service_accounts: Vec<ServiceAccount>,
+    /// The scheduling options.
+    pub scheduling: Scheduling,
+    /// The keys of the instance's metadata items. Values are left out, as they may hold
+    /// secrets such as startup scripts.
+    pub metadata_keys: Vec<String>,
+}
+
+impl TryFrom<JsonValue> for InstanceDetail {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `InstanceDetail` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the instance data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstanceDetail)` - The created `InstanceDetail` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the fields required for an `Instance` are missing
+    ///   or invalid.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let service_accounts = json
+            .get("serviceAccounts")
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .map(|account| ServiceAccount {
+                email: account
+                    .get("email")
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                scopes: account
+                    .get("scopes")
+                    .and_then(JsonValue::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(JsonValue::as_str)
+                    .map(|scope| scope.to_string())
+                    .collect(),
+            })
+            .collect();
+        let scheduling = json
+            .get("scheduling")
+            .map(Scheduling::from)
+            .unwrap_or_default();
+        let metadata_keys = json
+            .get("metadata")
+            .and_then(|metadata| metadata.get("items"))
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| item.get("key").and_then(JsonValue::as_str))
+            .map(|key| key.to_string())
+            .collect();
+
+        Ok(InstanceDetail {
+            instance: Instance::try_from(json)?,
+            service_accounts,
+            scheduling,
+            metadata_keys,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_instance_detail_from_json() {
+        let json = json!({
+            "name": "db-1",
+            "zone": "projects/p/zones/us-east1-b",
+            "machineType": "projects/p/zones/us-east1-b/machineTypes/n2-standard-4",
+            "cpuPlatform": "Intel Cascade Lake",
+            "status": "RUNNING",
+            "creationTimestamp": "2024-03-01T09:30:00.000-08:00",
+            "serviceAccounts": [{
+                "email": "db@p.iam.gserviceaccount.com",
+                "scopes": ["https://www.googleapis.com/auth/cloud-platform"]
+            }],
+            "scheduling": {
+                "preemptible": true,
+                "provisioningModel": "SPOT",
+                "onHostMaintenance": "TERMINATE",
+                "automaticRestart": false
+            },
+            "metadata": {
+                "items": [
+                    {"key": "startup-script", "value": "#!/bin/sh"},
+                    {"key": "ssh-keys", "value": "..."}
+                ]
+            }
+        });
+
+        let detail = InstanceDetail::try_from(json).unwrap();
+        assert_eq!(detail.instance.name, "db-1");
+        assert_eq!(
+            detail.service_accounts,
+            vec![ServiceAccount {
+                email: "db@p.iam.gserviceaccount.com".to_string(),
+                scopes: vec!["https://www.googleapis.com/auth/cloud-platform".to_string()],
+            }]
+        );
+        assert!(detail.scheduling.preemptible);
+        assert!(detail.scheduling.is_spot());
+        assert_eq!(detail.scheduling.automatic_restart, Some(false));
+        assert_eq!(detail.metadata_keys, vec!["startup-script", "ssh-keys"]);
+    }
+
+    #[test]
+    fn test_instance_detail_defaults() {
+        let json = json!({
+            "name": "db-1",
+            "zone": "us-east1-b",
+            "machineType": "n2-standard-4",
+            "cpuPlatform": "Intel Cascade Lake",
+            "status": "TERMINATED",
+        });
+
+        let detail = InstanceDetail::try_from(json).unwrap();
+        assert!(detail.service_accounts.is_empty());
+        assert_eq!(detail.scheduling, Scheduling::default());
+        assert!(detail.metadata_keys.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+//! This module defines the records of managed instance groups (MIGs): the
+//! `InstanceGroupManager` itself, the `Autoscaler` that may resize it and the
+//! `ManagedInstance`s it runs, with `TryFrom` implementations for creating them from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+use std::fmt;
+
+/// Where a managed instance group runs: in a single zone, or spread over a region.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MigLocation {
+    /// A zonal group, e.g. in `us-east1-b`.
+    Zone(String),
+    /// A regional group, e.g. in `us-east1`.
+    Region(String),
+}
+
+impl MigLocation {
+    /// Reads the location from the `zone` or `region` URL of a resource.
+    fn from_json(json: &JsonValue) -> Option<Self> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        str_field("zone")
+            .map(|zone| MigLocation::Zone(last_segment(zone)))
+            .or_else(|| str_field("region").map(|region| MigLocation::Region(last_segment(region))))
+    }
+}
+
+impl fmt::Display for MigLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigLocation::Zone(zone) => write!(f, "{}", zone),
+            MigLocation::Region(region) => write!(f, "{}", region),
+        }
+    }
+}
+
+/// Represents a managed instance group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceGroupManager {
+    /// The name of the group.
+    pub name: String,
+    /// The zone or region of the group.
+    pub location: MigLocation,
+    /// The number of instances the group should run.
+    pub target_size: u64,
+    /// The number of instances the group currently manages, whatever they are doing.
+    pub current_size: u64,
+    /// The name of the instance template new instances are created from.
+    pub instance_template: Option<String>,
+    /// Whether all instances are running their intended version and no actions are pending.
+    pub is_stable: bool,
+}
+
+impl TryFrom<JsonValue> for InstanceGroupManager {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `InstanceGroupManager` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the group data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(InstanceGroupManager)` - The created group on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let name = json
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let location =
+            MigLocation::from_json(&json).ok_or("Missing or invalid 'zone' or 'region' field")?;
+        // Every managed instance is counted under exactly one current action, `none` if idle
+        let current_size = json
+            .get("currentActions")
+            .and_then(JsonValue::as_object)
+            .into_iter()
+            .flatten()
+            .filter_map(|(_, count)| count.as_u64())
+            .sum();
+
+        Ok(InstanceGroupManager {
+            name,
+            location,
+            target_size: json
+                .get("targetSize")
+                .and_then(JsonValue::as_u64)
+                .unwrap_or(0),
+            current_size,
+            instance_template: json
+                .get("instanceTemplate")
+                .and_then(JsonValue::as_str)
+                .map(last_segment),
+            is_stable: json
+                .pointer("/status/isStable")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+/// Represents an autoscaler, which resizes a managed instance group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Autoscaler {
+    /// The name of the autoscaler.
+    pub name: String,
+    /// The name of the group it resizes.
+    pub target: String,
+    /// The zone or region of the autoscaler and its group.
+    pub location: MigLocation,
+    /// The status of the autoscaler, e.g. `ACTIVE` or `ERROR`.
+    pub status: String,
+}
+
+impl TryFrom<JsonValue> for Autoscaler {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `Autoscaler` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the autoscaler data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Autoscaler)` - The created autoscaler on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        Ok(Autoscaler {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            target: str_field("target")
+                .map(last_segment)
+                .ok_or("Missing or invalid 'target' field")?,
+            location: MigLocation::from_json(&json)
+                .ok_or("Missing or invalid 'zone' or 'region' field")?,
+            status: str_field("status").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Represents an instance run by a managed instance group.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManagedInstance {
+    /// The name of the instance.
+    pub name: String,
+    /// The zone of the instance.
+    pub zone: String,
+    /// The status of the instance, e.g. `RUNNING`. `None` while it is being created.
+    pub status: Option<String>,
+    /// What the group is doing with the instance, e.g. `NONE` or `RECREATING`.
+    pub current_action: String,
+    /// The health state of the instance for each health check, e.g. `HEALTHY` or `TIMEOUT`.
+    pub health: Vec<String>,
+}
+
+impl TryFrom<JsonValue> for ManagedInstance {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `ManagedInstance` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the managed instance data, as returned by
+    ///   `listManagedInstances`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ManagedInstance)` - The created instance on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        // The instance URL ends in zones/{zone}/instances/{name}
+        let url = str_field("instance").ok_or("Missing or invalid 'instance' field")?;
+        let segments = url.rsplit('/').collect::<Vec<_>>();
+        let (name, zone) = match segments.as_slice() {
+            [name, "instances", zone, "zones", ..] => (name.to_string(), zone.to_string()),
+            _ => return Err(format!("Invalid instance URL: {}", url).into()),
+        };
+
+        Ok(ManagedInstance {
+            name,
+            zone,
+            status: str_field("instanceStatus").map(str::to_string),
+            current_action: str_field("currentAction").unwrap_or_default().to_string(),
+            health: json
+                .get("instanceHealth")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|health| health.get("detailedHealthState"))
+                .filter_map(JsonValue::as_str)
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_instance_group_manager_from_json() {
+        let json = json!({
+            "name": "web",
+            "region": "https://www.googleapis.com/compute/v1/projects/p/regions/us-east1",
+            "targetSize": 3,
+            "currentActions": {"none": 2, "creating": 1, "deleting": 0},
+            "instanceTemplate": "https://www.googleapis.com/compute/v1/projects/p/global/instanceTemplates/web-v7",
+            "status": {"isStable": false}
+        });
+
+        let mig = InstanceGroupManager::try_from(json).unwrap();
+
+        assert_eq!(mig.name, "web");
+        assert_eq!(mig.location, MigLocation::Region("us-east1".to_string()));
+        assert_eq!(mig.target_size, 3);
+        assert_eq!(mig.current_size, 3);
+        assert_eq!(mig.instance_template, Some("web-v7".to_string()));
+        assert!(!mig.is_stable);
+    }
+
+    #[test]
+    fn test_autoscaler_from_json() {
+        let json = json!({
+            "name": "web-autoscaler",
+            "target": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/instanceGroupManagers/web",
+            "zone": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b",
+            "status": "ACTIVE"
+        });
+
+        let autoscaler = Autoscaler::try_from(json).unwrap();
+
+        assert_eq!(autoscaler.target, "web");
+        assert_eq!(
+            autoscaler.location,
+            MigLocation::Zone("us-east1-b".to_string())
+        );
+        assert_eq!(autoscaler.status, "ACTIVE");
+    }
+
+    #[test]
+    fn test_managed_instance_from_json() {
+        let json = json!({
+            "instance": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-c/instances/web-x1z2",
+            "instanceStatus": "RUNNING",
+            "currentAction": "NONE",
+            "instanceHealth": [{"healthCheck": "hc", "detailedHealthState": "HEALTHY"}]
+        });
+
+        let instance = ManagedInstance::try_from(json).unwrap();
+
+        assert_eq!(instance.name, "web-x1z2");
+        assert_eq!(instance.zone, "us-east1-c");
+        assert_eq!(instance.status, Some("RUNNING".to_string()));
+        assert_eq!(instance.current_action, "NONE");
+        assert_eq!(instance.health, vec!["HEALTHY"]);
+
+        assert!(ManagedInstance::try_from(json!({"instance": "web-x1z2"})).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! This module defines the `Labels` struct, which represents the labels of an instance along
+//! with the fingerprint needed to change them, and the `LabelChange` a `label` or `unlabel`
+//! makes to them.
+
+use serde_json::{json, Value as JsonValue};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A label to set, e.g. from `label web-1 cell=c3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// The key: lowercase letters, digits, `-` and `_`, starting with a letter.
+    pub key: String,
+    /// The value: lowercase letters, digits, `-` and `_`, possibly empty.
+    pub value: String,
+}
+
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got `{}`", s))?;
+        validate_key(key)?;
+        // <https://cloud.google.com/compute/docs/labeling-resources#requirements>
+        if value.len() > 63 || !value.chars().all(is_label_char) {
+            return Err(format!(
+                "invalid label value `{}`: use up to 63 lowercase letters, digits, - and _",
+                value
+            ));
+        }
+        Ok(Label {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Checks a label key, e.g. one to remove with `unlabel`.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the key is valid.
+/// * `Err(String)` - What is wrong with it.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    let valid = key.len() <= 63
+        && key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key.chars().all(is_label_char);
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "invalid label key `{}`: use up to 63 lowercase letters, digits, - and _, starting \
+             with a letter",
+            key
+        )),
+    }
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+}
+
+/// A change to one label of an instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelChange {
+    /// A label the instance didn't have.
+    Add { key: String, value: String },
+    /// A label whose value changes.
+    Update {
+        key: String,
+        from: String,
+        to: String,
+    },
+    /// A label that is removed.
+    Remove { key: String, value: String },
+}
+
+impl fmt::Display for LabelChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelChange::Add { key, value } => write!(f, "+ {}={}", key, value),
+            LabelChange::Update { key, from, to } => write!(f, "~ {}={} -> {}", key, from, to),
+            LabelChange::Remove { key, value } => write!(f, "- {}={}", key, value),
+        }
+    }
+}
+
+/// The labels of an instance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    /// The fingerprint of the labels as read, which the API requires when changing them so
+    /// that concurrent changes aren't lost.
+    pub fingerprint: String,
+    /// The labels, by key.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Labels {
+    /// Works out what setting and removing labels would change, without changing anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `set` - The labels to set.
+    /// * `remove` - The keys of the labels to remove. Keys the instance doesn't have are
+    ///   ignored.
+    ///
+    /// # Returns
+    ///
+    /// The changes in the order given, leaving out labels that already have their value.
+    pub fn changes(&self, set: &[Label], remove: &[String]) -> Vec<LabelChange> {
+        let mut labels = self.labels.clone();
+        let mut changes = vec![];
+        for label in set {
+            match labels.insert(label.key.clone(), label.value.clone()) {
+                Some(from) if from == label.value => {}
+                Some(from) => changes.push(LabelChange::Update {
+                    key: label.key.clone(),
+                    from,
+                    to: label.value.clone(),
+                }),
+                None => changes.push(LabelChange::Add {
+                    key: label.key.clone(),
+                    value: label.value.clone(),
+                }),
+            }
+        }
+        for key in remove {
+            if let Some(value) = labels.remove(key) {
+                changes.push(LabelChange::Remove {
+                    key: key.clone(),
+                    value,
+                });
+            }
+        }
+        changes
+    }
+
+    /// Applies changes, e.g. from `changes`.
+    pub fn apply(&mut self, changes: &[LabelChange]) {
+        for change in changes {
+            match change {
+                LabelChange::Add { key, value } | LabelChange::Update { key, to: value, .. } => {
+                    self.labels.insert(key.clone(), value.clone());
+                }
+                LabelChange::Remove { key, .. } => {
+                    self.labels.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Returns the request body for the `setLabels` method, which replaces all labels.
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "labelFingerprint": self.fingerprint,
+            "labels": self.labels,
+        })
+    }
+}
+
+impl TryFrom<&JsonValue> for Labels {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create `Labels` from an instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the instance data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Labels)` - The created `Labels` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: &JsonValue) -> Result<Self, Self::Error> {
+        let fingerprint = json
+            .get("labelFingerprint")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'labelFingerprint' field")?
+            .to_string();
+        // An instance without labels has no labels field at all
+        let labels = json
+            .get("labels")
+            .and_then(JsonValue::as_object)
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect();
+        Ok(Labels {
+            fingerprint,
+            labels,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> Labels {
+        Labels::try_from(&json!({
+            "name": "web-1",
+            "labelFingerprint": "42WmSpB8rSM=",
+            "labels": {"cell": "c1", "team": "web"},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_labels_from_json() {
+        let labels = labels();
+        assert_eq!(labels.fingerprint, "42WmSpB8rSM=");
+        assert_eq!(labels.labels["cell"], "c1");
+        assert!(Labels::try_from(&json!({"labelFingerprint": "x"}))
+            .unwrap()
+            .labels
+            .is_empty());
+        assert!(Labels::try_from(&json!({"labels": {}})).is_err());
+    }
+
+    #[test]
+    fn test_label_changes() {
+        let mut labels = labels();
+        let set = ["cell=c3".parse().unwrap(), "team=web".parse().unwrap()];
+        let remove = ["team".to_string(), "owner".to_string()];
+
+        let changes = labels.changes(&set, &remove);
+
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["~ cell=c1 -> c3", "- team=web"]
+        );
+        labels.apply(&changes);
+        assert_eq!(
+            labels.to_json(),
+            json!({"labelFingerprint": "42WmSpB8rSM=", "labels": {"cell": "c3"}})
+        );
+        assert!(labels.changes(&set[..1], &remove).is_empty());
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            "cell=c3".parse::<Label>().unwrap(),
+            Label {
+                key: "cell".to_string(),
+                value: "c3".to_string()
+            }
+        );
+        assert_eq!("drain=".parse::<Label>().unwrap().value, "");
+        assert!("cell".parse::<Label>().is_err());
+        assert!("Cell=c3".parse::<Label>().is_err());
+        assert!("3cell=c3".parse::<Label>().is_err());
+        assert!("cell=C3".parse::<Label>().is_err());
+        assert!(validate_key("cell").is_ok());
+        assert!(validate_key("ce ll").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! This module defines the `MachineType` struct, which describes the resources of a Google
+//! Compute Engine machine type, and provides a `TryFrom` implementation for creating a
+//! `MachineType` from JSON data.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine machine type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MachineType {
+    /// The name of the machine type, e.g. `n2-standard-16`.
+    pub name: String,
+    /// The number of virtual CPUs.
+    pub vcpus: u64,
+    /// The amount of memory in MB.
+    pub memory_mb: u64,
+}
+
+impl TryFrom<JsonValue> for MachineType {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `MachineType` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the machine type data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(MachineType)` - The created `MachineType` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let name = json
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let vcpus = json
+            .get("guestCpus")
+            .and_then(JsonValue::as_u64)
+            .ok_or("Missing or invalid 'guestCpus' field")?;
+        let memory_mb = json
+            .get("memoryMb")
+            .and_then(JsonValue::as_u64)
+            .ok_or("Missing or invalid 'memoryMb' field")?;
+
+        Ok(MachineType {
+            name,
+            vcpus,
+            memory_mb,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_machine_type_from_json() {
+        let json = json!({
+            "name": "n2-standard-16",
+            "guestCpus": 16,
+            "memoryMb": 65536,
+            "zone": "us-east1-b"
+        });
+
+        let machine_type = MachineType::try_from(json).unwrap();
+
+        assert_eq!(machine_type.name, "n2-standard-16");
+        assert_eq!(machine_type.vcpus, 16);
+        assert_eq!(machine_type.memory_mb, 65536);
+    }
+}
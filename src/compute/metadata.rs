@@ -0,0 +1,209 @@
+//! This module defines the `Metadata` struct, which represents the custom metadata of an
+//! instance along with the fingerprint needed to change it, and provides a `TryFrom`
+//! implementation for creating `Metadata` from JSON data.
+
+use serde_json::{json, Value as JsonValue};
+use std::error::Error;
+use std::str::FromStr;
+
+/// A metadata entry of an instance, e.g. from `metadata set web-1 maintenance=true`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataItem {
+    /// The key, made of letters, digits, `-` and `_`.
+    pub key: String,
+    /// The value, which may be empty.
+    pub value: String,
+}
+
+impl FromStr for MetadataItem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got `{}`", s))?;
+        // <https://cloud.google.com/compute/docs/metadata/setting-custom-metadata#limitations>
+        let valid = !key.is_empty()
+            && key.len() <= 128
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "invalid metadata key `{}`: use up to 128 letters, digits, - and _",
+                key
+            ));
+        }
+        Ok(MetadataItem {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// The custom metadata of an instance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// The fingerprint of the metadata as read, which the API requires when changing it so
+    /// that concurrent changes aren't lost.
+    pub fingerprint: String,
+    /// The entries, in the order the API returned them.
+    pub items: Vec<MetadataItem>,
+}
+
+impl Metadata {
+    /// Returns the value of a key, if the instance has it.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|item| item.key == key)
+            .map(|item| item.value.as_str())
+    }
+
+    /// Sets entries, replacing the values of keys that exist and appending the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The entries to set.
+    ///
+    /// # Returns
+    ///
+    /// Whether anything changed, i.e. whether an entry was new or had a different value.
+    pub fn set(&mut self, items: &[MetadataItem]) -> bool {
+        let mut changed = false;
+        for item in items {
+            match self
+                .items
+                .iter_mut()
+                .find(|existing| existing.key == item.key)
+            {
+                Some(existing) if existing.value == item.value => {}
+                Some(existing) => {
+                    existing.value = item.value.clone();
+                    changed = true;
+                }
+                None => {
+                    self.items.push(item.clone());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Returns the request body for the `setMetadata` method, which replaces all entries.
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "fingerprint": self.fingerprint,
+            "items": self
+                .items
+                .iter()
+                .map(|item| json!({"key": item.key, "value": item.value}))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl TryFrom<JsonValue> for Metadata {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create `Metadata` from the `metadata` field of an instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the metadata.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Metadata)` - The created `Metadata` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let fingerprint = json
+            .get("fingerprint")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'fingerprint' field")?
+            .to_string();
+        // An instance without custom metadata has no items at all
+        let items = json
+            .get("items")
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                Some(MetadataItem {
+                    key: item.get("key")?.as_str()?.to_string(),
+                    value: item
+                        .get("value")
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+            })
+            .collect();
+        Ok(Metadata { fingerprint, items })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_from_json() {
+        let json = json!({
+            "fingerprint": "abc=",
+            "items": [
+                {"key": "maintenance", "value": "false"},
+                {"key": "startup-script", "value": "#!/bin/sh\necho hi"},
+            ]
+        });
+
+        let metadata = Metadata::try_from(json).unwrap();
+
+        assert_eq!(metadata.fingerprint, "abc=");
+        assert_eq!(metadata.get("maintenance"), Some("false"));
+        assert_eq!(metadata.get("ssh-keys"), None);
+        assert!(Metadata::try_from(json!({"fingerprint": "x"}))
+            .unwrap()
+            .items
+            .is_empty());
+        assert!(Metadata::try_from(json!({})).is_err());
+    }
+
+    #[test]
+    fn test_set_metadata() {
+        let mut metadata = Metadata::try_from(json!({
+            "fingerprint": "abc=",
+            "items": [{"key": "maintenance", "value": "false"}]
+        }))
+        .unwrap();
+
+        let items = [
+            "maintenance=true".parse().unwrap(),
+            "owner=sre".parse().unwrap(),
+        ];
+        assert!(metadata.set(&items));
+        assert!(!metadata.set(&items));
+        assert_eq!(
+            metadata.to_json(),
+            json!({
+                "fingerprint": "abc=",
+                "items": [
+                    {"key": "maintenance", "value": "true"},
+                    {"key": "owner", "value": "sre"},
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_item() {
+        let item = "motd=a=b".parse::<MetadataItem>().unwrap();
+        assert_eq!((item.key.as_str(), item.value.as_str()), ("motd", "a=b"));
+        assert_eq!("flag=".parse::<MetadataItem>().unwrap().value, "");
+        assert!("maintenance".parse::<MetadataItem>().is_err());
+        assert!("=true".parse::<MetadataItem>().is_err());
+        assert!("bad key=1".parse::<MetadataItem>().is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! This module defines the records of VPC networking: the `Network` itself and its
+//! `Subnetwork`s with their `SecondaryRange`s, with `TryFrom` implementations for creating them
+//! from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+use std::net::IpAddr;
+
+/// Represents a VPC network.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Network {
+    /// The name of the network.
+    pub name: String,
+    /// How subnets are created: `auto`, `custom`, or `legacy` for networks without subnets.
+    pub mode: String,
+    /// Whether routes are shared `REGIONAL`ly or `GLOBAL`ly, if the API reports it.
+    pub routing_mode: Option<String>,
+    /// The maximum transmission unit in bytes, if the API reports it.
+    pub mtu: Option<u64>,
+    /// The number of subnets in the network.
+    pub subnet_count: usize,
+}
+
+impl TryFrom<JsonValue> for Network {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Network` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the network data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Network)` - The created `Network` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let name = json
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        // Legacy networks have a single range of their own instead of subnets
+        let mode = if json.get("IPv4Range").is_some() {
+            "legacy"
+        } else if json
+            .get("autoCreateSubnetworks")
+            .and_then(JsonValue::as_bool)
+            .unwrap_or(false)
+        {
+            "auto"
+        } else {
+            "custom"
+        };
+
+        Ok(Network {
+            name,
+            mode: mode.to_string(),
+            routing_mode: json
+                .pointer("/routingConfig/routingMode")
+                .and_then(JsonValue::as_str)
+                .map(str::to_string),
+            mtu: json.get("mtu").and_then(JsonValue::as_u64),
+            subnet_count: json
+                .get("subnetworks")
+                .and_then(JsonValue::as_array)
+                .map_or(0, Vec::len),
+        })
+    }
+}
+
+/// Represents a secondary IP range of a subnet, e.g. for GKE pods or services.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecondaryRange {
+    /// The name of the range.
+    pub name: String,
+    /// The range in CIDR notation.
+    pub cidr: String,
+}
+
+/// Represents a subnet of a VPC network.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Subnetwork {
+    /// The name of the subnet.
+    pub name: String,
+    /// The name of the network the subnet belongs to.
+    pub network: String,
+    /// The region of the subnet.
+    pub region: String,
+    /// The primary range in CIDR notation.
+    pub ip_cidr_range: String,
+    /// The secondary ranges.
+    pub secondary_ranges: Vec<SecondaryRange>,
+}
+
+impl Subnetwork {
+    /// Finds the range of the subnet an IP address falls into.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The IP address to look up.
+    ///
+    /// # Returns
+    ///
+    /// The range containing the address: `Some(None)` for the primary range,
+    /// `Some(Some(range))` for a secondary range, or `None` if no range contains it.
+    pub fn range_containing(&self, ip: IpAddr) -> Option<Option<&SecondaryRange>> {
+        if cidr_contains(&self.ip_cidr_range, ip) {
+            return Some(None);
+        }
+        self.secondary_ranges
+            .iter()
+            .find(|range| cidr_contains(&range.cidr, ip))
+            .map(Some)
+    }
+}
+
+impl TryFrom<JsonValue> for Subnetwork {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Subnetwork` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the subnet data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Subnetwork)` - The created `Subnetwork` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+
+        Ok(Subnetwork {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            network: str_field("network")
+                .map(last_segment)
+                .ok_or("Missing or invalid 'network' field")?,
+            region: str_field("region")
+                .map(last_segment)
+                .ok_or("Missing or invalid 'region' field")?,
+            ip_cidr_range: str_field("ipCidrRange")
+                .ok_or("Missing or invalid 'ipCidrRange' field")?
+                .to_string(),
+            secondary_ranges: json
+                .get("secondaryIpRanges")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|range| {
+                    Some(SecondaryRange {
+                        name: range.get("rangeName")?.as_str()?.to_string(),
+                        cidr: range.get("ipCidrRange")?.as_str()?.to_string(),
+                    })
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Checks whether a CIDR range, e.g. `10.12.0.0/20`, contains an IP address. Ranges that can't
+/// be parsed, or of the other IP version, contain nothing.
+fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+    let Some((network, prefix)) = cidr.split_once('/') else {
+        return false;
+    };
+    let (Ok(network), Ok(prefix)) = (network.parse::<IpAddr>(), prefix.parse::<u32>()) else {
+        return false;
+    };
+    // Compare the leading `prefix` bits of both addresses
+    let matches = |network: u128, ip: u128, bits: u32| {
+        prefix <= bits && (prefix == 0 || (network ^ ip) >> (bits - prefix) == 0)
+    };
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            matches(u32::from(network).into(), u32::from(ip).into(), 32)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => matches(network.into(), ip.into(), 128),
+        _ => false,
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_network_from_json() {
+        let json = json!({
+            "name": "shared",
+            "autoCreateSubnetworks": false,
+            "routingConfig": {"routingMode": "GLOBAL"},
+            "mtu": 1460,
+            "subnetworks": [
+                "https://www.googleapis.com/compute/v1/projects/p/regions/us-east1/subnetworks/a",
+                "https://www.googleapis.com/compute/v1/projects/p/regions/us-west1/subnetworks/b"
+            ]
+        });
+
+        let network = Network::try_from(json).unwrap();
+
+        assert_eq!(network.name, "shared");
+        assert_eq!(network.mode, "custom");
+        assert_eq!(network.routing_mode, Some("GLOBAL".to_string()));
+        assert_eq!(network.mtu, Some(1460));
+        assert_eq!(network.subnet_count, 2);
+
+        let legacy = Network::try_from(json!({"name": "old", "IPv4Range": "10.240.0.0/16"}));
+        assert_eq!(legacy.unwrap().mode, "legacy");
+    }
+
+    #[test]
+    fn test_subnetwork_from_json() {
+        let json = json!({
+            "name": "gke-east",
+            "network": "https://www.googleapis.com/compute/v1/projects/p/global/networks/shared",
+            "region": "https://www.googleapis.com/compute/v1/projects/p/regions/us-east1",
+            "ipCidrRange": "10.12.0.0/20",
+            "secondaryIpRanges": [
+                {"rangeName": "pods", "ipCidrRange": "10.64.0.0/14"},
+                {"rangeName": "services", "ipCidrRange": "10.68.0.0/20"}
+            ]
+        });
+
+        let subnet = Subnetwork::try_from(json).unwrap();
+
+        assert_eq!(subnet.network, "shared");
+        assert_eq!(subnet.region, "us-east1");
+        assert_eq!(subnet.ip_cidr_range, "10.12.0.0/20");
+        assert_eq!(subnet.secondary_ranges.len(), 2);
+        assert_eq!(subnet.secondary_ranges[1].name, "services");
+    }
+
+    #[test]
+    fn test_range_containing() {
+        let subnet = Subnetwork {
+            ip_cidr_range: "10.12.0.0/20".to_string(),
+            secondary_ranges: vec![SecondaryRange {
+                name: "pods".to_string(),
+                cidr: "10.64.0.0/14".to_string(),
+            }],
+            ..Default::default()
+        };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert_eq!(subnet.range_containing(ip("10.12.3.4")), Some(None));
+        assert_eq!(subnet.range_containing(ip("10.12.15.255")), Some(None));
+        assert_eq!(subnet.range_containing(ip("10.12.16.0")), None);
+        assert_eq!(
+            subnet.range_containing(ip("10.67.255.1")),
+            Some(Some(&subnet.secondary_ranges[0]))
+        );
+        assert_eq!(subnet.range_containing(ip("fd00::1")), None);
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(cidr_contains("0.0.0.0/0", ip("192.168.1.1")));
+        assert!(cidr_contains("10.1.2.3/32", ip("10.1.2.3")));
+        assert!(!cidr_contains("10.1.2.3/32", ip("10.1.2.4")));
+        assert!(cidr_contains("fd20:1::/64", ip("fd20:1::abcd")));
+        assert!(!cidr_contains("10.0.0.0/33", ip("10.0.0.1")));
+        assert!(!cidr_contains("not a range", ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,122 @@
+//! This module defines the `Operation` struct, which represents a long-running Google Compute
+//! Engine operation (e.g. stopping an instance), and provides a `TryFrom` implementation for
+//! creating an `Operation` from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine operation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operation {
+    /// The name of the operation.
+    pub name: String,
+    /// The type of operation, e.g. `stop`.
+    pub operation_type: String,
+    /// The name of the resource the operation acts on.
+    pub target: String,
+    /// The status of the operation: `PENDING`, `RUNNING` or `DONE`.
+    pub status: String,
+    /// The zone of a zonal operation.
+    pub zone: Option<String>,
+    /// The region of a regional operation.
+    pub region: Option<String>,
+    /// The user who requested the operation.
+    pub user: Option<String>,
+    /// When the operation was requested (RFC3339).
+    pub insert_time: Option<String>,
+    /// When the operation started (RFC3339).
+    pub start_time: Option<String>,
+    /// When the operation finished (RFC3339).
+    pub end_time: Option<String>,
+    /// The error messages reported by a failed operation.
+    pub errors: Vec<String>,
+}
+
+impl Operation {
+    /// Whether the operation has finished, successfully or not.
+    pub fn is_done(&self) -> bool {
+        self.status == "DONE"
+    }
+}
+
+impl TryFrom<JsonValue> for Operation {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `Operation` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the operation data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Operation)` - The created `Operation` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        let name = str_field("name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let status = str_field("status")
+            .ok_or("Missing or invalid 'status' field")?
+            .to_string();
+        let errors = json
+            .get("error")
+            .and_then(|e| e.get("errors"))
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.get("message").and_then(JsonValue::as_str))
+            .map(|message| message.to_string())
+            .collect();
+
+        Ok(Operation {
+            name,
+            operation_type: str_field("operationType").unwrap_or_default().to_string(),
+            target: str_field("targetLink")
+                .map(last_segment)
+                .unwrap_or_default(),
+            status,
+            zone: str_field("zone").map(last_segment),
+            region: str_field("region").map(last_segment),
+            user: str_field("user").map(|user| user.to_string()),
+            insert_time: str_field("insertTime").map(|time| time.to_string()),
+            start_time: str_field("startTime").map(|time| time.to_string()),
+            end_time: str_field("endTime").map(|time| time.to_string()),
+            errors,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_operation_from_json() {
+        let json = json!({
+            "name": "operation-123",
+            "operationType": "stop",
+            "targetLink": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/instances/instance-1",
+            "status": "DONE",
+            "zone": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b",
+            "user": "jdoe@example.com",
+            "insertTime": "2024-01-01T00:00:00.000-08:00",
+            "error": {
+                "errors": [{"code": "RESOURCE_NOT_READY", "message": "The resource is not ready"}]
+            }
+        });
+
+        let operation = Operation::try_from(json).unwrap();
+
+        assert_eq!(operation.name, "operation-123");
+        assert_eq!(operation.operation_type, "stop");
+        assert_eq!(operation.target, "instance-1");
+        assert!(operation.is_done());
+        assert_eq!(operation.zone, Some("us-east1-b".to_string()));
+        assert_eq!(operation.errors, vec!["The resource is not ready"]);
+    }
+}
@@ -0,0 +1,95 @@
+//! This module runs independent API calls, such as listing several projects, on a bounded
+//! number of threads, returning the results in a deterministic order.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// How many calls run at once unless configured otherwise.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// Applies a function to every item on up to `jobs` threads at once.
+///
+/// Items are handed out in order to whichever thread is free, so a slow item doesn't hold up
+/// the others. A panic in `f` is propagated once all threads have finished.
+///
+/// # Arguments
+///
+/// * `items` - The items, e.g. the projects to list.
+/// * `jobs` - The maximum number of threads. `0` is treated as `1`.
+/// * `f` - The function to apply, e.g. a listing.
+///
+/// # Returns
+///
+/// The results in the order of the items, whatever order they finished in.
+pub fn map_bounded<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let jobs = jobs.clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results = items.iter().map(|_| Mutex::new(None)).collect::<Vec<_>>();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(item);
+                *results[index]
+                    .lock()
+                    .expect("no thread panics holding the lock") = Some(result);
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .expect("no thread panics holding the lock")
+                .expect("every item is mapped")
+        })
+        .collect()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_keep_item_order() {
+        // Earlier items take longer, so they finish last
+        let items = (0..8).collect::<Vec<u64>>();
+        let results = map_bounded(&items, 3, |i| {
+            std::thread::sleep(Duration::from_millis(8 - i));
+            i * 10
+        });
+        assert_eq!(results, [0, 10, 20, 30, 40, 50, 60, 70]);
+    }
+
+    #[test]
+    fn test_concurrency_is_bounded() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let items = vec![(); 10];
+
+        map_bounded(&items, 2, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        assert!(map_bounded(&[] as &[u8], 4, |i| *i).is_empty());
+        assert_eq!(map_bounded(&[1, 2], 0, |i| i + 1), [2, 3]);
+    }
+}
@@ -1,31 +1,299 @@
 //! This module defines the `Instance` struct, which represents a Google Compute Engine instance,
-//! and provides a `TryFrom` implementation for creating an `Instance` from JSON data.
+//! and provides a `TryFrom` implementation for creating an `Instance` from JSON data. It also
+//! defines the records nested within an instance, such as network interfaces and attached disks.
 
+use super::last_segment;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 
 /// Represents a Google Compute Engine instance.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Instance {
     /// The name of the instance.
     pub name: String,
-    /// The IP address of the instance.
-    pub ip: String,
+    /// The canonical URL of the instance, e.g.
+    /// `https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/instances/db-1`,
+    /// which other Google Cloud tools and APIs accept to identify it.
+    pub self_link: Option<String>,
+    /// The network interfaces of the instance, in the order reported by the API.
+    pub network_interfaces: Vec<NetworkInterface>,
     /// The zone the instance is running in.
     pub zone: String,
     /// The machine type of the instance.
     pub machine_type: String,
-    /// The CPU platform of the instance.
+    /// The CPU platform of the instance, empty for instances that have never run.
     pub cpu_platform: String,
     /// The status of the instance.
     pub status: String,
+    /// When the instance was created.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+    /// When the instance was last started, if it has been since it was created.
+    pub last_start_timestamp: Option<DateTime<Utc>>,
     /// The labels associated with the instance.
     pub labels: Option<HashMap<String, String>>,
     /// The region the instance is running in.
     pub region: String,
     /// The cell the instance is running in.
     pub cell: Option<String>,
+    /// The disks attached to the instance, including local SSDs.
+    pub disks: Vec<AttachedDisk>,
+    /// The instance's reservation affinity, if set.
+    pub reservation_affinity: Option<ReservationAffinity>,
+    /// The names of the resource policies attached to the instance, e.g. instance schedules.
+    pub resource_policies: Vec<String>,
+    /// The provisioning model, `STANDARD` or `SPOT`, if reported.
+    pub provisioning_model: Option<String>,
+    /// Whether the instance is a legacy preemptible VM.
+    #[serde(default)]
+    pub preemptible: bool,
+    /// The emails of the service accounts the instance runs as. There is at most one.
+    #[serde(default)]
+    pub service_accounts: Vec<String>,
+    /// Fields only returned by the beta API. `None` when listed through `compute/v1`.
+    pub beta: Option<BetaFields>,
+    /// Extra fields added after listing by enrichment stages, keyed by field name.
+    pub enrichments: BTreeMap<String, String>,
+}
+
+/// Represents a disk attached to an instance.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AttachedDisk {
+    /// The device name of the disk within the instance.
+    pub device_name: String,
+    /// Whether this is the boot disk.
+    pub boot: bool,
+    /// The disk type: `PERSISTENT` or `SCRATCH` (local SSD).
+    pub kind: String,
+    /// The disk interface, e.g. `SCSI` or `NVME`.
+    pub interface: Option<String>,
+    /// The size of the disk in GB, if reported.
+    pub size_gb: Option<u64>,
+    /// The URL of the persistent disk resource. Local SSDs have no source.
+    pub source: Option<String>,
+}
+
+impl AttachedDisk {
+    /// Whether the disk is a local SSD, which is tied to the host and cannot be moved.
+    pub fn is_local_ssd(&self) -> bool {
+        self.kind == "SCRATCH"
+    }
+
+    /// Whether the disk is a zonal persistent disk (as opposed to a regional one).
+    pub fn is_zonal(&self) -> bool {
+        self.source
+            .as_deref()
+            .is_some_and(|source| source.contains("/zones/"))
+    }
+
+    /// Returns the name of the persistent disk resource, if any.
+    pub fn source_name(&self) -> Option<String> {
+        self.source.as_deref().map(last_segment)
+    }
+}
+
+impl From<AttachedDiskResource> for AttachedDisk {
+    /// Creates an `AttachedDisk` from an element of an instance's `disks` array.
+    fn from(disk: AttachedDiskResource) -> Self {
+        AttachedDisk {
+            device_name: disk.device_name,
+            boot: disk.boot,
+            kind: disk.kind.unwrap_or_else(|| "PERSISTENT".to_string()),
+            interface: disk.interface,
+            // int64 fields are encoded as strings in the API
+            size_gb: disk.disk_size_gb.and_then(|size| size.parse().ok()),
+            source: disk.source,
+        }
+    }
+}
+
+/// Represents which reservations an instance may consume.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReservationAffinity {
+    /// `ANY_RESERVATION`, `SPECIFIC_RESERVATION` or `NO_RESERVATION`.
+    pub consume_type: String,
+    /// The reservation names (or URLs) for `SPECIFIC_RESERVATION`.
+    pub values: Vec<String>,
+}
+
+impl ReservationAffinity {
+    /// Whether the instance is tied to specific, named reservations.
+    pub fn is_specific(&self) -> bool {
+        self.consume_type == "SPECIFIC_RESERVATION"
+    }
+}
+
+impl From<ReservationAffinityResource> for ReservationAffinity {
+    /// Creates a `ReservationAffinity` from an instance's `reservationAffinity` object.
+    fn from(affinity: ReservationAffinityResource) -> Self {
+        ReservationAffinity {
+            consume_type: affinity.consume_reservation_type,
+            values: affinity.values,
+        }
+    }
+}
+
+/// Instance fields that are only available from the `compute/beta` API.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BetaFields {
+    /// How long the instance may be unresponsive before it is restarted after a host error.
+    pub host_error_timeout_seconds: Option<u64>,
+    /// Whether graceful shutdown is enabled for the instance.
+    pub graceful_shutdown: Option<bool>,
+}
+
+impl From<&JsonValue> for BetaFields {
+    /// Extracts the beta-only fields from an instance's JSON data. All fields are optional, and
+    /// any of the wrong type are left out.
+    fn from(json: &JsonValue) -> Self {
+        let scheduling = json
+            .get("scheduling")
+            .and_then(|scheduling| BetaSchedulingResource::deserialize(scheduling).ok())
+            .unwrap_or_default();
+        BetaFields {
+            host_error_timeout_seconds: scheduling.host_error_timeout_seconds,
+            graceful_shutdown: scheduling
+                .graceful_shutdown
+                .and_then(|graceful_shutdown| graceful_shutdown.enabled),
+        }
+    }
+}
+
+/// Represents a network interface (NIC) attached to an instance.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    /// The name of the interface, e.g. `nic0`.
+    pub name: String,
+    /// The name of the VPC network the interface is attached to.
+    pub network: Option<String>,
+    /// The name of the subnetwork the interface is attached to.
+    pub subnetwork: Option<String>,
+    /// The internal IP address of the interface.
+    pub ip: Option<String>,
+    /// The external (NAT) IP address of the interface, if it has one.
+    pub external_ip: Option<String>,
+}
+
+impl From<NetworkInterfaceResource> for NetworkInterface {
+    /// Creates a `NetworkInterface` from an element of an instance's `networkInterfaces` array.
+    ///
+    /// All fields are optional in the API response, so this conversion cannot fail.
+    fn from(iface: NetworkInterfaceResource) -> Self {
+        // Use the first NAT IP found in the interface's access configs
+        let external_ip = iface
+            .access_configs
+            .into_iter()
+            .find_map(|config| config.nat_ip);
+
+        NetworkInterface {
+            name: iface.name,
+            network: iface.network.as_deref().map(last_segment),
+            subnetwork: iface.subnetwork.as_deref().map(last_segment),
+            ip: iface.network_ip,
+            external_ip,
+        }
+    }
+}
+
+/// An instance as returned by the API. Only the fields records are made from are read.
+/// <https://cloud.google.com/compute/docs/reference/rest/v1/instances#resource:-instance>
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceResource {
+    name: String,
+    self_link: Option<String>,
+    #[serde(default)]
+    network_interfaces: Vec<NetworkInterfaceResource>,
+    /// The URL of the zone.
+    zone: String,
+    /// The URL of the machine type.
+    machine_type: String,
+    /// Missing for instances that have never run.
+    #[serde(default)]
+    cpu_platform: String,
+    status: String,
+    creation_timestamp: Option<String>,
+    last_start_timestamp: Option<String>,
+    labels: Option<HashMap<String, String>>,
+    #[serde(default)]
+    disks: Vec<AttachedDiskResource>,
+    reservation_affinity: Option<ReservationAffinityResource>,
+    /// The URLs of the resource policies.
+    #[serde(default)]
+    resource_policies: Vec<String>,
+    scheduling: Option<SchedulingResource>,
+    #[serde(default)]
+    service_accounts: Vec<ServiceAccountResource>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NetworkInterfaceResource {
+    #[serde(default)]
+    name: String,
+    network: Option<String>,
+    subnetwork: Option<String>,
+    #[serde(rename = "networkIP")]
+    network_ip: Option<String>,
+    #[serde(default)]
+    access_configs: Vec<AccessConfigResource>,
+}
+
+#[derive(Deserialize)]
+struct AccessConfigResource {
+    #[serde(rename = "natIP")]
+    nat_ip: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachedDiskResource {
+    #[serde(default)]
+    device_name: String,
+    #[serde(default)]
+    boot: bool,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    interface: Option<String>,
+    disk_size_gb: Option<String>,
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReservationAffinityResource {
+    #[serde(default)]
+    consume_reservation_type: String,
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchedulingResource {
+    provisioning_model: Option<String>,
+    #[serde(default)]
+    preemptible: bool,
+}
+
+/// The scheduling fields only returned by the beta API.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BetaSchedulingResource {
+    host_error_timeout_seconds: Option<u64>,
+    graceful_shutdown: Option<GracefulShutdownResource>,
+}
+
+#[derive(Deserialize)]
+struct GracefulShutdownResource {
+    enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountResource {
+    email: Option<String>,
 }
 
 impl TryFrom<JsonValue> for Instance {
@@ -46,57 +314,20 @@ impl TryFrom<JsonValue> for Instance {
     /// * `Ok(Instance)` - The created `Instance` on success.
     /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
     fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
-        let name = json
-            .get("name")
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'name' field")?
-            .to_string();
-        let ip = json
-            .get("networkInterfaces")
-            .and_then(JsonValue::as_array)
-            .and_then(|arr| arr.first()) // Get the first network interface
-            .and_then(|iface| iface.get("networkIP"))
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'networkInterfaces[0].networkIP' field")?
-            .to_string();
-        let zone = json
-            .get("zone")
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'zone' field")?
-            .split('/')
-            .last()
-            .ok_or("Invalid 'zone' format")?
-            .to_string();
-        let machine_type = json
-            .get("machineType")
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'machineType' field")?
-            .split('/')
-            .last()
-            .ok_or("Invalid 'machineType' format")?
-            .to_string();
-        let cpu_platform = json
-            .get("cpuPlatform")
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'cpuPlatform' field")?
-            .to_string();
-        let status = json
-            .get("status")
-            .and_then(JsonValue::as_str)
-            .ok_or("Missing or invalid 'status' field")?
-            .to_string();
-        let labels = json
-            .get("labels")
-            .and_then(JsonValue::as_object) // Convert to object or None
-            // Convert the labels (which is Map<String, &Value> to a HashMap of String key-value pairs if as_object is
-            // Some otherwise, if map was called on None it returns None
-            .map(|obj| {
-                obj.iter()
-                    .map(|(k, v)| (k.clone(), v.as_str().unwrap_or("").to_string()))
-                    .collect::<HashMap<String, String>>()
-            });
+        let instance = InstanceResource::deserialize(json)?;
+        let timestamp = |timestamp: Option<&str>| {
+            timestamp
+                .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+                .map(|timestamp| timestamp.with_timezone(&Utc))
+        };
+        let creation_timestamp = timestamp(instance.creation_timestamp.as_deref());
+        let last_start_timestamp = timestamp(instance.last_start_timestamp.as_deref());
+        let zone = last_segment(&instance.zone);
+        let scheduling = instance.scheduling.unwrap_or_default();
+
         // Extract the cell from the labels if it exists
-        let cell = labels
+        let cell = instance
+            .labels
             .as_ref()
             .and_then(|labels| labels.get("cell"))
             .map(|cell| cell.to_string());
@@ -110,20 +341,120 @@ impl TryFrom<JsonValue> for Instance {
             .to_string();
 
         Ok(Instance {
-            name,
-            ip,
+            name: instance.name,
+            self_link: instance.self_link,
+            // An instance may have no interfaces at all, so an empty list is not an error
+            network_interfaces: instance
+                .network_interfaces
+                .into_iter()
+                .map(NetworkInterface::from)
+                .collect(),
             zone,
-            machine_type,
-            cpu_platform,
-            status,
-            labels,
+            machine_type: last_segment(&instance.machine_type),
+            cpu_platform: instance.cpu_platform,
+            status: instance.status,
+            creation_timestamp,
+            last_start_timestamp,
+            labels: instance.labels,
             region,
             cell,
+            disks: instance.disks.into_iter().map(AttachedDisk::from).collect(),
+            reservation_affinity: instance.reservation_affinity.map(ReservationAffinity::from),
+            resource_policies: instance
+                .resource_policies
+                .iter()
+                .map(|url| last_segment(url))
+                .collect(),
+            provisioning_model: scheduling.provisioning_model,
+            preemptible: scheduling.preemptible,
+            service_accounts: instance
+                .service_accounts
+                .into_iter()
+                .filter_map(|account| account.email)
+                .collect(),
+            beta: None,
+            enrichments: BTreeMap::new(),
         })
     }
 }
 
+/// Checks whether a service account email is a project's default Compute Engine service
+/// account.
+fn is_default_service_account(email: &str) -> bool {
+    email
+        .strip_suffix("-compute@developer.gserviceaccount.com")
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
 impl Instance {
+    /// Summarises the attached disks, e.g. `boot:50GB +2 data +1 local-ssd`.
+    ///
+    /// # Returns
+    ///
+    /// The summary, or `None` if no disks are attached.
+    pub fn disk_summary(&self) -> Option<String> {
+        let mut parts = vec![];
+        if let Some(boot) = self.disks.iter().find(|disk| disk.boot) {
+            parts.push(match boot.size_gb {
+                Some(size) => format!("boot:{}GB", size),
+                None => "boot".to_string(),
+            });
+        }
+        let others = self.disks.iter().filter(|disk| !disk.boot);
+        let (local_ssds, data): (Vec<_>, Vec<_>) = others.partition(|disk| disk.is_local_ssd());
+        if !data.is_empty() {
+            parts.push(format!("+{} data", data.len()));
+        }
+        if !local_ssds.is_empty() {
+            parts.push(format!("+{} local-ssd", local_ssds.len()));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+
+    /// Whether the instance runs as the project's default Compute Engine service account,
+    /// `PROJECT_NUMBER-compute@developer.gserviceaccount.com`, which has broad access by default.
+    pub fn uses_default_service_account(&self) -> bool {
+        self.service_accounts
+            .iter()
+            .any(|email| is_default_service_account(email))
+    }
+
+    /// Whether the instance runs on spot capacity, as a spot VM or a legacy preemptible VM, so
+    /// it can be stopped by Compute Engine at any time.
+    pub fn is_spot(&self) -> bool {
+        self.provisioning_model.as_deref() == Some("SPOT") || self.preemptible
+    }
+
+    /// Returns the internal IP of the first network interface, if any.
+    pub fn primary_ip(&self) -> Option<&str> {
+        self.network_interfaces
+            .first()
+            .and_then(|iface| iface.ip.as_deref())
+    }
+
+    /// Returns the first external (NAT) IP found on any network interface, if any.
+    pub fn external_ip(&self) -> Option<&str> {
+        self.network_interfaces
+            .iter()
+            .find_map(|iface| iface.external_ip.as_deref())
+    }
+
+    /// Returns the internal IPs of all network interfaces, in interface order.
+    pub fn ips(&self) -> Vec<&str> {
+        self.network_interfaces
+            .iter()
+            .filter_map(|iface| iface.ip.as_deref())
+            .collect()
+    }
+
+    /// Returns the external (NAT) IPs of all network interfaces, in interface order.
+    pub fn external_ips(&self) -> Vec<&str> {
+        self.network_interfaces
+            .iter()
+            .filter_map(|iface| iface.external_ip.as_deref())
+            .collect()
+    }
+
     /// Formats the `Instance` data into a human-readable string.
     ///
     /// This function creates a string representation of the `Instance`
@@ -146,7 +477,7 @@ impl Instance {
         format!(
             "Name: {} IP: {} Zone: {} Machine Type: {} CPU Platform: {} Status: {} Labels: {}",
             self.name,
-            self.ip,
+            self.primary_ip().unwrap_or("None"),
             self.zone,
             self.machine_type,
             self.cpu_platform,
@@ -167,6 +498,7 @@ mod tests {
         // Test data representing a valid instance JSON
         let json = json!({
             "name": "test-instance",
+            "selfLink": "https://www.googleapis.com/compute/v1/projects/12345/zones/test-region-foo/instances/test-instance",
             "networkInterfaces": [
                 {
                     "networkIP": "127.0.0.1"
@@ -176,6 +508,13 @@ mod tests {
             "machineType": "projects/12345/machineTypes/test-machine-type", // Full machine type path
             "cpuPlatform": "test-cpu-platform",
             "status": "test-status",
+            "creationTimestamp": "2024-03-01T09:30:00.123-08:00",
+            "serviceAccounts": [{
+                "email": "123456789-compute@developer.gserviceaccount.com",
+                "scopes": ["https://www.googleapis.com/auth/cloud-platform"],
+            }],
+            "lastStartTimestamp": "2024-06-10T08:00:00.000-07:00",
+            "resourcePolicies": ["projects/12345/regions/test-region/resourcePolicies/nightly-stop"],
             "labels": {
                 "key1": "value1",
                 "cell": "int-test-cell",
@@ -187,11 +526,23 @@ mod tests {
 
         // Assertions to check if the Instance fields are correctly populated.
         assert_eq!(instance.name, "test-instance");
-        assert_eq!(instance.ip, "127.0.0.1");
+        assert_eq!(
+            instance.self_link.as_deref(),
+            Some("https://www.googleapis.com/compute/v1/projects/12345/zones/test-region-foo/instances/test-instance")
+        );
+        assert_eq!(instance.primary_ip(), Some("127.0.0.1"));
         assert_eq!(instance.zone, "test-region-foo"); // Extracted zone
         assert_eq!(instance.machine_type, "test-machine-type"); // Extracted machine type
         assert_eq!(instance.cpu_platform, "test-cpu-platform");
         assert_eq!(instance.status, "test-status");
+        assert_eq!(
+            instance.creation_timestamp.unwrap().to_rfc3339(),
+            "2024-03-01T17:30:00.123+00:00"
+        );
+        assert_eq!(
+            instance.last_start_timestamp.unwrap().to_rfc3339(),
+            "2024-06-10T15:00:00+00:00"
+        );
         assert_eq!(instance.labels, {
             let mut map = HashMap::new();
             map.insert("key1".to_string(), "value1".to_string());
@@ -200,5 +551,174 @@ mod tests {
         });
         assert_eq!(instance.region, "test-region");
         assert_eq!(instance.cell, Some("int-test-cell".to_string()));
+        assert_eq!(instance.resource_policies, vec!["nightly-stop"]);
+        assert_eq!(
+            instance.service_accounts,
+            vec!["123456789-compute@developer.gserviceaccount.com"]
+        );
+        assert!(instance.uses_default_service_account());
+        assert_eq!(instance.external_ip(), None);
+    }
+
+    #[test]
+    fn test_instance_multiple_interfaces_from_json() {
+        let json = json!({
+            "name": "test-instance",
+            "networkInterfaces": [
+                {
+                    "name": "nic0",
+                    "network": "projects/12345/global/networks/default",
+                    "subnetwork": "projects/12345/regions/test-region/subnetworks/default",
+                    "networkIP": "10.0.0.1"
+                },
+                {
+                    "name": "nic1",
+                    "networkIP": "10.1.0.1",
+                    "accessConfigs": [
+                        {
+                            "name": "External NAT",
+                            "natIP": "34.1.2.3"
+                        }
+                    ]
+                }
+            ],
+            "zone": "projects/12345/zones/test-region-foo",
+            "machineType": "projects/12345/machineTypes/test-machine-type",
+            "cpuPlatform": "test-cpu-platform",
+            "status": "test-status"
+        });
+
+        let instance = Instance::try_from(json).unwrap();
+
+        assert_eq!(instance.network_interfaces.len(), 2);
+        assert_eq!(
+            instance.network_interfaces[0],
+            NetworkInterface {
+                name: "nic0".to_string(),
+                network: Some("default".to_string()),
+                subnetwork: Some("default".to_string()),
+                ip: Some("10.0.0.1".to_string()),
+                external_ip: None,
+            }
+        );
+        assert_eq!(instance.primary_ip(), Some("10.0.0.1"));
+        assert_eq!(instance.external_ip(), Some("34.1.2.3"));
+        assert_eq!(instance.ips(), vec!["10.0.0.1", "10.1.0.1"]);
+        assert_eq!(instance.external_ips(), vec!["34.1.2.3"]);
+    }
+
+    #[test]
+    fn test_instance_disks_and_reservation_from_json() {
+        let json = json!({
+            "name": "test-instance",
+            "zone": "projects/12345/zones/test-region-foo",
+            "machineType": "projects/12345/machineTypes/test-machine-type",
+            "cpuPlatform": "test-cpu-platform",
+            "status": "test-status",
+            "disks": [
+                {
+                    "deviceName": "boot",
+                    "boot": true,
+                    "type": "PERSISTENT",
+                    "interface": "SCSI",
+                    "diskSizeGb": "50",
+                    "source": "https://www.googleapis.com/compute/v1/projects/p/zones/test-region-foo/disks/boot-disk"
+                },
+                {
+                    "deviceName": "local-ssd-0",
+                    "type": "SCRATCH",
+                    "interface": "NVME",
+                    "diskSizeGb": "375"
+                },
+                {
+                    "deviceName": "shared",
+                    "type": "PERSISTENT",
+                    "source": "https://www.googleapis.com/compute/v1/projects/p/regions/test-region/disks/shared-disk"
+                }
+            ],
+            "reservationAffinity": {
+                "consumeReservationType": "SPECIFIC_RESERVATION",
+                "key": "compute.googleapis.com/reservation-name",
+                "values": ["my-reservation"]
+            }
+        });
+
+        let instance = Instance::try_from(json).unwrap();
+
+        assert_eq!(instance.disks.len(), 3);
+        assert!(instance.disks[0].boot);
+        assert_eq!(instance.disks[0].size_gb, Some(50));
+        assert!(instance.disks[0].is_zonal());
+        assert_eq!(
+            instance.disks[0].source_name().as_deref(),
+            Some("boot-disk")
+        );
+        assert!(instance.disks[1].is_local_ssd());
+        assert_eq!(instance.disks[1].source_name(), None);
+        assert!(!instance.disks[2].is_zonal());
+        assert_eq!(instance.disks[2].size_gb, None);
+        assert_eq!(
+            instance.disk_summary().as_deref(),
+            Some("boot:50GB +1 data +1 local-ssd")
+        );
+
+        let affinity = instance.reservation_affinity.unwrap();
+        assert!(affinity.is_specific());
+        assert_eq!(affinity.values, vec!["my-reservation"]);
+    }
+
+    #[test]
+    fn test_instance_without_interfaces_from_json() {
+        let json = json!({
+            "name": "test-instance",
+            "networkInterfaces": [],
+            "zone": "projects/12345/zones/test-region-foo",
+            "machineType": "projects/12345/machineTypes/test-machine-type",
+            "cpuPlatform": "test-cpu-platform",
+            "status": "test-status"
+        });
+
+        let instance = Instance::try_from(json).unwrap();
+
+        assert!(instance.network_interfaces.is_empty());
+        assert_eq!(instance.primary_ip(), None);
+        assert_eq!(instance.disk_summary(), None);
+        assert!(!instance.is_spot());
+    }
+
+    #[test]
+    fn test_spot_instances_from_json() {
+        let instance = |scheduling: JsonValue| {
+            Instance::try_from(json!({
+                "name": "test-instance",
+                "zone": "projects/12345/zones/test-region-foo",
+                "machineType": "projects/12345/machineTypes/test-machine-type",
+                "cpuPlatform": "test-cpu-platform",
+                "status": "test-status",
+                "scheduling": scheduling,
+            }))
+            .unwrap()
+        };
+
+        let spot = instance(json!({"provisioningModel": "SPOT"}));
+        assert_eq!(spot.provisioning_model.as_deref(), Some("SPOT"));
+        assert!(spot.is_spot());
+        assert!(instance(json!({"preemptible": true})).is_spot());
+        assert!(
+            !instance(json!({"provisioningModel": "STANDARD", "preemptible": false})).is_spot()
+        );
+    }
+
+    #[test]
+    fn test_is_default_service_account() {
+        assert!(is_default_service_account(
+            "123456789-compute@developer.gserviceaccount.com"
+        ));
+        assert!(!is_default_service_account(
+            "app@acme-prd.iam.gserviceaccount.com"
+        ));
+        assert!(!is_default_service_account(
+            "-compute@developer.gserviceaccount.com"
+        ));
     }
 }
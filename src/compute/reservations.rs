@@ -0,0 +1,108 @@
+//! This module defines the `Reservation` struct, which represents a Google Compute Engine zonal
+//! capacity reservation, and provides a `TryFrom` implementation for creating a `Reservation`
+//! from JSON data.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine capacity reservation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reservation {
+    /// The name of the reservation.
+    pub name: String,
+    /// The zone the capacity is reserved in.
+    pub zone: String,
+    /// The machine type of the reserved capacity.
+    pub machine_type: Option<String>,
+    /// The number of reserved instances.
+    pub count: u64,
+    /// The number of reserved instances currently in use.
+    pub in_use_count: u64,
+    /// Whether only instances that target this reservation by name can consume it.
+    pub specific_reservation_required: bool,
+}
+
+impl TryFrom<JsonValue> for Reservation {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Reservation` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the reservation data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Reservation)` - The created `Reservation` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let name = json
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let zone = json
+            .get("zone")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'zone' field")?
+            .split('/')
+            .next_back()
+            .ok_or("Invalid 'zone' format")?
+            .to_string();
+        let specific = json.get("specificReservation");
+        // int64 fields are encoded as strings in the API
+        let count_field = |key: &str| {
+            specific
+                .and_then(|s| s.get(key))
+                .and_then(JsonValue::as_str)
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(0)
+        };
+
+        Ok(Reservation {
+            name,
+            zone,
+            machine_type: specific
+                .and_then(|s| s.get("instanceProperties"))
+                .and_then(|p| p.get("machineType"))
+                .and_then(JsonValue::as_str)
+                .map(|machine_type| machine_type.to_string()),
+            count: count_field("count"),
+            in_use_count: count_field("inUseCount"),
+            specific_reservation_required: json
+                .get("specificReservationRequired")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reservation_from_json() {
+        let json = json!({
+            "name": "res-1",
+            "zone": "projects/p/zones/us-east1-b",
+            "specificReservation": {
+                "instanceProperties": {"machineType": "n2-standard-16"},
+                "count": "10",
+                "inUseCount": "4"
+            },
+            "specificReservationRequired": true
+        });
+
+        let reservation = Reservation::try_from(json).unwrap();
+
+        assert_eq!(reservation.name, "res-1");
+        assert_eq!(reservation.zone, "us-east1-b");
+        assert_eq!(reservation.machine_type, Some("n2-standard-16".to_string()));
+        assert_eq!(reservation.count, 10);
+        assert_eq!(reservation.in_use_count, 4);
+        assert!(reservation.specific_reservation_required);
+    }
+}
@@ -0,0 +1,231 @@
+//! This module defines the `ResourcePolicy` struct, which represents a Google Compute Engine
+//! resource policy such as an instance schedule or a snapshot schedule, and provides a
+//! `TryFrom` implementation for creating a `ResourcePolicy` from JSON data.
+
+use super::last_segment;
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// The kinds of resource policy bcls knows about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PolicyKind {
+    /// Starts and stops the instances it is attached to on a schedule.
+    InstanceSchedule,
+    /// Snapshots the disks it is attached to on a schedule.
+    SnapshotSchedule,
+    /// Any other policy, e.g. a placement policy.
+    #[default]
+    Other,
+}
+
+/// Represents a Google Compute Engine resource policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourcePolicy {
+    /// The name of the policy.
+    pub name: String,
+    /// The region of the policy.
+    pub region: String,
+    /// The kind of policy.
+    pub kind: PolicyKind,
+    /// A summary of the schedule of an instance or snapshot schedule, e.g.
+    /// `start 0 8 * * 1-5, stop 0 20 * * * (Europe/Berlin)` or
+    /// `daily at 04:00, kept 14 days`.
+    pub schedule: Option<String>,
+}
+
+/// Summarises an `instanceSchedulePolicy` object.
+fn instance_schedule(policy: &JsonValue) -> String {
+    let cron = |key: &str| {
+        policy
+            .get(key)
+            .and_then(|schedule| schedule.get("schedule"))
+            .and_then(JsonValue::as_str)
+    };
+    let mut parts = Vec::new();
+    if let Some(start) = cron("vmStartSchedule") {
+        parts.push(format!("start {}", start));
+    }
+    if let Some(stop) = cron("vmStopSchedule") {
+        parts.push(format!("stop {}", stop));
+    }
+    let mut schedule = parts.join(", ");
+    if let Some(time_zone) = policy.get("timeZone").and_then(JsonValue::as_str) {
+        schedule += &format!(" ({})", time_zone);
+    }
+    schedule
+}
+
+/// Summarises a `snapshotSchedulePolicy` object.
+fn snapshot_schedule(policy: &JsonValue) -> String {
+    let schedule = policy.get("schedule");
+    let cycle = |kind: &str| schedule.and_then(|schedule| schedule.get(kind));
+    let start_time = |cycle: &JsonValue| {
+        cycle
+            .get("startTime")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("00:00")
+            .to_string()
+    };
+    let mut summary = if let Some(hourly) = cycle("hourlySchedule") {
+        let hours = hourly.get("hoursInCycle").and_then(JsonValue::as_u64);
+        format!("every {}h from {}", hours.unwrap_or(1), start_time(hourly))
+    } else if let Some(daily) = cycle("dailySchedule") {
+        match daily.get("daysInCycle").and_then(JsonValue::as_u64) {
+            Some(days) if days > 1 => format!("every {} days at {}", days, start_time(daily)),
+            _ => format!("daily at {}", start_time(daily)),
+        }
+    } else if let Some(weekly) = cycle("weeklySchedule") {
+        let days = weekly
+            .get("dayOfWeeks")
+            .and_then(JsonValue::as_array)
+            .into_iter()
+            .flatten()
+            .map(|day| {
+                format!(
+                    "{} {}",
+                    day.get("day")
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or_default(),
+                    start_time(day)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("weekly on {}", days.join(", "))
+    } else {
+        "no schedule".to_string()
+    };
+    if let Some(days) = policy
+        .get("retentionPolicy")
+        .and_then(|retention| retention.get("maxRetentionDays"))
+        .and_then(JsonValue::as_u64)
+    {
+        summary += &format!(", kept {} days", days);
+    }
+    summary
+}
+
+impl TryFrom<JsonValue> for ResourcePolicy {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `ResourcePolicy` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the resource policy data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResourcePolicy)` - The created `ResourcePolicy` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let name = json
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let region = json
+            .get("region")
+            .and_then(JsonValue::as_str)
+            .map(last_segment)
+            .ok_or("Missing or invalid 'region' field")?;
+        let (kind, schedule) = if let Some(policy) = json.get("instanceSchedulePolicy") {
+            (
+                PolicyKind::InstanceSchedule,
+                Some(instance_schedule(policy)),
+            )
+        } else if let Some(policy) = json.get("snapshotSchedulePolicy") {
+            (
+                PolicyKind::SnapshotSchedule,
+                Some(snapshot_schedule(policy)),
+            )
+        } else {
+            (PolicyKind::Other, None)
+        };
+
+        Ok(ResourcePolicy {
+            name,
+            region,
+            kind,
+            schedule,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_instance_schedule_from_json() {
+        let json = json!({
+            "name": "office-hours",
+            "region": "projects/p/regions/europe-west1",
+            "instanceSchedulePolicy": {
+                "vmStartSchedule": {"schedule": "0 8 * * 1-5"},
+                "vmStopSchedule": {"schedule": "0 20 * * *"},
+                "timeZone": "Europe/Berlin"
+            }
+        });
+
+        let policy = ResourcePolicy::try_from(json).unwrap();
+
+        assert_eq!(policy.name, "office-hours");
+        assert_eq!(policy.region, "europe-west1");
+        assert_eq!(policy.kind, PolicyKind::InstanceSchedule);
+        assert_eq!(
+            policy.schedule.as_deref(),
+            Some("start 0 8 * * 1-5, stop 0 20 * * * (Europe/Berlin)")
+        );
+    }
+
+    #[test]
+    fn test_snapshot_schedule_from_json() {
+        let policy = |schedule: JsonValue| {
+            ResourcePolicy::try_from(json!({
+                "name": "backups",
+                "region": "projects/p/regions/europe-west1",
+                "snapshotSchedulePolicy": {
+                    "schedule": schedule,
+                    "retentionPolicy": {"maxRetentionDays": 14}
+                }
+            }))
+            .unwrap()
+        };
+
+        let daily = policy(json!({"dailySchedule": {"daysInCycle": 1, "startTime": "04:00"}}));
+        assert_eq!(daily.kind, PolicyKind::SnapshotSchedule);
+        assert_eq!(
+            daily.schedule.as_deref(),
+            Some("daily at 04:00, kept 14 days")
+        );
+        let hourly = policy(json!({"hourlySchedule": {"hoursInCycle": 6, "startTime": "01:00"}}));
+        assert_eq!(
+            hourly.schedule.as_deref(),
+            Some("every 6h from 01:00, kept 14 days")
+        );
+        let weekly = policy(json!({"weeklySchedule": {"dayOfWeeks": [
+            {"day": "MONDAY", "startTime": "02:00"},
+            {"day": "THURSDAY", "startTime": "02:00"}
+        ]}}));
+        assert_eq!(
+            weekly.schedule.as_deref(),
+            Some("weekly on MONDAY 02:00, THURSDAY 02:00, kept 14 days")
+        );
+    }
+
+    #[test]
+    fn test_other_policy_from_json() {
+        let json = json!({
+            "name": "spread",
+            "region": "projects/p/regions/europe-west1",
+            "groupPlacementPolicy": {"availabilityDomainCount": 2}
+        });
+
+        let policy = ResourcePolicy::try_from(json).unwrap();
+
+        assert_eq!(policy.kind, PolicyKind::Other);
+        assert_eq!(policy.schedule, None);
+    }
+}
@@ -0,0 +1,91 @@
+//! This module defines the `SerialOutput` struct, which represents a chunk of an instance's
+//! serial port output, and provides a `TryFrom` implementation for creating it from JSON data.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// A chunk of the output of an instance's serial port.
+///
+/// Compute Engine keeps the most recent 1 MB of output of each port. Positions are byte
+/// offsets into all the output since the instance started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SerialOutput {
+    /// The output, with invalid UTF-8 replaced.
+    pub contents: String,
+    /// The position of the first byte of `contents`. It is later than the position asked for
+    /// if the output in between is no longer kept.
+    pub start: u64,
+    /// The position to ask for to get the output that follows.
+    pub next: u64,
+}
+
+impl SerialOutput {
+    /// Returns how many bytes of output were lost before this chunk, if it was asked for from
+    /// `requested` onwards.
+    pub fn skipped(&self, requested: u64) -> u64 {
+        self.start.saturating_sub(requested)
+    }
+}
+
+impl TryFrom<JsonValue> for SerialOutput {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `SerialOutput` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the serial port output.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SerialOutput)` - The created `SerialOutput` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        // int64 fields are sent as strings
+        let position = |key: &str| -> Result<u64, Box<dyn Error>> {
+            let value = json.get(key);
+            value
+                .and_then(JsonValue::as_u64)
+                .or_else(|| value?.as_str()?.parse().ok())
+                .ok_or_else(|| format!("Missing or invalid '{}' field", key).into())
+        };
+        let next = position("next")?;
+        Ok(SerialOutput {
+            // An instance that printed nothing yet has no contents
+            contents: json
+                .get("contents")
+                .and_then(JsonValue::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            start: position("start").unwrap_or(next),
+            next,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serial_output_from_json() {
+        let output = SerialOutput::try_from(json!({
+            "kind": "compute#serialPortOutput",
+            "contents": "Booting...\n",
+            "start": "2048",
+            "next": "2059",
+        }))
+        .unwrap();
+
+        assert_eq!(output.contents, "Booting...\n");
+        assert_eq!((output.start, output.next), (2048, 2059));
+        assert_eq!(output.skipped(1000), 1048);
+        assert_eq!(output.skipped(2048), 0);
+
+        let empty = SerialOutput::try_from(json!({"next": 0})).unwrap();
+        assert_eq!((empty.contents.as_str(), empty.start), ("", 0));
+        assert!(SerialOutput::try_from(json!({"contents": "x"})).is_err());
+    }
+}
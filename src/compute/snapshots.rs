@@ -0,0 +1,109 @@
+//! This module defines the `Snapshot` struct, which represents a Google Compute Engine disk
+//! snapshot, and provides a `TryFrom` implementation for creating a `Snapshot` from JSON data.
+
+use super::last_segment;
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Google Compute Engine disk snapshot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// The name of the snapshot.
+    pub name: String,
+    /// The name of the disk the snapshot was taken from, if the API reports it.
+    pub source_disk: Option<String>,
+    /// The size of the source disk in GB.
+    pub disk_size_gb: u64,
+    /// The number of bytes the snapshot takes up in storage, which is what is billed.
+    pub storage_bytes: Option<u64>,
+    /// When the snapshot was created.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+    /// Where the snapshot is stored, e.g. `us` or `us-east1`.
+    pub storage_locations: Vec<String>,
+    /// The status of the snapshot, e.g. `READY`.
+    pub status: String,
+}
+
+impl TryFrom<JsonValue> for Snapshot {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Snapshot` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the snapshot data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Snapshot)` - The created `Snapshot` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        // int64 fields are encoded as strings in the API
+        let int_field = |key: &str| str_field(key).and_then(|value| value.parse().ok());
+
+        let name = str_field("name")
+            .ok_or("Missing or invalid 'name' field")?
+            .to_string();
+        let disk_size_gb =
+            int_field("diskSizeGb").ok_or("Missing or invalid 'diskSizeGb' field")?;
+
+        Ok(Snapshot {
+            name,
+            source_disk: str_field("sourceDisk").map(last_segment),
+            disk_size_gb,
+            storage_bytes: int_field("storageBytes"),
+            creation_timestamp: str_field("creationTimestamp")
+                .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+                .map(|timestamp| timestamp.with_timezone(&Utc)),
+            storage_locations: json
+                .get("storageLocations")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .map(str::to_string)
+                .collect(),
+            status: str_field("status").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_snapshot_from_json() {
+        let json = json!({
+            "name": "data-1-20240301",
+            "sourceDisk": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b/disks/data-1",
+            "diskSizeGb": "500",
+            "storageBytes": "1073741824",
+            "creationTimestamp": "2024-03-01T04:00:00.000-08:00",
+            "storageLocations": ["us"],
+            "status": "READY"
+        });
+
+        let snapshot = Snapshot::try_from(json).unwrap();
+
+        assert_eq!(snapshot.name, "data-1-20240301");
+        assert_eq!(snapshot.source_disk, Some("data-1".to_string()));
+        assert_eq!(snapshot.disk_size_gb, 500);
+        assert_eq!(snapshot.storage_bytes, Some(1 << 30));
+        assert_eq!(
+            snapshot.creation_timestamp,
+            Some("2024-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap())
+        );
+        assert_eq!(snapshot.storage_locations, vec!["us"]);
+        assert_eq!(snapshot.status, "READY");
+    }
+
+    #[test]
+    fn test_snapshot_without_size_is_invalid() {
+        assert!(Snapshot::try_from(json!({"name": "s"})).is_err());
+    }
+}
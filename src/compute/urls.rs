@@ -0,0 +1,470 @@
+//! This module builds the URLs of the Google Compute Engine API endpoints bcls calls, so that
+//! path segments and query parameters are always escaped the same way.
+
+use super::{ApiVersion, MigLocation};
+use std::fmt;
+
+/// The root of all Compute Engine API URLs.
+const BASE_URL: &str = "https://compute.googleapis.com/compute";
+
+/// An endpoint of the Compute Engine API, relative to a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// The zones of the project, `zones`.
+    Zones,
+    /// The aggregated list of a resource across all zones or regions, e.g.
+    /// `aggregated/instances`.
+    Aggregated(String),
+    /// A global collection of a resource, e.g. `global/snapshots`.
+    Global(String),
+    /// The collection of a resource in one zone, e.g. `zones/{zone}/instances`.
+    Zonal { zone: String, resource: String },
+    /// A machine type, `zones/{zone}/machineTypes/{name}`.
+    MachineType { zone: String, name: String },
+    /// An instance, `zones/{zone}/instances/{name}`.
+    Instance { zone: String, name: String },
+    /// A custom method of an instance, e.g. `zones/{zone}/instances/{name}/start`.
+    InstanceMethod {
+        zone: String,
+        name: String,
+        method: String,
+    },
+    /// The wait method of a zone operation, `zones/{zone}/operations/{name}/wait`.
+    OperationWait { zone: String, name: String },
+    /// The wait method of a region operation, `regions/{region}/operations/{name}/wait`.
+    RegionOperationWait { region: String, name: String },
+    /// The wait method of a global operation, `global/operations/{name}/wait`.
+    GlobalOperationWait { name: String },
+    /// A custom method of a managed instance group, e.g.
+    /// `zones/{zone}/instanceGroupManagers/{name}/listManagedInstances`, or the same under
+    /// `regions/{region}` for regional groups.
+    GroupManagerMethod {
+        location: MigLocation,
+        name: String,
+        method: String,
+    },
+}
+
+impl Endpoint {
+    /// Returns the unescaped path segments of the endpoint.
+    fn segments(&self) -> Vec<&str> {
+        match self {
+            Endpoint::Zones => vec!["zones"],
+            Endpoint::Aggregated(resource) => vec!["aggregated", resource],
+            Endpoint::Global(resource) => vec!["global", resource],
+            Endpoint::Zonal { zone, resource } => vec!["zones", zone, resource],
+            Endpoint::MachineType { zone, name } => vec!["zones", zone, "machineTypes", name],
+            Endpoint::Instance { zone, name } => vec!["zones", zone, "instances", name],
+            Endpoint::InstanceMethod { zone, name, method } => {
+                vec!["zones", zone, "instances", name, method]
+            }
+            Endpoint::OperationWait { zone, name } => {
+                vec!["zones", zone, "operations", name, "wait"]
+            }
+            Endpoint::RegionOperationWait { region, name } => {
+                vec!["regions", region, "operations", name, "wait"]
+            }
+            Endpoint::GlobalOperationWait { name } => vec!["global", "operations", name, "wait"],
+            Endpoint::GroupManagerMethod {
+                location,
+                name,
+                method,
+            } => {
+                let (scope, location) = match location {
+                    MigLocation::Zone(zone) => ("zones", zone),
+                    MigLocation::Region(region) => ("regions", region),
+                };
+                vec![scope, location, "instanceGroupManagers", name, method]
+            }
+        }
+    }
+
+    /// Recognises an endpoint from its unescaped path segments.
+    fn from_segments(segments: &[String]) -> Option<Self> {
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        let owned = |s: &str| s.to_string();
+        Some(match segments.as_slice() {
+            ["zones"] => Endpoint::Zones,
+            ["aggregated", resource] => Endpoint::Aggregated(owned(resource)),
+            ["global", resource] => Endpoint::Global(owned(resource)),
+            ["zones", zone, resource] => Endpoint::Zonal {
+                zone: owned(zone),
+                resource: owned(resource),
+            },
+            ["zones", zone, "machineTypes", name] => Endpoint::MachineType {
+                zone: owned(zone),
+                name: owned(name),
+            },
+            ["zones", zone, "instances", name] => Endpoint::Instance {
+                zone: owned(zone),
+                name: owned(name),
+            },
+            ["zones", zone, "instances", name, method] => Endpoint::InstanceMethod {
+                zone: owned(zone),
+                name: owned(name),
+                method: owned(method),
+            },
+            ["zones", zone, "operations", name, "wait"] => Endpoint::OperationWait {
+                zone: owned(zone),
+                name: owned(name),
+            },
+            ["regions", region, "operations", name, "wait"] => Endpoint::RegionOperationWait {
+                region: owned(region),
+                name: owned(name),
+            },
+            ["global", "operations", name, "wait"] => {
+                Endpoint::GlobalOperationWait { name: owned(name) }
+            }
+            ["zones", zone, "instanceGroupManagers", name, method] => {
+                Endpoint::GroupManagerMethod {
+                    location: MigLocation::Zone(owned(zone)),
+                    name: owned(name),
+                    method: owned(method),
+                }
+            }
+            ["regions", region, "instanceGroupManagers", name, method] => {
+                Endpoint::GroupManagerMethod {
+                    location: MigLocation::Region(owned(region)),
+                    name: owned(name),
+                    method: owned(method),
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// A URL of the Compute Engine API.
+///
+/// Displaying it gives the URL to request, e.g.
+/// `https://compute.googleapis.com/compute/v1/projects/my-project/zones?maxResults=1`.
+/// Path segments and query parameters are percent-encoded, except that segments consisting
+/// only of dots can't be represented, as URL parsers resolve them. Compute Engine names never
+/// are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComputeUrl {
+    /// The API version to call.
+    pub api_version: ApiVersion,
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The endpoint within the project.
+    pub endpoint: Endpoint,
+    /// The unescaped query parameters, in order.
+    pub params: Vec<(String, String)>,
+}
+
+impl ComputeUrl {
+    /// Creates a URL without query parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_version` - The API version to call.
+    /// * `project` - The Google Cloud project ID.
+    /// * `endpoint` - The endpoint within the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The URL.
+    pub fn new(api_version: ApiVersion, project: &str, endpoint: Endpoint) -> Self {
+        ComputeUrl {
+            api_version,
+            project: project.to_string(),
+            endpoint,
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds a query parameter. The value is escaped when the URL is displayed.
+    pub fn param(mut self, key: &str, value: impl ToString) -> Self {
+        self.params.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds a query parameter if it has a value.
+    pub fn optional_param(self, key: &str, value: Option<impl ToString>) -> Self {
+        match value {
+            Some(value) => self.param(key, value),
+            None => self,
+        }
+    }
+
+    /// Parses a URL as displayed by `ComputeUrl`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ComputeUrl)` - The parsed URL.
+    /// * `Err(String)` - A description of why the URL isn't a known Compute Engine endpoint.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix(BASE_URL)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .ok_or_else(|| format!("Not a Compute Engine API URL: {}", url))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let decode = |s: &str| {
+            urlencoding::decode(s)
+                .map(|s| s.into_owned())
+                .map_err(|e| format!("Invalid escape in {}: {}", url, e))
+        };
+
+        let segments = path.split('/').map(decode).collect::<Result<Vec<_>, _>>()?;
+        let (api_version, project, endpoint) = match segments.as_slice() {
+            [version, projects, project, endpoint @ ..] if projects == "projects" => {
+                (version, project, endpoint)
+            }
+            _ => return Err(format!("Not a project URL: {}", url)),
+        };
+        let api_version = match api_version.as_str() {
+            "v1" => ApiVersion::V1,
+            "beta" => ApiVersion::Beta,
+            other => return Err(format!("Unknown API version {} in {}", other, url)),
+        };
+        let endpoint = Endpoint::from_segments(endpoint)
+            .ok_or_else(|| format!("Unknown endpoint: {}", url))?;
+
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (key, value) = param.split_once('=').unwrap_or((param, ""));
+                Ok((decode(key)?, decode(value)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(ComputeUrl {
+            api_version,
+            project: project.clone(),
+            endpoint,
+            params,
+        })
+    }
+}
+
+impl fmt::Display for ComputeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/projects/{}",
+            BASE_URL,
+            self.api_version.as_str(),
+            urlencoding::encode(&self.project)
+        )?;
+        for segment in self.endpoint.segments() {
+            write!(f, "/{}", urlencoding::encode(segment))?;
+        }
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            write!(
+                f,
+                "{}{}={}",
+                separator,
+                urlencoding::encode(key),
+                urlencoding::encode(value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::SplitMix64;
+
+    #[test]
+    fn test_urls() {
+        let url = |endpoint| ComputeUrl::new(ApiVersion::V1, "my-project", endpoint).to_string();
+        let base = "https://compute.googleapis.com/compute/v1/projects/my-project";
+
+        assert_eq!(url(Endpoint::Zones), format!("{}/zones", base));
+        assert_eq!(
+            url(Endpoint::Aggregated("instances".to_string())),
+            format!("{}/aggregated/instances", base)
+        );
+        assert_eq!(
+            url(Endpoint::Global("snapshots".to_string())),
+            format!("{}/global/snapshots", base)
+        );
+        assert_eq!(
+            url(Endpoint::Zonal {
+                zone: "us-east1-b".to_string(),
+                resource: "instances".to_string(),
+            }),
+            format!("{}/zones/us-east1-b/instances", base)
+        );
+        assert_eq!(
+            url(Endpoint::InstanceMethod {
+                zone: "us-east1-b".to_string(),
+                name: "db-1".to_string(),
+                method: "start".to_string(),
+            }),
+            format!("{}/zones/us-east1-b/instances/db-1/start", base)
+        );
+        assert_eq!(
+            url(Endpoint::OperationWait {
+                zone: "us-east1-b".to_string(),
+                name: "operation-1".to_string(),
+            }),
+            format!("{}/zones/us-east1-b/operations/operation-1/wait", base)
+        );
+        assert_eq!(
+            url(Endpoint::RegionOperationWait {
+                region: "us-east1".to_string(),
+                name: "operation-2".to_string(),
+            }),
+            format!("{}/regions/us-east1/operations/operation-2/wait", base)
+        );
+        assert_eq!(
+            url(Endpoint::GlobalOperationWait {
+                name: "operation-3".to_string(),
+            }),
+            format!("{}/global/operations/operation-3/wait", base)
+        );
+        assert_eq!(
+            url(Endpoint::GroupManagerMethod {
+                location: MigLocation::Region("us-east1".to_string()),
+                name: "web".to_string(),
+                method: "listManagedInstances".to_string(),
+            }),
+            format!(
+                "{}/regions/us-east1/instanceGroupManagers/web/listManagedInstances",
+                base
+            )
+        );
+    }
+
+    #[test]
+    fn test_params_are_escaped() {
+        let url = ComputeUrl::new(
+            ApiVersion::Beta,
+            "my-project",
+            Endpoint::Aggregated("instances".to_string()),
+        )
+        .param("filter", "name = \"db-*\" AND labels.env=prd")
+        .optional_param("pageToken", None::<&str>)
+        .param("maxResults", 10);
+
+        assert_eq!(
+            url.to_string(),
+            "https://compute.googleapis.com/compute/beta/projects/my-project/aggregated/instances\
+             ?filter=name%20%3D%20%22db-%2A%22%20AND%20labels.env%3Dprd&maxResults=10"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_other_urls() {
+        assert!(ComputeUrl::parse("https://example.com/compute/v1/projects/p/zones").is_err());
+        assert!(
+            ComputeUrl::parse("https://compute.googleapis.com/compute/v2/projects/p/zones")
+                .is_err()
+        );
+        assert!(
+            ComputeUrl::parse("https://compute.googleapis.com/compute/v1/projects/p/regions")
+                .is_err()
+        );
+    }
+
+    /// Returns a random string starting with a letter, as Compute Engine names do, followed by
+    /// characters that need escaping in URLs.
+    fn random_string(rng: &mut SplitMix64) -> String {
+        const FIRST: &[char] = &['a', 'q', 'z', 'D', 'é'];
+        const REST: &[char] = &[
+            'a', 'z', '0', '9', '-', '_', '.', '~', ' ', '/', '?', '#', '&', '=', '%', '+', '"',
+            '*', ':', '@', 'é', '日',
+        ];
+        let mut s = FIRST[rng.below(FIRST.len())].to_string();
+        for _ in 0..rng.below(12) {
+            s.push(REST[rng.below(REST.len())]);
+        }
+        s
+    }
+
+    fn random_url(rng: &mut SplitMix64) -> ComputeUrl {
+        let api_version = [ApiVersion::V1, ApiVersion::Beta][rng.below(2)];
+        let project = random_string(rng);
+        let endpoint = match rng.below(11) {
+            0 => Endpoint::Zones,
+            1 => Endpoint::Aggregated(random_string(rng)),
+            6 => Endpoint::Global(random_string(rng)),
+            7 => Endpoint::GroupManagerMethod {
+                location: match rng.below(2) {
+                    0 => MigLocation::Zone(random_string(rng)),
+                    _ => MigLocation::Region(random_string(rng)),
+                },
+                name: random_string(rng),
+                method: random_string(rng),
+            },
+            2 => Endpoint::MachineType {
+                zone: random_string(rng),
+                name: random_string(rng),
+            },
+            3 => Endpoint::Instance {
+                zone: random_string(rng),
+                name: random_string(rng),
+            },
+            4 => Endpoint::InstanceMethod {
+                zone: random_string(rng),
+                name: random_string(rng),
+                method: random_string(rng),
+            },
+            8 => Endpoint::RegionOperationWait {
+                region: random_string(rng),
+                name: random_string(rng),
+            },
+            9 => Endpoint::GlobalOperationWait {
+                name: random_string(rng),
+            },
+            10 => Endpoint::Zonal {
+                zone: random_string(rng),
+                resource: random_string(rng),
+            },
+            _ => Endpoint::OperationWait {
+                zone: random_string(rng),
+                name: random_string(rng),
+            },
+        };
+        let mut url = ComputeUrl::new(api_version, &project, endpoint);
+        for _ in 0..rng.below(4) {
+            url = url.param(&random_string(rng), random_string(rng));
+        }
+        url
+    }
+
+    #[test]
+    fn test_random_urls_parse_and_round_trip() {
+        let mut rng = SplitMix64(42);
+        for _ in 0..1000 {
+            let url = random_url(&mut rng);
+            let displayed = url.to_string();
+
+            // A standard URL parser agrees on the host, path and query
+            let parsed = reqwest::Url::parse(&displayed).unwrap();
+            assert_eq!(parsed.host_str(), Some("compute.googleapis.com"));
+            let segments = parsed
+                .path_segments()
+                .unwrap()
+                .map(|s| urlencoding::decode(s).unwrap().into_owned())
+                .collect::<Vec<_>>();
+            let mut expected = vec![
+                "compute".to_string(),
+                url.api_version.as_str().to_string(),
+                "projects".to_string(),
+                url.project.clone(),
+            ];
+            expected.extend(url.endpoint.segments().into_iter().map(str::to_string));
+            assert_eq!(segments, expected, "{}", displayed);
+            let params = parsed
+                .query_pairs()
+                .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                .collect::<Vec<_>>();
+            assert_eq!(params, url.params, "{}", displayed);
+
+            // And the URL round-trips
+            assert_eq!(ComputeUrl::parse(&displayed), Ok(url));
+        }
+    }
+}
@@ -1,13 +1,37 @@
 //! This module defines the configuration structures used by the application.
 //! These structures are used to deserialize configuration data from a TOML file.
 
+use crate::cache::CacheConfig;
+use crate::compute::ApiVersion;
+use crate::http::{HttpOptions, RetryPolicy, Transport};
+use crate::paths::CONFIG_PATH_VAR;
+use crate::source::Source;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 /// Represents the configuration for a single habitat (environment).
 #[derive(Debug, Deserialize)]
 pub struct Habitat {
     /// The Google Cloud project ID associated with this habitat.
     pub project: String,
+    /// The Compute Engine API version to use for this habitat. Defaults to `v1`.
+    pub api_version: Option<ApiVersion>,
+    /// How to reach the API from this machine. Defaults to connecting directly.
+    #[serde(default)]
+    pub transport: Transport,
+    /// Where instances are listed from. Defaults to the Compute Engine API.
+    #[serde(default)]
+    pub source: Source,
+    /// The email address of a service account to impersonate for this habitat, for users
+    /// who may not access the project with their own credentials.
+    pub impersonate_service_account: Option<String>,
+    /// Whether starting, stopping and resetting instances must be confirmed, interactively or
+    /// with `--yes`. Defaults to `true` for `prd` and `false` otherwise.
+    pub confirm: Option<bool>,
+    /// How many requests per second may be sent to the project. Overrides
+    /// `http.requests_per_second`.
+    pub requests_per_second: Option<f64>,
 }
 
 /// Represents the overall configuration structure read from the config file.
@@ -19,4 +43,384 @@ pub struct FileConfig {
     pub stg: Habitat,
     /// Configuration for the production environment.
     pub prd: Habitat,
+    /// Output rendering options.
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Options for the `ssh` subcommand.
+    #[serde(default)]
+    pub ssh: SshConfig,
+    /// The bastion hosts ssh connections jump through, keyed by cell or region, e.g.
+    /// `us-east1 = "bastion-e1.corp"`.
+    #[serde(default)]
+    pub bastions: BTreeMap<String, String>,
+    /// Authentication options.
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// How API requests that fail transiently are retried.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// HTTP timeouts and proxy.
+    #[serde(default)]
+    pub http: HttpOptions,
+    /// How long instance listings are cached.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// How many resources to request per page when listing, up to 500. Defaults to the API's
+    /// own page size of 500.
+    pub page_size: Option<u32>,
+    /// Whether a listing fails on an instance that can't be parsed, rather than skipping it
+    /// with a warning.
+    #[serde(default)]
+    pub strict: bool,
+    /// How instances are listed: `aggregated`, the default, or `per-zone` for very large
+    /// projects.
+    #[serde(default)]
+    pub list_strategy: crate::compute::ListStrategy,
+    /// How many projects are listed at once by commands spanning several, e.g. `bcls all`,
+    /// and how many zones at once with the `per-zone` list strategy. Defaults to 4.
+    pub jobs: Option<usize>,
+    /// How many instances a command may act on before it needs `--i-know-what-im-doing`.
+    #[serde(default = "default_max_results_warning")]
+    pub max_results_warning: usize,
+    /// Custom subcommands, keyed by name.
+    #[serde(default)]
+    pub commands: BTreeMap<String, CustomCommand>,
+    /// Where to record a summary of each API request, set from `--trace-file` rather than the
+    /// config file.
+    #[serde(skip)]
+    pub trace: Option<std::sync::Arc<crate::http::TraceLog>>,
+}
+
+/// Returns the default for `FileConfig::max_results_warning`.
+fn default_max_results_warning() -> usize {
+    crate::query::DEFAULT_MAX_RESULTS_WARNING
+}
+
+/// Identifies one of the configured habitats (environments).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    /// The integration environment.
+    Int,
+    /// The staging environment.
+    Stg,
+    /// The production environment.
+    Prd,
+}
+
+impl Environment {
+    /// Returns the name of the environment as used on the command line, e.g. `prd`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Int => "int",
+            Environment::Stg => "stg",
+            Environment::Prd => "prd",
+        }
+    }
+}
+
+impl FileConfig {
+    /// Returns the configuration for the given habitat.
+    pub fn habitat(&self, env: Environment) -> &Habitat {
+        match env {
+            Environment::Int => &self.int,
+            Environment::Stg => &self.stg,
+            Environment::Prd => &self.prd,
+        }
+    }
+
+    /// Returns whether changes to the instances of a habitat must be confirmed, as set by its
+    /// `confirm` option.
+    pub fn requires_confirmation(&self, env: Environment) -> bool {
+        self.habitat(env).confirm.unwrap_or(env == Environment::Prd)
+    }
+}
+
+/// The prefix of environment variables that override config file settings.
+const ENV_PREFIX: &str = "BCLS_";
+
+/// Translates `BCLS_*` environment variables into the config keys they override, so that CI
+/// jobs can configure bcls without writing a config file.
+///
+/// * `BCLS_INT_PROJECT`, `BCLS_PRD_API_VERSION` etc. set a key of a habitat.
+/// * `BCLS_PAGE_SIZE` etc. set a top-level key.
+/// * A double underscore separates the parts of any other key, e.g. `BCLS_OUTPUT__TIMEZONE`
+///   sets `timezone` in `[output]`.
+///
+/// `BCLS_CONFIG` and empty variables are skipped.
+///
+/// # Arguments
+///
+/// * `vars` - The environment variables, e.g. `std::env::vars()`.
+///
+/// # Returns
+///
+/// The dotted config keys, e.g. `int.project`, with the values of the variables.
+pub fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(name, value)| name != CONFIG_PATH_VAR && !value.is_empty())
+        .filter_map(|(name, value)| {
+            let name = name.strip_prefix(ENV_PREFIX)?.to_lowercase();
+            let key = if name.contains("__") {
+                name.replace("__", ".")
+            } else {
+                match name.split_once('_') {
+                    Some((env @ ("int" | "stg" | "prd"), key)) => format!("{}.{}", env, key),
+                    _ => name,
+                }
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Represents the `[ssh]` section of the config file.
+///
+/// The `proxy_jump` and `identity_file` settings, and the `user` in generated ssh config
+/// blocks, are templates: `{name}`, `{zone}`, `{region}`, `{cell}`, `{env}` and
+/// `{labels.KEY}` are replaced with the values of each instance.
+///
+/// ```toml
+/// [ssh]
+/// user = "jdoe"
+/// options = ["-o", "StrictHostKeyChecking=no"]
+/// proxy_jump = "bastion-{region}.corp"
+/// identity_file = "~/.ssh/{env}_ed25519"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct SshConfig {
+    /// The remote user to log in as. Defaults to ssh's own default.
+    pub user: Option<String>,
+    /// Extra options passed to `ssh` before the destination.
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// The `ProxyJump` host of generated ssh config blocks, e.g. a bastion.
+    pub proxy_jump: Option<String>,
+    /// The `IdentityFile` of generated ssh config blocks.
+    pub identity_file: Option<String>,
+}
+
+/// Represents the `[auth]` section of the config file.
+///
+/// ```toml
+/// [auth]
+/// method = "auto"
+/// service_account_key = "/etc/bcls/ci-key.json"
+/// plaintext_token_cache = false
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuthConfig {
+    /// How to obtain access tokens. Defaults to `auto`.
+    #[serde(default)]
+    pub method: AuthMethod,
+    /// Path to a service account JSON key file. With the `auto` method, tokens are obtained
+    /// with the key when it is set.
+    pub service_account_key: Option<PathBuf>,
+    /// Whether tokens may be cached in plain files under `~/.bcls/token-cache` when the OS
+    /// credential store is unavailable. Defaults to `false`.
+    #[serde(default)]
+    pub plaintext_token_cache: bool,
+}
+
+/// The ways bcls can obtain access tokens.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    /// Use the service account key if configured, otherwise try the GCE metadata server and
+    /// fall back to `gcloud`.
+    #[default]
+    Auto,
+    /// Run `gcloud auth application-default print-access-token`.
+    Gcloud,
+    /// Ask the GCE metadata server, when running on a GCE VM or in Cloud Shell.
+    Metadata,
+    /// Exchange a signed assertion for the key in `service_account_key`.
+    ServiceAccount,
+}
+
+/// A custom subcommand defined in a `[commands.NAME]` section of the config file, which runs
+/// an environment listing with saved options as `bcls NAME`.
+///
+/// ```toml
+/// [commands.storelb]
+/// description = "The store load balancers with their cells"
+/// env = "prd"
+/// pattern = "store-lb"
+/// columns = ["name", "ip", "cell"]
+/// format = "table"
+/// args = ["--sort", "cell"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomCommand {
+    /// What the command shows, listed by `bcls commands`.
+    pub description: Option<String>,
+    /// The environment to list.
+    pub env: Environment,
+    /// The search pattern to match against instance names.
+    pub pattern: Option<String>,
+    /// The columns to show, as for `--columns`.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// The output format, as for `--output`.
+    pub format: Option<String>,
+    /// Any other options of the environment listing, e.g. `["--label", "team=payments"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl CustomCommand {
+    /// Builds the command line the custom command stands for, apart from the pattern, which
+    /// only applies if none is given on the command line.
+    ///
+    /// # Arguments
+    ///
+    /// * `extra` - The arguments given after the custom command's name. They come after the
+    ///   saved options, so they can add to them.
+    ///
+    /// # Returns
+    ///
+    /// The arguments after the program name, starting with the environment, e.g.
+    /// `["prd", "--columns", "name,ip", "--reverse"]`.
+    pub fn command_line(&self, extra: &[String]) -> Vec<String> {
+        let mut args = vec![self.env.as_str().to_string()];
+        if !self.columns.is_empty() {
+            args.extend(["--columns".to_string(), self.columns.join(",")]);
+        }
+        if let Some(format) = &self.format {
+            args.extend(["--output".to_string(), format.clone()]);
+        }
+        args.extend(self.args.iter().cloned());
+        args.extend(extra.iter().cloned());
+        args
+    }
+}
+
+/// Represents the `[output]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct OutputConfig {
+    /// How missing values are rendered in each output format.
+    #[serde(default)]
+    pub null: NullValues,
+    /// The domain appended to instance names in `hosts` output, e.g. `corp.example`.
+    pub hosts_domain: Option<String>,
+    /// When tables are colored, e.g. `never`. Defaults to `auto`; `--color` overrides it.
+    #[serde(default)]
+    pub color: crate::output::ColorChoice,
+    /// Never truncate tables to the width of the terminal; `--wide` sets it for one command.
+    #[serde(default)]
+    pub wide: bool,
+    /// Leave out the header row of tables; `--no-header` sets it for one command.
+    #[serde(default)]
+    pub no_header: bool,
+}
+
+/// The placeholders used for missing values (e.g. an instance without labels), per output format.
+///
+/// ```toml
+/// [output.null]
+/// table = "-"
+/// csv = ""
+/// json = "n/a"  # omit to emit JSON `null`
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NullValues {
+    /// Placeholder for table output. Defaults to `-`.
+    pub table: String,
+    /// Placeholder string for JSON output. Defaults to `None`, which emits a JSON `null`.
+    pub json: Option<String>,
+    /// Placeholder for CSV output. Defaults to an empty field.
+    pub csv: String,
+}
+
+impl Default for NullValues {
+    fn default() -> Self {
+        NullValues {
+            table: "-".to_string(),
+            json: None,
+            csv: String::new(),
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storelb() -> CustomCommand {
+        CustomCommand {
+            description: None,
+            env: Environment::Prd,
+            pattern: Some("store-lb".to_string()),
+            columns: vec!["name".to_string(), "ip".to_string()],
+            format: Some("csv".to_string()),
+            args: vec!["--sort".to_string(), "cell".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_requires_confirmation() {
+        let config = ::config::Config::builder()
+            .add_source(::config::File::from_str(
+                "[int]\nproject = \"a-int\"\n\
+                 [stg]\nproject = \"a-stg\"\nconfirm = true\n\
+                 [prd]\nproject = \"a-prd\"\n",
+                ::config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<FileConfig>()
+            .unwrap();
+
+        assert!(!config.requires_confirmation(Environment::Int));
+        assert!(config.requires_confirmation(Environment::Stg));
+        // Production is guarded unless that is switched off
+        assert!(config.requires_confirmation(Environment::Prd));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = [
+            ("BCLS_PRD_PROJECT", "acme-prd"),
+            ("BCLS_INT_API_VERSION", "beta"),
+            ("BCLS_PAGE_SIZE", "100"),
+            ("BCLS_OUTPUT__TIMEZONE", "utc"),
+            ("BCLS_CONFIG", "/etc/bcls.toml"),
+            ("BCLS_STG_PROJECT", ""),
+            ("HOME", "/root"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert_eq!(
+            env_overrides(vars),
+            BTreeMap::from([
+                ("int.api_version".to_string(), "beta".to_string()),
+                ("output.timezone".to_string(), "utc".to_string()),
+                ("page_size".to_string(), "100".to_string()),
+                ("prd.project".to_string(), "acme-prd".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_custom_command_line() {
+        assert_eq!(
+            storelb().command_line(&[]),
+            [
+                "prd",
+                "--columns",
+                "name,ip",
+                "--output",
+                "csv",
+                "--sort",
+                "cell"
+            ]
+        );
+        assert_eq!(
+            storelb().command_line(&["--reverse".to_string(), "store-lb-eu".to_string()])[5..],
+            ["--sort", "cell", "--reverse", "store-lb-eu"]
+        );
+    }
 }
@@ -0,0 +1,207 @@
+//! This module provides a client for the Google Kubernetes Engine API, listing clusters and
+//! their node pools the way the `compute` module lists instances.
+
+mod clusters;
+
+use crate::compute::TokenSource;
+use crate::http;
+
+pub use clusters::{Cluster, NodePool, NodePoolAutoscaling};
+
+/// The root of all GKE API URLs.
+const BASE_URL: &str = "https://container.googleapis.com/v1";
+
+/// Configuration for the `Container` service.
+pub struct ContainerConfig<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The HTTP client implementation.
+    pub client: H,
+    /// The token source.
+    pub token_source: T,
+}
+
+/// The clusters of a project, and the zones whose clusters couldn't be listed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterList {
+    /// The clusters that were listed.
+    pub clusters: Vec<Cluster>,
+    /// The zones the API couldn't reach, e.g. during an outage. Clusters there are missing.
+    pub missing_zones: Vec<String>,
+}
+
+/// A client for the Google Kubernetes Engine API of a project.
+pub struct Container<H: http::HttpClient, T: TokenSource> {
+    config: ContainerConfig<H, T>,
+}
+
+impl<H: http::HttpClient, T: TokenSource> Container<H, T> {
+    /// Creates a new `Container` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the `Container` service.
+    pub fn new(config: ContainerConfig<H, T>) -> Self {
+        Self { config }
+    }
+
+    /// Returns the URL of a path below the project, escaping each segment.
+    fn url(&self, segments: &[&str]) -> String {
+        let mut url = format!(
+            "{}/projects/{}",
+            BASE_URL,
+            urlencoding::encode(&self.config.project)
+        );
+        for segment in segments {
+            url.push('/');
+            url.push_str(&urlencoding::encode(segment));
+        }
+        url
+    }
+
+    /// Lists the clusters in all zones and regions of the project, with their node pools.
+    /// The API returns them all at once; it isn't paginated.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ClusterList)` - The clusters, and the zones that couldn't be listed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_clusters(&self) -> Result<ClusterList, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/kubernetes-engine/docs/reference/rest/v1/projects.locations.clusters/list>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let resp = self
+            .config
+            .client
+            .get(&token, &self.url(&["locations", "-", "clusters"]))?;
+        let clusters = resp["clusters"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|json| Cluster::try_from(json.clone()))
+            .collect::<Result<_, _>>()?;
+        let missing_zones = resp["missingZones"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|zone| zone.as_str())
+            .map(str::to_string)
+            .collect();
+        Ok(ClusterList {
+            clusters,
+            missing_zones,
+        })
+    }
+
+    /// Lists the node pools of a cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - The zone or region of the cluster.
+    /// * `cluster` - The name of the cluster.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<NodePool>)` - The node pools of the cluster.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_node_pools(
+        &self,
+        location: &str,
+        cluster: &str,
+    ) -> Result<Vec<NodePool>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/kubernetes-engine/docs/reference/rest/v1/projects.locations.clusters.nodePools/list>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = self.url(&["locations", location, "clusters", cluster, "nodePools"]);
+        let resp = self.config.client.get(&token, &url)?;
+        resp["nodePools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|json| NodePool::try_from(json.clone()))
+            .collect()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::MockHttpClient;
+    use mockall::predicate;
+    use serde_json::json;
+
+    fn container(mock_http: MockHttpClient) -> Container<MockHttpClient, MockTokenSource> {
+        Container::new(ContainerConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+        })
+    }
+
+    #[test]
+    fn test_list_clusters() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .with(
+                predicate::eq("mock_token"),
+                predicate::eq(
+                    "https://container.googleapis.com/v1/projects/test-project/locations/-/clusters",
+                ),
+            )
+            .times(1)
+            .returning(|_, _| {
+                Ok(json!({
+                    "clusters": [
+                        {"name": "apps", "location": "us-east1", "status": "RUNNING"},
+                        {"name": "batch", "location": "us-east1-b", "status": "PROVISIONING"}
+                    ],
+                    "missingZones": ["us-west1-a"]
+                }))
+            });
+
+        let list = container(mock_http).list_clusters().unwrap();
+
+        assert_eq!(list.clusters.len(), 2);
+        assert_eq!(list.clusters[1].location, "us-east1-b");
+        assert_eq!(list.missing_zones, vec!["us-west1-a"]);
+    }
+
+    #[test]
+    fn test_list_clusters_when_there_are_none() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http.expect_get().returning(|_, _| Ok(json!({})));
+
+        let list = container(mock_http).list_clusters().unwrap();
+
+        assert_eq!(list, ClusterList::default());
+    }
+
+    #[test]
+    fn test_list_node_pools() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .with(
+                predicate::always(),
+                predicate::eq(
+                    "https://container.googleapis.com/v1/projects/test-project/locations/us-east1/clusters/apps/nodePools",
+                ),
+            )
+            .times(1)
+            .returning(|_, _| {
+                Ok(json!({"nodePools": [
+                    {"name": "default", "config": {"machineType": "e2-standard-4"}}
+                ]}))
+            });
+
+        let pools = container(mock_http)
+            .list_node_pools("us-east1", "apps")
+            .unwrap();
+
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].machine_type, "e2-standard-4");
+    }
+}
@@ -0,0 +1,203 @@
+//! This module defines the records of Google Kubernetes Engine: the `Cluster` and its
+//! `NodePool`s, with `TryFrom` implementations for creating them from JSON data.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a GKE cluster.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cluster {
+    /// The name of the cluster.
+    pub name: String,
+    /// The zone of a zonal cluster, or the region of a regional one.
+    pub location: String,
+    /// The Kubernetes version of the control plane, e.g. `1.29.4-gke.1043002`.
+    pub master_version: String,
+    /// The number of nodes in all node pools.
+    pub node_count: u64,
+    /// The status of the cluster, e.g. `RUNNING` or `RECONCILING`.
+    pub status: String,
+    /// The node pools of the cluster.
+    pub node_pools: Vec<NodePool>,
+}
+
+impl TryFrom<JsonValue> for Cluster {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `Cluster` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the cluster data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Cluster)` - The created `Cluster` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+
+        Ok(Cluster {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            location: str_field("location")
+                .ok_or("Missing or invalid 'location' field")?
+                .to_string(),
+            master_version: str_field("currentMasterVersion")
+                .unwrap_or_default()
+                .to_string(),
+            node_count: json
+                .get("currentNodeCount")
+                .and_then(JsonValue::as_u64)
+                .unwrap_or(0),
+            status: str_field("status").unwrap_or_default().to_string(),
+            node_pools: json
+                .get("nodePools")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .map(|pool| NodePool::try_from(pool.clone()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// The autoscaling limits of a node pool, per zone the pool runs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodePoolAutoscaling {
+    /// The minimum number of nodes.
+    pub min_node_count: u64,
+    /// The maximum number of nodes.
+    pub max_node_count: u64,
+}
+
+/// Represents a node pool of a GKE cluster.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodePool {
+    /// The name of the node pool.
+    pub name: String,
+    /// The machine type of the nodes, e.g. `e2-standard-4`.
+    pub machine_type: String,
+    /// The Kubernetes version of the nodes.
+    pub version: String,
+    /// The number of nodes the pool was created with, per zone.
+    pub initial_node_count: u64,
+    /// The autoscaling limits, if autoscaling is enabled.
+    pub autoscaling: Option<NodePoolAutoscaling>,
+    /// Whether the nodes are Spot or preemptible VMs.
+    pub spot: bool,
+    /// The status of the node pool, e.g. `RUNNING`.
+    pub status: String,
+}
+
+impl TryFrom<JsonValue> for NodePool {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `NodePool` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the node pool data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NodePool)` - The created `NodePool` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+        let bool_field = |pointer: &str| {
+            json.pointer(pointer)
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false)
+        };
+        let autoscaling = json.get("autoscaling");
+        // Disabled autoscaling may still carry the limits it had
+        let autoscaling = autoscaling
+            .filter(|_| bool_field("/autoscaling/enabled"))
+            .map(|autoscaling| {
+                let count = |key: &str| autoscaling.get(key).and_then(JsonValue::as_u64);
+                NodePoolAutoscaling {
+                    min_node_count: count("minNodeCount").unwrap_or(0),
+                    max_node_count: count("maxNodeCount").unwrap_or(0),
+                }
+            });
+
+        Ok(NodePool {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            machine_type: json
+                .pointer("/config/machineType")
+                .and_then(JsonValue::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            version: str_field("version").unwrap_or_default().to_string(),
+            initial_node_count: json
+                .get("initialNodeCount")
+                .and_then(JsonValue::as_u64)
+                .unwrap_or(0),
+            autoscaling,
+            spot: bool_field("/config/spot") || bool_field("/config/preemptible"),
+            status: str_field("status").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cluster_from_json() {
+        let json = json!({
+            "name": "apps",
+            "location": "us-east1",
+            "currentMasterVersion": "1.29.4-gke.1043002",
+            "currentNodeCount": 9,
+            "status": "RUNNING",
+            "nodePools": [
+                {
+                    "name": "default",
+                    "config": {"machineType": "e2-standard-4"},
+                    "version": "1.29.4-gke.1043002",
+                    "initialNodeCount": 1,
+                    "autoscaling": {"enabled": true, "minNodeCount": 1, "maxNodeCount": 5},
+                    "status": "RUNNING"
+                },
+                {
+                    "name": "batch",
+                    "config": {"machineType": "n2-highmem-8", "spot": true},
+                    "initialNodeCount": 2,
+                    "autoscaling": {"enabled": false, "minNodeCount": 1, "maxNodeCount": 3},
+                    "status": "RUNNING"
+                }
+            ]
+        });
+
+        let cluster = Cluster::try_from(json).unwrap();
+
+        assert_eq!(cluster.location, "us-east1");
+        assert_eq!(cluster.master_version, "1.29.4-gke.1043002");
+        assert_eq!(cluster.node_count, 9);
+        assert_eq!(cluster.node_pools.len(), 2);
+        assert_eq!(cluster.node_pools[0].machine_type, "e2-standard-4");
+        assert_eq!(
+            cluster.node_pools[0].autoscaling,
+            Some(NodePoolAutoscaling {
+                min_node_count: 1,
+                max_node_count: 5
+            })
+        );
+        assert!(!cluster.node_pools[0].spot);
+        assert_eq!(cluster.node_pools[1].autoscaling, None);
+        assert!(cluster.node_pools[1].spot);
+    }
+
+    #[test]
+    fn test_cluster_without_location_is_invalid() {
+        assert!(Cluster::try_from(json!({"name": "apps"})).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+//! This module compares inventory snapshots taken on different runs, for notifying about
+//! instances that were added, removed or changed in between.
+
+use crate::compute::Instance;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// The tracked state of a single instance.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstanceState {
+    /// The zone of the instance.
+    pub zone: String,
+    /// The machine type of the instance.
+    pub machine_type: String,
+    /// The status of the instance, e.g. `RUNNING`.
+    pub status: String,
+    /// The primary internal IP address.
+    pub ip: Option<String>,
+    /// The primary external IP address.
+    pub external_ip: Option<String>,
+    /// The cell the instance belongs to.
+    pub cell: Option<String>,
+    /// The labels of the instance.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl From<&Instance> for InstanceState {
+    fn from(instance: &Instance) -> Self {
+        InstanceState {
+            zone: instance.zone.clone(),
+            machine_type: instance.machine_type.clone(),
+            status: instance.status.clone(),
+            ip: instance.primary_ip().map(str::to_string),
+            external_ip: instance.external_ip().map(str::to_string),
+            cell: instance.cell.clone(),
+            labels: instance
+                .labels
+                .iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl InstanceState {
+    /// Returns the tracked fields as name-value pairs, for comparing and reporting.
+    fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("zone".to_string(), self.zone.clone()),
+            ("machine_type".to_string(), self.machine_type.clone()),
+            ("status".to_string(), self.status.clone()),
+            ("ip".to_string(), self.ip.clone().unwrap_or_default()),
+            (
+                "external_ip".to_string(),
+                self.external_ip.clone().unwrap_or_default(),
+            ),
+            ("cell".to_string(), self.cell.clone().unwrap_or_default()),
+        ];
+        fields.extend(
+            self.labels
+                .iter()
+                .map(|(k, v)| (format!("labels.{}", k), v.clone())),
+        );
+        fields
+    }
+}
+
+/// The state of every instance in a project at one point in time, keyed by `zone/name`, as
+/// instance names are only unique within a zone.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The instances, keyed by `zone/name`, e.g. `us-east1-b/db-1`.
+    pub instances: BTreeMap<String, InstanceState>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of a list of instances.
+    pub fn new(instances: &[Instance]) -> Self {
+        Snapshot {
+            instances: instances
+                .iter()
+                .map(|inst| (key(&inst.zone, &inst.name), InstanceState::from(inst)))
+                .collect(),
+        }
+    }
+
+    /// Keys the instances of a snapshot saved by older versions, which were keyed by name
+    /// alone, by `zone/name` like the others. Names can't contain `/`, so a key without one is
+    /// a name.
+    fn rekey(self) -> Self {
+        Snapshot {
+            instances: self
+                .instances
+                .into_iter()
+                .map(|(key_or_name, state)| match key_or_name.contains('/') {
+                    true => (key_or_name, state),
+                    false => (key(&state.zone, &key_or_name), state),
+                })
+                .collect(),
+        }
+    }
+
+    /// Loads a snapshot saved by `save`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Snapshot))` - The snapshot on success.
+    /// * `Ok(None)` - If there is no snapshot yet.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the snapshot can't be read or parsed.
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let snapshot = serde_json::from_str::<Snapshot>(&contents)
+                    .map_err(|e| format!("Invalid snapshot {}: {}", path.display(), e))?;
+                Ok(Some(snapshot.rekey()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    /// Saves the snapshot atomically, so an interrupted run never leaves a partial snapshot.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Returns the key of an instance in a snapshot, e.g. `us-east1-b/db-1`.
+fn key(zone: &str, name: &str) -> String {
+    format!("{}/{}", zone, name)
+}
+
+/// A change to a single field of an instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// The name of the field, e.g. `status` or `labels.team`.
+    pub field: String,
+    /// The previous value. Empty if the field was not set.
+    pub old: String,
+    /// The new value. Empty if the field is no longer set.
+    pub new: String,
+}
+
+/// The changed fields of an instance present in both snapshots.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstanceChange {
+    /// The instance, as `zone/name`.
+    pub name: String,
+    /// The fields that changed.
+    pub changes: Vec<FieldChange>,
+}
+
+/// The differences between two snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct InventoryDiff {
+    /// Instances only in the new snapshot, as `zone/name`.
+    pub added: Vec<String>,
+    /// Instances only in the old snapshot, as `zone/name`.
+    pub removed: Vec<String>,
+    /// Instances in both snapshots whose state changed.
+    pub changed: Vec<InstanceChange>,
+}
+
+impl InventoryDiff {
+    /// Whether the snapshots are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for InventoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+        for name in &self.added {
+            write!(f, "\n+ {}", name)?;
+        }
+        for name in &self.removed {
+            write!(f, "\n- {}", name)?;
+        }
+        for change in &self.changed {
+            let fields = change
+                .changes
+                .iter()
+                .map(|c| format!("{}: {} -> {}", c.field, or_dash(&c.old), or_dash(&c.new)))
+                .collect::<Vec<_>>();
+            write!(f, "\n~ {} ({})", change.name, fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows unset values as `-` in the summary.
+fn or_dash(value: &str) -> &str {
+    match value.is_empty() {
+        true => "-",
+        false => value,
+    }
+}
+
+/// Compares two snapshots.
+///
+/// # Arguments
+///
+/// * `old` - The earlier snapshot.
+/// * `new` - The later snapshot.
+///
+/// # Returns
+///
+/// The added, removed and changed instances, each sorted by zone and name.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> InventoryDiff {
+    let mut result = InventoryDiff::default();
+    for (name, state) in &new.instances {
+        match old.instances.get(name) {
+            None => result.added.push(name.clone()),
+            Some(previous) if previous != state => result.changed.push(InstanceChange {
+                name: name.clone(),
+                changes: field_changes(previous, state),
+            }),
+            Some(_) => {}
+        }
+    }
+    result.removed = old
+        .instances
+        .keys()
+        .filter(|name| !new.instances.contains_key(*name))
+        .cloned()
+        .collect();
+    result
+}
+
+/// Lists the fields that differ between two states of an instance.
+fn field_changes(old: &InstanceState, new: &InstanceState) -> Vec<FieldChange> {
+    let old_fields = old.fields().into_iter().collect::<BTreeMap<_, _>>();
+    let new_fields = new.fields().into_iter().collect::<BTreeMap<_, _>>();
+    let mut names = old_fields
+        .keys()
+        .chain(new_fields.keys())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|field| {
+            let old = old_fields.get(field).cloned().unwrap_or_default();
+            let new = new_fields.get(field).cloned().unwrap_or_default();
+            (old != new).then(|| FieldChange {
+                field: field.clone(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(status: &str) -> InstanceState {
+        InstanceState {
+            zone: "us-east1-b".to_string(),
+            machine_type: "n2-standard-4".to_string(),
+            status: status.to_string(),
+            ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(instances: Vec<(&str, InstanceState)>) -> Snapshot {
+        Snapshot {
+            instances: instances
+                .into_iter()
+                .map(|(name, state)| (key(&state.zone, name), state))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut labelled = state("RUNNING");
+        labelled
+            .labels
+            .insert("team".to_string(), "storage".to_string());
+        let old = snapshot(vec![
+            ("db", state("RUNNING")),
+            ("gone", state("RUNNING")),
+            ("same", state("RUNNING")),
+        ]);
+        let new = snapshot(vec![
+            ("db", labelled),
+            ("new", state("PROVISIONING")),
+            ("same", state("RUNNING")),
+        ]);
+
+        let result = diff(&old, &new);
+        assert_eq!(result.added, vec!["us-east1-b/new"]);
+        assert_eq!(result.removed, vec!["us-east1-b/gone"]);
+        assert_eq!(
+            result.changed,
+            vec![InstanceChange {
+                name: "us-east1-b/db".to_string(),
+                changes: vec![FieldChange {
+                    field: "labels.team".to_string(),
+                    old: String::new(),
+                    new: "storage".to_string(),
+                }],
+            }]
+        );
+        assert_eq!(
+            result.to_string(),
+            concat!(
+                "1 added, 1 removed, 1 changed\n+ us-east1-b/new\n- us-east1-b/gone\n",
+                "~ us-east1-b/db (labels.team: - -> storage)"
+            )
+        );
+        assert!(diff(&new, &new).is_empty());
+
+        // The JSON form is what `diff --json` prints and webhooks receive
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({
+                "added": ["us-east1-b/new"],
+                "removed": ["us-east1-b/gone"],
+                "changed": [{
+                    "name": "us-east1-b/db",
+                    "changes": [{"field": "labels.team", "old": "", "new": "storage"}]
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_same_name_in_two_zones() {
+        let in_zone = |zone: &str, status: &str| InstanceState {
+            zone: zone.to_string(),
+            ..state(status)
+        };
+        let instances = [
+            Instance {
+                name: "db".to_string(),
+                zone: "us-east1-b".to_string(),
+                status: "RUNNING".to_string(),
+                ..Default::default()
+            },
+            Instance {
+                name: "db".to_string(),
+                zone: "us-east1-c".to_string(),
+                status: "RUNNING".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(Snapshot::new(&instances).instances.len(), 2);
+
+        let old = snapshot(vec![
+            ("db", in_zone("us-east1-b", "RUNNING")),
+            ("db", in_zone("us-east1-c", "RUNNING")),
+        ]);
+        let new = snapshot(vec![
+            ("db", in_zone("us-east1-c", "TERMINATED")),
+            ("db", in_zone("us-east1-d", "RUNNING")),
+        ]);
+        let result = diff(&old, &new);
+        assert_eq!(result.added, vec!["us-east1-d/db"]);
+        assert_eq!(result.removed, vec!["us-east1-b/db"]);
+        assert_eq!(result.changed.len(), 1);
+        assert_eq!(result.changed[0].name, "us-east1-c/db");
+    }
+
+    #[test]
+    fn test_load_snapshot_keyed_by_name() {
+        let dir =
+            std::env::temp_dir().join(format!("bcls-test-{}-old-snapshot", std::process::id()));
+        let path = dir.join("p.json");
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = serde_json::json!({"instances": {"db": state("RUNNING")}});
+        std::fs::write(&path, old.to_string()).unwrap();
+
+        let loaded = Snapshot::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, snapshot(vec![("db", state("RUNNING"))]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = std::env::temp_dir().join(format!("bcls-test-{}-snapshot", std::process::id()));
+        let path = dir.join("p.json");
+        assert_eq!(Snapshot::load(&path).unwrap(), None);
+
+        let snapshot = snapshot(vec![("db", state("RUNNING"))]);
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), Some(snapshot));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
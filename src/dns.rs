@@ -0,0 +1,215 @@
+//! This module provides a client for the Cloud DNS API, listing managed zones and the record
+//! sets within them.
+
+mod records;
+
+use crate::compute::TokenSource;
+use crate::http;
+use serde_json::Value as JsonValue;
+
+pub use records::{ManagedZone, RecordSet};
+
+/// The root of all Cloud DNS API URLs.
+const BASE_URL: &str = "https://dns.googleapis.com/dns/v1";
+
+/// Configuration for the `Dns` service.
+pub struct DnsConfig<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The HTTP client implementation.
+    pub client: H,
+    /// The token source.
+    pub token_source: T,
+    /// The maximum number of results per page. `None` leaves it to the API.
+    pub page_size: Option<u32>,
+}
+
+/// A client for the Cloud DNS API of a project.
+pub struct Dns<H: http::HttpClient, T: TokenSource> {
+    config: DnsConfig<H, T>,
+}
+
+impl<H: http::HttpClient, T: TokenSource> Dns<H, T> {
+    /// Creates a new `Dns` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the `Dns` service.
+    pub fn new(config: DnsConfig<H, T>) -> Self {
+        Self { config }
+    }
+
+    /// Fetches every page of a collection below the project and parses its items into records.
+    ///
+    /// # Arguments
+    ///
+    /// * `segments` - The unescaped path segments of the collection, e.g. `["managedZones"]`.
+    /// * `key` - The key of the list in each page, e.g. `managedZones`.
+    /// * `parse` - Converts each item of the list into a record.
+    fn list_all<R>(
+        &self,
+        segments: &[&str],
+        key: &str,
+        parse: fn(&JsonValue) -> Result<R, Box<dyn std::error::Error>>,
+    ) -> Result<Vec<R>, Box<dyn std::error::Error>> {
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let mut path = format!(
+            "{}/projects/{}",
+            BASE_URL,
+            urlencoding::encode(&self.config.project)
+        );
+        for segment in segments {
+            path.push('/');
+            path.push_str(&urlencoding::encode(segment));
+        }
+        let mut records = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let params = self
+                .config
+                .page_size
+                .map(|size| ("maxResults", size.to_string()))
+                .into_iter()
+                .chain(page_token.map(|token| ("pageToken", token)))
+                .map(|(key, value)| format!("{}={}", key, urlencoding::encode(&value)))
+                .collect::<Vec<_>>();
+            let url = if params.is_empty() {
+                path.clone()
+            } else {
+                format!("{}?{}", path, params.join("&"))
+            };
+            let resp = self.config.client.get(&token, &url)?;
+            for item in resp[key].as_array().into_iter().flatten() {
+                records.push(parse(item)?);
+            }
+            match resp["nextPageToken"].as_str() {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(records),
+            }
+        }
+    }
+
+    /// Lists the managed zones of the project.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<ManagedZone>)` - The zones.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_managed_zones(&self) -> Result<Vec<ManagedZone>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/dns/docs/reference/rest/v1/managedZones/list>
+        self.list_all(&["managedZones"], "managedZones", |json| {
+            ManagedZone::try_from(json.clone())
+        })
+    }
+
+    /// Lists the record sets of a managed zone.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The name of the managed zone, not its DNS name.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<RecordSet>)` - The record sets.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn list_record_sets(
+        &self,
+        zone: &str,
+    ) -> Result<Vec<RecordSet>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/dns/docs/reference/rest/v1/resourceRecordSets/list>
+        self.list_all(&["managedZones", zone, "rrsets"], "rrsets", |json| {
+            RecordSet::try_from(json.clone())
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::MockHttpClient;
+    use serde_json::json;
+
+    fn dns(
+        mock_http: MockHttpClient,
+        page_size: Option<u32>,
+    ) -> Dns<MockHttpClient, MockTokenSource> {
+        Dns::new(DnsConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            page_size,
+        })
+    }
+
+    #[test]
+    fn test_list_managed_zones() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .withf(|_, url| {
+                url == "https://dns.googleapis.com/dns/v1/projects/test-project/managedZones"
+            })
+            .times(1)
+            .returning(|_, _| {
+                Ok(json!({"managedZones": [
+                    {"name": "internal", "dnsName": "corp.internal.", "visibility": "private"}
+                ]}))
+            });
+
+        let zones = dns(mock_http, None).list_managed_zones().unwrap();
+
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].dns_name, "corp.internal.");
+    }
+
+    #[test]
+    fn test_list_record_sets_follows_pages() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+
+        let base =
+            "https://dns.googleapis.com/dns/v1/projects/test-project/managedZones/internal/rrsets";
+        let first_url = format!("{}?maxResults=2", base);
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == first_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(json!({
+                    "rrsets": [
+                        {"name": "corp.internal.", "type": "SOA", "rrdatas": ["ns. admin. 1"]},
+                        {"name": "db.corp.internal.", "type": "A", "rrdatas": ["10.12.0.5"]}
+                    ],
+                    "nextPageToken": "page/2"
+                }))
+            });
+        let second_url = format!("{}?maxResults=2&pageToken=page%2F2", base);
+        mock_http
+            .expect_get()
+            .withf(move |_, url| url == second_url)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(json!({"rrsets": [
+                    {"name": "web.corp.internal.", "type": "CNAME", "rrdatas": ["lb.corp.internal."]}
+                ]}))
+            });
+
+        let record_sets = dns(mock_http, Some(2))
+            .list_record_sets("internal")
+            .unwrap();
+
+        assert_eq!(
+            record_sets
+                .iter()
+                .map(|record_set| record_set.record_type.as_str())
+                .collect::<Vec<_>>(),
+            ["SOA", "A", "CNAME"]
+        );
+    }
+}
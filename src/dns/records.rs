@@ -0,0 +1,163 @@
+//! This module defines the records of Cloud DNS: the `ManagedZone` and its `RecordSet`s, with
+//! `TryFrom` implementations for creating them from JSON data.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents a Cloud DNS managed zone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManagedZone {
+    /// The name of the zone, which identifies it in the API, e.g. `internal`.
+    pub name: String,
+    /// The DNS name the zone serves, with a trailing dot, e.g. `corp.internal.`.
+    pub dns_name: String,
+    /// Whether the zone is `public` or `private` to some networks.
+    pub visibility: String,
+    /// The description of the zone.
+    pub description: String,
+}
+
+impl TryFrom<JsonValue> for ManagedZone {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `ManagedZone` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the zone data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ManagedZone)` - The created `ManagedZone` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+
+        Ok(ManagedZone {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            dns_name: str_field("dnsName")
+                .ok_or("Missing or invalid 'dnsName' field")?
+                .to_string(),
+            visibility: str_field("visibility").unwrap_or("public").to_string(),
+            description: str_field("description").unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Represents the records of one name and type in a managed zone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordSet {
+    /// The DNS name, with a trailing dot, e.g. `db.corp.internal.`.
+    pub name: String,
+    /// The record type, e.g. `A` or `CNAME`.
+    pub record_type: String,
+    /// The time to live in seconds.
+    pub ttl: Option<u64>,
+    /// The data of each record, e.g. the IP addresses of an `A` record set.
+    pub rrdatas: Vec<String>,
+}
+
+impl RecordSet {
+    /// Checks whether the name of the record set contains a pattern, ignoring case and the
+    /// trailing dot of the name, so that `db.corp.internal` finds `db.corp.internal.`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to look for.
+    pub fn name_matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        let name = self.name.to_lowercase();
+        name.contains(&pattern) || name.trim_end_matches('.').contains(&pattern)
+    }
+}
+
+impl TryFrom<JsonValue> for RecordSet {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create a `RecordSet` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the record set data.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RecordSet)` - The created `RecordSet` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is invalid or missing required fields.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        let str_field = |key: &str| json.get(key).and_then(JsonValue::as_str);
+
+        Ok(RecordSet {
+            name: str_field("name")
+                .ok_or("Missing or invalid 'name' field")?
+                .to_string(),
+            record_type: str_field("type")
+                .ok_or("Missing or invalid 'type' field")?
+                .to_string(),
+            ttl: json.get("ttl").and_then(JsonValue::as_u64),
+            // Routing policies have no plain rrdatas; their records aren't shown
+            rrdatas: json
+                .get("rrdatas")
+                .and_then(JsonValue::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(JsonValue::as_str)
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_managed_zone_from_json() {
+        let json = json!({
+            "name": "internal",
+            "dnsName": "corp.internal.",
+            "visibility": "private",
+            "description": ""
+        });
+
+        let zone = ManagedZone::try_from(json).unwrap();
+
+        assert_eq!(zone.name, "internal");
+        assert_eq!(zone.dns_name, "corp.internal.");
+        assert_eq!(zone.visibility, "private");
+    }
+
+    #[test]
+    fn test_record_set_from_json() {
+        let json = json!({
+            "name": "db.corp.internal.",
+            "type": "A",
+            "ttl": 300,
+            "rrdatas": ["10.12.0.5", "10.12.0.6"]
+        });
+
+        let record_set = RecordSet::try_from(json).unwrap();
+
+        assert_eq!(record_set.record_type, "A");
+        assert_eq!(record_set.ttl, Some(300));
+        assert_eq!(record_set.rrdatas, vec!["10.12.0.5", "10.12.0.6"]);
+    }
+
+    #[test]
+    fn test_record_set_name_matches() {
+        let record_set = RecordSet {
+            name: "db.corp.internal.".to_string(),
+            ..Default::default()
+        };
+
+        assert!(record_set.name_matches("db.corp.internal"));
+        assert!(record_set.name_matches("db.corp.internal."));
+        assert!(record_set.name_matches("DB.Corp"));
+        assert!(!record_set.name_matches("web"));
+    }
+}
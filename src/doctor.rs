@@ -0,0 +1,166 @@
+//! This module provides the checks run by `bcls doctor --self` and renders their results as a
+//! checklist, so most "bcls doesn't work on my machine" problems can be diagnosed in one go.
+
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+
+/// The result of a single check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The check passed, with a detail such as the value found.
+    Pass(String),
+    /// The check failed, with the reason.
+    Fail(String),
+    /// Information that is neither good nor bad, such as terminal capabilities.
+    Info(String),
+}
+
+/// A named check and its outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked, e.g. `config` or `token (prd)`.
+    pub name: String,
+    /// The outcome.
+    pub outcome: Outcome,
+}
+
+impl Check {
+    /// Creates a check from a result: `Ok` passes with its detail, `Err` fails with the error.
+    pub fn from_result<E: std::fmt::Display>(name: &str, result: Result<String, E>) -> Self {
+        Check {
+            name: name.to_string(),
+            outcome: match result {
+                Ok(detail) => Outcome::Pass(detail),
+                Err(e) => Outcome::Fail(e.to_string()),
+            },
+        }
+    }
+
+    /// Creates an informational check.
+    pub fn info(name: &str, detail: String) -> Self {
+        Check {
+            name: name.to_string(),
+            outcome: Outcome::Info(detail),
+        }
+    }
+
+    /// Whether the check failed.
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Fail(_))
+    }
+}
+
+/// Renders checks as a checklist, one line per check.
+///
+/// # Arguments
+///
+/// * `checks` - The checks, in display order.
+/// * `color` - Whether to color passes green and failures red with ANSI escapes.
+pub fn render(checks: &[Check], color: bool) -> String {
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    checks
+        .iter()
+        .map(|check| {
+            let (mark, ansi, detail) = match &check.outcome {
+                Outcome::Pass(detail) => ("✓", "32", detail),
+                Outcome::Fail(detail) => ("✗", "31", detail),
+                Outcome::Info(detail) => ("-", "", detail),
+            };
+            let mark = match color && !ansi.is_empty() {
+                true => format!("\x1b[{}m{}\x1b[0m", ansi, mark),
+                false => mark.to_string(),
+            };
+            format!(
+                "{} {:width$}  {}\n",
+                mark,
+                check.name,
+                detail,
+                width = width
+            )
+        })
+        .collect()
+}
+
+/// Whether output to stdout should be colored: it is a terminal and `NO_COLOR` is not set.
+/// <https://no-color.org>
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+/// Computes the SHA-256 digest of a file, e.g. the running binary, as lowercase hex.
+pub fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Checks that files can be created in a directory, creating the directory if needed.
+pub fn check_writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".bcls-doctor-{}", std::process::id()));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+/// Describes what the terminal supports, for the checklist.
+pub fn terminal_capabilities() -> String {
+    let term = std::env::var("TERM").unwrap_or_else(|_| "unset".to_string());
+    let tty = match std::io::stdout().is_terminal() {
+        true => "stdout is a terminal",
+        false => "stdout is not a terminal",
+    };
+    let color = match use_color() {
+        true => "color",
+        false => "no color",
+    };
+    format!("{}, TERM={}, {}", tty, term, color)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let checks = vec![
+            Check::from_result::<String>("config", Ok("~/.bcls/config.toml".to_string())),
+            Check::from_result("token (prd)", Err("gcloud not found")),
+            Check::info("terminal", "no color".to_string()),
+        ];
+        assert!(checks[1].failed());
+        assert_eq!(
+            render(&checks, false),
+            "✓ config       ~/.bcls/config.toml\n\
+             ✗ token (prd)  gcloud not found\n\
+             - terminal     no color\n"
+        );
+        assert!(render(&checks, true).starts_with("\x1b[32m✓\x1b[0m config"));
+    }
+
+    #[test]
+    fn test_file_sha256_and_check_writable() {
+        let dir = std::env::temp_dir().join(format!("bcls-test-{}-doctor", std::process::id()));
+        check_writable(&dir).unwrap();
+        let path = dir.join("file");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            file_sha256(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,535 @@
+//! This module provides a pipeline of optional enrichment stages that add information to
+//! instances after the base listing, such as machine-type details or DNS names.
+//!
+//! Stages are independent of each other, so they run concurrently, one thread per stage. Each
+//! stage keeps its own cache so that instances sharing a lookup key (e.g. a machine type) only
+//! cost one API call.
+
+use crate::compute::{Compute, Instance, MachineType, PolicyKind, TokenSource};
+use crate::http::HttpClient;
+use crate::monitoring::{Metric, Monitoring};
+use crate::output::Column;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// The available enrichment stages, as selected on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StageKind {
+    /// vCPU count and memory of the instance's machine type.
+    MachineType,
+    /// Internal DNS name of the instance.
+    Dns,
+    /// Start and stop schedule from the instance's resource policies.
+    Schedule,
+}
+
+/// The error type returned by stages. It must be `Send` to cross the stage's thread boundary.
+pub type StageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An optional step that adds fields to each instance.
+pub trait Stage: Sync {
+    /// A short name for the stage, used in error messages.
+    fn name(&self) -> &'static str;
+
+    /// The output columns that show the fields this stage adds.
+    fn columns(&self) -> Vec<Column>;
+
+    /// Computes the fields to add to each instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `instances` - The instances to enrich.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<BTreeMap<String, String>>)` - The fields for each instance, in the same order
+    ///   as `instances`.
+    /// * `Err(StageError)` - An error if the stage could not compute its fields.
+    fn enrich(&self, instances: &[Instance]) -> Result<Vec<BTreeMap<String, String>>, StageError>;
+}
+
+/// Runs the stages concurrently and merges their fields into the instances.
+///
+/// # Arguments
+///
+/// * `stages` - The enabled stages.
+/// * `instances` - The instances to enrich in place.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every stage succeeded.
+/// * `Err(Box<dyn std::error::Error>)` - The error of the first failing stage, prefixed with
+///   its name.
+pub fn run(
+    stages: &[&dyn Stage],
+    instances: &mut [Instance],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let shared = &*instances;
+    let results = std::thread::scope(|scope| {
+        let handles = stages
+            .iter()
+            .map(|stage| scope.spawn(move || stage.enrich(shared)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err("stage panicked".into()))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    for (stage, result) in stages.iter().zip(results) {
+        let fields = result.map_err(|e| format!("{} enrichment failed: {}", stage.name(), e))?;
+        for (instance, fields) in instances.iter_mut().zip(fields) {
+            instance.enrichments.extend(fields);
+        }
+    }
+    Ok(())
+}
+
+/// Adds the vCPU count and memory of each instance's machine type.
+pub struct MachineTypeStage<'a, H: HttpClient, T: TokenSource> {
+    /// The compute service used to look machine types up.
+    compute: &'a Compute<H, T>,
+    /// Machine types already fetched, keyed by zone and name.
+    cache: Mutex<HashMap<(String, String), MachineType>>,
+}
+
+impl<'a, H: HttpClient, T: TokenSource> MachineTypeStage<'a, H, T> {
+    /// Creates a new `MachineTypeStage` with an empty cache.
+    pub fn new(compute: &'a Compute<H, T>) -> Self {
+        Self {
+            compute,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a machine type from the cache, fetching it on a miss.
+    fn machine_type(&self, zone: &str, name: &str) -> Result<MachineType, StageError> {
+        let key = (zone.to_string(), name.to_string());
+        if let Some(machine_type) = self.cache.lock().unwrap().get(&key) {
+            return Ok(machine_type.clone());
+        }
+        let machine_type = self
+            .compute
+            .get_machine_type(zone, name)
+            .map_err(|e| e.to_string())?;
+        self.cache.lock().unwrap().insert(key, machine_type.clone());
+        Ok(machine_type)
+    }
+}
+
+impl<H: HttpClient + Sync, T: TokenSource + Sync> Stage for MachineTypeStage<'_, H, T> {
+    fn name(&self) -> &'static str {
+        "machine type"
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        vec![
+            Column::new("vcpus", "vCPUs", |i| {
+                i.enrichments.get("vcpus").map(|v| v.clone().into())
+            }),
+            Column::new("memory_gb", "Memory (GB)", |i| {
+                i.enrichments.get("memory_gb").map(|v| v.clone().into())
+            }),
+        ]
+    }
+
+    fn enrich(&self, instances: &[Instance]) -> Result<Vec<BTreeMap<String, String>>, StageError> {
+        instances
+            .iter()
+            .map(|inst| {
+                let machine_type = self.machine_type(&inst.zone, &inst.machine_type)?;
+                Ok(BTreeMap::from([
+                    ("vcpus".to_string(), machine_type.vcpus.to_string()),
+                    (
+                        "memory_gb".to_string(),
+                        format!("{:.1}", machine_type.memory_mb as f64 / 1024.0),
+                    ),
+                ]))
+            })
+            .collect()
+    }
+}
+
+/// The vCPUs and memory of a set of instances, added up from the fields of the machine-type
+/// stage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceTotals {
+    /// The number of instances.
+    pub instances: usize,
+    /// The total number of vCPUs.
+    pub vcpus: u64,
+    /// The total memory in GB.
+    pub memory_gb: f64,
+}
+
+impl ResourceTotals {
+    /// Adds up the resources of instances enriched by the machine-type stage. Instances
+    /// without the stage's fields are counted but add no resources.
+    pub fn of(instances: &[Instance]) -> Self {
+        let field = |inst: &Instance, key: &str| inst.enrichments.get(key)?.parse::<f64>().ok();
+        let mut totals = ResourceTotals {
+            instances: instances.len(),
+            ..Default::default()
+        };
+        for inst in instances {
+            totals.vcpus += field(inst, "vcpus").unwrap_or_default() as u64;
+            totals.memory_gb += field(inst, "memory_gb").unwrap_or_default();
+        }
+        totals
+    }
+}
+
+impl std::fmt::Display for ResourceTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Total: {} instances, {} vCPUs, {:.1} GB memory",
+            self.instances, self.vcpus, self.memory_gb
+        )
+    }
+}
+
+/// Adds the start and stop schedule of each instance, from the instance schedule policies
+/// attached to it.
+pub struct ScheduleStage<'a, H: HttpClient, T: TokenSource> {
+    /// The compute service used to list resource policies.
+    compute: &'a Compute<H, T>,
+}
+
+impl<'a, H: HttpClient, T: TokenSource> ScheduleStage<'a, H, T> {
+    /// Creates a new `ScheduleStage`.
+    pub fn new(compute: &'a Compute<H, T>) -> Self {
+        Self { compute }
+    }
+}
+
+impl<H: HttpClient + Sync, T: TokenSource + Sync> Stage for ScheduleStage<'_, H, T> {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        vec![Column::new("instance_schedule", "Instance Schedule", |i| {
+            i.enrichments
+                .get("instance_schedule")
+                .map(|v| v.clone().into())
+        })]
+    }
+
+    fn enrich(&self, instances: &[Instance]) -> Result<Vec<BTreeMap<String, String>>, StageError> {
+        // One listing covers every instance, so there is nothing to cache
+        let schedules = self
+            .compute
+            .list_all_resource_policies()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|policy| policy.kind == PolicyKind::InstanceSchedule)
+            .filter_map(|policy| Some(((policy.region, policy.name), policy.schedule?)))
+            .collect::<HashMap<_, _>>();
+        Ok(instances
+            .iter()
+            .map(|inst| {
+                let attached = inst
+                    .resource_policies
+                    .iter()
+                    .filter_map(|name| schedules.get(&(inst.region.clone(), name.clone())))
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                match attached.is_empty() {
+                    true => BTreeMap::new(),
+                    false => {
+                        BTreeMap::from([("instance_schedule".to_string(), attached.join("; "))])
+                    }
+                }
+            })
+            .collect())
+    }
+}
+
+/// How far back the metrics stage averages each metric.
+pub const METRICS_WINDOW: chrono::Duration = chrono::Duration::minutes(10);
+
+/// The columns showing the fields the metrics stage adds for the given metrics.
+pub fn metric_columns(metrics: &[Metric]) -> Vec<Column> {
+    metrics
+        .iter()
+        .map(|metric| {
+            let key = metric.key();
+            Column::new(key, metric.title(), move |i| {
+                i.enrichments.get(key).map(|v| v.clone().into())
+            })
+        })
+        .collect()
+}
+
+/// Adds recent metrics of each instance from Cloud Monitoring, e.g. its CPU utilization,
+/// averaged over `METRICS_WINDOW`.
+pub struct MetricsStage<'a, H: HttpClient, T: TokenSource> {
+    /// The monitoring service used to query the metrics.
+    monitoring: &'a Monitoring<H, T>,
+    /// The metrics to add.
+    metrics: Vec<Metric>,
+}
+
+impl<'a, H: HttpClient, T: TokenSource> MetricsStage<'a, H, T> {
+    /// Creates a new `MetricsStage` adding the given metrics.
+    pub fn new(monitoring: &'a Monitoring<H, T>, metrics: &[Metric]) -> Self {
+        Self {
+            monitoring,
+            metrics: metrics.to_vec(),
+        }
+    }
+}
+
+impl<H: HttpClient + Sync, T: TokenSource + Sync> Stage for MetricsStage<'_, H, T> {
+    fn name(&self) -> &'static str {
+        "metrics"
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        metric_columns(&self.metrics)
+    }
+
+    fn enrich(&self, instances: &[Instance]) -> Result<Vec<BTreeMap<String, String>>, StageError> {
+        // Each metric is queried for all instances at once, so there is nothing to cache
+        let names = instances
+            .iter()
+            .map(|inst| inst.name.as_str())
+            .collect::<Vec<_>>();
+        let now = chrono::Utc::now();
+        let mut fields = vec![BTreeMap::new(); instances.len()];
+        for metric in &self.metrics {
+            let values = self
+                .monitoring
+                .instance_metric(*metric, &names, METRICS_WINDOW, now)
+                .map_err(|e| e.to_string())?;
+            for (inst, fields) in instances.iter().zip(fields.iter_mut()) {
+                if let Some(value) = values.get(&(inst.zone.clone(), inst.name.clone())) {
+                    fields.insert(metric.key().to_string(), format!("{:.1}", value));
+                }
+            }
+        }
+        Ok(fields)
+    }
+}
+
+/// Adds each instance's internal (zonal) DNS name, `NAME.ZONE.c.PROJECT.internal`.
+pub struct DnsStage {
+    /// The project the instances belong to.
+    project: String,
+}
+
+impl DnsStage {
+    /// Creates a new `DnsStage` for instances in the given project.
+    pub fn new(project: &str) -> Self {
+        Self {
+            project: project.to_string(),
+        }
+    }
+}
+
+impl Stage for DnsStage {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        vec![Column::new("dns_name", "DNS Name", |i| {
+            i.enrichments.get("dns_name").map(|v| v.clone().into())
+        })]
+    }
+
+    fn enrich(&self, instances: &[Instance]) -> Result<Vec<BTreeMap<String, String>>, StageError> {
+        // Internal DNS names are derived from the instance, so no lookup (or cache) is needed
+        Ok(instances
+            .iter()
+            .map(|inst| {
+                BTreeMap::from([(
+                    "dns_name".to_string(),
+                    format!("{}.{}.c.{}.internal", inst.name, inst.zone, self.project),
+                )])
+            })
+            .collect())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::{ApiVersion, ComputeConfig, ListStrategy, MockTokenSource, DEFAULT_JOBS};
+    use crate::http::MockHttpClient;
+    use serde_json::json;
+
+    fn instance(name: &str, machine_type: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            zone: "us-east1-b".to_string(),
+            machine_type: machine_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_merges_stage_fields() {
+        let mut mock_http = MockHttpClient::new();
+
+        // Two instances share a machine type, so it is only fetched once
+        mock_http.expect_get().times(2).returning(|_, url| {
+            let name = crate::compute::last_segment(url);
+            let vcpus = if name == "e2-medium" { 2 } else { 16 };
+            Ok(json!({"name": name, "guestCpus": vcpus, "memoryMb": vcpus * 2048}))
+        });
+
+        let compute = Compute::new(ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        });
+        let machine_types = MachineTypeStage::new(&compute);
+        let dns = DnsStage::new("test-project");
+        let mut instances = vec![
+            instance("a", "e2-medium"),
+            instance("b", "n2-standard-16"),
+            instance("c", "e2-medium"),
+        ];
+
+        run(&[&machine_types, &dns], &mut instances).unwrap();
+
+        assert_eq!(instances[0].enrichments["vcpus"], "2");
+        assert_eq!(instances[0].enrichments["memory_gb"], "4.0");
+        assert_eq!(instances[1].enrichments["vcpus"], "16");
+        assert_eq!(instances[2].enrichments["vcpus"], "2");
+        assert_eq!(
+            instances[2].enrichments["dns_name"],
+            "c.us-east1-b.c.test-project.internal"
+        );
+
+        let totals = ResourceTotals::of(&instances);
+        assert_eq!(
+            totals.to_string(),
+            "Total: 3 instances, 20 vCPUs, 40.0 GB memory"
+        );
+    }
+
+    #[test]
+    fn test_schedule_stage() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http.expect_get().times(1).returning(|_, url| {
+            assert!(url.contains("/aggregated/resourcePolicies"));
+            Ok(json!({"items": {"regions/us-east1": {"resourcePolicies": [
+                {
+                    "name": "nightly-stop",
+                    "region": "projects/p/regions/us-east1",
+                    "instanceSchedulePolicy": {"vmStopSchedule": {"schedule": "0 20 * * *"}}
+                },
+                {
+                    "name": "backups",
+                    "region": "projects/p/regions/us-east1",
+                    "snapshotSchedulePolicy": {"schedule": {"dailySchedule": {}}}
+                }
+            ]}}}))
+        });
+
+        let compute = Compute::new(ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        });
+        let schedules = ScheduleStage::new(&compute);
+        let mut instances = vec![instance("a", "e2-medium"), instance("b", "e2-medium")];
+        instances[0].region = "us-east1".to_string();
+        instances[0].resource_policies = vec!["nightly-stop".to_string(), "backups".to_string()];
+
+        run(&[&schedules], &mut instances).unwrap();
+
+        assert_eq!(
+            instances[0].enrichments["instance_schedule"],
+            "stop 0 20 * * *"
+        );
+        assert!(instances[1].enrichments.is_empty());
+    }
+
+    #[test]
+    fn test_metrics_stage() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http.expect_get().times(2).returning(|_, url| {
+            assert!(url.contains("one_of"));
+            let value = match url.contains("cpu") {
+                true => json!({"doubleValue": 0.123}),
+                false => json!({"doubleValue": 61.25}),
+            };
+            Ok(json!({"timeSeries": [{
+                "resource": {"labels": {"zone": "us-east1-b"}},
+                "metadata": {"systemLabels": {"name": "a"}},
+                "points": [{"value": value}]
+            }]}))
+        });
+
+        let monitoring = Monitoring::new(crate::monitoring::MonitoringConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            page_size: None,
+        });
+        let metrics = MetricsStage::new(&monitoring, &[Metric::Cpu, Metric::Mem]);
+        let mut instances = vec![instance("a", "e2-medium"), instance("b", "e2-medium")];
+
+        run(&[&metrics], &mut instances).unwrap();
+
+        assert_eq!(instances[0].enrichments["cpu_percent"], "12.3");
+        assert_eq!(instances[0].enrichments["memory_percent"], "61.2");
+        // Instances without data have no fields, which show as missing values
+        assert!(instances[1].enrichments.is_empty());
+        assert_eq!(
+            metrics
+                .columns()
+                .iter()
+                .map(|c| c.key.as_str())
+                .collect::<Vec<_>>(),
+            ["cpu_percent", "memory_percent"]
+        );
+    }
+
+    #[test]
+    fn test_run_reports_failing_stage() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .returning(|_, _| Err("permission denied".into()));
+
+        let compute = Compute::new(ComputeConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            api_version: ApiVersion::V1,
+            page_size: None,
+            strict: false,
+            list_strategy: ListStrategy::Aggregated,
+            jobs: DEFAULT_JOBS,
+        });
+        let machine_types = MachineTypeStage::new(&compute);
+        let mut instances = vec![instance("a", "e2-medium")];
+
+        let err = run(&[&machine_types], &mut instances).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "machine type enrichment failed: permission denied"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! This module works out which environment an instance belongs to, for searching all habitats
+//! at once with `bcls find`. The project an instance lives in is the primary signal; an `env`
+//! or `environment` label confirms it, or picks between habitats that share a project.
+
+use crate::compute::Instance;
+use crate::config::Environment;
+
+/// The labels that may name an instance's environment, in order of preference.
+const ENV_LABELS: [&str; 2] = ["env", "environment"];
+
+/// The environment an instance was attributed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribution {
+    /// The most likely environment.
+    pub environment: Environment,
+    /// Why the attribution is uncertain, if it is.
+    pub ambiguity: Option<String>,
+}
+
+/// Maps a label value such as `prod` or `staging` to an environment.
+fn environment_from_label(value: &str) -> Option<Environment> {
+    match value.to_ascii_lowercase().as_str() {
+        "int" | "integration" => Some(Environment::Int),
+        "stg" | "stage" | "staging" => Some(Environment::Stg),
+        "prd" | "prod" | "production" => Some(Environment::Prd),
+        _ => None,
+    }
+}
+
+/// Infers the environment of an instance.
+///
+/// # Arguments
+///
+/// * `project_environments` - The environments configured with the instance's project, in
+///   config order. Must not be empty.
+/// * `instance` - The instance, whose environment label is checked.
+///
+/// # Returns
+///
+/// The environment of the project if there is only one, otherwise the one named by the label,
+/// falling back to the first. The attribution is marked ambiguous if the label contradicts the
+/// project or the project is shared and the label doesn't settle it.
+pub fn infer_environment(project_environments: &[Environment], instance: &Instance) -> Attribution {
+    let label = ENV_LABELS.iter().find_map(|key| {
+        let value = instance.labels.as_ref()?.get(*key)?;
+        Some((*key, value, environment_from_label(value)?))
+    });
+    let first = project_environments[0];
+
+    match (project_environments, label) {
+        ([env], Some((key, value, labelled))) if labelled != *env => Attribution {
+            environment: *env,
+            ambiguity: Some(format!(
+                "labelled {}={} but in the {} project",
+                key,
+                value,
+                env.as_str()
+            )),
+        },
+        ([env], _) => Attribution {
+            environment: *env,
+            ambiguity: None,
+        },
+        (_, Some((_, _, labelled))) if project_environments.contains(&labelled) => Attribution {
+            environment: labelled,
+            ambiguity: None,
+        },
+        _ => Attribution {
+            environment: first,
+            ambiguity: Some(format!(
+                "project shared by {}",
+                project_environments
+                    .iter()
+                    .map(Environment::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        },
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(labels: &[(&str, &str)]) -> Instance {
+        Instance {
+            name: "db-1".to_string(),
+            labels: Some(
+                labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_single_project() {
+        let prd = [Environment::Prd];
+        assert_eq!(
+            infer_environment(&prd, &instance(&[])),
+            Attribution {
+                environment: Environment::Prd,
+                ambiguity: None
+            }
+        );
+        assert_eq!(
+            infer_environment(&prd, &instance(&[("env", "production")])).ambiguity,
+            None
+        );
+        assert_eq!(
+            infer_environment(&prd, &instance(&[("environment", "staging")])),
+            Attribution {
+                environment: Environment::Prd,
+                ambiguity: Some("labelled environment=staging but in the prd project".to_string())
+            }
+        );
+        // Labels that don't name an environment are ignored
+        assert_eq!(
+            infer_environment(&prd, &instance(&[("env", "sandbox")])).ambiguity,
+            None
+        );
+    }
+
+    #[test]
+    fn test_shared_project() {
+        let shared = [Environment::Int, Environment::Stg];
+        assert_eq!(
+            infer_environment(&shared, &instance(&[("env", "stg")])),
+            Attribution {
+                environment: Environment::Stg,
+                ambiguity: None
+            }
+        );
+        assert_eq!(
+            infer_environment(&shared, &instance(&[])),
+            Attribution {
+                environment: Environment::Int,
+                ambiguity: Some("project shared by int, stg".to_string())
+            }
+        );
+        assert_eq!(
+            infer_environment(&shared, &instance(&[("env", "prod")]))
+                .ambiguity
+                .unwrap(),
+            "project shared by int, stg"
+        );
+    }
+}
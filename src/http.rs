@@ -1,8 +1,33 @@
 //! This module provides an HTTP client abstraction and a concrete implementation using `reqwest`.
 //! It also defines a trait `HttpTrait` for mocking in tests.
 
-use reqwest::blocking::Client as ReqwestClient;
+mod etag;
+mod fixture;
+mod options;
+mod rate_limit;
+mod retry;
+mod trace;
+mod transport;
+
+use reqwest::blocking::{Client as ReqwestClient, RequestBuilder};
+use ring::rand::SecureRandom;
 use serde_json::Value as JsonValue;
+use std::fmt;
+use std::sync::Arc;
+
+pub use etag::{CachedResponse, EtagStore};
+pub use fixture::{Exchange, RecordedError, RecordingHttp, ReplayHttp};
+pub use options::HttpOptions;
+pub use rate_limit::RateLimiter;
+pub use retry::{RetryPolicy, RetryingHttp};
+pub use trace::{TraceEntry, TraceLog};
+pub use transport::{LocalProxy, Transport};
+
+/// The header carrying the ID bcls generates for each request.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Response headers that may carry the server's ID for a request, in order of preference.
+const SERVER_REQUEST_ID_HEADERS: [&str; 2] = ["x-goog-request-id", "x-request-id"];
 
 /// A trait defining the interface for an HTTP client.
 /// This trait allows for mocking the HTTP client in tests.
@@ -20,12 +45,83 @@ pub trait HttpClient {
     /// * `Ok(JsonValue)` - The JSON response from the server on success.
     /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails.
     fn get(&self, token: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>>;
+
+    /// Sends a POST request with a JSON body to the specified URL with the given bearer token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token for authentication.
+    /// * `url` - The URL to send the request to.
+    /// * `body` - The JSON request body.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails.
+    fn post(
+        &self,
+        token: &str,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>>;
+
+    /// Sends an unauthenticated GET request to the GCE metadata server, with the
+    /// `Metadata-Flavor: Google` header it requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The metadata URL to send the request to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails.
+    fn get_metadata(&self, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>>;
+
+    /// Sends an unauthenticated POST request with a JSON body, e.g. to a webhook.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `body` - The JSON request body.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success, or `null` if the
+    ///   response body is not JSON.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails.
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>>;
+
+    /// Sends an unauthenticated POST request with a form-encoded body, e.g. to exchange
+    /// credentials for a token.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `form` - The form fields, as a JSON object of string values.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails.
+    fn post_form(
+        &self,
+        url: &str,
+        form: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>>;
 }
 
-/// An HTTP client implementation using `reqwest`.
+/// An HTTP client implementation using `reqwest`. Clones share the connection pool.
+#[derive(Clone)]
 pub struct Http {
     /// The underlying `reqwest` client.
     client: ReqwestClient,
+    /// Where GET responses are kept for conditional requests, if anywhere.
+    etags: Option<Arc<EtagStore>>,
 }
 
 impl Default for Http {
@@ -40,7 +136,94 @@ impl Http {
     pub fn new() -> Self {
         Http {
             client: ReqwestClient::new(),
+            etags: None,
+        }
+    }
+
+    /// Creates a new `Http` client whose requests time out after the given duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout for each request, from connecting to reading the body.
+    pub fn with_timeout(timeout: std::time::Duration) -> Self {
+        Http {
+            client: ReqwestClient::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Failed to build HTTP client"),
+            etags: None,
+        }
+    }
+
+    /// Creates a new `Http` client that sends googleapis.com requests through a transport,
+    /// such as a SOCKS5 proxy or an SSH jump host, with the default connection options.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - How to connect to the API.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Http)` - The client on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the local proxy for the transport
+    ///   can't be started.
+    pub fn with_transport(transport: &Transport) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_options(&HttpOptions::default(), transport)
+    }
+
+    /// Creates a new `Http` client with timeouts, a proxy and TLS certificates, which sends
+    /// googleapis.com requests through a transport if it isn't direct.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The timeouts, proxy and certificates.
+    /// * `transport` - How to connect to the API.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Http)` - The client on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the proxy or a certificate is
+    ///   invalid, or the local proxy for the transport can't be started.
+    pub fn with_options(
+        options: &HttpOptions,
+        transport: &Transport,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut builder = ReqwestClient::builder()
+            .connect_timeout(options.connect_timeout())
+            .timeout(options.timeout());
+        // The transport's proxy comes first, so API requests never take the configured one
+        if !transport.is_direct() {
+            let proxy_url = LocalProxy::start(transport.clone())?.url();
+            builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+                url.host_str()
+                    .filter(|host| LocalProxy::is_tunneled(host))
+                    .map(|_| proxy_url.clone())
+            }));
+        }
+        if let Some(proxy) = options.proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        for certificate in options.root_certificates()? {
+            builder = builder.add_root_certificate(certificate);
         }
+        if let Some(identity) = options.identity()? {
+            builder = builder.identity(identity);
+        }
+        Ok(Http {
+            client: builder.build()?,
+            etags: None,
+        })
+    }
+
+    /// Keeps GET responses that carry an `ETag`, and asks for them again with
+    /// `If-None-Match`, so that an unchanged response isn't sent again.
+    ///
+    /// # Arguments
+    ///
+    /// * `etags` - Where responses are kept, which may be shared with other clients.
+    pub fn with_etag_store(mut self, etags: Arc<EtagStore>) -> Self {
+        self.etags = Some(etags);
+        self
     }
 }
 
@@ -63,12 +246,513 @@ impl HttpClient for Http {
     /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails,
     ///   including network errors, deserialization errors, and invalid token errors.
     fn get(&self, token: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
-        let resp = self
+        let request = self.client.get(url).bearer_auth(token.to_owned());
+        let Some(etags) = self.etags.as_ref().filter(|_| EtagStore::is_cacheable(url)) else {
+            return send(request);
+        };
+        let cached = etags.load(url);
+        let request = match &cached {
+            Some(cached) => request.header(reqwest::header::IF_NONE_MATCH, &cached.etag),
+            None => request,
+        };
+        match execute(request)? {
+            Response::NotModified => {
+                tracing::debug!(url, "not modified, using the stored response");
+                cached
+                    .map(|cached| cached.body)
+                    .ok_or_else(|| "Not Modified response to an unconditional request".into())
+            }
+            Response::Json { body, etag } => {
+                let etag = etag.or_else(|| body["etag"].as_str().map(str::to_string));
+                let response = etag.and_then(|etag| CachedResponse::new(url, etag, body.clone()));
+                if let Some(response) = response {
+                    if let Err(e) = etags.save(&response) {
+                        tracing::warn!(error = %e, "failed to store response");
+                    }
+                }
+                Ok(body)
+            }
+        }
+    }
+
+    /// Sends a POST request with a JSON body using `reqwest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The bearer token for authentication.
+    /// * `url` - The URL to send the request to.
+    /// * `body` - The JSON request body.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails,
+    ///   including network errors, deserialization errors, and invalid token errors.
+    fn post(
+        &self,
+        token: &str,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let request = self
             .client
-            .get(url)
+            .post(url)
             .bearer_auth(token.to_owned())
-            .send()?
-            .json::<JsonValue>()?;
-        Ok(resp)
+            .json(body);
+        send(request)
+    }
+
+    /// Sends a GET request to the GCE metadata server using `reqwest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The metadata URL to send the request to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails, e.g. because
+    ///   the metadata server is unreachable when not running on GCE.
+    fn get_metadata(&self, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let request = self.client.get(url).header("Metadata-Flavor", "Google");
+        send(request)
+    }
+
+    /// Sends an unauthenticated POST request with a JSON body using `reqwest`.
+    ///
+    /// Webhooks often answer with plain text such as `ok`, so a non-JSON response body is
+    /// returned as `null` rather than treated as an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `body` - The JSON request body.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server, or `null`, on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails or the server
+    ///   responds with an error status.
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let request = self.client.post(url).json(body);
+        match send(request) {
+            Err(e)
+                if e.downcast_ref::<HttpError>()
+                    .is_some_and(|e| e.is_success()) =>
+            {
+                Ok(JsonValue::Null)
+            }
+            result => result,
+        }
+    }
+
+    /// Sends an unauthenticated POST request with a form-encoded body using `reqwest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to send the request to.
+    /// * `form` - The form fields, as a JSON object of string values.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(JsonValue)` - The JSON response from the server on success.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the request fails,
+    ///   including network errors and deserialization errors.
+    fn post_form(
+        &self,
+        url: &str,
+        form: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let request = self.client.post(url).form(form);
+        send(request)
+    }
+}
+
+/// An error from an HTTP request, carrying the request IDs needed to trace it with the server
+/// operator, e.g. when escalating to Google support.
+#[derive(Debug)]
+pub struct HttpError {
+    /// The HTTP status, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// What went wrong, e.g. the error message from the response body.
+    pub message: String,
+    /// The status from a Google API error body, e.g. `PERMISSION_DENIED`.
+    pub api_status: Option<String>,
+    /// The ID bcls sent with the request.
+    pub client_request_id: String,
+    /// The ID the server returned for the request, if any.
+    pub server_request_id: Option<String>,
+    /// How long the server asked clients to wait before retrying, from `Retry-After`.
+    pub retry_after: Option<std::time::Duration>,
+}
+
+impl HttpError {
+    /// Whether the request was rejected because the credentials are missing, invalid or
+    /// expired.
+    pub fn is_unauthenticated(&self) -> bool {
+        self.status == Some(401)
+    }
+
+    /// Whether the server responded successfully, i.e. the request only failed afterwards,
+    /// e.g. because the response was not JSON.
+    pub fn is_success(&self) -> bool {
+        self.status
+            .is_some_and(|status| (200..300).contains(&status))
+    }
+
+    /// Whether the credentials were accepted but lack permission for the request.
+    pub fn is_permission_denied(&self) -> bool {
+        self.status == Some(403)
+    }
+
+    /// Whether the requested resource doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        self.status == Some(404)
+    }
+
+    /// Whether a change was rejected because the resource changed since it was read, e.g. a
+    /// stale metadata fingerprint.
+    pub fn is_precondition_failed(&self) -> bool {
+        self.status == Some(412)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match (self.status, &self.api_status) {
+            (Some(status), Some(api_status)) => write!(f, " (HTTP {} {})", status, api_status)?,
+            (Some(status), None) => write!(f, " (HTTP {})", status)?,
+            _ => {}
+        }
+        write!(f, " [client request id {}", self.client_request_id)?;
+        if let Some(id) = &self.server_request_id {
+            write!(f, ", server request id {}", id)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// A successful response.
+enum Response {
+    /// A JSON body, and its entity tag if the server sent one.
+    Json {
+        body: JsonValue,
+        etag: Option<String>,
+    },
+    /// `304 Not Modified`, in answer to `If-None-Match`.
+    NotModified,
+}
+
+/// Sends a request tagged with a new request ID and parses the JSON response.
+///
+/// # Returns
+///
+/// * `Ok(JsonValue)` - The JSON response on success.
+/// * `Err(Box<dyn std::error::Error>)` - An `HttpError` if the request fails or the server
+///   responds with an error status.
+fn send(request: RequestBuilder) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    match execute(request)? {
+        Response::Json { body, .. } => Ok(body),
+        Response::NotModified => Err("Not Modified response to an unconditional request".into()),
+    }
+}
+
+/// Sends a request tagged with a new request ID, and parses the JSON response unless it is
+/// `304 Not Modified`.
+///
+/// # Returns
+///
+/// * `Ok(Response)` - The response on success.
+/// * `Err(Box<dyn std::error::Error>)` - An `HttpError` if the request fails or the server
+///   responds with an error status.
+fn execute(request: RequestBuilder) -> Result<Response, Box<dyn std::error::Error>> {
+    let client_request_id = new_request_id();
+    let error = |status, message, server_request_id| HttpError {
+        status,
+        message,
+        api_status: None,
+        client_request_id: client_request_id.clone(),
+        server_request_id,
+        retry_after: None,
+    };
+
+    let (client, request) = request
+        .header(REQUEST_ID_HEADER, &client_request_id)
+        .build_split();
+    let request = request.map_err(|e| error(None, e.to_string(), None))?;
+    let span = tracing::debug_span!(
+        "http_request",
+        method = %request.method(),
+        url = %request.url(),
+        request_id = %client_request_id,
+    );
+    let _entered = span.enter();
+
+    let resp = client.execute(request).map_err(|e| {
+        tracing::debug!(error = %e, "no response");
+        error(None, e.to_string(), None)
+    })?;
+    let status = resp.status();
+    let server_request_id = SERVER_REQUEST_ID_HEADERS.iter().find_map(|name| {
+        resp.headers()
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    });
+    let retry_after = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let etag = resp
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = resp.text().map_err(|e| {
+        error(
+            Some(status.as_u16()),
+            e.to_string(),
+            server_request_id.clone(),
+        )
+    })?;
+    tracing::debug!(status = status.as_u16(), bytes = body.len(), "response");
+    tracing::trace!(
+        body = %serde_json::from_str(&body).map_or(body.clone(), |json| fixture::redact(&json).to_string()),
+        "response body"
+    );
+
+    if status == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Response::NotModified);
+    }
+    if !status.is_success() {
+        let (message, api_status) =
+            parse_error_body(&body).unwrap_or_else(|| (status.to_string(), None));
+        return Err(HttpError {
+            api_status,
+            retry_after,
+            ..error(Some(status.as_u16()), message, server_request_id)
+        }
+        .into());
+    }
+    let body = serde_json::from_str(&body).map_err(|e| {
+        error(
+            Some(status.as_u16()),
+            format!("Invalid JSON response: {}", e),
+            server_request_id,
+        )
+    })?;
+    Ok(Response::Json { body, etag })
+}
+
+/// Extracts the error message, and the API status if any, from an error response body.
+///
+/// Handles the Google API error envelope (`{"error": {"code": ..., "message": ...,
+/// "status": ...}}`) and OAuth errors (`{"error": ..., "error_description": ...}`).
+fn parse_error_body(body: &str) -> Option<(String, Option<String>)> {
+    let json = serde_json::from_str::<JsonValue>(body).ok()?;
+    let error = &json["error"];
+    if let Some(message) = error["message"].as_str() {
+        // Older APIs put the status in the first error's reason instead
+        let api_status = error["status"]
+            .as_str()
+            .or_else(|| error["errors"][0]["reason"].as_str())
+            .map(str::to_string);
+        return Some((message.to_string(), api_status));
+    }
+    let error = error.as_str()?;
+    let message = match json["error_description"].as_str() {
+        Some(description) => format!("{}: {}", error, description),
+        None => error.to_string(),
+    };
+    Some((message, None))
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    // A date in the past means retrying right away
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Generates a random (version 4) UUID to identify a request.
+pub(crate) fn new_request_id() -> String {
+    let mut bytes = [0u8; 16];
+    // Fall back to the clock if the system RNG is unavailable; the ID only needs to be unique
+    if ring::rand::SystemRandom::new().fill(&mut bytes).is_err() {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        bytes = nanos.to_le_bytes();
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_request_id() {
+        let id = new_request_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(
+            id.split('-').map(str::len).collect::<Vec<_>>(),
+            vec![8, 4, 4, 4, 12]
+        );
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(id, new_request_id());
+    }
+
+    #[test]
+    fn test_parse_error_body() {
+        let body = r#"{"error": {
+            "code": 403,
+            "message": "Required 'compute.instances.list' permission for 'projects/p'",
+            "errors": [{"reason": "forbidden"}],
+            "status": "PERMISSION_DENIED"
+        }}"#;
+        assert_eq!(
+            parse_error_body(body),
+            Some((
+                "Required 'compute.instances.list' permission for 'projects/p'".to_string(),
+                Some("PERMISSION_DENIED".to_string())
+            ))
+        );
+        let body = r#"{"error": {"code": 401, "message": "Invalid Credentials",
+            "errors": [{"reason": "authError"}]}}"#;
+        assert_eq!(
+            parse_error_body(body),
+            Some((
+                "Invalid Credentials".to_string(),
+                Some("authError".to_string())
+            ))
+        );
+        let body = r#"{"error": "invalid_grant", "error_description": "Invalid JWT Signature."}"#;
+        assert_eq!(
+            parse_error_body(body),
+            Some(("invalid_grant: Invalid JWT Signature.".to_string(), None))
+        );
+        assert_eq!(parse_error_body("<html>Bad Gateway</html>"), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(
+            parse_retry_after("120"),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_http_error_display() {
+        let error = HttpError {
+            status: Some(403),
+            message: "Forbidden".to_string(),
+            api_status: Some("PERMISSION_DENIED".to_string()),
+            client_request_id: "c-1".to_string(),
+            server_request_id: Some("s-1".to_string()),
+            retry_after: None,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Forbidden (HTTP 403 PERMISSION_DENIED) [client request id c-1, server request id s-1]"
+        );
+        let error = HttpError {
+            status: None,
+            message: "connection refused".to_string(),
+            api_status: None,
+            client_request_id: "c-2".to_string(),
+            server_request_id: None,
+            retry_after: None,
+        };
+        assert_eq!(
+            error.to_string(),
+            "connection refused [client request id c-2]"
+        );
+    }
+
+    #[test]
+    fn test_get_not_modified() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // A server that sends an ETag, then answers a request with it by 304
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/items", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut conditional = vec![];
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut if_none_match = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("if-none-match: ") {
+                        if_none_match = Some(value.to_string());
+                    }
+                }
+                let response = match &if_none_match {
+                    Some(_) => "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+                    None => concat!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Type: application/json\r\n",
+                        "Content-Length: 13\r\nConnection: close\r\n\r\n{\"items\":[1]}"
+                    ),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                conditional.push(if_none_match);
+            }
+            conditional
+        });
+
+        let dir = std::env::temp_dir().join(format!("bcls-http-etags-{}", std::process::id()));
+        let http = Http::new().with_etag_store(Arc::new(EtagStore::new(&dir)));
+        assert_eq!(
+            http.get("t", &url).unwrap(),
+            serde_json::json!({"items": [1]})
+        );
+        assert_eq!(
+            http.get("t", &url).unwrap(),
+            serde_json::json!({"items": [1]})
+        );
+
+        assert_eq!(server.join().unwrap(), [None, Some("\"v1\"".to_string())]);
+        EtagStore::new(&dir).clear().unwrap();
+        std::fs::remove_dir(&dir).unwrap();
     }
 }
@@ -0,0 +1,242 @@
+//! This module keeps the responses of GET requests on disk along with their `ETag`, so that a
+//! repeated request can ask with `If-None-Match` and the server can answer `304 Not Modified`
+//! instead of sending a listing again.
+//!
+//! Instance metadata often holds secrets such as startup scripts, so it is never stored: it is
+//! removed from the resources of a listing, and a single resource that has it isn't stored at
+//! all. The files are only readable by the current user.
+
+use crate::auth::write_private;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::{Path, PathBuf};
+
+/// A response as stored on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// The URL requested, to tell apart URLs whose file names collide.
+    pub url: String,
+    /// The entity tag the server sent with the response.
+    pub etag: String,
+    /// The response body.
+    pub body: JsonValue,
+}
+
+impl CachedResponse {
+    /// Prepares a response for storing.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL requested.
+    /// * `etag` - The entity tag the server sent with the response.
+    /// * `body` - The response body.
+    ///
+    /// # Returns
+    ///
+    /// The response with the metadata of any listed resources removed, or `None` if it can't
+    /// be stored: a single resource with metadata, whose callers may need it, or a page of a
+    /// listing with more pages, whose page token would be stale on the next run.
+    pub fn new(url: &str, etag: String, mut body: JsonValue) -> Option<Self> {
+        if !Self::is_reusable(&body) {
+            return None;
+        }
+        strip_metadata(&mut body);
+        Some(CachedResponse {
+            url: url.to_string(),
+            etag,
+            body,
+        })
+    }
+
+    /// Whether a body may be stored and returned in place of a `304 Not Modified` response.
+    fn is_reusable(body: &JsonValue) -> bool {
+        body.get("metadata").is_none() && body.get("nextPageToken").is_none()
+    }
+}
+
+/// Removes the `metadata` of every resource within a listing.
+fn strip_metadata(value: &mut JsonValue) {
+    match value {
+        JsonValue::Object(object) => {
+            object.remove("metadata");
+            object.values_mut().for_each(strip_metadata);
+        }
+        JsonValue::Array(items) => items.iter_mut().for_each(strip_metadata),
+        _ => {}
+    }
+}
+
+/// Stores responses by URL in `<dir>/<sha256 of the URL>.json`.
+#[derive(Debug)]
+pub struct EtagStore {
+    /// The directory holding the responses.
+    dir: PathBuf,
+}
+
+impl EtagStore {
+    /// Creates a new `EtagStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to store responses in. It is created on first use.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        EtagStore { dir: dir.into() }
+    }
+
+    /// Whether responses to a URL are stored. Later pages of a listing aren't, as their page
+    /// tokens differ on every run and the files would only pile up.
+    pub fn is_cacheable(url: &str) -> bool {
+        !url.contains("pageToken=")
+    }
+
+    /// Returns the path of the response to a URL.
+    fn path(&self, url: &str) -> PathBuf {
+        let digest = ring::digest::digest(&ring::digest::SHA256, url.as_bytes());
+        let name = digest
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Returns the stored response to a URL. Missing or unreadable files are treated as a miss,
+    /// as are responses that can't be reused, which older versions stored.
+    pub fn load(&self, url: &str) -> Option<CachedResponse> {
+        let contents = std::fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str::<CachedResponse>(&contents)
+            .ok()
+            .filter(|response| response.url == url && CachedResponse::is_reusable(&response.body))
+    }
+
+    /// Stores the response to a URL, replacing any previous one.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the response was stored.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the file can't be written.
+    pub fn save(&self, response: &CachedResponse) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        write_private(&self.path(&response.url), &serde_json::to_vec(response)?)
+    }
+
+    /// Removes all stored responses.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` - The number of responses removed.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the directory can't be read or a
+    ///   response can't be removed.
+    pub fn clear(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut removed = 0;
+        for path in entries.map(|entry| entry.map(|entry| entry.path())) {
+            let path = path?;
+            if path.extension().is_some_and(|ext| ext == "json") {
+                remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn remove_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::remove_file(path)
+        .map_err(|e| format!("Failed to remove {}: {}", path.display(), e).into())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_store_and_clear() {
+        let dir = std::env::temp_dir().join(format!("bcls-etags-{}", std::process::id()));
+        let store = EtagStore::new(&dir);
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/aggregated/instances";
+        assert_eq!(store.load(url), None);
+        assert_eq!(store.clear().unwrap(), 0);
+
+        let response =
+            CachedResponse::new(url, "\"abc\"".to_string(), json!({"items": {}})).unwrap();
+        store.save(&response).unwrap();
+        assert_eq!(store.load(url), Some(response));
+        assert_eq!(store.load(&format!("{}?maxResults=10", url)), None);
+
+        assert_eq!(store.clear().unwrap(), 1);
+        assert_eq!(store.load(url), None);
+        std::fs::remove_dir(&dir).unwrap();
+
+        assert!(EtagStore::is_cacheable(url));
+        assert!(!EtagStore::is_cacheable(&format!("{}?pageToken=x", url)));
+    }
+
+    #[test]
+    fn test_new_strips_metadata() {
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/aggregated/instances";
+        let body = json!({"items": {"zones/us-east1-b": {"instances": [
+            {"name": "db-1", "metadata": {"items": [{"key": "startup-script", "value": "pw"}]}}
+        ]}}});
+        let response = CachedResponse::new(url, "\"abc\"".to_string(), body).unwrap();
+        assert_eq!(
+            response.body,
+            json!({"items": {"zones/us-east1-b": {"instances": [{"name": "db-1"}]}}})
+        );
+
+        // A single instance is only useful with its metadata, so it isn't stored
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/zones/z/instances/db-1";
+        let body = json!({"name": "db-1", "metadata": {"items": []}});
+        assert_eq!(CachedResponse::new(url, "\"abc\"".to_string(), body), None);
+    }
+
+    #[test]
+    fn test_pages_with_more_pages_are_not_reused() {
+        let dir = std::env::temp_dir().join(format!("bcls-etags-pages-{}", std::process::id()));
+        let store = EtagStore::new(&dir);
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/aggregated/instances";
+        let body = json!({"items": {}, "nextPageToken": "p2"});
+        assert_eq!(
+            CachedResponse::new(url, "\"abc\"".to_string(), body.clone()),
+            None
+        );
+
+        // As stored by older versions
+        let response = CachedResponse {
+            url: url.to_string(),
+            etag: "\"abc\"".to_string(),
+            body,
+        };
+        store.save(&response).unwrap();
+        assert_eq!(store.load(url), None);
+
+        store.clear().unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("bcls-etags-mode-{}", std::process::id()));
+        let store = EtagStore::new(&dir);
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/zones/z/instances";
+        let response = CachedResponse::new(url, "\"abc\"".to_string(), json!({})).unwrap();
+        store.save(&response).unwrap();
+        let mode = std::fs::metadata(store.path(url))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        store.clear().unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+//! This module records API responses to JSON fixture files and replays them, so that tests
+//! can run the listing and parsing code against real payloads instead of hand-written mocks.
+//!
+//! A fixture is a JSON array of exchanges, each a request and its response or error, in the
+//! order they were made. Credentials in request and response bodies are redacted.
+
+use super::{HttpClient, HttpError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Keys whose values are replaced with `REDACTED` wherever they appear in a recorded body.
+const SECRET_KEYS: [&str; 7] = [
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "assertion",
+    "client_secret",
+    "private_key",
+    "accessToken",
+];
+
+/// A request and what it returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The method of the `HttpClient` called, e.g. `get` or `post_form`.
+    pub method: String,
+    /// The URL requested.
+    pub url: String,
+    /// The request body, if the method sends one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<JsonValue>,
+    /// The response body, if the request succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<JsonValue>,
+    /// The error, if the request failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RecordedError>,
+}
+
+/// A failed request, as far as it can be replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedError {
+    /// The HTTP status, or `None` if no response was received.
+    pub status: Option<u16>,
+    /// The error message.
+    pub message: String,
+}
+
+/// Replaces the values of credential fields, at any depth.
+pub(crate) fn redact(value: &JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(object) => object
+            .iter()
+            .map(|(key, value)| match SECRET_KEYS.contains(&key.as_str()) {
+                true => (key.clone(), JsonValue::from("REDACTED")),
+                false => (key.clone(), redact(value)),
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        JsonValue::Array(values) => values.iter().map(redact).collect(),
+        value => value.clone(),
+    }
+}
+
+/// An `HttpClient` that passes requests on to another and records every exchange, to be
+/// saved as a fixture with `save`. Bearer tokens are never recorded. Clones add to the same
+/// recording.
+#[derive(Clone)]
+pub struct RecordingHttp<H> {
+    inner: H,
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl<H: HttpClient> RecordingHttp<H> {
+    /// Wraps a client, e.g. `Http`, to record what it returns.
+    pub fn new(inner: H) -> Self {
+        RecordingHttp {
+            inner,
+            exchanges: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Returns the exchanges recorded so far.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges
+            .lock()
+            .expect("no thread panics holding the lock")
+            .clone()
+    }
+
+    /// Writes the exchanges recorded so far to a fixture file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write, e.g. `fixtures/aggregated_instances.json`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file was written.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the file can't be written.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(&self.exchanges())?;
+        std::fs::write(path, json + "\n")
+            .map_err(|e| format!("Failed to write fixture {}: {}", path.display(), e).into())
+    }
+
+    fn record(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&JsonValue>,
+        result: Result<JsonValue, Box<dyn std::error::Error>>,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let (response, error) = match &result {
+            Ok(response) => (Some(redact(response)), None),
+            Err(e) => {
+                let status = e.downcast_ref::<HttpError>().and_then(|e| e.status);
+                let message = match e.downcast_ref::<HttpError>() {
+                    Some(e) => e.message.clone(),
+                    None => e.to_string(),
+                };
+                (None, Some(RecordedError { status, message }))
+            }
+        };
+        self.exchanges
+            .lock()
+            .expect("no thread panics holding the lock")
+            .push(Exchange {
+                method: method.to_string(),
+                url: url.to_string(),
+                body: body.map(redact),
+                response,
+                error,
+            });
+        result
+    }
+}
+
+impl<H: HttpClient> HttpClient for RecordingHttp<H> {
+    fn get(&self, token: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.record("get", url, None, self.inner.get(token, url))
+    }
+
+    fn post(
+        &self,
+        token: &str,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.record("post", url, Some(body), self.inner.post(token, url, body))
+    }
+
+    fn get_metadata(&self, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.record("get_metadata", url, None, self.inner.get_metadata(url))
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.record(
+            "post_json",
+            url,
+            Some(body),
+            self.inner.post_json(url, body),
+        )
+    }
+
+    fn post_form(
+        &self,
+        url: &str,
+        form: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.record(
+            "post_form",
+            url,
+            Some(form),
+            self.inner.post_form(url, form),
+        )
+    }
+}
+
+/// An `HttpClient` that answers requests from a fixture instead of the network.
+///
+/// Each request is answered by the first exchange not yet replayed with the same method and
+/// URL, so a request made several times, such as polling an operation, gets the recorded
+/// responses in order. Request bodies aren't compared. Clones replay the same exchanges, so
+/// one can be kept to check `remaining` after handing another to an API client.
+#[derive(Clone)]
+pub struct ReplayHttp {
+    exchanges: Arc<Mutex<Vec<Option<Exchange>>>>,
+}
+
+impl ReplayHttp {
+    /// Creates a client replaying exchanges.
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        ReplayHttp {
+            exchanges: Arc::new(Mutex::new(exchanges.into_iter().map(Some).collect())),
+        }
+    }
+
+    /// Loads a fixture file written by `RecordingHttp::save`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The fixture file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ReplayHttp)` - A client replaying the fixture.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the file can't be read or parsed.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read fixture {}: {}", path.display(), e))?;
+        let exchanges = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid fixture {}: {}", path.display(), e))?;
+        Ok(ReplayHttp::new(exchanges))
+    }
+
+    /// Returns how many exchanges haven't been replayed, e.g. to check that a test made every
+    /// request it was expected to.
+    pub fn remaining(&self) -> usize {
+        self.exchanges
+            .lock()
+            .expect("no thread panics holding the lock")
+            .iter()
+            .flatten()
+            .count()
+    }
+
+    fn replay(&self, method: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let exchange = self
+            .exchanges
+            .lock()
+            .expect("no thread panics holding the lock")
+            .iter_mut()
+            .find(|exchange| {
+                exchange
+                    .as_ref()
+                    .is_some_and(|exchange| exchange.method == method && exchange.url == url)
+            })
+            .and_then(Option::take)
+            .ok_or_else(|| format!("No recorded response for {} {}", method, url))?;
+        match exchange.error {
+            Some(error) => Err(Box::new(HttpError {
+                status: error.status,
+                message: error.message,
+                api_status: None,
+                client_request_id: "replayed".to_string(),
+                server_request_id: None,
+                retry_after: None,
+            })),
+            None => Ok(exchange.response.unwrap_or(JsonValue::Null)),
+        }
+    }
+}
+
+impl HttpClient for ReplayHttp {
+    fn get(&self, _token: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.replay("get", url)
+    }
+
+    fn post(
+        &self,
+        _token: &str,
+        url: &str,
+        _body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.replay("post", url)
+    }
+
+    fn get_metadata(&self, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.replay("get_metadata", url)
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        _body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.replay("post_json", url)
+    }
+
+    fn post_form(
+        &self,
+        url: &str,
+        _form: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.replay("post_form", url)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockHttpClient;
+    use serde_json::json;
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .returning(|_, _| Ok(json!({"items": [1, 2]})));
+        mock_http
+            .expect_post_form()
+            .returning(|_, _| Ok(json!({"access_token": "ya29.secret", "expires_in": 3599})));
+        mock_http.expect_post().returning(|_, _, _| {
+            Err(Box::new(HttpError {
+                status: Some(412),
+                message: "Supplied fingerprint does not match".to_string(),
+                api_status: None,
+                client_request_id: "c-1".to_string(),
+                server_request_id: None,
+                retry_after: None,
+            }))
+        });
+
+        let recording = RecordingHttp::new(mock_http);
+        recording.get("token", "https://example.com/a").unwrap();
+        recording
+            .post_form(
+                "https://oauth2.example.com/token",
+                &json!({"assertion": "jwt"}),
+            )
+            .unwrap();
+        assert!(recording
+            .post("token", "https://example.com/b", &json!({}))
+            .is_err());
+
+        let path = std::env::temp_dir().join(format!("bcls-fixture-{}.json", std::process::id()));
+        recording.save(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("secret") && !saved.contains("jwt"));
+        assert!(
+            !saved.contains("\"token\""),
+            "bearer tokens aren't recorded"
+        );
+
+        let replay = ReplayHttp::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            replay.get("other", "https://example.com/a").unwrap(),
+            json!({"items": [1, 2]})
+        );
+        let error = replay
+            .post("t", "https://example.com/b", &json!({}))
+            .unwrap_err();
+        assert!(error
+            .downcast_ref::<HttpError>()
+            .is_some_and(HttpError::is_precondition_failed));
+        // Each exchange is replayed once
+        assert!(replay.get("t", "https://example.com/a").is_err());
+        assert_eq!(replay.remaining(), 1);
+    }
+}
@@ -0,0 +1,274 @@
+//! This module defines how the HTTP client connects: its timeouts, the proxy for requests
+//! that don't go through a habitat's transport, and the certificates TLS uses.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Connection options of `Http`, read from the `[http]` section of the config file.
+///
+/// ```toml
+/// [http]
+/// connect_timeout_secs = 10
+/// timeout_secs = 60
+/// proxy = "http://proxy.corp:3128"
+/// no_proxy = "metadata.google.internal,.corp"
+/// ca_certificate = "/etc/ssl/corp-root.pem"
+/// client_certificate = "/etc/bcls/client.pem"
+/// client_key = "/etc/bcls/client-key.pem"
+/// requests_per_second = 20
+/// burst = 40
+/// etag_cache = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HttpOptions {
+    /// How long to wait for a connection to be established. Defaults to 10s.
+    pub connect_timeout_secs: u64,
+    /// How long a request may take, from connecting until the whole response is read.
+    /// Defaults to 60s, as the pages of aggregated lists can be several MB.
+    pub timeout_secs: u64,
+    /// The proxy for all requests, e.g. `http://proxy.corp:3128`, overriding the
+    /// `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY` environment variables. Requests to
+    /// googleapis.com go through the habitat's transport instead if it has one.
+    pub proxy: Option<String>,
+    /// Hosts reached without `proxy`, as in `NO_PROXY`: a comma-separated list of hosts,
+    /// domains such as `.corp`, and IP ranges.
+    pub no_proxy: Option<String>,
+    /// A PEM file of CA certificates trusted in addition to the system's, e.g. the root of a
+    /// TLS-intercepting proxy.
+    pub ca_certificate: Option<PathBuf>,
+    /// A PEM file with the certificate chain to authenticate with, for endpoints requiring
+    /// mutual TLS. Needs `client_key`.
+    pub client_certificate: Option<PathBuf>,
+    /// A PEM file with the PKCS #8 private key of `client_certificate`.
+    pub client_key: Option<PathBuf>,
+    /// How many requests per second may be sent to a project, shared by all requests to it
+    /// from this process, at least 0.001. Unlimited by default. A habitat can set its own.
+    pub requests_per_second: Option<f64>,
+    /// How many requests may be sent at once before `requests_per_second` applies. Defaults
+    /// to 10.
+    pub burst: u32,
+    /// Whether GET responses with an `ETag` are kept on disk and asked for again with
+    /// `If-None-Match`, so that unchanged listings aren't downloaded again. Defaults to
+    /// `false`; it only applies to clients given a directory for the responses.
+    pub etag_cache: bool,
+}
+
+/// The lowest `requests_per_second`. Waits at lower rates can grow too long for a `Duration`.
+const MIN_REQUESTS_PER_SECOND: f64 = 0.001;
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            connect_timeout_secs: 10,
+            timeout_secs: 60,
+            proxy: None,
+            no_proxy: None,
+            ca_certificate: None,
+            client_certificate: None,
+            client_key: None,
+            requests_per_second: None,
+            burst: 10,
+            etag_cache: false,
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Checks the options.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the options are valid.
+    /// * `Err(String)` - What is wrong with them.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.connect_timeout_secs == 0 || self.timeout_secs == 0 {
+            return Err("http timeouts must be at least 1 second".to_string());
+        }
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy.as_str())
+                .map_err(|e| format!("Invalid http proxy `{}`: {}", proxy, e))?;
+        }
+        if self
+            .requests_per_second
+            .is_some_and(|rate| !(rate.is_finite() && rate >= MIN_REQUESTS_PER_SECOND))
+        {
+            return Err(format!(
+                "http.requests_per_second must be at least {}",
+                MIN_REQUESTS_PER_SECOND
+            ));
+        }
+        if self.burst == 0 {
+            return Err("http.burst must be at least 1".to_string());
+        }
+        if self.client_certificate.is_some() != self.client_key.is_some() {
+            return Err(
+                "http.client_certificate and http.client_key must be set together".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the connect timeout.
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// Returns the request timeout.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+
+    /// Returns the configured proxy, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(reqwest::Proxy))` - The proxy, excluding the `no_proxy` hosts.
+    /// * `Ok(None)` - If no proxy is configured, so the environment variables apply.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the proxy URL is invalid.
+    pub(super) fn proxy(&self) -> Result<Option<reqwest::Proxy>, Box<dyn std::error::Error>> {
+        let Some(proxy) = &self.proxy else {
+            return Ok(None);
+        };
+        let no_proxy = self
+            .no_proxy
+            .as_deref()
+            .and_then(reqwest::NoProxy::from_string);
+        Ok(Some(
+            reqwest::Proxy::all(proxy.as_str())?.no_proxy(no_proxy),
+        ))
+    }
+
+    /// Reads the CA certificates to trust in addition to the system's.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<reqwest::Certificate>)` - The certificates, none if no file is configured.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the file can't be read or parsed.
+    pub(super) fn root_certificates(
+        &self,
+    ) -> Result<Vec<reqwest::Certificate>, Box<dyn std::error::Error>> {
+        let Some(path) = &self.ca_certificate else {
+            return Ok(vec![]);
+        };
+        let certificates = reqwest::Certificate::from_pem_bundle(&read_pem(path)?)
+            .map_err(|e| format!("Invalid CA certificate {}: {}", path.display(), e))?;
+        match certificates.is_empty() {
+            true => Err(format!("No certificates in {}", path.display()).into()),
+            false => Ok(certificates),
+        }
+    }
+
+    /// Reads the client certificate and key for mutual TLS.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(reqwest::Identity))` - The identity to present.
+    /// * `Ok(None)` - If no client certificate is configured.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the files can't be read or parsed.
+    pub(super) fn identity(&self) -> Result<Option<reqwest::Identity>, Box<dyn std::error::Error>> {
+        let (Some(certificate), Some(key)) = (&self.client_certificate, &self.client_key) else {
+            return Ok(None);
+        };
+        let identity = reqwest::Identity::from_pkcs8_pem(&read_pem(certificate)?, &read_pem(key)?)
+            .map_err(|e| {
+                format!(
+                    "Invalid client certificate {} or key {}: {}",
+                    certificate.display(),
+                    key.display(),
+                    e
+                )
+            })?;
+        Ok(Some(identity))
+    }
+}
+
+/// Reads a PEM file.
+fn read_pem(path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e).into())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_options() {
+        let options: HttpOptions = serde_json::from_value(serde_json::json!({
+            "connect_timeout_secs": 3,
+            "proxy": "http://proxy.corp:3128",
+        }))
+        .unwrap();
+        assert_eq!(options.connect_timeout(), Duration::from_secs(3));
+        assert_eq!(options.timeout(), Duration::from_secs(60));
+        assert!(options.validate().is_ok());
+        assert!(options.proxy().unwrap().is_some());
+        assert!(HttpOptions::default().proxy().unwrap().is_none());
+
+        let zero = HttpOptions {
+            timeout_secs: 0,
+            ..HttpOptions::default()
+        };
+        assert!(zero.validate().is_err());
+        let invalid = HttpOptions {
+            proxy: Some("not a url".to_string()),
+            ..HttpOptions::default()
+        };
+        assert!(invalid.validate().is_err());
+        let key_only = HttpOptions {
+            client_key: Some(PathBuf::from("/etc/bcls/client-key.pem")),
+            ..HttpOptions::default()
+        };
+        assert!(key_only.validate().is_err());
+        let stalled = HttpOptions {
+            requests_per_second: Some(0.0),
+            ..HttpOptions::default()
+        };
+        assert!(stalled.validate().is_err());
+        let tiny = HttpOptions {
+            requests_per_second: Some(1e-300),
+            ..HttpOptions::default()
+        };
+        assert_eq!(
+            tiny.validate(),
+            Err("http.requests_per_second must be at least 0.001".to_string())
+        );
+        let slowest = HttpOptions {
+            requests_per_second: Some(MIN_REQUESTS_PER_SECOND),
+            ..HttpOptions::default()
+        };
+        assert!(slowest.validate().is_ok());
+    }
+
+    #[test]
+    fn test_certificate_files() {
+        assert!(HttpOptions::default()
+            .root_certificates()
+            .unwrap()
+            .is_empty());
+        assert!(HttpOptions::default().identity().unwrap().is_none());
+
+        let path = std::env::temp_dir().join(format!("bcls-ca-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a certificate\n").unwrap();
+        let options = HttpOptions {
+            ca_certificate: Some(path.clone()),
+            ..HttpOptions::default()
+        };
+        let error = options.root_certificates().unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("bcls-ca-"), "{}", error);
+
+        let missing = HttpOptions {
+            client_certificate: Some(PathBuf::from("/nonexistent/client.pem")),
+            client_key: Some(PathBuf::from("/nonexistent/client-key.pem")),
+            ..HttpOptions::default()
+        };
+        assert!(missing
+            .identity()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Failed to read /nonexistent/client.pem"));
+    }
+}
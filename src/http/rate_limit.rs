@@ -0,0 +1,126 @@
+//! This module limits how fast requests are sent to a project, so that listing many zones or
+//! projects at once stays under the Compute Engine API's per-project read quota instead of
+//! getting everyone sharing the project throttled.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The limiters of the process, keyed by project.
+static SHARED: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+
+/// A token bucket: requests may be sent in a burst, and then at a steady rate.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The requests allowed per second.
+    rate: f64,
+    /// How many requests may be sent at once after a quiet spell.
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// The requests that may be sent now, negative if requests are already waiting.
+    tokens: f64,
+    /// When `tokens` was last brought up to date.
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with a full bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests_per_second` - The steady rate, at least 0.001.
+    /// * `burst` - How many requests may be sent at once, at least 1.
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        RateLimiter {
+            rate: requests_per_second,
+            burst: f64::from(burst.max(1)),
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(burst.max(1)),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns the limiter of a project, creating it on first use, so that all clients of
+    /// the project in this process share one bucket. The rate of the first call applies.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - The project the requests are made to.
+    /// * `requests_per_second` - The steady rate, at least 0.001.
+    /// * `burst` - How many requests may be sent at once, at least 1.
+    pub fn shared(project: &str, requests_per_second: f64, burst: u32) -> Arc<Self> {
+        SHARED
+            .get_or_init(Default::default)
+            .lock()
+            .expect("no thread panics holding the lock")
+            .entry(project.to_string())
+            .or_insert_with(|| Arc::new(RateLimiter::new(requests_per_second, burst)))
+            .clone()
+    }
+
+    /// Waits until a request may be sent.
+    pub fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tracing::debug!(wait_ms = wait.as_millis() as u64, "rate limited");
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Takes a token for a request, and returns how long to wait before sending it. Waiting
+    /// requests reserve tokens in turn, so they are sent in the order they asked.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self
+            .bucket
+            .lock()
+            .expect("no thread panics holding the lock");
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = bucket.updated.max(now);
+        bucket.tokens -= 1.0;
+        match bucket.tokens >= 0.0 {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64(-bucket.tokens / self.rate),
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(2.0, 2);
+        let start = limiter.bucket.lock().unwrap().updated;
+
+        // A burst, then requests queue at the steady rate
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(start), Duration::from_secs(1));
+
+        // The queue drains, then the bucket refills up to the burst
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_shared_per_project() {
+        let a = RateLimiter::shared("rate-limit-test-a", 5.0, 5);
+        let b = RateLimiter::shared("rate-limit-test-b", 5.0, 5);
+        assert!(Arc::ptr_eq(
+            &a,
+            &RateLimiter::shared("rate-limit-test-a", 1.0, 1)
+        ));
+        assert!(!Arc::ptr_eq(&a, &b));
+    }
+}
@@ -0,0 +1,447 @@
+//! This module provides an `HttpClient` wrapper that retries transient failures, such as the
+//! 429 and 503 responses aggregated lists return under load, with jittered exponential backoff.
+//!
+//! A POST that got no response may have been acted on, so it is only sent again if that is
+//! safe: Compute Engine mutations carry a `requestId` the API acts on once, and some Compute
+//! Engine methods only read. Other POSTs, such as webhook posts, are only retried when the
+//! server answered with a retryable status.
+
+use super::{HttpClient, HttpError, RateLimiter, TraceEntry, TraceLog};
+use ring::rand::SecureRandom;
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// HTTP statuses worth retrying: rate limiting and server-side failures.
+const RETRYABLE_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// Compute Engine methods that are POSTed but only read, so they can always be sent again.
+const READ_ONLY_POST_METHODS: [&str; 2] = ["wait", "listManagedInstances"];
+
+/// Controls how often and how long `RetryingHttp` retries, read from the `[retry]` section of
+/// the config file.
+///
+/// ```toml
+/// [retry]
+/// max_attempts = 5
+/// initial_backoff_ms = 500
+/// max_backoff_ms = 30000
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first. `1` disables retries. Defaults to 5.
+    pub max_attempts: u32,
+    /// The backoff before the first retry, doubled for each further retry. Defaults to 500ms.
+    pub initial_backoff_ms: u64,
+    /// The upper bound for any single backoff, including a server's `Retry-After`. Defaults to
+    /// 30s.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Computes how long to wait before the next attempt.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - The number of the retry, starting at 1.
+    /// * `retry_after` - The delay the server asked for, which takes precedence.
+    /// * `jitter` - A random number in `[0, 1)`. Half of the backoff is randomised by it so
+    ///   that concurrent clients don't retry in lockstep.
+    fn backoff(&self, retry: u32, retry_after: Option<Duration>, jitter: f64) -> Duration {
+        let max = Duration::from_millis(self.max_backoff_ms);
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(max);
+        }
+        let exponential = Duration::from_millis(self.initial_backoff_ms)
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(max);
+        exponential.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// An `HttpClient` that retries requests failing with a retryable status or without any
+/// response, waiting with jittered exponential backoff between attempts.
+pub struct RetryingHttp<H: HttpClient> {
+    /// The client to send requests with.
+    inner: H,
+    /// When and how long to retry.
+    policy: RetryPolicy,
+    /// Where to record a summary of each request, if anywhere.
+    trace: Option<Arc<TraceLog>>,
+    /// How fast requests may be sent, if limited.
+    rate_limit: Option<Arc<RateLimiter>>,
+}
+
+impl<H: HttpClient> RetryingHttp<H> {
+    /// Creates a new `RetryingHttp`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The client to send requests with.
+    /// * `policy` - When and how long to retry.
+    pub fn new(inner: H, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            trace: None,
+            rate_limit: None,
+        }
+    }
+
+    /// Records a summary of each request, after any retries, in a trace log.
+    ///
+    /// # Arguments
+    ///
+    /// * `trace` - The trace log, which may be shared with other clients.
+    pub fn with_trace(mut self, trace: Arc<TraceLog>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Waits for a rate limiter before each attempt, retries included.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate_limit` - The limiter, which may be shared with other clients of the project.
+    pub fn with_rate_limit(mut self, rate_limit: Arc<RateLimiter>) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Calls `send` until it succeeds, fails permanently or runs out of attempts, and traces
+    /// the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method, for the trace.
+    /// * `url` - The URL of the request, for the trace.
+    /// * `idempotent` - Whether the request may be sent again after failing without a
+    ///   response, when the server may have acted on it.
+    /// * `send` - Sends the request once.
+    fn retry(
+        &self,
+        method: &str,
+        url: &str,
+        idempotent: bool,
+        send: impl Fn() -> Result<JsonValue, Box<dyn std::error::Error>>,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        let time = chrono::Utc::now();
+        let started = Instant::now();
+        let (result, retries) = self.attempts(idempotent, send);
+        if let Some(trace) = &self.trace {
+            trace.record(&TraceEntry::new(
+                method,
+                url,
+                time,
+                started.elapsed(),
+                &result,
+                retries,
+            ));
+        }
+        result
+    }
+
+    /// Calls `send` until it succeeds, fails permanently or runs out of attempts.
+    ///
+    /// # Returns
+    ///
+    /// The result of the last attempt and the number of retries before it.
+    fn attempts(
+        &self,
+        idempotent: bool,
+        send: impl Fn() -> Result<JsonValue, Box<dyn std::error::Error>>,
+    ) -> (Result<JsonValue, Box<dyn std::error::Error>>, u32) {
+        let mut attempt = 1;
+        loop {
+            if let Some(rate_limit) = &self.rate_limit {
+                rate_limit.acquire();
+            }
+            let error = match send() {
+                Err(e) if attempt < self.policy.max_attempts => e,
+                result => return (result, attempt - 1),
+            };
+            let retry_after = match error.downcast_ref::<HttpError>() {
+                Some(http_error) if is_retryable(http_error, idempotent) => http_error.retry_after,
+                _ => return (Err(error), attempt - 1),
+            };
+            let backoff = self.policy.backoff(attempt, retry_after, jitter());
+            eprintln!(
+                "warning: {}; retrying in {:.1}s ({}/{})",
+                error,
+                backoff.as_secs_f64(),
+                attempt,
+                self.policy.max_attempts - 1
+            );
+            std::thread::sleep(backoff);
+            attempt += 1;
+        }
+    }
+}
+
+/// Whether a failed request may succeed if sent again.
+///
+/// # Arguments
+///
+/// * `error` - Why the request failed.
+/// * `idempotent` - Whether the request may be sent again if there was no response.
+fn is_retryable(error: &HttpError, idempotent: bool) -> bool {
+    match error.status {
+        Some(status) => RETRYABLE_STATUSES.contains(&status),
+        None => idempotent,
+    }
+}
+
+/// Whether an authenticated POST may be sent again after failing without a response: a
+/// Compute Engine mutation with a `requestId`, or a Compute Engine method that only reads.
+fn is_idempotent_post(url: &str) -> bool {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let method = path.rsplit('/').next().unwrap_or_default();
+    query
+        .split('&')
+        .any(|param| param.starts_with("requestId="))
+        || READ_ONLY_POST_METHODS.contains(&method)
+}
+
+/// Returns a random number in `[0, 1)`, or `0.5` if the system RNG is unavailable.
+fn jitter() -> f64 {
+    let mut bytes = [0u8; 8];
+    match ring::rand::SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64,
+        Err(_) => 0.5,
+    }
+}
+
+impl<H: HttpClient> HttpClient for RetryingHttp<H> {
+    fn get(&self, token: &str, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.retry("GET", url, true, || self.inner.get(token, url))
+    }
+
+    fn post(
+        &self,
+        token: &str,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.retry("POST", url, is_idempotent_post(url), || {
+            self.inner.post(token, url, body)
+        })
+    }
+
+    fn get_metadata(&self, url: &str) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.retry("GET", url, true, || self.inner.get_metadata(url))
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.retry("POST", url, false, || self.inner.post_json(url, body))
+    }
+
+    fn post_form(
+        &self,
+        url: &str,
+        form: &JsonValue,
+    ) -> Result<JsonValue, Box<dyn std::error::Error>> {
+        self.retry("POST", url, false, || self.inner.post_form(url, form))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::MockHttpClient;
+    use serde_json::json;
+
+    fn http_error(status: Option<u16>) -> Box<dyn std::error::Error> {
+        Box::new(HttpError {
+            status,
+            message: "error".to_string(),
+            api_status: None,
+            client_request_id: "c-1".to_string(),
+            server_request_id: None,
+            retry_after: None,
+        })
+    }
+
+    /// A policy that doesn't wait, so tests run quickly.
+    fn no_backoff(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff_ms: 0,
+            max_backoff_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+        mock_http
+            .expect_get()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(http_error(Some(503))));
+        mock_http
+            .expect_get()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(http_error(None)));
+        mock_http
+            .expect_get()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(json!({"items": {}})));
+
+        let http = RetryingHttp::new(mock_http, no_backoff(5));
+        assert_eq!(http.get("token", "url").unwrap(), json!({"items": {}}));
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .times(3)
+            .returning(|_, _| Err(http_error(Some(429))));
+
+        let http = RetryingHttp::new(mock_http, no_backoff(3));
+        assert!(http.get("token", "url").is_err());
+    }
+
+    #[test]
+    fn test_does_not_retry_permanent_errors() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_post()
+            .times(1)
+            .returning(|_, _, _| Err(http_error(Some(403))));
+        mock_http
+            .expect_get()
+            .times(1)
+            .returning(|_, _| Err("not an HTTP error".into()));
+
+        let http = RetryingHttp::new(mock_http, no_backoff(5));
+        assert!(http.post("token", "url", &json!({})).is_err());
+        assert!(http.get("token", "url").is_err());
+    }
+
+    #[test]
+    fn test_posts_without_response() {
+        let mut mock_http = MockHttpClient::new();
+        // Sent again: the API acts on the request ID once
+        let start =
+            "https://compute.googleapis.com/compute/v1/projects/p/zones/z/instances/i/start";
+        let start_with_id = format!("{}?requestId=6f1c", start);
+        let mut seq = mockall::Sequence::new();
+        mock_http
+            .expect_post()
+            .withf(|_, url, _| url.ends_with("requestId=6f1c"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Err(http_error(None)));
+        mock_http
+            .expect_post()
+            .withf(|_, url, _| url.ends_with("requestId=6f1c"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Ok(json!({"name": "op"})));
+        // Not sent again: the instance may have been started already
+        mock_http
+            .expect_post()
+            .withf(move |_, url, _| url == start)
+            .times(1)
+            .returning(|_, _, _| Err(http_error(None)));
+        // Not sent again without a response, but with a retryable status
+        mock_http
+            .expect_post_json()
+            .times(1)
+            .returning(|_, _| Err(http_error(None)));
+        mock_http
+            .expect_post_form()
+            .times(2)
+            .returning(|_, _| Err(http_error(Some(503))));
+
+        let http = RetryingHttp::new(mock_http, no_backoff(2));
+        assert!(http.post("token", &start_with_id, &json!({})).is_ok());
+        assert!(http.post("token", start, &json!({})).is_err());
+        assert!(http
+            .post_json("https://hooks.example.com", &json!({}))
+            .is_err());
+        assert!(http
+            .post_form("https://oauth2.example.com", &json!({}))
+            .is_err());
+
+        assert!(is_idempotent_post(
+            "https://compute.googleapis.com/compute/v1/projects/p/zones/z/operations/op/wait"
+        ));
+        assert!(!is_idempotent_post(
+            "https://logging.googleapis.com/v2/entries:list"
+        ));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        // Half of the exponential backoff is jittered
+        assert_eq!(policy.backoff(1, None, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(3, None, 0.0), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(3, None, 0.5), Duration::from_millis(1500));
+        // Backoff is capped, and the server's Retry-After takes precedence within the cap
+        assert_eq!(policy.backoff(20, None, 0.0), Duration::from_secs(15));
+        assert_eq!(
+            policy.backoff(1, Some(Duration::from_secs(7)), 0.0),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            policy.backoff(1, Some(Duration::from_secs(600)), 0.0),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_traces_requests_with_retries() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+        mock_http
+            .expect_post()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Err(http_error(Some(429))));
+        mock_http
+            .expect_post()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| Err(http_error(Some(404))));
+
+        let path = std::env::temp_dir().join(format!(
+            "bcls-test-{}-retry-trace.jsonl",
+            std::process::id()
+        ));
+        let trace = Arc::new(TraceLog::create(&path).unwrap());
+        let http = RetryingHttp::new(mock_http, no_backoff(5)).with_trace(trace);
+        assert!(http.post("token", "url", &json!({})).is_err());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let entry = serde_json::from_str::<JsonValue>(contents.trim()).unwrap();
+        assert_eq!(entry["method"], "POST");
+        assert_eq!(entry["endpoint"], "url");
+        assert_eq!(entry["status"], 404);
+        assert_eq!(entry["retries"], 1);
+    }
+}
@@ -0,0 +1,197 @@
+//! This module writes an audit trail of API calls as JSON lines, one per request, e.g. to
+//! profile a slow run or to show that a run only read from the API.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A summary of one API request and its response, including any retries.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceEntry {
+    /// When the request was sent.
+    pub time: DateTime<Utc>,
+    /// The HTTP method, e.g. `GET`.
+    pub method: String,
+    /// The URL of the request, including its query parameters.
+    pub endpoint: String,
+    /// How long the request took, including retries and the backoff between them.
+    pub duration_ms: u64,
+    /// Whether the request eventually succeeded.
+    pub ok: bool,
+    /// The HTTP status of a failed request, or `None` if it succeeded or got no response.
+    pub status: Option<u16>,
+    /// The number of items in a successful list response, e.g. the instances on a page.
+    pub items: Option<usize>,
+    /// How often the request was retried.
+    pub retries: u32,
+    /// The error of a failed request.
+    pub error: Option<String>,
+}
+
+impl TraceEntry {
+    /// Summarises a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method.
+    /// * `endpoint` - The URL of the request.
+    /// * `time` - When the request was sent.
+    /// * `duration` - How long the request took, including retries.
+    /// * `result` - The response or the error of the last attempt.
+    /// * `retries` - How often the request was retried.
+    pub fn new(
+        method: &str,
+        endpoint: &str,
+        time: DateTime<Utc>,
+        duration: Duration,
+        result: &Result<JsonValue, Box<dyn std::error::Error>>,
+        retries: u32,
+    ) -> Self {
+        let (ok, status, items, error) = match result {
+            Ok(response) => (true, None, item_count(response), None),
+            Err(e) => {
+                let status = e
+                    .downcast_ref::<super::HttpError>()
+                    .and_then(|http_error| http_error.status);
+                (false, status, None, Some(e.to_string()))
+            }
+        };
+        TraceEntry {
+            time,
+            method: method.to_string(),
+            endpoint: endpoint.to_string(),
+            duration_ms: duration.as_millis().try_into().unwrap_or(u64::MAX),
+            ok,
+            status,
+            items,
+            retries,
+            error,
+        }
+    }
+}
+
+/// Counts the items in a list response: the elements of `items` for plain lists, or the
+/// resources in every scope for aggregated lists, whose `items` map each zone or region to an
+/// object holding a list named after the resource.
+fn item_count(response: &JsonValue) -> Option<usize> {
+    match &response["items"] {
+        JsonValue::Array(items) => Some(items.len()),
+        JsonValue::Object(scopes) => Some(
+            scopes
+                .values()
+                .filter_map(JsonValue::as_object)
+                .flat_map(|scope| scope.values())
+                .filter_map(JsonValue::as_array)
+                .map(Vec::len)
+                .sum(),
+        ),
+        _ => None,
+    }
+}
+
+/// A file the summaries of API requests are appended to, one JSON object per line. It can be
+/// shared between threads.
+#[derive(Debug)]
+pub struct TraceLog {
+    /// The trace file.
+    file: Mutex<File>,
+}
+
+impl TraceLog {
+    /// Creates the trace file, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the trace file.
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(TraceLog {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    /// Appends a request summary. A trace that can't be written doesn't fail the request, so
+    /// write errors are only reported on stderr.
+    pub fn record(&self, entry: &TraceEntry) {
+        let line = serde_json::to_string(entry).expect("trace entries always serialize") + "\n";
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            eprintln!("warning: failed to write trace: {}", e);
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_item_count() {
+        let aggregated = json!({"items": {
+            "zones/us-east1-b": {"instances": [{}, {}]},
+            "zones/us-east1-c": {"warning": {"code": "NO_RESULTS_ON_PAGE"}},
+            "zones/us-east1-d": {"instances": [{}]}
+        }});
+        assert_eq!(item_count(&aggregated), Some(3));
+        assert_eq!(item_count(&json!({"items": [{}, {}]})), Some(2));
+        assert_eq!(item_count(&json!({"status": "DONE"})), None);
+    }
+
+    #[test]
+    fn test_trace_log_writes_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("bcls-test-{}-trace.jsonl", std::process::id()));
+        let log = TraceLog::create(&path).unwrap();
+        let time = DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let url = "https://compute.googleapis.com/compute/v1/projects/p/zones";
+
+        log.record(&TraceEntry::new(
+            "GET",
+            url,
+            time,
+            Duration::from_millis(120),
+            &Ok(json!({"items": [{}]})),
+            0,
+        ));
+        log.record(&TraceEntry::new(
+            "POST",
+            url,
+            time,
+            Duration::from_millis(5),
+            &Err("connection refused".into()),
+            4,
+        ));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = contents
+            .lines()
+            .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            json!({
+                "time": "2024-03-01T12:00:00Z",
+                "method": "GET",
+                "endpoint": url,
+                "duration_ms": 120,
+                "ok": true,
+                "status": null,
+                "items": 1,
+                "retries": 0,
+                "error": null
+            })
+        );
+        assert_eq!(lines[1]["ok"], false);
+        assert_eq!(lines[1]["retries"], 4);
+        assert_eq!(lines[1]["error"], "connection refused");
+    }
+}
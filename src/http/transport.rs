@@ -0,0 +1,353 @@
+//! This module routes API traffic through a SOCKS5 proxy or an SSH jump host, for habitats
+//! whose googleapis.com endpoints are only reachable from a bastion network.
+//!
+//! `reqwest` can't open such connections itself, so `LocalProxy` listens on localhost as an
+//! HTTPS (`CONNECT`) proxy and forwards each tunnel through the transport. TLS still runs
+//! end-to-end between bcls and Google.
+
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+
+/// The domain whose hosts are tunneled. Other requests, e.g. to webhooks or the metadata
+/// server, are sent directly.
+const TUNNELED_DOMAIN: &str = ".googleapis.com";
+
+/// How API connections are made, set per habitat in the config file.
+///
+/// ```toml
+/// [prd]
+/// project = "my-prd-project"
+/// transport = { socks5 = "127.0.0.1:1080" }
+/// # or
+/// transport = { ssh_jump = "me@bastion.example.com" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// Connect to the API directly. The default.
+    #[default]
+    Direct,
+    /// Connect through a SOCKS5 proxy at `host:port`, which resolves the API hosts.
+    Socks5(String),
+    /// Connect through an SSH jump host, as with `ssh -W`. The destination is passed to `ssh`
+    /// as is, so it can be a `user@host` or an alias from `~/.ssh/config`. Authentication must
+    /// not need a prompt, e.g. by using an agent.
+    SshJump(String),
+}
+
+/// A connection to an API host opened through a transport.
+enum Upstream {
+    /// A TCP connection, e.g. through a SOCKS5 proxy.
+    Tcp(TcpStream),
+    /// An `ssh -W` process whose stdin and stdout are the connection.
+    Command(Child),
+}
+
+impl Transport {
+    /// Whether connections go through a proxy or jump host.
+    pub fn is_direct(&self) -> bool {
+        *self == Transport::Direct
+    }
+
+    /// Opens a connection to `host:port` through the transport.
+    fn connect(&self, host: &str, port: u16) -> io::Result<Upstream> {
+        match self {
+            Transport::Direct => TcpStream::connect((host, port)).map(Upstream::Tcp),
+            Transport::Socks5(proxy) => socks5_connect(proxy, host, port).map(Upstream::Tcp),
+            Transport::SshJump(jump_host) => Command::new("ssh")
+                .args(["-o", "BatchMode=yes", "-W"])
+                .arg(format!("{}:{}", host, port))
+                .arg(jump_host)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map(Upstream::Command),
+        }
+    }
+}
+
+/// A `CONNECT` proxy on localhost that forwards tunnels to googleapis.com hosts through a
+/// transport. It runs on background threads until the process exits.
+pub struct LocalProxy {
+    /// The address the proxy listens on.
+    addr: SocketAddr,
+}
+
+impl LocalProxy {
+    /// Starts a proxy on a free port on localhost.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - How to connect to the hosts requested by clients.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(LocalProxy)` - The running proxy.
+    /// * `Err(io::Error)` - An error if no port could be bound.
+    pub fn start(transport: Transport) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        std::thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let transport = transport.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_client(client, &transport) {
+                        eprintln!("warning: tunnel failed: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(LocalProxy { addr })
+    }
+
+    /// Returns the proxy URL to configure clients with, e.g. `http://127.0.0.1:40123`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Whether requests to a host should go through the proxy.
+    pub fn is_tunneled(host: &str) -> bool {
+        host.ends_with(TUNNELED_DOMAIN)
+    }
+}
+
+/// Reads a client's `CONNECT` request, opens the tunnel and forwards data both ways.
+fn handle_client(client: TcpStream, transport: &Transport) -> io::Result<()> {
+    let mut reader = BufReader::new(client.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers up to the blank line that ends them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (host, port) = match parse_connect(&request_line) {
+        Some((host, port)) if LocalProxy::is_tunneled(host) => (host.to_string(), port),
+        _ => {
+            (&client).write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n")?;
+            return Err(io::Error::other(format!(
+                "refused request `{}`",
+                request_line.trim_end()
+            )));
+        }
+    };
+    let upstream = match transport.connect(&host, port) {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            (&client).write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n")?;
+            return Err(io::Error::other(format!("{}:{}: {}", host, port, e)));
+        }
+    };
+    (&client).write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+    splice(reader, client, upstream)
+}
+
+/// Parses a request line like `CONNECT compute.googleapis.com:443 HTTP/1.1`.
+fn parse_connect(request_line: &str) -> Option<(&str, u16)> {
+    let mut parts = request_line.split_whitespace();
+    if parts.next()? != "CONNECT" {
+        return None;
+    }
+    let (host, port) = parts.next()?.rsplit_once(':')?;
+    Some((host, port.parse().ok()?))
+}
+
+/// Opens a connection to `host:port` through a SOCKS5 proxy, without authentication.
+/// <https://www.rfc-editor.org/rfc/rfc1928>
+fn socks5_connect(proxy: &str, host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy)?;
+    stream.write_all(&[5, 1, 0])?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method)?;
+    if method != [5, 0] {
+        return Err(io::Error::other(format!(
+            "SOCKS5 proxy {} requires unsupported authentication",
+            proxy
+        )));
+    }
+
+    // Let the proxy resolve the host name, as it may not resolve on this side
+    let host_len = u8::try_from(host.len()).map_err(|_| io::Error::other("host name too long"))?;
+    let mut request = vec![5, 1, 0, 3, host_len];
+    request.extend(host.as_bytes());
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 proxy {} failed to connect to {}:{} (reply code {})",
+            proxy, host, port, reply[1]
+        )));
+    }
+    // Skip the bound address, which clients don't need
+    let addr_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        other => {
+            return Err(io::Error::other(format!(
+                "SOCKS5 proxy {} sent unknown address type {}",
+                proxy, other
+            )))
+        }
+    };
+    stream.read_exact(&mut vec![0u8; addr_len + 2])?;
+    Ok(stream)
+}
+
+/// Forwards data between a client and an upstream connection until the upstream closes.
+///
+/// # Arguments
+///
+/// * `client_reader` - The client's read side, which may hold data buffered after the
+///   `CONNECT` request.
+/// * `client` - The client connection, for writing.
+/// * `upstream` - The connection to the API host.
+fn splice(
+    mut client_reader: BufReader<TcpStream>,
+    client: TcpStream,
+    upstream: Upstream,
+) -> io::Result<()> {
+    let (mut upstream_reader, mut upstream_writer, child): (
+        Box<dyn Read>,
+        Box<dyn Write + Send>,
+        Option<Child>,
+    ) = match upstream {
+        Upstream::Tcp(stream) => (
+            Box::new(stream.try_clone()?),
+            Box::new(ShutdownOnDrop(stream)),
+            None,
+        ),
+        Upstream::Command(mut child) => {
+            let stdin = child
+                .stdin
+                .take()
+                .ok_or("ssh has no stdin")
+                .map_err(io::Error::other)?;
+            let stdout = child
+                .stdout
+                .take()
+                .ok_or("ssh has no stdout")
+                .map_err(io::Error::other)?;
+            (Box::new(stdout), Box::new(stdin), Some(child))
+        }
+    };
+
+    // Dropping the writer when the client is done tells the upstream there is no more data
+    let outbound = std::thread::spawn(move || io::copy(&mut client_reader, &mut upstream_writer));
+    let inbound = io::copy(&mut upstream_reader, &mut &client);
+    // Stop the outbound copy too, if the client hasn't closed its side yet
+    let _ = client.shutdown(Shutdown::Both);
+    let _ = outbound.join();
+    if let Some(mut child) = child {
+        child.wait()?;
+    }
+    inbound.map(|_| ())
+}
+
+/// A TCP stream whose write side is shut down when it is dropped.
+struct ShutdownOnDrop(TcpStream);
+
+impl Write for ShutdownOnDrop {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Drop for ShutdownOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Write);
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts a SOCKS5 server that accepts one connection to `compute.googleapis.com:443` and
+    /// echoes everything sent through it.
+    fn echo_socks5_server() -> SocketAddr {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+
+            let host = b"compute.googleapis.com";
+            let mut request = vec![0u8; 5 + host.len() + 2];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(request[..5], [5, 1, 0, 3, host.len() as u8]);
+            assert_eq!(&request[5..5 + host.len()], host);
+            assert_eq!(request[5 + host.len()..], 443u16.to_be_bytes());
+            stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+
+            let mut reader = stream.try_clone().unwrap();
+            io::copy(&mut reader, &mut stream).unwrap();
+        });
+        addr
+    }
+
+    /// Sends a `CONNECT` request to the proxy and returns the connection and response.
+    fn connect(proxy: &LocalProxy, target: &str) -> (TcpStream, String) {
+        let mut stream = TcpStream::connect(proxy.addr).unwrap();
+        write!(
+            stream,
+            "CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n",
+            target, target
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        let mut blank = String::new();
+        reader.read_line(&mut blank).unwrap();
+        (stream, response)
+    }
+
+    #[test]
+    fn test_socks5_tunnel() {
+        let socks = echo_socks5_server();
+        let proxy = LocalProxy::start(Transport::Socks5(socks.to_string())).unwrap();
+
+        let (mut stream, response) = connect(&proxy, "compute.googleapis.com:443");
+        assert_eq!(response, "HTTP/1.1 200 Connection established\r\n");
+        stream.write_all(b"ping").unwrap();
+        let mut echo = [0u8; 4];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"ping");
+    }
+
+    #[test]
+    fn test_refuses_other_hosts() {
+        let proxy = LocalProxy::start(Transport::Socks5("127.0.0.1:9".to_string())).unwrap();
+        let (_, response) = connect(&proxy, "example.com:443");
+        assert_eq!(response, "HTTP/1.1 403 Forbidden\r\n");
+    }
+
+    #[test]
+    fn test_parse_connect() {
+        assert_eq!(
+            parse_connect("CONNECT compute.googleapis.com:443 HTTP/1.1\r\n"),
+            Some(("compute.googleapis.com", 443))
+        );
+        assert_eq!(parse_connect("GET / HTTP/1.1\r\n"), None);
+        assert_eq!(parse_connect("CONNECT example.com HTTP/1.1\r\n"), None);
+    }
+}
@@ -0,0 +1,273 @@
+//! This module provides the pieces of `bcls config init`: discovering the projects the user can
+//! see with gcloud, guessing which one belongs to each environment, reading the user's choices
+//! and writing a starter config file.
+
+use crate::config::Environment;
+use serde_json::Value as JsonValue;
+
+/// A Google Cloud project, as listed by `gcloud projects list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectInfo {
+    /// The project ID, e.g. `acme-prd-42`.
+    pub project_id: String,
+    /// The display name of the project.
+    pub name: String,
+}
+
+/// Lists the projects the current gcloud account can see.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ProjectInfo>)` - The projects, sorted by ID.
+/// * `Err(String)` - An error if gcloud isn't installed, fails or prints something unexpected.
+pub fn discover_projects() -> Result<Vec<ProjectInfo>, String> {
+    let output = std::process::Command::new("gcloud")
+        .args(["projects", "list", "--format=json"])
+        .output()
+        .map_err(|e| format!("Failed to run gcloud: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    parse_projects(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the output of `gcloud projects list --format=json`.
+///
+/// # Arguments
+///
+/// * `output` - The standard output of the gcloud command.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ProjectInfo>)` - The projects, sorted by ID.
+/// * `Err(String)` - An error if the output isn't a list of projects.
+pub fn parse_projects(output: &str) -> Result<Vec<ProjectInfo>, String> {
+    let json = serde_json::from_str::<JsonValue>(output)
+        .map_err(|e| format!("Failed to parse gcloud output: {}", e))?;
+    let mut projects = json
+        .as_array()
+        .ok_or("gcloud didn't print a list of projects")?
+        .iter()
+        .filter_map(|project| {
+            let project_id = project.get("projectId")?.as_str()?.to_string();
+            let name = project
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .unwrap_or(&project_id)
+                .to_string();
+            Some(ProjectInfo { project_id, name })
+        })
+        .collect::<Vec<_>>();
+    projects.sort_by(|a, b| a.project_id.cmp(&b.project_id));
+    Ok(projects)
+}
+
+/// Guesses which project belongs to an environment from the words in the project IDs, e.g.
+/// `acme-prd` or `acme-production` for `prd`.
+///
+/// # Arguments
+///
+/// * `projects` - The projects to choose from.
+/// * `env` - The environment.
+///
+/// # Returns
+///
+/// The ID of the only project that looks like it belongs to the environment, or `None` if no
+/// project or several do.
+pub fn guess_project(projects: &[ProjectInfo], env: Environment) -> Option<&str> {
+    let words: &[&str] = match env {
+        Environment::Int => &["int", "integration", "dev"],
+        Environment::Stg => &["stg", "stage", "staging"],
+        Environment::Prd => &["prd", "prod", "production"],
+    };
+    let mut matching = projects.iter().filter(|project| {
+        project
+            .project_id
+            .split('-')
+            .any(|word| words.contains(&word))
+    });
+    match (matching.next(), matching.next()) {
+        (Some(project), None) => Some(&project.project_id),
+        _ => None,
+    }
+}
+
+/// Checks that a project ID is well-formed: 6 to 30 lowercase letters, digits or hyphens,
+/// starting with a letter and not ending with a hyphen.
+pub fn is_valid_project_id(project_id: &str) -> bool {
+    (6..=30).contains(&project_id.len())
+        && project_id.starts_with(|c: char| c.is_ascii_lowercase())
+        && !project_id.ends_with('-')
+        && project_id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Interprets the user's answer to the question which project belongs to an environment.
+///
+/// # Arguments
+///
+/// * `answer` - What the user typed: nothing to take the default, the number of a listed
+///   project, or a project ID.
+/// * `projects` - The projects that were listed, numbered from 1.
+/// * `default` - The project to take if the answer is empty.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The chosen project ID.
+/// * `Err(String)` - Why the answer can't be used, so the user can be asked again.
+pub fn choose_project(
+    answer: &str,
+    projects: &[ProjectInfo],
+    default: Option<&str>,
+) -> Result<String, String> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return default
+            .map(str::to_string)
+            .ok_or_else(|| "Please enter a project".to_string());
+    }
+    if let Ok(number) = answer.parse::<usize>() {
+        return number
+            .checked_sub(1)
+            .and_then(|index| projects.get(index))
+            .map(|project| project.project_id.clone())
+            .ok_or_else(|| format!("There is no project number {}", number));
+    }
+    if !is_valid_project_id(answer) {
+        return Err(format!("'{}' is not a valid project ID", answer));
+    }
+    Ok(answer.to_string())
+}
+
+/// Renders a starter config file mapping each environment to its project.
+///
+/// # Arguments
+///
+/// * `projects` - The project ID of each environment.
+pub fn render_config(projects: &[(Environment, String)]) -> String {
+    let mut text = String::from(
+        "# Generated by `bcls config init`. See the README for all options, e.g. [output],\n\
+         # [ssh] and [auth].\n",
+    );
+    for (env, project) in projects {
+        text.push_str(&format!(
+            "\n[{}]\nproject = {}\n",
+            env.as_str(),
+            toml_string(project)
+        ));
+    }
+    text
+}
+
+/// Quotes a string for TOML.
+fn toml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projects(ids: &[&str]) -> Vec<ProjectInfo> {
+        ids.iter()
+            .map(|id| ProjectInfo {
+                project_id: id.to_string(),
+                name: id.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_projects() {
+        let output = r#"[
+            {"projectId": "acme-prd", "name": "Acme Production", "projectNumber": "1"},
+            {"projectId": "acme-int", "projectNumber": "2"},
+            {"name": "no id"}
+        ]"#;
+
+        assert_eq!(
+            parse_projects(output).unwrap(),
+            vec![
+                ProjectInfo {
+                    project_id: "acme-int".to_string(),
+                    name: "acme-int".to_string(),
+                },
+                ProjectInfo {
+                    project_id: "acme-prd".to_string(),
+                    name: "Acme Production".to_string(),
+                },
+            ]
+        );
+        assert!(parse_projects("{}").is_err());
+        assert!(parse_projects("ERROR: not logged in").is_err());
+    }
+
+    #[test]
+    fn test_guess_project() {
+        let projects = projects(&["acme-int", "acme-staging-2", "acme-prod", "acme-prd-old"]);
+
+        assert_eq!(guess_project(&projects, Environment::Int), Some("acme-int"));
+        assert_eq!(
+            guess_project(&projects, Environment::Stg),
+            Some("acme-staging-2")
+        );
+        // Two projects look like production, so there's no good guess
+        assert_eq!(guess_project(&projects, Environment::Prd), None);
+        // Words must match whole, "internal" isn't "int"
+        assert_eq!(
+            guess_project(&self::projects(&["acme-internal"]), Environment::Int),
+            None
+        );
+    }
+
+    #[test]
+    fn test_choose_project() {
+        let projects = projects(&["acme-int", "acme-prd"]);
+
+        assert_eq!(
+            choose_project("2", &projects, None),
+            Ok("acme-prd".to_string())
+        );
+        assert_eq!(
+            choose_project("  \n", &projects, Some("acme-int")),
+            Ok("acme-int".to_string())
+        );
+        assert_eq!(
+            choose_project("other-project-1\n", &projects, None),
+            Ok("other-project-1".to_string())
+        );
+        assert!(choose_project("", &projects, None).is_err());
+        assert!(choose_project("0", &projects, None).is_err());
+        assert!(choose_project("3", &projects, None).is_err());
+        assert!(choose_project("Not A Project", &projects, None).is_err());
+    }
+
+    #[test]
+    fn test_is_valid_project_id() {
+        assert!(is_valid_project_id("acme-prd-42"));
+        assert!(!is_valid_project_id("acme"));
+        assert!(!is_valid_project_id("1acme-prd"));
+        assert!(!is_valid_project_id("acme-prd-"));
+        assert!(!is_valid_project_id("acme_prd"));
+    }
+
+    #[test]
+    fn test_rendered_config_parses() {
+        let text = render_config(&[
+            (Environment::Int, "acme-int".to_string()),
+            (Environment::Stg, "acme-stg".to_string()),
+            (Environment::Prd, "acme-prd".to_string()),
+        ]);
+
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(&text, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize::<crate::config::FileConfig>()
+            .unwrap();
+        assert_eq!(config.prd.project, "acme-prd");
+        assert_eq!(config.int.project, "acme-int");
+    }
+}
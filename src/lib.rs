@@ -1,3 +1,37 @@
+//! bcls lists and manages the Compute Engine instances of a set of Google Cloud projects.
+//!
+//! The `bcls` command is built on this library. To use it directly, start with `Client`, which
+//! sets up authentication and retries and lists instances with an `InstanceFilter`.
+
+pub mod auth;
+pub mod cache;
+pub mod client;
+pub mod cloud_logging;
 pub mod compute;
 pub mod config;
+pub mod container;
+pub mod diff;
+pub mod dns;
+pub mod doctor;
+pub mod enrichment;
+pub mod find;
 pub mod http;
+pub mod init;
+pub mod logging;
+pub mod monitoring;
+pub mod osconfig;
+pub mod output;
+pub mod paths;
+pub mod plan;
+pub mod query;
+pub mod report;
+pub mod secrets;
+pub mod source;
+pub mod ssh;
+pub mod ui;
+pub mod version;
+
+pub use client::{CacheMode, Client, ClientBuilder, InstanceQuery};
+pub use compute::Instance;
+pub use http::HttpError;
+pub use query::{Filter, InstanceFilter, InstanceSet, LabelFilter, Status};
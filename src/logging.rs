@@ -0,0 +1,526 @@
+//! This module writes the diagnostic log of a run to stderr, as text or as JSON lines. The
+//! library reports what it does through `tracing` events and spans, e.g. an `http_request`
+//! span around every API call, and this module decides which of them are shown.
+//!
+//! How much is logged is set with `-v` flags or, without them, with `RUST_LOG`, e.g.
+//! `RUST_LOG=bcls::http=trace`. By default only warnings are logged.
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Map as JsonMap, Value as JsonValue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// One line per event, for people.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// Which events are logged: a level per target, e.g. `warn,bcls::http=trace`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    /// The level of targets no directive names.
+    default: LevelFilter,
+    /// Levels of targets and the modules within them.
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            default: LevelFilter::WARN,
+            directives: vec![],
+        }
+    }
+}
+
+impl LogFilter {
+    /// Chooses the filter for a run.
+    ///
+    /// # Arguments
+    ///
+    /// * `verbosity` - How often `-v` was given. Once logs what bcls does, twice every API
+    ///   request, and three times the responses too. Dependencies stay at warnings.
+    /// * `rust_log` - The `RUST_LOG` variable, used only without `-v`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(LogFilter)` - The filter.
+    /// * `Err(String)` - An error if `RUST_LOG` is invalid.
+    pub fn from_args(verbosity: u8, rust_log: Option<&str>) -> Result<Self, String> {
+        let level = match verbosity {
+            0 => return rust_log.map_or(Ok(LogFilter::default()), str::parse),
+            1 => LevelFilter::INFO,
+            2 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        };
+        Ok(LogFilter {
+            directives: vec![("bcls".to_string(), level)],
+            ..LogFilter::default()
+        })
+    }
+
+    /// Whether events of a target and level are logged. The directive for the longest
+    /// matching target applies.
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        let within = |prefix: &str| {
+            target
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        };
+        let filter = self
+            .directives
+            .iter()
+            .filter(|(prefix, _)| within(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, filter)| *filter);
+        filter >= *level
+    }
+
+    /// The most verbose level any target is logged at.
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|(_, filter)| *filter)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+impl std::str::FromStr for LogFilter {
+    type Err = String;
+
+    /// Parses directives in the `RUST_LOG` syntax: a comma-separated list of `level`,
+    /// `target=level` or `target`, which logs everything of the target.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = |level: &str| {
+            level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("invalid log level `{}` in RUST_LOG", level))
+        };
+        let mut filter = LogFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, value)) => {
+                    filter.directives.push((target.to_string(), level(value)?))
+                }
+                None => match level(directive) {
+                    Ok(level) => filter.default = level,
+                    Err(_) => filter
+                        .directives
+                        .push((directive.to_string(), LevelFilter::TRACE)),
+                },
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// The fields of an event or span, in the order they were recorded.
+#[derive(Default)]
+struct Fields(Vec<(String, JsonValue)>);
+
+impl Fields {
+    fn push(&mut self, field: &Field, value: JsonValue) {
+        self.0.push((field.name().to_string(), value));
+    }
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, value.into());
+    }
+}
+
+/// A span that hasn't closed yet.
+struct SpanData {
+    metadata: &'static Metadata<'static>,
+    fields: Fields,
+    created: Instant,
+    /// How many handles to the span exist.
+    refs: usize,
+}
+
+thread_local! {
+    /// The spans the current thread is in, innermost last.
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(vec![]) };
+}
+
+/// A `tracing` subscriber that writes log lines. Each span is logged when it closes, with how
+/// long it was open as `elapsed_ms`, and events within spans carry the spans' fields.
+pub struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+impl Logger {
+    /// Creates a logger.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Which events to log.
+    /// * `format` - How to write them.
+    /// * `writer` - Where to write them, e.g. stderr.
+    pub fn new(filter: LogFilter, format: LogFormat, writer: Box<dyn Write + Send>) -> Self {
+        Logger {
+            filter,
+            format,
+            writer: Mutex::new(writer),
+            spans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Formats and writes one line.
+    fn write(
+        &self,
+        metadata: &Metadata<'_>,
+        message: Option<String>,
+        fields: &[(String, JsonValue)],
+        spans: &[(&'static str, Vec<(String, JsonValue)>)],
+    ) {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let line = match self.format {
+            LogFormat::Json => {
+                let object = |fields: &[(String, JsonValue)]| {
+                    fields.iter().cloned().collect::<JsonMap<_, _>>()
+                };
+                let spans = spans
+                    .iter()
+                    .map(|(name, fields)| {
+                        let mut span = object(fields);
+                        span.insert("name".to_string(), (*name).into());
+                        span
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "timestamp": timestamp,
+                    "level": metadata.level().as_str(),
+                    "target": metadata.target(),
+                    "message": message,
+                    "fields": object(fields),
+                    "spans": spans,
+                })
+                .to_string()
+            }
+            LogFormat::Text => {
+                let pairs = |fields: &[(String, JsonValue)]| {
+                    fields
+                        .iter()
+                        .map(|(key, value)| match value {
+                            JsonValue::String(s) if !s.contains(char::is_whitespace) => {
+                                format!("{}={}", key, s)
+                            }
+                            value => format!("{}={}", key, value),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                let context = spans
+                    .iter()
+                    .map(|(name, fields)| format!("{}{{{}}}: ", name, pairs(fields)))
+                    .collect::<String>();
+                let text = [message.unwrap_or_default(), pairs(fields)]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!(
+                    "{} {:>5} {}: {}{}",
+                    timestamp,
+                    metadata.level().as_str(),
+                    metadata.target(),
+                    context,
+                    text
+                )
+            }
+        };
+        let mut writer = self
+            .writer
+            .lock()
+            .expect("no thread panics holding the lock");
+        // Logging must never fail the command
+        let _ = writeln!(writer, "{}", line);
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.enabled(metadata.target(), metadata.level())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter.max_level())
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans
+            .lock()
+            .expect("no thread panics holding the lock")
+            .insert(
+                id,
+                SpanData {
+                    metadata: attributes.metadata(),
+                    fields,
+                    created: Instant::now(),
+                    refs: 1,
+                },
+            );
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(span) = self
+            .spans
+            .lock()
+            .expect("no thread panics holding the lock")
+            .get_mut(&span.into_u64())
+        {
+            values.record(&mut span.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields
+            .0
+            .iter()
+            .position(|(key, _)| key == "message")
+            .map(|i| match fields.0.remove(i).1 {
+                JsonValue::String(s) => s,
+                value => value.to_string(),
+            });
+        let spans = {
+            let spans = self
+                .spans
+                .lock()
+                .expect("no thread panics holding the lock");
+            ENTERED.with(|entered| {
+                entered
+                    .borrow()
+                    .iter()
+                    .filter_map(|id| spans.get(id))
+                    .map(|span| (span.metadata.name(), span.fields.0.clone()))
+                    .collect::<Vec<_>>()
+            })
+        };
+        self.write(event.metadata(), message, &fields.0, &spans);
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(i) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self
+            .spans
+            .lock()
+            .expect("no thread panics holding the lock")
+            .get_mut(&span.into_u64())
+        {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let closed = {
+            let mut spans = self
+                .spans
+                .lock()
+                .expect("no thread panics holding the lock");
+            match spans.get_mut(&span.into_u64()) {
+                Some(data) if data.refs > 1 => {
+                    data.refs -= 1;
+                    None
+                }
+                Some(_) => spans.remove(&span.into_u64()),
+                None => None,
+            }
+        };
+        match closed {
+            Some(data) => {
+                let mut fields = data.fields.0;
+                let elapsed_ms = data.created.elapsed().as_millis() as u64;
+                fields.push(("elapsed_ms".to_string(), elapsed_ms.into()));
+                let message = format!("{} done", data.metadata.name());
+                self.write(data.metadata, Some(message), &fields, &[]);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sends the log of the run to stderr.
+///
+/// # Arguments
+///
+/// * `filter` - Which events to log.
+/// * `format` - How to write them.
+///
+/// # Returns
+///
+/// * `Ok(())` - If logging was set up.
+/// * `Err(Box<dyn std::error::Error>)` - An error if it was already set up.
+pub fn init(filter: LogFilter, format: LogFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let logger = Logger::new(filter, format, Box::new(std::io::stderr()));
+    tracing::subscriber::set_global_default(logger)
+        .map_err(|e| format!("Failed to set up logging: {}", e).into())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A writer whose output the test can read back.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_filter_from_args() {
+        let quiet = LogFilter::from_args(0, None).unwrap();
+        assert!(quiet.enabled("bcls::http", &Level::WARN));
+        assert!(!quiet.enabled("bcls::http", &Level::INFO));
+
+        let verbose = LogFilter::from_args(2, Some("trace")).unwrap();
+        assert!(verbose.enabled("bcls::http", &Level::DEBUG));
+        assert!(!verbose.enabled("bcls::http", &Level::TRACE));
+        assert!(!verbose.enabled("hyper", &Level::DEBUG));
+
+        // RUST_LOG only applies without -v
+        let rust_log = LogFilter::from_args(0, Some("info,bcls::http=trace,bcls::auth")).unwrap();
+        assert!(rust_log.enabled("hyper", &Level::INFO));
+        assert!(rust_log.enabled("bcls::http::retry", &Level::TRACE));
+        assert!(rust_log.enabled("bcls::auth", &Level::TRACE));
+        assert!(!rust_log.enabled("bcls::compute", &Level::DEBUG));
+        assert!(!rust_log.enabled("bcls::httpx", &Level::DEBUG));
+        assert_eq!(rust_log.max_level(), LevelFilter::TRACE);
+        assert!(LogFilter::from_args(0, Some("bcls=loud")).is_err());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let buffer = Buffer::default();
+        let filter = LogFilter::from_args(2, None).unwrap();
+        let logger = Logger::new(filter, LogFormat::Json, Box::new(buffer.clone()));
+
+        tracing::subscriber::with_default(logger, || {
+            let span = tracing::debug_span!(target: "bcls::http", "http_request", method = "GET");
+            let _entered = span.enter();
+            tracing::debug!(target: "bcls::http", status = 200, "response");
+            tracing::trace!(target: "bcls::http", "not logged at -vv");
+        });
+
+        let lines = buffer
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "DEBUG");
+        assert_eq!(lines[0]["message"], "response");
+        assert_eq!(lines[0]["fields"], json!({"status": 200}));
+        assert_eq!(
+            lines[0]["spans"],
+            json!([{"name": "http_request", "method": "GET"}])
+        );
+        assert_eq!(lines[1]["message"], "http_request done");
+        assert!(lines[1]["fields"]["elapsed_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_text_lines() {
+        let buffer = Buffer::default();
+        let logger = Logger::new(
+            LogFilter::default(),
+            LogFormat::Text,
+            Box::new(buffer.clone()),
+        );
+
+        tracing::subscriber::with_default(logger, || {
+            tracing::warn!(target: "bcls::auth", path = "/tmp/token cache", "cache unreadable");
+            tracing::info!(target: "bcls::auth", "not logged by default");
+        });
+
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].ends_with(r#" WARN bcls::auth: cache unreadable path="/tmp/token cache""#),
+            "{}",
+            lines[0]
+        );
+    }
+}
@@ -1,173 +1,3394 @@
-#[macro_use]
-extern crate prettytable;
-
+use bcls::config::Environment;
 use clap::Parser;
 use config::{Config, File, FileFormat};
-use prettytable::format;
+use std::io::{BufRead, IsTerminal, Write};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 pub struct Args {
     #[clap(subcommand)]
     pub cmd: Command,
+
+    /// Write a JSON line per API request to this file: the endpoint, duration, status,
+    /// number of items and retries
+    #[arg(long, global = true, value_name = "PATH")]
+    pub trace_file: Option<std::path::PathBuf>,
+
+    /// How many resources to request per page when listing, from 1 to 500. Overrides the
+    /// page_size setting of the config file
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..=500))]
+    pub page_size: Option<u32>,
+
+    /// Seconds to wait for a connection before giving up. Overrides the
+    /// http.connect_timeout_secs setting of the config file
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..=3600)
+    )]
+    pub connect_timeout: Option<u64>,
+
+    /// Seconds an HTTP request may take until its whole response is read. Overrides the
+    /// http.timeout_secs setting of the config file
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..=3600)
+    )]
+    pub timeout: Option<u64>,
+
+    /// Send requests through this proxy, e.g. http://proxy.corp:3128, rather than the one in
+    /// the config file or HTTPS_PROXY. API requests of a habitat with a transport still use
+    /// the transport
+    #[arg(long, global = true, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Fail when an instance in a listing can't be parsed, instead of skipping it with a
+    /// warning
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// How to list instances: with one aggregated listing of all zones, or zone by zone,
+    /// several zones at once, which is faster for very large projects. Overrides the
+    /// list_strategy setting of the config file
+    #[arg(long, global = true, value_enum)]
+    pub list_strategy: Option<bcls::compute::ListStrategy>,
+
+    /// How many projects to list at once when a command spans several, e.g. all and find,
+    /// and how many zones with --list-strategy per-zone. Overrides the jobs setting of the
+    /// config file
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..=64)
+    )]
+    pub jobs: Option<u64>,
+
+    /// Read the config from this file instead of $BCLS_CONFIG,
+    /// $XDG_CONFIG_HOME/bcls/config.toml or ~/.bcls/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<std::path::PathBuf>,
+
+    /// Query this Google Cloud project instead of the configured one, with the Compute API.
+    /// Works without a config file; every environment then means this project
+    #[arg(long, global = true, value_name = "ID")]
+    pub project: Option<String>,
+
+    /// Log what bcls does to stderr: -v for progress, -vv for every API request and -vvv
+    /// for the responses too. Without it, RUST_LOG selects what is logged
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// How to write log lines
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub log_format: bcls::logging::LogFormat,
+
+    /// When to color tables, e.g. the status column. auto colors a terminal unless NO_COLOR
+    /// is set. Overrides the color setting of the config file
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    pub color: Option<bcls::output::ColorChoice>,
+
+    /// Don't truncate tables to the width of the terminal
+    #[arg(long, global = true)]
+    pub wide: bool,
+
+    /// Leave out the header row of tables, e.g. for scripts
+    #[arg(long, global = true)]
+    pub no_header: bool,
 }
 
-//#[derive(Parser, Debug)]
-//pub enum Command {
-//    /// List instances in Integration environment
-//    Int(EnvArgs),
-//    /// List instances in Staging environment
-//    Stg(EnvArgs),
-//    /// List instances in Production environment
-//    Prd(EnvArgs),
-//}
 #[derive(Parser, Debug)]
 pub enum Command {
     /// List instances in Integration environment
-    Int,
+    Int(EnvArgs),
     /// List instances in Staging environment
-    Stg,
+    Stg(EnvArgs),
     /// List instances in Production environment
-    Prd,
-}
-
-//#[derive(Parser, Debug)]
-//#[command(author, version, about, long_about = None)]
-//pub struct EnvArgs {
-//    /// Long output. Show machine-type, cpu-platform, zone, cell, etc. info.
-//    /// By default only instance-name and IP are shown.
-//    /// Can't be used with ip option
-//    //#[arg(short, long, conflicts_with = "ip")]
-//    //long: bool,
-//
-//    /// Show IP only. Handy for pipeing to other commands like bolt.
-//    /// Can't be used with long option
-//    #[arg(short, long, conflicts_with = "long")]
-//    ip: bool,
-//
-//    /// Search pattern to match against instance names. E.g. "^store-lb"
-//    //pattern: String,
-//}
+    Prd(EnvArgs),
+    /// List instances in all environments at once, with an Environment column
+    All(AllArgs),
+    /// SSH to an instance by name
+    Ssh(SshArgs),
+    /// Print ssh config `Host` blocks for the instances of an environment
+    SshConfig(SshConfigArgs),
+    /// Browse the instances of an environment interactively
+    Tui(TuiArgs),
+    /// Search all environments for instances by name, showing which environment each one
+    /// belongs to
+    Find(FindArgs),
+    /// Show version, build and environment information
+    Version(VersionArgs),
+    /// Check that bcls is set up correctly on this machine
+    Doctor(DoctorArgs),
+    /// Manage the cache of instance listings
+    Cache(CacheArgs),
+    /// Manage the config file
+    Config(ConfigArgs),
+    /// Compare the inventory with the previous run and report added, removed and changed
+    /// instances. Meant to be run from cron.
+    CronDiff(CronDiffArgs),
+    /// List the custom commands defined in the config file
+    Commands,
+    /// A custom command defined in a [commands.NAME] section of the config file
+    #[command(external_subcommand)]
+    Custom(Vec<String>),
+}
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+#[derive(Parser, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    action: CacheCommand,
+}
 
-    let configpath = dirs::home_dir()
-        .expect("Homedir not found")
-        .join(".bcls/config.toml");
+#[derive(Parser, Debug)]
+pub enum CacheCommand {
+    /// Remove all cached instance listings
+    Clear,
+}
 
-    // get habitat and token from config file
-    let builder = Config::builder()
-        // read from config in .bcls/config.toml under home directory
-        // or from config in current directory
-        .add_source(File::from(configpath).required(false))
-        .add_source(File::new("config", FileFormat::Toml).required(false));
-    let config = builder.build()?;
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    action: ConfigCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum ConfigCommand {
+    /// Create the config file, asking which project belongs to each environment. Projects
+    /// given as options aren't asked for
+    Init(InitArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct InitArgs {
+    /// Project of the integration environment
+    #[arg(long, value_name = "PROJECT")]
+    int: Option<String>,
 
-    let config: bcls::config::FileConfig = config.try_deserialize()?;
+    /// Project of the staging environment
+    #[arg(long, value_name = "PROJECT")]
+    stg: Option<String>,
 
-    run(args, config)
+    /// Project of the production environment
+    #[arg(long, value_name = "PROJECT")]
+    prd: Option<String>,
+
+    /// Overwrite an existing config file
+    #[arg(long)]
+    force: bool,
 }
 
-fn run(args: Args, config: bcls::config::FileConfig) -> Result<(), Box<dyn std::error::Error>> {
-    //match args.cmd {
-    //    Command::Int(args) => handle_command(args, &config.int.project)?,
-    //    Command::Stg(args) => handle_command(args, &config.stg.project)?,
-    //    Command::Prd(args) => handle_command(args, &config.prd.project)?,
-    //}
-    match args.cmd {
-        Command::Int => handle_command(&config.int.project)?,
-        Command::Stg => handle_command(&config.stg.project)?,
-        Command::Prd => handle_command(&config.prd.project)?,
-    }
-    Ok(())
+#[derive(Parser, Debug)]
+pub struct FindArgs {
+    /// Search pattern to match against instance names. E.g. "store-lb"
+    pattern: String,
 }
 
-//fn handle_command(args: EnvArgs, project: &str) -> Result<(), Box<dyn std::error::Error>> {
-fn handle_command(project: &str) -> Result<(), Box<dyn std::error::Error>> {
-    //let pattern = args.pattern;
-    //let long = args.long;
-    //let ip = args.ip;
+#[derive(Parser, Debug)]
+pub struct CronDiffArgs {
+    /// Environment to take the inventory of
+    #[arg(value_enum)]
+    env: bcls::config::Environment,
+
+    /// Directory holding the snapshot from the previous run
+    #[arg(long, value_name = "DIR")]
+    state_dir: std::path::PathBuf,
 
-    //show_instances(project, &pattern, long, ip)
-    show_instances(project)
+    /// URL to post the summary to when anything changed, e.g. a Slack incoming webhook
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
 }
 
-fn show_instances(
-    project: &str,
-    //_pattern: &str,
-    //_long: bool,
-    //_ip: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let cc = bcls::compute::ComputeConfig {
-        project: project.to_owned(),
-        client: bcls::http::Http::default(),
-        token_source: bcls::compute::GcloudTokenSource,
-    };
-    let c = bcls::compute::Compute::new(cc);
-    let instances = c.list_all_instances();
-    match instances {
-        Ok(instances) => {
-            print_instances_table(instances);
-            //print_instances(instances);
-            Ok(())
-        }
-        Err(e) => Err(format!("Failed to list instances: {:?}", e).into()),
-    }
+#[derive(Parser, Debug)]
+pub struct DoctorArgs {
+    /// Check the binary, config, credentials and API access of each environment, cache
+    /// directory and terminal
+    #[arg(long = "self", required = true)]
+    self_check: bool,
 }
 
-#[allow(dead_code)]
-fn print_instances(instances: Vec<bcls::compute::Instance>) {
-    // Print each instance as a string
-    for inst in instances {
-        println!("{}", inst.as_string());
-    }
+#[derive(Parser, Debug)]
+pub struct VersionArgs {
+    /// Print the information as JSON
+    #[arg(long)]
+    json: bool,
 }
 
-#[allow(dead_code)]
-fn print_instances_table(instances: Vec<bcls::compute::Instance>) {
-    // Print a header for each field of the Instance struct
-    // and then print each instance as a row in the table
-    let mut table = prettytable::Table::new();
-    table.set_format(
-        format::FormatBuilder::new()
-            .borders(' ')
-            .separators(
-                &[prettytable::format::LinePosition::Top],
-                prettytable::format::LineSeparator::new(' ', ' ', ' ', ' '),
-            )
-            .padding(1, 1)
-            .build(),
-    );
-    table.add_row(row![
-        "Name",
-        "IP",
-        "Zone",
-        "Machine Type",
-        "CPU Platform",
-        "Status",
-        "Labels"
-    ]);
+#[derive(Parser, Debug)]
+pub struct TuiArgs {
+    /// Environment to browse
+    #[arg(value_enum)]
+    env: bcls::config::Environment,
+}
 
-    for inst in instances {
-        let labels_str = match &inst.labels {
-            Some(labels) => labels
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, v))
-                .collect::<Vec<String>>()
-                .join(", "),
-            None => "None".to_string(),
-        };
-        table.add_row(row![
-            inst.name,
-            inst.ip,
-            inst.zone,
-            inst.machine_type,
-            inst.cpu_platform,
-            inst.status,
-            labels_str
-        ]);
-    }
+#[derive(Parser, Debug)]
+pub struct AllArgs {
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
 
-    table.printstd();
+    /// Long output. Show external IP, machine-type, cpu-platform, zone, cell, etc. info.
+    /// Can't be used with ip option
+    #[arg(short, long, conflicts_with = "ip")]
+    long: bool,
+
+    /// Show IP only. Can't be used with long option
+    #[arg(short, long, conflicts_with_all = ["long", "output"])]
+    ip: bool,
+
+    /// With --ip, show external (NAT) IPs instead of internal ones.
+    /// Instances without an external IP are skipped.
+    #[arg(short, long, requires = "ip")]
+    external: bool,
+
+    /// Only show instances with this label, e.g. --label team=payments. Can be repeated to
+    /// require several labels.
+    #[arg(long = "label", value_name = "KEY=VALUE")]
+    labels: Vec<bcls::query::LabelFilter>,
+
+    /// Only show instances with this status. Several statuses can be given, separated by
+    /// commas.
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true)]
+    status: Vec<bcls::query::Status>,
+
+    /// Hide instances with this status. Several statuses can be given, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true)]
+    exclude_status: Vec<bcls::query::Status>,
+
+    /// Sort the instances by this field. By default they are shown per environment, in the
+    /// order the API returns them.
+    #[arg(long, value_enum, value_name = "FIELD")]
+    sort: Option<bcls::query::SortKey>,
+
+    /// Reverse the sort order
+    #[arg(long, requires = "sort")]
+    reverse: bool,
+
+    /// Don't read or write the cached instance listings
+    #[arg(long, conflicts_with = "refresh")]
+    no_cache: bool,
+
+    /// Ignore the cached instance listings, and cache the fresh ones
+    #[arg(long)]
+    refresh: bool,
+
+    /// Search pattern to match against instance names. E.g. "store-lb"
+    pattern: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SshArgs {
+    /// Environment the instance is in
+    #[arg(value_enum)]
+    env: bcls::config::Environment,
+
+    /// Name of the instance
+    name: String,
+
+    /// Connect to the external (NAT) IP instead of the internal one.
+    #[arg(short, long)]
+    external: bool,
+
+    /// Extra arguments passed to ssh after the destination, e.g. a remote command.
+    #[arg(last = true)]
+    ssh_args: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SshConfigArgs {
+    /// Environment to generate host blocks for
+    #[arg(value_enum)]
+    env: bcls::config::Environment,
+
+    /// Only include instances whose names match this pattern
+    pattern: Option<String>,
+
+    /// Use the external (NAT) IPs instead of the internal ones.
+    #[arg(short, long)]
+    external: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct EnvArgs {
+    #[command(subcommand)]
+    action: Option<EnvCommand>,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+
+    /// Long output. Show external IP, machine-type, cpu-platform, zone, cell, etc. info.
+    /// By default only instance-name and IP are shown.
+    /// Can't be used with ip option
+    #[arg(short, long, conflicts_with = "ip")]
+    long: bool,
+
+    /// Show exactly these columns, in this order, e.g. --columns name,ip,zone,labels.team.
+    /// labels.KEY shows the value of a single label. Selecting an enrichment column such as
+    /// vcpus runs its stage. Can't be used with ip or long options
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "COLUMNS",
+        conflicts_with_all = ["ip", "long"]
+    )]
+    columns: Vec<String>,
+
+    /// Show IP only. Handy for pipeing to other commands like bolt.
+    /// Can't be used with long option
+    #[arg(short, long, conflicts_with_all = ["long", "output"])]
+    ip: bool,
+
+    /// With --ip, show external (NAT) IPs instead of internal ones.
+    /// Instances without an external IP are skipped.
+    #[arg(short, long, requires = "ip")]
+    external: bool,
+
+    /// Print tab-separated fields for scripts, in a layout that only changes with a new
+    /// version: name, zone, status, internal IP, external IP, machine type and cell, sorted by
+    /// name. No header and no color
+    #[arg(
+        long,
+        value_enum,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1",
+        conflicts_with_all = [
+            "output", "long", "columns", "ip", "enrich", "group_by", "summary", "sort", "watch"
+        ]
+    )]
+    porcelain: Option<bcls::output::PorcelainVersion>,
+
+    /// Print one line per instance from a template, e.g. --format '{{.name}} {{.ip}}
+    /// {{.labels.cell}}'. Fields take the column keys of --columns; \t and \n stand for a tab
+    /// and a line break
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = bcls::output::Template::parse,
+        conflicts_with_all = [
+            "output", "long", "columns", "ip", "porcelain", "group_by", "summary", "watch"
+        ]
+    )]
+    format: Option<bcls::output::Template>,
+
+    /// Show the addresses of every network interface rather than just the first.
+    #[arg(short, long)]
+    all_ips: bool,
+
+    /// Show a random sample of N matching instances. The total number of matches is
+    /// reported on stderr.
+    #[arg(long, value_name = "N")]
+    sample: Option<usize>,
+
+    /// Seed for --sample, to pick the same instances on every run.
+    #[arg(long, value_name = "S", requires = "sample")]
+    seed: Option<u64>,
+
+    /// Stop listing once N matching instances have been found, without fetching the
+    /// remaining pages. --sort then only orders those N
+    #[arg(long, value_name = "N", conflicts_with_all = ["sample", "summary"])]
+    limit: Option<usize>,
+
+    /// Enrich the listing with extra information. Stages run concurrently after the listing.
+    /// Can't be used with ip option
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "ip")]
+    enrich: Vec<bcls::enrichment::StageKind>,
+
+    /// Show recent metrics of each instance from Cloud Monitoring, averaged over the last 10
+    /// minutes, e.g. --metrics cpu,mem. mem needs the Ops Agent on the instance. Runs as an
+    /// enrichment stage
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "ip")]
+    metrics: Vec<bcls::monitoring::Metric>,
+
+    /// Only show instances with this label, e.g. --label env=prod. Can be repeated to require
+    /// several labels.
+    #[arg(long = "label", value_name = "KEY=VALUE")]
+    labels: Vec<bcls::query::LabelFilter>,
+
+    /// Only show instances with this status, e.g. --status TERMINATED. Several statuses can be
+    /// given, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true)]
+    status: Vec<bcls::query::Status>,
+
+    /// Hide instances with this status, e.g. --exclude-status RUNNING to show what isn't
+    /// running. Several statuses can be given, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', ignore_case = true)]
+    exclude_status: Vec<bcls::query::Status>,
+
+    /// Only show instances with this resource policy attached, e.g. an instance schedule.
+    /// Can be repeated to require several policies.
+    #[arg(long = "resource-policy", value_name = "NAME")]
+    resource_policies: Vec<String>,
+
+    /// Only show instances in this cell, i.e. with the label cell=NAME.
+    #[arg(long, value_name = "NAME")]
+    cell: Option<String>,
+
+    /// Only show instances of this machine type, e.g. --machine-type n2-standard-8. Several
+    /// types can be given, separated by commas.
+    #[arg(long, value_name = "TYPE", value_delimiter = ',')]
+    machine_type: Vec<String>,
+
+    /// Only show instances created more than this long ago, e.g. 90d to spot instances that
+    /// were never recycled. Also 12h or 2w
+    #[arg(long, value_name = "AGE", value_parser = bcls::query::parse_age)]
+    older_than: Option<chrono::Duration>,
+
+    /// Only show instances created less than this long ago, e.g. 12h
+    #[arg(long, value_name = "AGE", value_parser = bcls::query::parse_age)]
+    newer_than: Option<chrono::Duration>,
+
+    /// Only show instances running on spot capacity: spot and preemptible VMs
+    #[arg(long, conflicts_with = "no_spot")]
+    spot_only: bool,
+
+    /// Hide instances running on spot capacity
+    #[arg(long)]
+    no_spot: bool,
+
+    /// Only show instances running as this service account, or as the project's default
+    /// Compute Engine service account with --service-account default. Several accounts can be
+    /// given, separated by commas.
+    #[arg(long, value_name = "EMAIL", value_delimiter = ',')]
+    service_account: Vec<String>,
+
+    /// Show the vCPUs and memory of each instance's machine type, and their totals. Same as
+    /// --enrich machine-type, plus the totals
+    #[arg(long, conflicts_with_all = ["ip", "summary", "watch", "porcelain"])]
+    resources: bool,
+
+    /// Show the instances grouped under a heading per cell, zone, machine-type, status or
+    /// labels.KEY, with the number of instances in each. Only for table output, unless used
+    /// with --summary.
+    #[arg(long, value_name = "FIELD", conflicts_with = "ip")]
+    group_by: Option<bcls::query::GroupBy>,
+
+    /// Show how many instances there are per value of the --group-by field instead of
+    /// listing them, e.g. --summary --group-by machine-type
+    #[arg(
+        long,
+        requires = "group_by",
+        conflicts_with_all = ["ip", "long", "columns", "watch"]
+    )]
+    summary: bool,
+
+    /// Sort the instances by this field. By default they are shown in the order the API
+    /// returns them.
+    #[arg(long, value_enum, value_name = "FIELD")]
+    sort: Option<bcls::query::SortKey>,
+
+    /// Reverse the sort order
+    #[arg(long, requires = "sort")]
+    reverse: bool,
+
+    /// Refresh the table every N seconds until interrupted, marking the instances whose
+    /// status changed since the previous refresh. Only for table output
+    #[arg(long, value_name = "N", conflicts_with_all = ["ip", "group_by"])]
+    watch: Option<u64>,
+
+    /// Don't read or write the cached instance listing
+    #[arg(long, conflicts_with = "refresh")]
+    no_cache: bool,
+
+    /// Ignore the cached instance listing, and cache the fresh one
+    #[arg(long)]
+    refresh: bool,
+
+    /// Show timestamps in UTC rather than the local timezone
+    #[arg(long, global = true)]
+    utc: bool,
+
+    /// Compute Engine API version to call. Overrides the habitat's api_version setting.
+    /// Beta-only fields are shown in long output when using beta.
+    #[arg(long, value_enum)]
+    api_version: Option<bcls::compute::ApiVersion>,
+
+    /// Treat the search pattern as a regular expression, e.g. '^store-lb-[0-9]+$'
+    #[arg(long, requires = "pattern")]
+    regex: bool,
+
+    /// Search pattern to match against instance names. E.g. "store-lb", or a glob matching
+    /// the whole name such as "store-lb-*"
+    pattern: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub enum EnvCommand {
+    /// Report which instances have zone-pinned resources (local SSDs, zonal disks,
+    /// reservations) blocking a move to another zone
+    PlanZoneMove(PlanZoneMoveArgs),
+    /// Start a stopped instance
+    Start(InstanceActionArgs),
+    /// Stop a running instance
+    Stop(InstanceActionArgs),
+    /// Hard-reset an instance
+    Reset(InstanceActionArgs),
+    /// Generate a rollup report over the instances
+    Report(ReportArgs),
+    /// Show the full details of a single instance: disks, network interfaces, service
+    /// accounts, scheduling and metadata keys
+    Describe(DescribeArgs),
+    /// List reserved static IP addresses with their status and the resources using them
+    Addresses(AddressesArgs),
+    /// List VPC networks with their subnet mode, routing mode and MTU
+    Networks(NetworksArgs),
+    /// List subnets with their primary and secondary ranges, or find the subnet of an IP
+    Subnets(SubnetsArgs),
+    /// List persistent disks with their size, type, location, attached instances and status
+    Disks(DisksArgs),
+    /// List disk snapshots with their source disk, size, age and storage locations, oldest
+    /// first
+    Snapshots(SnapshotsArgs),
+    /// List custom images with their family, creation time, source disk and deprecation state
+    Images(ImagesArgs),
+    /// List instance templates with their machine type and source image, and the managed
+    /// instance groups using each
+    Templates(TemplatesArgs),
+    /// Show the OS, kernel and pending package updates of instances, from the inventory their
+    /// OS Config agent reports
+    Os(OsArgs),
+    /// List Cloud DNS managed zones, or search their records by name
+    Dns(DnsArgs),
+    /// List GKE clusters with their version, node count and status, or the node pools of one
+    Gke(GkeArgs),
+    /// List managed instance groups with their size, template and autoscaler status
+    Migs(MigsArgs),
+    /// Inspect a managed instance group
+    Mig(MigArgs),
+    /// Show or change an instance's custom metadata, e.g. the maintenance key
+    Metadata(MetadataArgs),
+    /// Print an instance's serial port output, e.g. to see why it won't boot
+    Serial(SerialArgs),
+    /// Set labels of an instance, keeping the others
+    Label(LabelArgs),
+    /// Remove labels from an instance
+    Unlabel(UnlabelArgs),
+    /// List recent operations with their type, target, status and user, or wait for one
+    Ops(OpsArgs),
+    /// Show who started, stopped or changed an instance and when, from the audit logs
+    History(HistoryArgs),
+    /// Compare the instances with a snapshot saved earlier with diff --write, e.g. after a
+    /// maintenance window: added, removed and changed instances
+    Diff(DiffArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct DnsArgs {
+    /// Search the records whose name contains this pattern, e.g. db.corp.internal. Without
+    /// it, the managed zones are listed
+    pattern: Option<String>,
+
+    /// Only search the managed zone with this name
+    #[arg(long, requires = "pattern")]
+    zone: Option<String>,
+
+    /// Only include records of this type, e.g. A or CNAME
+    #[arg(long = "type", value_name = "TYPE", requires = "pattern")]
+    record_type: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct GkeArgs {
+    #[command(subcommand)]
+    action: Option<GkeCommand>,
+
+    /// Only include clusters whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub enum GkeCommand {
+    /// List a cluster's node pools with their machine type, version and autoscaling limits
+    Nodepools(NodePoolsArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct NodePoolsArgs {
+    /// Name of the cluster
+    cluster: String,
+
+    /// Zone or region of the cluster, if clusters in several locations have the name
+    #[arg(long)]
+    location: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct MigsArgs {
+    /// Only include groups whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct MigArgs {
+    #[command(subcommand)]
+    action: MigCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum MigCommand {
+    /// Show a group's size, template and autoscaler, and its instances with their health
+    Describe(MigDescribeArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct MetadataArgs {
+    #[command(subcommand)]
+    action: MetadataCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum MetadataCommand {
+    /// Show an instance's metadata entries, or the value of one key
+    Get(MetadataGetArgs),
+    /// Set metadata entries of an instance, keeping the others
+    Set(MetadataSetArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct MetadataGetArgs {
+    /// Name of the instance
+    instance: String,
+
+    /// Only print the value of this key, e.g. for scripts. Fails if the instance doesn't have it
+    key: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t, conflicts_with = "key")]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct MetadataSetArgs {
+    /// Name of the instance
+    instance: String,
+
+    /// Entries to set, e.g. maintenance=true
+    #[arg(required = true, value_name = "KEY=VALUE")]
+    items: Vec<bcls::compute::MetadataItem>,
+
+    /// Don't ask for confirmation in habitats with confirm set, such as production
+    #[arg(short, long)]
+    yes: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct SerialArgs {
+    /// Name of the instance
+    instance: String,
+
+    /// Serial port to read, from 1 to 4. Port 1 is the console
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4))]
+    port: u8,
+
+    /// Keep printing new output as it arrives, until interrupted
+    #[arg(short, long)]
+    follow: bool,
+
+    /// With --follow, how many seconds to wait between reads
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "follow",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+}
+
+#[derive(Parser, Debug)]
+pub struct LabelArgs {
+    /// Name of the instance
+    instance: String,
+
+    /// Labels to set, e.g. cell=c3
+    #[arg(required = true, value_name = "KEY=VALUE")]
+    labels: Vec<bcls::compute::Label>,
+
+    #[command(flatten)]
+    options: RelabelOptions,
+}
+
+#[derive(Parser, Debug)]
+pub struct UnlabelArgs {
+    /// Name of the instance
+    instance: String,
+
+    /// Keys of the labels to remove
+    #[arg(required = true, value_name = "KEY", value_parser = parse_label_key)]
+    keys: Vec<String>,
+
+    #[command(flatten)]
+    options: RelabelOptions,
+}
+
+#[derive(Parser, Debug)]
+pub struct RelabelOptions {
+    /// Show what would change without changing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Don't ask for confirmation in habitats with confirm set, such as production
+    #[arg(short, long)]
+    yes: bool,
+}
+
+fn parse_label_key(key: &str) -> Result<String, String> {
+    bcls::compute::validate_label_key(key).map(|()| key.to_string())
+}
+
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct OpsArgs {
+    #[command(subcommand)]
+    action: Option<OpsCommand>,
+
+    /// Only include operations whose target contains this pattern
+    pattern: Option<String>,
+
+    /// Only include operations that haven't finished
+    #[arg(long)]
+    running: bool,
+
+    /// Show at most this many operations, the most recent first
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    limit: u64,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct HistoryArgs {
+    /// Name of the instance. Deleted instances are found as well
+    name: String,
+
+    /// How far back to look, e.g. 30d. Audit logs are kept for 400 days
+    #[arg(long, default_value = "7d", value_parser = bcls::query::parse_age)]
+    since: chrono::Duration,
+
+    /// Show at most this many entries, the most recent first
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    limit: u64,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub enum OpsCommand {
+    /// Wait until an operation is done, and fail if it reports an error
+    Wait(OpsWaitArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct OpsWaitArgs {
+    /// Name of the operation, e.g. operation-1712345678901-abcdef
+    operation: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct MigDescribeArgs {
+    /// Name of the group
+    name: String,
+
+    /// Zone or region of the group, if groups in several locations have the name
+    #[arg(long)]
+    location: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct SnapshotsArgs {
+    /// Only include snapshots whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include snapshots older than this, e.g. 30d, 12h or 2w
+    #[arg(long, value_parser = bcls::query::parse_age)]
+    older_than: Option<chrono::Duration>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct ImagesArgs {
+    /// Only include images whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include images of this family
+    #[arg(long)]
+    family: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct TemplatesArgs {
+    /// Only include templates whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct OsArgs {
+    /// Only include instances whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include instances whose kernel release contains this pattern, e.g. an old
+    /// kernel version still running after a patching cycle
+    #[arg(long)]
+    kernel: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct AddressesArgs {
+    /// Only include addresses whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include addresses that are reserved but not used by any resource
+    #[arg(long)]
+    unused: bool,
+
+    /// Only include external addresses, which are billed while unused
+    #[arg(long)]
+    external: bool,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct NetworksArgs {
+    /// Only include networks whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct SubnetsArgs {
+    /// Only include subnets whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include subnets with a primary or secondary range containing this IP address
+    #[arg(long, value_name = "IP")]
+    contains: Option<std::net::IpAddr>,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct DisksArgs {
+    /// Only include disks whose name contains this pattern
+    pattern: Option<String>,
+
+    /// Only include disks not attached to any instance
+    #[arg(long)]
+    unattached: bool,
+
+    /// Output format: table, json or csv.
+    #[arg(short, long, value_enum, default_value_t)]
+    output: bcls::output::Format,
+}
+
+#[derive(Parser, Debug)]
+pub struct DescribeArgs {
+    /// Name of the instance
+    name: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    kind: ReportKind,
+}
+
+#[derive(Parser, Debug)]
+pub enum ReportKind {
+    /// Per-cell instance counts by machine family and status, total vCPUs and the age of the
+    /// oldest instance
+    Cells(CellsReportArgs),
+    /// Persistent disks and the snapshot schedules attached to them
+    Disks(DisksReportArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct CellsReportArgs {
+    /// Report format
+    #[arg(long, value_enum, default_value_t)]
+    format: bcls::report::ReportFormat,
+
+    /// Only include instances whose name contains this pattern
+    #[arg(long)]
+    pattern: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct DisksReportArgs {
+    /// Report format
+    #[arg(long, value_enum, default_value_t)]
+    format: bcls::report::ReportFormat,
+
+    /// Only include disks without a snapshot schedule
+    #[arg(long)]
+    unscheduled: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct InstanceActionArgs {
+    /// Name of the instance, or a search pattern with --all-matching
+    name: String,
+
+    /// Act on every instance whose name contains NAME, rather than the one named NAME
+    #[arg(long)]
+    all_matching: bool,
+
+    /// Act on more instances than max_results_warning from the config allows
+    #[arg(long = "i-know-what-im-doing", requires = "all_matching")]
+    i_know_what_im_doing: bool,
+
+    /// Don't ask for confirmation in habitats with confirm set, such as production
+    #[arg(short, long)]
+    yes: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct PlanZoneMoveArgs {
+    /// Zone the instances are currently in, e.g. us-east1-b
+    #[arg(long)]
+    from: String,
+
+    /// Zone the instances would move to, e.g. us-east1-c
+    #[arg(long)]
+    to: String,
+
+    /// Only consider instances whose name contains this pattern
+    #[arg(long)]
+    pattern: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    /// The snapshot to compare the instances with, or to save them to with --write
+    file: std::path::PathBuf,
+
+    /// Save a snapshot of the instances to the file instead of comparing with it
+    #[arg(long)]
+    write: bool,
+
+    /// Print the differences as JSON
+    #[arg(long, conflicts_with = "write")]
+    json: bool,
+}
+
+/// The files and directories bcls uses, resolved once at startup.
+static PATHS: std::sync::OnceLock<bcls::paths::Paths> = std::sync::OnceLock::new();
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let rust_log = std::env::var("RUST_LOG").ok();
+    let filter = bcls::logging::LogFilter::from_args(args.verbose, rust_log.as_deref())?;
+    bcls::logging::init(filter, args.log_format)?;
+    let paths = bcls::paths::Paths::resolve(args.config.as_deref())?;
+    PATHS.set(paths).expect("paths are only resolved once");
+
+    // version, doctor and config init must work even without a valid config file
+    match &args.cmd {
+        Command::Version(version_args) => return version(version_args),
+        Command::Doctor(_) => {
+            return doctor(args.project.as_deref(), args.color.unwrap_or_default())
+        }
+        Command::Config(config_args) => return config_command(config_args),
+        _ => {}
+    }
+
+    let mut config = load_config(args.project.as_deref())?;
+    if let Some(path) = &args.trace_file {
+        let trace = bcls::http::TraceLog::create(path)
+            .map_err(|e| format!("Failed to create trace file {}: {}", path.display(), e))?;
+        config.trace = Some(std::sync::Arc::new(trace));
+    }
+    config.page_size = args.page_size.or(config.page_size);
+    if config
+        .page_size
+        .is_some_and(|size| !(1..=500).contains(&size))
+    {
+        return Err("page_size must be between 1 and 500".into());
+    }
+    config.strict |= args.strict;
+    config.list_strategy = args.list_strategy.unwrap_or(config.list_strategy);
+    config.output.color = args.color.unwrap_or(config.output.color);
+    config.output.wide |= args.wide;
+    config.output.no_header |= args.no_header;
+    config.http.connect_timeout_secs = args
+        .connect_timeout
+        .unwrap_or(config.http.connect_timeout_secs);
+    config.http.timeout_secs = args.timeout.unwrap_or(config.http.timeout_secs);
+    config.http.proxy = args.proxy.clone().or(config.http.proxy);
+    config.http.validate()?;
+    config.jobs = args.jobs.map(|jobs| jobs as usize).or(config.jobs);
+    if config.jobs.is_some_and(|jobs| !(1..=64).contains(&jobs)) {
+        return Err("jobs must be between 1 and 64".into());
+    }
+    run(args, config)
+}
+
+/// Reads the config files and resolves the references in them.
+///
+/// `project`, from `--project`, replaces the project of every habitat, so no config file is
+/// needed. As a snapshot would belong to the configured project, the API is used instead.
+fn load_config(
+    project: Option<&str>,
+) -> Result<bcls::config::FileConfig, Box<dyn std::error::Error>> {
+    let configpath = config_path();
+
+    // get habitat and token from config file
+    let builder = Config::builder()
+        // read from the config file found by bcls::paths
+        // or from config in current directory
+        .add_source(File::from(configpath).required(false))
+        .add_source(File::new("config", FileFormat::Toml).required(false))
+        // BCLS_* environment variables override both files
+        .add_source(
+            config::Environment::default()
+                .source(Some(
+                    bcls::config::env_overrides(std::env::vars())
+                        .into_iter()
+                        .collect(),
+                ))
+                .try_parsing(true),
+        );
+    let builder = match project {
+        Some(project) => {
+            use clap::ValueEnum;
+            let mut builder = builder;
+            for env in Environment::value_variants() {
+                builder = builder
+                    .set_override(format!("{}.project", env.as_str()), project)?
+                    .set_override(format!("{}.source", env.as_str()), "api")?;
+            }
+            builder
+        }
+        None => builder,
+    };
+    let config = builder.build()?;
+
+    // resolve env:, file: and cmd: references before deserializing the config
+    let mut config: serde_json::Value = config.try_deserialize()?;
+    bcls::secrets::resolve_secrets(&mut config, &bcls::secrets::default_resolvers())?;
+    Ok(serde_json::from_value(config)?)
+}
+
+/// Returns the files and directories bcls uses.
+fn paths() -> &'static bcls::paths::Paths {
+    PATHS.get().expect("paths are resolved at startup")
+}
+
+/// Returns the path of the config file, see `bcls::paths::Paths::resolve_with`.
+fn config_path() -> std::path::PathBuf {
+    paths().config_file.clone()
+}
+
+/// Returns the directory of the instance listing cache.
+fn cache_dir() -> std::path::PathBuf {
+    paths().cache_dir.clone()
+}
+
+/// Returns the cache of instance listings.
+fn instance_cache(config: &bcls::config::FileConfig) -> bcls::cache::InstanceCache {
+    bcls::cache::InstanceCache::new(cache_dir(), &config.cache)
+}
+
+fn config_command(args: &ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.action {
+        ConfigCommand::Init(init_args) => config_init(init_args),
+    }
+}
+
+fn config_init(args: &InitArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use clap::ValueEnum;
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let path = config_path();
+    if path.exists() && !args.force {
+        return Err(format!(
+            "{} already exists; pass --force to overwrite it",
+            path.display()
+        )
+        .into());
+    }
+
+    let given = |env: Environment| match env {
+        Environment::Int => args.int.as_ref(),
+        Environment::Stg => args.stg.as_ref(),
+        Environment::Prd => args.prd.as_ref(),
+    };
+    let missing = Environment::value_variants()
+        .iter()
+        .filter(|env| given(**env).is_none())
+        .map(|env| format!("--{}", env.as_str()))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() && !std::io::stdin().is_terminal() {
+        return Err(format!(
+            "Can't ask for projects without a terminal; pass {}",
+            missing.join(", ")
+        )
+        .into());
+    }
+    let projects = match missing.is_empty() {
+        true => vec![],
+        false => bcls::init::discover_projects().unwrap_or_else(|e| {
+            eprintln!("Could not list projects with gcloud: {}", e);
+            vec![]
+        }),
+    };
+    for (i, project) in projects.iter().enumerate() {
+        println!("{:>3}. {} ({})", i + 1, project.project_id, project.name);
+    }
+
+    let mut chosen = vec![];
+    let mut lines = std::io::stdin().lock().lines();
+    for env in Environment::value_variants() {
+        if let Some(project) = given(*env) {
+            if !bcls::init::is_valid_project_id(project) {
+                return Err(format!("'{}' is not a valid project ID", project).into());
+            }
+            chosen.push((*env, project.clone()));
+            continue;
+        }
+        let default = bcls::init::guess_project(&projects, *env);
+        loop {
+            match default {
+                Some(default) => print!("Project for {} [{}]: ", env.as_str(), default),
+                None => print!("Project for {} (number or ID): ", env.as_str()),
+            }
+            std::io::stdout().flush()?;
+            let answer = lines.next().ok_or("No answer given")??;
+            match bcls::init::choose_project(&answer, &projects, default) {
+                Ok(project) => {
+                    chosen.push((*env, project));
+                    break;
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(&path, bcls::init::render_config(&chosen))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn version(args: &VersionArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // The config crate tries the bare name before adding the format's extension
+    let cwd = std::env::current_dir()?;
+    let config_paths = [config_path(), cwd.join("config"), cwd.join("config.toml")];
+    let info = bcls::version::VersionInfo::detect(&config_paths);
+    match args.json {
+        true => println!("{}", serde_json::to_string_pretty(&info)?),
+        false => print!("{}", info.to_text()),
+    }
+    Ok(())
+}
+
+fn doctor(
+    project: Option<&str>,
+    color: bcls::output::ColorChoice,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use bcls::doctor::Check;
+    use clap::ValueEnum;
+
+    let mut checks = vec![];
+    let binary = std::env::current_exe()
+        .map_err(|e| e.to_string())
+        .and_then(|path| {
+            let digest = bcls::doctor::file_sha256(&path).map_err(|e| e.to_string())?;
+            Ok(format!(
+                "bcls {} ({}), {} sha256 {}",
+                env!("CARGO_PKG_VERSION"),
+                env!("BCLS_GIT_SHA"),
+                path.display(),
+                digest
+            ))
+        });
+    checks.push(Check::from_result("binary", binary));
+
+    let config = load_config(project);
+    checks.push(Check::from_result(
+        "config",
+        config
+            .as_ref()
+            .map(|_| "parsed".to_string())
+            .map_err(|e| e.to_string()),
+    ));
+    if let Ok(config) = &config {
+        let mut checked_projects = vec![];
+        for env in Environment::value_variants() {
+            let habitat = config.habitat(*env);
+            let c = match compute_for(config, habitat, habitat.api_version.unwrap_or_default()) {
+                Ok(c) => c,
+                Err(e) => {
+                    checks.push(Check::from_result::<String>(
+                        &format!("setup ({})", env.as_str()),
+                        Err(e.to_string()),
+                    ));
+                    continue;
+                }
+            };
+            let token = client_for(config, habitat, habitat.api_version.unwrap_or_default())
+                .and_then(|client| client.token_source())
+                .and_then(|source| source.get_token(&habitat.project))
+                .map(|_| format!("acquired for {}", habitat.project))
+                .map_err(|e| e.to_string());
+            checks.push(Check::from_result(
+                &format!("token ({})", env.as_str()),
+                token,
+            ));
+            // Snapshot habitats need the export, not the API
+            if let bcls::source::Source::Snapshot(url) = &habitat.source {
+                let snapshot = source_for(config, habitat, habitat.api_version.unwrap_or_default())
+                    .and_then(|source| source.list_all_instances())
+                    .map(|listed| format!("{} has {} instances", url, listed.items.len()))
+                    .map_err(|e| e.to_string());
+                checks.push(Check::from_result(
+                    &format!("snapshot ({})", env.as_str()),
+                    snapshot,
+                ));
+                continue;
+            }
+            // Environments may share a project; one API call per project is enough
+            if !checked_projects.contains(&habitat.project) {
+                let access = c
+                    .check_access()
+                    .map(|_| format!("{} is reachable", habitat.project))
+                    .map_err(|e| api_error("API call failed", &habitat.project, e));
+                checks.push(Check::from_result(
+                    &format!("api ({})", env.as_str()),
+                    access,
+                ));
+                checked_projects.push(habitat.project.clone());
+            }
+        }
+    }
+
+    let dir = cache_dir();
+    checks.push(Check::from_result(
+        "cache dir",
+        bcls::doctor::check_writable(&dir).map(|_| format!("{} is writable", dir.display())),
+    ));
+    checks.push(Check::info(
+        "terminal",
+        bcls::doctor::terminal_capabilities(),
+    ));
+
+    print!("{}", bcls::doctor::render(&checks, color.enabled()));
+    let failed = checks.iter().filter(|check| check.failed()).count();
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} checks failed", failed, checks.len()).into()),
+    }
+}
+
+fn run(args: Args, config: bcls::config::FileConfig) -> Result<(), Box<dyn std::error::Error>> {
+    match args.cmd {
+        Command::Int(args) => handle_command(args, Environment::Int, &config)?,
+        Command::Stg(args) => handle_command(args, Environment::Stg, &config)?,
+        Command::Prd(args) => handle_command(args, Environment::Prd, &config)?,
+        Command::All(args) => all(args, &config)?,
+        Command::Ssh(args) => ssh(args, &config)?,
+        Command::SshConfig(args) => ssh_config(args, &config)?,
+        Command::Find(args) => find(args, &config)?,
+        Command::Version(args) => version(&args)?,
+        Command::Doctor(_) => doctor(args.project.as_deref(), args.color.unwrap_or_default())?,
+        Command::Cache(args) => match args.action {
+            CacheCommand::Clear => {
+                let removed = instance_cache(&config).clear()?;
+                let responses = bcls::http::EtagStore::new(etag_cache_dir()).clear()?;
+                println!(
+                    "Removed {} cached listings and {} stored API responses",
+                    removed, responses
+                );
+            }
+        },
+        Command::Config(args) => config_command(&args)?,
+        Command::CronDiff(args) => cron_diff(args, &config)?,
+        Command::Tui(args) => tui(args, &config)?,
+        Command::Commands => commands(&config),
+        Command::Custom(args) => custom(args, &config)?,
+    }
+    Ok(())
+}
+
+use bcls::client::{BoxedTokenSource as TokenSource, HttpClient};
+
+/// Sets up the library client of a habitat, as configured.
+fn client_for(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+) -> Result<bcls::Client, Box<dyn std::error::Error>> {
+    bcls::Client::builder()
+        .habitat(config, habitat)
+        .api_version(api_version)
+        .token_cache_dir(paths().token_cache_dir.clone())
+        .etag_cache_dir(etag_cache_dir())
+        .instance_cache_dir(cache_dir())
+        .build()
+}
+
+fn etag_cache_dir() -> std::path::PathBuf {
+    paths().cache_dir.join("etags")
+}
+
+/// Wraps a client to retry as configured, and to trace requests if `--trace-file` is given.
+fn retrying(config: &bcls::config::FileConfig, http: bcls::http::Http) -> HttpClient {
+    bcls::client::retrying(http, &config.retry, &config.trace)
+}
+
+fn compute_for(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+) -> Result<bcls::compute::Compute<HttpClient, TokenSource>, Box<dyn std::error::Error>> {
+    client_for(config, habitat, api_version)?.compute()
+}
+
+fn container_for(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+) -> Result<bcls::container::Container<HttpClient, TokenSource>, Box<dyn std::error::Error>> {
+    client_for(config, habitat, habitat.api_version.unwrap_or_default())?.container()
+}
+
+fn dns_for(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+) -> Result<bcls::dns::Dns<HttpClient, TokenSource>, Box<dyn std::error::Error>> {
+    client_for(config, habitat, habitat.api_version.unwrap_or_default())?.dns()
+}
+
+/// A provider of instance listings chosen at runtime from the config.
+type InstanceSource = Box<dyn bcls::source::InstanceSource>;
+
+/// Returns the source a habitat's instances are listed from: the Compute API, or a snapshot
+/// in Cloud Storage.
+fn source_for(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+) -> Result<InstanceSource, Box<dyn std::error::Error>> {
+    client_for(config, habitat, api_version)?.instance_source()
+}
+
+fn ssh(args: SshArgs, config: &bcls::config::FileConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let habitat = config.habitat(args.env);
+    let source = source_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instance = source
+        .find_instance(&args.name)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| format!("Instance {} not found in {}", args.name, habitat.project))?;
+
+    let ip = match args.external {
+        true => instance.external_ip(),
+        false => instance.primary_ip(),
+    }
+    .ok_or_else(|| format!("Instance {} has no suitable IP address", args.name))?;
+
+    let bastion = bcls::ssh::proxy_jump(&instance, args.env, &config.ssh, &config.bastions)?;
+    bcls::ssh::exec(bcls::ssh::ssh_command(
+        ip,
+        &config.ssh,
+        bastion.as_deref(),
+        &args.ssh_args,
+    ))
+}
+
+fn ssh_config(
+    args: SshConfigArgs,
+    config: &bcls::config::FileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let habitat = config.habitat(args.env);
+    let api_version = habitat.api_version.unwrap_or_default();
+    let query = bcls::InstanceQuery {
+        name_pattern: args.pattern.as_deref().map(bcls::query::NamePattern::plain),
+        ..Default::default()
+    };
+    let mut instances = list_instances(config, habitat, api_version, &query)?;
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+
+    println!("# Generated by bcls ssh-config {}", args.env.as_str());
+    for instance in &instances {
+        // Skip instances that can't be reached rather than emitting a broken block
+        match bcls::ssh::host_block(
+            instance,
+            args.env,
+            &config.ssh,
+            &config.bastions,
+            args.external,
+        ) {
+            Ok(block) => print!("\n{}", block),
+            Err(e) => eprintln!("warning: skipping {}: {}", instance.name, e),
+        }
+    }
+    Ok(())
+}
+
+fn tui(args: TuiArgs, config: &bcls::config::FileConfig) -> Result<(), Box<dyn std::error::Error>> {
+    use bcls::ui::Action;
+
+    let habitat = config.habitat(args.env);
+    let api_version = habitat.api_version.unwrap_or_default();
+    let c = compute_for(config, habitat, api_version)?;
+    let source = source_for(config, habitat, api_version)?;
+    let list = || {
+        // stderr is hidden behind the browser, so warnings about missing instances are dropped
+        source
+            .list_all_instances()
+            .map(|listed| listed.items)
+            .map_err(|e| api_error("Failed to list instances", &habitat.project, e))
+    };
+    // Shows the outcome of a start or stop, and lists the instances again for the new status
+    let finish =
+        |app: &mut bcls::ui::App,
+         action: &str,
+         result: Result<bcls::compute::Operation, Box<dyn std::error::Error>>| {
+            let message = match result {
+                Ok(operation) => {
+                    // stderr is hidden behind the browser, and a stale cache entry expires anyway
+                    let _ = instance_cache(config).invalidate(&habitat.project);
+                    if let Ok(instances) = list() {
+                        app.set_instances(instances);
+                    }
+                    format!(
+                        "{}: {} {}",
+                        operation.target, operation.operation_type, operation.status
+                    )
+                }
+                Err(e) => api_error(
+                    &format!("Failed to {} instance", action),
+                    &habitat.project,
+                    e,
+                ),
+            };
+            app.set_message(message);
+        };
+    let title = format!("{} ({})", args.env.as_str(), habitat.project);
+    let mut app = bcls::ui::App::new(&title, list()?);
+
+    let mut terminal = bcls::ui::Terminal::enter()?;
+    loop {
+        terminal.draw(&mut app)?;
+        let height = terminal.height()?;
+        for key in terminal.read_keys()? {
+            let Some(action) = app.handle_key(key, height) else {
+                continue;
+            };
+            match action {
+                Action::Quit => return Ok(()),
+                Action::Refresh => match list() {
+                    Ok(instances) => app.set_instances(instances),
+                    Err(e) => app.set_message(e),
+                },
+                Action::CopyIp(ip) => {
+                    terminal.copy(&ip)?;
+                    app.set_message(format!("Copied {}", ip));
+                }
+                Action::Ssh(instance) => {
+                    // ssh needs the terminal as it was, and the browser resumes afterwards
+                    let ip = instance.primary_ip().unwrap_or_default();
+                    let bastion = match bcls::ssh::proxy_jump(
+                        &instance,
+                        args.env,
+                        &config.ssh,
+                        &config.bastions,
+                    ) {
+                        Ok(bastion) => bastion,
+                        Err(e) => {
+                            app.set_message(format!("Can't choose a bastion: {}", e));
+                            continue;
+                        }
+                    };
+                    terminal.suspend()?;
+                    let status =
+                        bcls::ssh::ssh_command(ip, &config.ssh, bastion.as_deref(), &[]).status();
+                    terminal.resume()?;
+                    match status {
+                        Ok(status) if status.success() => {}
+                        Ok(status) => app.set_message(format!("ssh exited with {}", status)),
+                        Err(e) => app.set_message(format!("Failed to run ssh: {}", e)),
+                    }
+                }
+                Action::Start(instance) => {
+                    app.set_message(format!("Starting {}...", instance.name));
+                    terminal.draw(&mut app)?;
+                    let result = c.start_instance(&instance.zone, &instance.name);
+                    finish(&mut app, "start", result);
+                }
+                Action::Stop(instance) => {
+                    app.set_message(format!("Stopping {}...", instance.name));
+                    terminal.draw(&mut app)?;
+                    let result = c.stop_instance(&instance.zone, &instance.name);
+                    finish(&mut app, "stop", result);
+                }
+            }
+        }
+    }
+}
+
+/// Lists the custom commands defined in the config file.
+fn commands(config: &bcls::config::FileConfig) {
+    if config.commands.is_empty() {
+        eprintln!("No custom commands; define them in [commands.NAME] sections of the config");
+        return;
+    }
+    let mut table = bcls::output::Table::new(["Command", "Env", "Description"]);
+    for (name, command) in &config.commands {
+        table.add_row([
+            name.as_str(),
+            command.env.as_str(),
+            command.description.as_deref().unwrap_or_default(),
+        ]);
+    }
+    table.printstd();
+}
+
+/// Runs a custom command defined in the config file.
+///
+/// # Arguments
+///
+/// * `args` - The name of the command followed by any further arguments, which are added to
+///   the command's saved options.
+fn custom(
+    args: Vec<String>,
+    config: &bcls::config::FileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (name, extra) = args.split_first().ok_or("Missing command")?;
+    let command = config.commands.get(name).ok_or_else(|| {
+        format!(
+            "Unknown command {}; see bcls --help, or bcls commands for the custom commands",
+            name
+        )
+    })?;
+    let command_line = std::iter::once("bcls".to_string()).chain(command.command_line(extra));
+    // Exits with clap's message for invalid options, or shows the help for --help
+    let parsed = Args::try_parse_from(command_line).unwrap_or_else(|e| e.exit());
+    let (Command::Int(mut env_args) | Command::Stg(mut env_args) | Command::Prd(mut env_args)) =
+        parsed.cmd
+    else {
+        unreachable!("custom commands start with an environment");
+    };
+    if env_args.pattern.is_none() {
+        env_args.pattern = command.pattern.clone();
+    }
+    handle_command(env_args, command.env, config)
+}
+
+/// Groups the environments by the project of their habitat, as habitats may share a project
+/// and each project should only be listed once.
+///
+/// # Returns
+///
+/// The habitat of each project with the environments configured with it, in config order.
+fn project_environments(
+    config: &bcls::config::FileConfig,
+) -> Vec<(&bcls::config::Habitat, Vec<Environment>)> {
+    use clap::ValueEnum;
+
+    let mut projects: Vec<(&bcls::config::Habitat, Vec<Environment>)> = vec![];
+    for env in Environment::value_variants() {
+        let habitat = config.habitat(*env);
+        match projects
+            .iter_mut()
+            .find(|(h, _)| h.project == habitat.project)
+        {
+            Some((_, envs)) => envs.push(*env),
+            None => projects.push((habitat, vec![*env])),
+        }
+    }
+    projects
+}
+
+/// The number of projects to list at once, from `--jobs` or the config.
+fn jobs(config: &bcls::config::FileConfig) -> usize {
+    config.jobs.unwrap_or(bcls::compute::DEFAULT_JOBS)
+}
+
+/// Lists the instances of every environment concurrently and shows them in one listing, with
+/// the environment of each instance.
+fn all(args: AllArgs, config: &bcls::config::FileConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let projects = project_environments(config);
+    let query = bcls::InstanceQuery {
+        filter: bcls::query::InstanceFilter {
+            labels: args.labels.clone(),
+            statuses: args.status.clone(),
+            excluded_statuses: args.exclude_status.clone(),
+            ..Default::default()
+        },
+        name_pattern: args.pattern.as_deref().map(bcls::query::NamePattern::plain),
+        limit: None,
+        cache: cache_mode(args.no_cache, args.refresh),
+    };
+    let results = bcls::compute::map_bounded(&projects, jobs(config), |(habitat, _)| {
+        list_instances(
+            config,
+            habitat,
+            habitat.api_version.unwrap_or_default(),
+            &query,
+        )
+    });
+
+    // Tag each instance with its environment, keeping the other environments if one fails
+    let mut instances = vec![];
+    let mut failed = 0;
+    for ((_, envs), result) in projects.iter().zip(results) {
+        match result {
+            Ok(listed) => {
+                for mut instance in listed {
+                    let environment = bcls::find::infer_environment(envs, &instance).environment;
+                    instance
+                        .enrichments
+                        .insert("environment".to_string(), environment.as_str().to_string());
+                    instances.push(instance);
+                }
+            }
+            Err(e) => {
+                eprintln!("warning: {}", e);
+                failed += 1;
+            }
+        }
+    }
+    if failed == projects.len() {
+        return Err("Failed to list instances in every environment".into());
+    }
+
+    if let Some(key) = args.sort {
+        bcls::query::sort(&mut instances, key, args.reverse);
+    }
+
+    if args.ip {
+        print!(
+            "{}",
+            bcls::output::ip_lines(&instances, args.external, false)
+        );
+        return Ok(());
+    }
+    let mut columns = vec![bcls::output::environment_column()];
+    columns.extend(match args.long {
+        true => bcls::output::long_columns(),
+        false => bcls::output::default_columns(),
+    });
+    print!(
+        "{}",
+        bcls::output::render_instances(args.output, &columns, &instances, &config.output)
+    );
+    Ok(())
+}
+
+fn find(
+    args: FindArgs,
+    config: &bcls::config::FileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let projects = project_environments(config);
+    let mut table = bcls::output::Table::new(["Env", "Name", "IP", "Zone", "Note"]);
+    let mut found = 0;
+    let query = bcls::InstanceQuery {
+        name_pattern: Some(bcls::query::NamePattern::plain(&args.pattern)),
+        ..Default::default()
+    };
+    let results = bcls::compute::map_bounded(&projects, jobs(config), |(habitat, _)| {
+        list_instances(
+            config,
+            habitat,
+            habitat.api_version.unwrap_or_default(),
+            &query,
+        )
+    });
+    for ((_, envs), result) in projects.iter().zip(results) {
+        let instances = match result {
+            Ok(instances) => instances,
+            // Keep searching the other projects
+            Err(e) => {
+                eprintln!("warning: {}", e);
+                continue;
+            }
+        };
+        for instance in &instances {
+            let attribution = bcls::find::infer_environment(envs, instance);
+            let note = match &attribution.ambiguity {
+                Some(reason) => format!("ambiguous: {}", reason),
+                None => String::new(),
+            };
+            table.add_row([
+                attribution.environment.as_str().to_string(),
+                instance.name.clone(),
+                instance.primary_ip().unwrap_or("-").to_string(),
+                instance.zone.clone(),
+                note,
+            ]);
+            found += 1;
+        }
+    }
+
+    match found {
+        0 => eprintln!("No instances matching {}", args.pattern),
+        _ => table.printstd(),
+    }
+    Ok(())
+}
+
+/// Takes a snapshot of every instance of a habitat, for comparing with another snapshot.
+///
+/// # Returns
+///
+/// * `Ok(Snapshot)` - The snapshot.
+/// * `Err(Box<dyn std::error::Error>)` - An error if listing failed or the listing is
+///   incomplete, since the missing instances would be reported as removed.
+fn take_snapshot(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+) -> Result<bcls::diff::Snapshot, Box<dyn std::error::Error>> {
+    let source = source_for(config, habitat, api_version)?;
+    let listed = source
+        .list_all_instances()
+        .map_err(|e| api_error("Failed to list instances", &habitat.project, e))?;
+    if !listed.warnings.is_empty() {
+        report_incomplete(&habitat.project, listed);
+        return Err("Not comparing an incomplete listing with a snapshot".into());
+    }
+    Ok(bcls::diff::Snapshot::new(&listed.items))
+}
+
+fn fleet_diff(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &DiffArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = take_snapshot(config, habitat, api_version)?;
+    if args.write {
+        snapshot
+            .save(&args.file)
+            .map_err(|e| format!("Failed to write {}: {}", args.file.display(), e))?;
+        println!(
+            "Saved {} instances of {} to {}",
+            snapshot.instances.len(),
+            habitat.project,
+            args.file.display()
+        );
+        return Ok(());
+    }
+    let previous = bcls::diff::Snapshot::load(&args.file)?
+        .ok_or_else(|| format!("No snapshot at {}", args.file.display()))?;
+    let diff = bcls::diff::diff(&previous, &snapshot);
+    match args.json {
+        true => println!("{}", serde_json::to_string_pretty(&diff)?),
+        false => println!("{}", diff),
+    }
+    Ok(())
+}
+
+fn cron_diff(
+    args: CronDiffArgs,
+    config: &bcls::config::FileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use bcls::http::HttpClient;
+
+    let habitat = config.habitat(args.env);
+    let snapshot = take_snapshot(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let path = args.state_dir.join(format!("{}.json", habitat.project));
+
+    let Some(previous) = bcls::diff::Snapshot::load(&path)? else {
+        snapshot.save(&path)?;
+        println!(
+            "No previous snapshot; saved {} instances of {}",
+            snapshot.instances.len(),
+            habitat.project
+        );
+        return Ok(());
+    };
+
+    let diff = bcls::diff::diff(&previous, &snapshot);
+    let summary = format!("bcls inventory diff for {}: {}", habitat.project, diff);
+    println!("{}", summary);
+
+    // Post before saving, so a failed post is retried against the same baseline next run
+    if let (Some(webhook), false) = (&args.webhook, diff.is_empty()) {
+        let body = serde_json::json!({
+            "text": summary,
+            "project": habitat.project,
+            "diff": diff,
+        });
+        let http = bcls::http::Http::with_options(&config.http, &Default::default())?;
+        retrying(config, http)
+            .post_json(webhook, &body)
+            .map_err(|e| format!("Failed to post to webhook: {}", e))?;
+    }
+    snapshot.save(&path)
+}
+
+fn handle_command(
+    args: EnvArgs,
+    env: Environment,
+    config: &bcls::config::FileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let habitat = config.habitat(env);
+    // The command line flag takes precedence over the habitat's setting
+    let api_version = args.api_version.or(habitat.api_version).unwrap_or_default();
+    let timezone = match args.utc {
+        true => bcls::output::Timezone::Utc,
+        false => bcls::output::Timezone::Local,
+    };
+    match &args.action {
+        Some(EnvCommand::PlanZoneMove(plan_args)) => plan_zone_move(config, habitat, plan_args),
+        Some(EnvCommand::Start(action_args)) => {
+            instance_action(config, env, habitat, "start", action_args, timezone)
+        }
+        Some(EnvCommand::Stop(action_args)) => {
+            instance_action(config, env, habitat, "stop", action_args, timezone)
+        }
+        Some(EnvCommand::Reset(action_args)) => {
+            instance_action(config, env, habitat, "reset", action_args, timezone)
+        }
+        Some(EnvCommand::Report(report_args)) => match &report_args.kind {
+            ReportKind::Cells(cells_args) => cells_report(config, habitat, api_version, cells_args),
+            ReportKind::Disks(disks_args) => disks_report(config, habitat, api_version, disks_args),
+        },
+        Some(EnvCommand::Describe(describe_args)) => {
+            describe(config, habitat, api_version, describe_args, timezone)
+        }
+        Some(EnvCommand::Addresses(addresses_args)) => {
+            addresses(config, habitat, api_version, addresses_args)
+        }
+        Some(EnvCommand::Networks(networks_args)) => {
+            networks(config, habitat, api_version, networks_args)
+        }
+        Some(EnvCommand::Subnets(subnets_args)) => {
+            subnets(config, habitat, api_version, subnets_args)
+        }
+        Some(EnvCommand::Disks(disks_args)) => disks(config, habitat, api_version, disks_args),
+        Some(EnvCommand::Snapshots(snapshots_args)) => {
+            snapshots(config, habitat, api_version, snapshots_args, timezone)
+        }
+        Some(EnvCommand::Images(images_args)) => {
+            images(config, habitat, api_version, images_args, timezone)
+        }
+        Some(EnvCommand::Templates(templates_args)) => {
+            templates(config, habitat, api_version, templates_args, timezone)
+        }
+        Some(EnvCommand::Os(os_args)) => os(config, habitat, api_version, os_args, timezone),
+        Some(EnvCommand::Dns(dns_args)) => dns(config, habitat, dns_args),
+        Some(EnvCommand::Gke(gke_args)) => match &gke_args.action {
+            None => gke(config, habitat, gke_args),
+            Some(GkeCommand::Nodepools(node_pools_args)) => {
+                gke_node_pools(config, habitat, node_pools_args)
+            }
+        },
+        Some(EnvCommand::Migs(migs_args)) => migs(config, habitat, api_version, migs_args),
+        Some(EnvCommand::Mig(mig_args)) => match &mig_args.action {
+            MigCommand::Describe(describe_args) => {
+                mig_describe(config, habitat, api_version, describe_args)
+            }
+        },
+        Some(EnvCommand::Metadata(metadata_args)) => match &metadata_args.action {
+            MetadataCommand::Get(get_args) => metadata_get(config, habitat, get_args),
+            MetadataCommand::Set(set_args) => metadata_set(config, env, habitat, set_args),
+        },
+        Some(EnvCommand::Serial(serial_args)) => serial(config, habitat, serial_args),
+        Some(EnvCommand::Diff(diff_args)) => fleet_diff(config, habitat, api_version, diff_args),
+        Some(EnvCommand::Label(label_args)) => relabel(
+            config,
+            env,
+            habitat,
+            &label_args.instance,
+            &label_args.labels,
+            &[],
+            &label_args.options,
+        ),
+        Some(EnvCommand::Unlabel(unlabel_args)) => relabel(
+            config,
+            env,
+            habitat,
+            &unlabel_args.instance,
+            &[],
+            &unlabel_args.keys,
+            &unlabel_args.options,
+        ),
+        Some(EnvCommand::Ops(ops_args)) => match &ops_args.action {
+            None => ops(config, habitat, api_version, ops_args, timezone),
+            Some(OpsCommand::Wait(wait_args)) => ops_wait(config, habitat, api_version, wait_args),
+        },
+        Some(EnvCommand::History(history_args)) => {
+            history(config, habitat, api_version, history_args, timezone)
+        }
+        None => show_instances(config, habitat, api_version, &args, timezone),
+    }
+}
+
+/// Lists the instances of a habitat matching a query with `Client::list`, which uses and
+/// fills the instance cache, and reports what is missing from the listing.
+fn list_instances(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    query: &bcls::InstanceQuery,
+) -> Result<Vec<bcls::compute::Instance>, String> {
+    let listed = client_for(config, habitat, api_version)
+        .and_then(|client| client.list(query))
+        .map_err(|e| api_error("Failed to list instances", &habitat.project, e))?;
+    Ok(report_incomplete(&habitat.project, listed))
+}
+
+/// How listings use the instance cache, from `--no-cache` and `--refresh`.
+fn cache_mode(no_cache: bool, refresh: bool) -> bcls::CacheMode {
+    match (no_cache, refresh) {
+        (true, _) => bcls::CacheMode::Off,
+        (false, true) => bcls::CacheMode::Refresh,
+        (false, false) => bcls::CacheMode::Use,
+    }
+}
+
+/// Prints what is missing from a listing of a project to stderr, and returns the instances.
+fn report_incomplete(
+    project: &str,
+    listed: bcls::compute::ResultSet<bcls::compute::Instance>,
+) -> Vec<bcls::compute::Instance> {
+    if let Some(summary) = bcls::output::incomplete_listing(project, &listed.warnings) {
+        eprint!("{}", summary);
+    }
+    listed.items
+}
+
+/// Describes a failed API call, explaining authentication and permission errors.
+fn api_error(context: &str, project: &str, e: Box<dyn std::error::Error>) -> String {
+    match e.downcast_ref::<bcls::http::HttpError>() {
+        Some(error) if error.is_unauthenticated() => format!(
+            "{}: not authenticated for project {} — run `gcloud auth application-default login`\n{}",
+            context, project, error
+        ),
+        Some(error) if error.is_permission_denied() => format!(
+            "{}: permission denied on project {} — run `gcloud auth application-default login` \
+             with an account that has access\n{}",
+            context, project, error
+        ),
+        _ => format!("{}: {}", context, e),
+    }
+}
+
+/// Asks the user to confirm an action on stderr, reading the answer from stdin.
+///
+/// Returns an error rather than proceeding if stdin is not a terminal.
+fn confirm(prompt: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    if !std::io::stdin().is_terminal() {
+        return Err(format!(
+            "{} Refusing without a terminal; pass --yes to proceed",
+            prompt
+        )
+        .into());
+    }
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Asks the user to confirm a change to a habitat whose `confirm` option is set, unless they
+/// already did with `--yes`.
+///
+/// Returns an error if the user declines, so the caller can propagate it with `?`.
+fn confirm_change(
+    config: &bcls::config::FileConfig,
+    env: Environment,
+    yes: bool,
+    change: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if yes || !config.requires_confirmation(env) {
+        return Ok(());
+    }
+    let habitat = config.habitat(env);
+    match confirm(&format!(
+        "{} in {} ({})?",
+        change,
+        env.as_str(),
+        habitat.project
+    ))? {
+        true => Ok(()),
+        false => Err("Aborted".into()),
+    }
+}
+
+fn instance_action(
+    config: &bcls::config::FileConfig,
+    env: Environment,
+    habitat: &bcls::config::Habitat,
+    action: &str,
+    args: &InstanceActionArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instances = match args.all_matching {
+        true => c
+            .list_all_instances()
+            .map_err(|e| api_error("Failed to list instances", &habitat.project, e))
+            .map(|listed| report_incomplete(&habitat.project, listed))?
+            .into_iter()
+            .filter(|inst| bcls::query::matches_pattern(inst, Some(&args.name)))
+            .collect::<Vec<_>>(),
+        false => vec![c
+            .find_instance(&args.name)
+            .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+            .ok_or_else(|| format!("Instance {} not found in {}", args.name, habitat.project))?],
+    };
+    if instances.is_empty() {
+        return Err(format!("No instances matching {} in {}", args.name, habitat.project).into());
+    }
+    bcls::query::check_result_budget(
+        instances.len(),
+        config.max_results_warning,
+        args.i_know_what_im_doing,
+    )?;
+
+    let target = match instances.as_slice() {
+        [instance] => format!("instance {}", instance.name),
+        _ => format!("{} instances matching {}", instances.len(), args.name),
+    };
+    confirm_change(config, env, args.yes, &format!("{} {}", action, target))?;
+
+    let mut failed = 0;
+    for instance in &instances {
+        eprintln!("{} {} in {}...", action, instance.name, instance.zone);
+        let operation = match action {
+            "start" => c.start_instance(&instance.zone, &instance.name),
+            "stop" => c.stop_instance(&instance.zone, &instance.name),
+            _ => c.reset_instance(&instance.zone, &instance.name),
+        };
+        match operation {
+            Ok(operation) => {
+                let finished = operation
+                    .end_time
+                    .as_deref()
+                    .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| format!(" at {}", timezone.format(time.to_utc())))
+                    .unwrap_or_default();
+                println!(
+                    "{}: {} {}{}",
+                    operation.name, operation.operation_type, operation.status, finished
+                )
+            }
+            // Carry on with the other instances, and report the failures at the end
+            Err(e) if instances.len() > 1 => {
+                eprintln!("error: {}: {}", instance.name, e);
+                failed += 1;
+            }
+            Err(e) => {
+                return Err(api_error(
+                    &format!("Failed to {} instance", action),
+                    &habitat.project,
+                    e,
+                )
+                .into())
+            }
+        }
+    }
+    // The cached listing now shows a stale status
+    instance_cache(config).invalidate(&habitat.project)?;
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "Failed to {} {} of {} instances",
+            action,
+            failed,
+            instances.len()
+        )
+        .into()),
+    }
+}
+
+fn describe(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &DescribeArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    // Listings don't report the zone of an instance by name alone, so look it up first
+    let instance = c
+        .find_instance(&args.name)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| format!("Instance {} not found in {}", args.name, habitat.project))?;
+    let detail = c
+        .get_instance(&instance.zone, &instance.name)
+        .map_err(|e| api_error("Failed to describe instance", &habitat.project, e))?;
+    print!(
+        "{}",
+        bcls::output::instance_detail_text(&detail, &config.output.null.table, timezone)
+    );
+    Ok(())
+}
+
+fn cells_report(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &CellsReportArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut instances = source_for(config, habitat, api_version)?
+        .list_all_instances()
+        .map_err(|e| api_error("Failed to list instances", &habitat.project, e))
+        .map(|listed| report_incomplete(&habitat.project, listed))?
+        .into_iter()
+        .filter(|inst| bcls::query::matches_pattern(inst, args.pattern.as_deref()))
+        .collect::<Vec<_>>();
+
+    // The vCPU totals come from the machine-type enrichment
+    let machine_types = bcls::enrichment::MachineTypeStage::new(&c);
+    bcls::enrichment::run(&[&machine_types], &mut instances)?;
+
+    let rollups = bcls::report::cell_rollups(&instances);
+    print!(
+        "{}",
+        bcls::report::render_cells(&rollups, args.format, chrono::Utc::now())
+    );
+    Ok(())
+}
+
+fn addresses(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &AddressesArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut addresses = c
+        .list_all_addresses()
+        .map_err(|e| api_error("Failed to list addresses", &habitat.project, e))?
+        .into_iter()
+        .filter(|address| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| address.name.contains(pattern))
+        })
+        .filter(|address| !args.unused || address.is_unused())
+        .filter(|address| !args.external || address.address_type == "EXTERNAL")
+        .collect::<Vec<_>>();
+    addresses.sort_by(|a, b| a.name.cmp(&b.name));
+    print!(
+        "{}",
+        bcls::output::addresses_listing(&addresses).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn networks(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &NetworksArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut networks = c
+        .list_all_networks()
+        .map_err(|e| api_error("Failed to list networks", &habitat.project, e))?
+        .into_iter()
+        .filter(|network| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| network.name.contains(pattern))
+        })
+        .collect::<Vec<_>>();
+    networks.sort_by(|a, b| a.name.cmp(&b.name));
+    print!(
+        "{}",
+        bcls::output::networks_listing(&networks).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn subnets(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &SubnetsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut subnets = c
+        .list_all_subnetworks()
+        .map_err(|e| api_error("Failed to list subnets", &habitat.project, e))?
+        .into_iter()
+        .filter(|subnet| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| subnet.name.contains(pattern))
+        })
+        .filter(|subnet| {
+            args.contains
+                .is_none_or(|ip| subnet.range_containing(ip).is_some())
+        })
+        .collect::<Vec<_>>();
+    if let (Some(ip), true) = (args.contains, subnets.is_empty()) {
+        return Err(format!("No subnet in {} contains {}", habitat.project, ip).into());
+    }
+    subnets.sort_by(|a, b| (&a.network, &a.region, &a.name).cmp(&(&b.network, &b.region, &b.name)));
+    print!(
+        "{}",
+        bcls::output::subnets_listing(&subnets).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn disks(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &DisksArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut disks = c
+        .list_all_disks()
+        .map_err(|e| api_error("Failed to list disks", &habitat.project, e))?
+        .into_iter()
+        .filter(|disk| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| disk.name.contains(pattern))
+        })
+        .filter(|disk| !args.unattached || disk.users.is_empty())
+        .collect::<Vec<_>>();
+    disks.sort_by(|a, b| a.name.cmp(&b.name));
+    print!(
+        "{}",
+        bcls::output::disks_listing(&disks).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn snapshots(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &SnapshotsArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let now = chrono::Utc::now();
+    let mut snapshots = c
+        .list_all_snapshots()
+        .map_err(|e| api_error("Failed to list snapshots", &habitat.project, e))?
+        .into_iter()
+        .filter(|snapshot| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| snapshot.name.contains(pattern))
+        })
+        // Snapshots of unknown age can't be shown to be older
+        .filter(|snapshot| {
+            args.older_than.is_none_or(|age| {
+                snapshot
+                    .creation_timestamp
+                    .is_some_and(|created| now - created > age)
+            })
+        })
+        .collect::<Vec<_>>();
+    snapshots.sort_by(|a, b| {
+        a.creation_timestamp
+            .cmp(&b.creation_timestamp)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    print!(
+        "{}",
+        bcls::output::snapshots_listing(&snapshots, timezone, now)
+            .render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn images(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &ImagesArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut images = c
+        .list_all_images()
+        .map_err(|e| api_error("Failed to list images", &habitat.project, e))?
+        .into_iter()
+        .filter(|image| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| image.name.contains(pattern))
+        })
+        .filter(|image| {
+            args.family
+                .as_deref()
+                .is_none_or(|family| image.family.as_deref() == Some(family))
+        })
+        .collect::<Vec<_>>();
+    // Each family's images from oldest to newest, so the current one comes last
+    images.sort_by(|a, b| {
+        a.family
+            .cmp(&b.family)
+            .then_with(|| a.creation_timestamp.cmp(&b.creation_timestamp))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    print!(
+        "{}",
+        bcls::output::images_listing(&images, timezone).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn templates(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &TemplatesArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut templates = c
+        .list_all_instance_templates()
+        .map_err(|e| api_error("Failed to list instance templates", &habitat.project, e))?
+        .into_iter()
+        .filter(|template| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| template.name.contains(pattern))
+        })
+        .collect::<Vec<_>>();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    let migs = c
+        .list_all_instance_group_managers()
+        .map_err(|e| api_error("Failed to list instance groups", &habitat.project, e))?;
+    print!(
+        "{}",
+        bcls::output::templates_listing(&templates, &migs, timezone)
+            .render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn os(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &OsArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut instances = source_for(config, habitat, api_version)?
+        .list_all_instances()
+        .map_err(|e| api_error("Failed to list instances", &habitat.project, e))
+        .map(|listed| report_incomplete(&habitat.project, listed))?
+        .into_iter()
+        .filter(|inst| bcls::query::matches_pattern(inst, args.pattern.as_deref()))
+        .collect::<Vec<_>>();
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // The API has no batch lookup by instance name, so fetch the inventories concurrently
+    let osconfig = client_for(config, habitat, api_version)?.osconfig()?;
+    let inventories = bcls::compute::map_bounded(&instances, jobs(config), |inst| {
+        osconfig
+            .get_inventory(&inst.zone, &inst.name)
+            .map_err(|e| api_error("Failed to get OS inventory", &habitat.project, e))
+    })
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let rows = instances
+        .into_iter()
+        .zip(inventories)
+        .filter(|(_, inventory)| {
+            args.kernel.as_deref().is_none_or(|pattern| {
+                inventory
+                    .as_ref()
+                    .and_then(|inventory| inventory.kernel.as_deref())
+                    .is_some_and(|kernel| kernel.contains(pattern))
+            })
+        })
+        .collect::<Vec<_>>();
+    print!(
+        "{}",
+        bcls::output::os_listing(&rows, timezone).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn migs(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &MigsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut migs = c
+        .list_all_instance_group_managers()
+        .map_err(|e| api_error("Failed to list instance groups", &habitat.project, e))?
+        .into_iter()
+        .filter(|mig| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| mig.name.contains(pattern))
+        })
+        .collect::<Vec<_>>();
+    migs.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| a.location.to_string().cmp(&b.location.to_string()))
+    });
+    let autoscalers = c
+        .list_all_autoscalers()
+        .map_err(|e| api_error("Failed to list autoscalers", &habitat.project, e))?;
+    print!(
+        "{}",
+        bcls::output::migs_listing(&migs, &autoscalers).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn dns(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &DnsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let d = dns_for(config, habitat)?;
+    let mut zones = d
+        .list_managed_zones()
+        .map_err(|e| api_error("Failed to list DNS zones", &habitat.project, e))?;
+    zones.sort_by(|a, b| a.name.cmp(&b.name));
+    let Some(pattern) = &args.pattern else {
+        print!(
+            "{}",
+            bcls::output::dns_zones_listing(&zones).render(args.output, &config.output.null)?
+        );
+        return Ok(());
+    };
+
+    if let Some(zone) = &args.zone {
+        zones.retain(|z| z.name == *zone);
+        if zones.is_empty() {
+            return Err(format!("DNS zone {} not found in {}", zone, habitat.project).into());
+        }
+    }
+    // Names aren't searchable in the API, so every record of the zones is fetched
+    let mut record_sets = vec![];
+    for zone in &zones {
+        let zone_record_sets = d.list_record_sets(&zone.name).map_err(|e| {
+            api_error(
+                &format!("Failed to list records of DNS zone {}", zone.name),
+                &habitat.project,
+                e,
+            )
+        })?;
+        record_sets.extend(
+            zone_record_sets
+                .into_iter()
+                .filter(|record_set| record_set.name_matches(pattern))
+                .filter(|record_set| {
+                    args.record_type
+                        .as_ref()
+                        .is_none_or(|t| record_set.record_type.eq_ignore_ascii_case(t))
+                })
+                .map(|record_set| (zone.name.clone(), record_set)),
+        );
+    }
+    record_sets.sort_by(|(_, a), (_, b)| (&a.name, &a.record_type).cmp(&(&b.name, &b.record_type)));
+    print!(
+        "{}",
+        bcls::output::record_sets_listing(&record_sets).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+/// Lists the clusters of a habitat, warning about zones whose clusters couldn't be listed.
+fn list_clusters(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+) -> Result<Vec<bcls::container::Cluster>, Box<dyn std::error::Error>> {
+    let list = container_for(config, habitat)?
+        .list_clusters()
+        .map_err(|e| api_error("Failed to list GKE clusters", &habitat.project, e))?;
+    if !list.missing_zones.is_empty() {
+        eprintln!(
+            "Warning: clusters in {} could not be listed",
+            list.missing_zones.join(", ")
+        );
+    }
+    Ok(list.clusters)
+}
+
+fn metadata_get(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &MetadataGetArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instance = c
+        .find_instance(&args.instance)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| {
+            format!(
+                "Instance {} not found in {}",
+                args.instance, habitat.project
+            )
+        })?;
+    let metadata = c
+        .get_metadata(&instance.zone, &instance.name)
+        .map_err(|e| api_error("Failed to get metadata", &habitat.project, e))?;
+    match &args.key {
+        Some(key) => {
+            let value = metadata
+                .get(key)
+                .ok_or_else(|| format!("Instance {} has no metadata key {}", instance.name, key))?;
+            println!("{}", value);
+        }
+        None => print!(
+            "{}",
+            bcls::output::metadata_listing(&metadata).render(args.output, &config.output.null)?
+        ),
+    }
+    Ok(())
+}
+
+fn metadata_set(
+    config: &bcls::config::FileConfig,
+    env: Environment,
+    habitat: &bcls::config::Habitat,
+    args: &MetadataSetArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instance = c
+        .find_instance(&args.instance)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| {
+            format!(
+                "Instance {} not found in {}",
+                args.instance, habitat.project
+            )
+        })?;
+    let items = args
+        .items
+        .iter()
+        .map(|item| format!("{}={}", item.key, item.value))
+        .collect::<Vec<_>>()
+        .join(" ");
+    confirm_change(
+        config,
+        env,
+        args.yes,
+        &format!("set metadata {} on instance {}", items, instance.name),
+    )?;
+    eprintln!("setMetadata {} in {}...", instance.name, instance.zone);
+    let operation = c
+        .set_metadata(&instance.zone, &instance.name, &args.items)
+        .map_err(|e| api_error("Failed to set metadata", &habitat.project, e))?;
+    match operation {
+        Some(operation) => println!(
+            "{}: {} {}",
+            operation.name, operation.operation_type, operation.status
+        ),
+        None => println!("{}: metadata already set, nothing to do", instance.name),
+    }
+    Ok(())
+}
+
+fn serial(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &SerialArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instance = c
+        .find_instance(&args.instance)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| {
+            format!(
+                "Instance {} not found in {}",
+                args.instance, habitat.project
+            )
+        })?;
+    let mut start = None;
+    loop {
+        let output = c
+            .get_serial_output(&instance.zone, &instance.name, args.port, start)
+            .map_err(|e| api_error("Failed to get serial port output", &habitat.project, e))?;
+        // Output that scrolled out of the 1 MB kept between two reads is gone
+        let skipped = start.map_or(0, |start| output.skipped(start));
+        if skipped > 0 {
+            eprintln!("[{} bytes of output lost]", skipped);
+        }
+        print!("{}", output.contents);
+        std::io::stdout().flush()?;
+        if !args.follow {
+            return Ok(());
+        }
+        start = Some(output.next);
+        std::thread::sleep(std::time::Duration::from_secs(args.interval));
+    }
+}
+
+/// Sets and removes labels of an instance, or with `--dry-run` only shows what would change.
+fn relabel(
+    config: &bcls::config::FileConfig,
+    env: Environment,
+    habitat: &bcls::config::Habitat,
+    name: &str,
+    set: &[bcls::compute::Label],
+    remove: &[String],
+    options: &RelabelOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instance = c
+        .find_instance(name)
+        .map_err(|e| api_error("Failed to look up instance", &habitat.project, e))?
+        .ok_or_else(|| format!("Instance {} not found in {}", name, habitat.project))?;
+    let changes = c
+        .get_labels(&instance.zone, &instance.name)
+        .map_err(|e| api_error("Failed to get labels", &habitat.project, e))?
+        .changes(set, remove);
+    if changes.is_empty() {
+        println!("{}: labels already set, nothing to do", instance.name);
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    if options.dry_run {
+        eprintln!("Dry run, {} not changed", instance.name);
+        return Ok(());
+    }
+    confirm_change(
+        config,
+        env,
+        options.yes,
+        &format!("change labels of instance {}", instance.name),
+    )?;
+    eprintln!("setLabels {} in {}...", instance.name, instance.zone);
+    let operation = c
+        .set_labels(&instance.zone, &instance.name, set, remove)
+        .map_err(|e| api_error("Failed to set labels", &habitat.project, e))?;
+    if let Some(operation) = operation {
+        println!(
+            "{}: {} {}",
+            operation.name, operation.operation_type, operation.status
+        );
+    }
+    // The cached listing now shows stale labels
+    instance_cache(config).invalidate(&habitat.project)?;
+    Ok(())
+}
+
+fn ops(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &OpsArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let mut operations = c
+        .list_all_operations()
+        .map_err(|e| api_error("Failed to list operations", &habitat.project, e))?
+        .into_iter()
+        .filter(|operation| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| operation.target.contains(pattern))
+        })
+        .filter(|operation| !args.running || !operation.is_done())
+        .collect::<Vec<_>>();
+    // The API reports times with an offset, e.g. -07:00, so compare them as instants
+    let inserted = |operation: &bcls::compute::Operation| {
+        operation
+            .insert_time
+            .as_deref()
+            .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+    };
+    operations.sort_by(|a, b| {
+        inserted(b)
+            .cmp(&inserted(a))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    operations.truncate(args.limit as usize);
+    print!(
+        "{}",
+        bcls::output::operations_listing(&operations, timezone)
+            .render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn history(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &HistoryArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let since = chrono::Utc::now()
+        .checked_sub_signed(args.since)
+        .ok_or("--since reaches back too far")?;
+    let entries = client_for(config, habitat, api_version)?
+        .cloud_logging()?
+        .instance_history(&args.name, since, args.limit as usize)
+        .map_err(|e| api_error("Failed to read audit logs", &habitat.project, e))?;
+    if entries.is_empty() {
+        eprintln!(
+            "No admin activity on {} in {} since {}",
+            args.name,
+            habitat.project,
+            timezone.format(since)
+        );
+    }
+    print!(
+        "{}",
+        bcls::output::history_listing(&entries, timezone)
+            .render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn ops_wait(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &OpsWaitArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let operation = c
+        .find_operation(&args.operation)
+        .map_err(|e| api_error("Failed to find operation", &habitat.project, e))?
+        .ok_or_else(|| {
+            format!(
+                "Operation {} not found in project {}",
+                args.operation, habitat.project
+            )
+        })?;
+    if !operation.is_done() {
+        eprintln!(
+            "Waiting for {} of {} ({})...",
+            operation.operation_type, operation.target, operation.name
+        );
+    }
+    let operation = c.wait_operation(operation)?;
+    println!(
+        "{} {} of {}: {}",
+        operation.name, operation.operation_type, operation.target, operation.status
+    );
+    Ok(())
+}
+
+fn gke(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &GkeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut clusters = list_clusters(config, habitat)?
+        .into_iter()
+        .filter(|cluster| {
+            args.pattern
+                .as_deref()
+                .is_none_or(|pattern| cluster.name.contains(pattern))
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| a.name.cmp(&b.name));
+    print!(
+        "{}",
+        bcls::output::clusters_listing(&clusters).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn gke_node_pools(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &NodePoolsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // The API needs the cluster's zone or region, so look it up by name unless it was given
+    let location = match &args.location {
+        Some(location) => location.clone(),
+        None => {
+            let locations = list_clusters(config, habitat)?
+                .into_iter()
+                .filter(|cluster| cluster.name == args.cluster)
+                .map(|cluster| cluster.location)
+                .collect::<Vec<_>>();
+            match locations.as_slice() {
+                [] => {
+                    return Err(format!(
+                        "GKE cluster {} not found in {}",
+                        args.cluster, habitat.project
+                    )
+                    .into())
+                }
+                [location] => location.clone(),
+                _ => {
+                    return Err(format!(
+                        "GKE cluster {} exists in {}; pick one with --location",
+                        args.cluster,
+                        locations.join(", ")
+                    )
+                    .into())
+                }
+            }
+        }
+    };
+    let pools = container_for(config, habitat)?
+        .list_node_pools(&location, &args.cluster)
+        .map_err(|e| api_error("Failed to list node pools", &habitat.project, e))?;
+    print!(
+        "{}",
+        bcls::output::node_pools_listing(&pools).render(args.output, &config.output.null)?
+    );
+    Ok(())
+}
+
+fn mig_describe(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &MigDescribeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    // The API needs the group's zone or region, so look it up by name first
+    let mut migs = c
+        .list_all_instance_group_managers()
+        .map_err(|e| api_error("Failed to list instance groups", &habitat.project, e))?
+        .into_iter()
+        .filter(|mig| mig.name == args.name)
+        .filter(|mig| {
+            args.location
+                .as_ref()
+                .is_none_or(|location| mig.location.to_string() == *location)
+        })
+        .collect::<Vec<_>>();
+    let mig = match migs.len() {
+        0 => {
+            return Err(format!(
+                "Instance group {} not found in {}",
+                args.name, habitat.project
+            )
+            .into())
+        }
+        1 => migs.remove(0),
+        _ => {
+            let locations = migs
+                .iter()
+                .map(|mig| mig.location.to_string())
+                .collect::<Vec<_>>();
+            return Err(format!(
+                "Instance group {} exists in {}; pick one with --location",
+                args.name,
+                locations.join(", ")
+            )
+            .into());
+        }
+    };
+    let autoscalers = c
+        .list_all_autoscalers()
+        .map_err(|e| api_error("Failed to list autoscalers", &habitat.project, e))?;
+    let mut instances = c
+        .list_managed_instances(&mig.location, &mig.name)
+        .map_err(|e| api_error("Failed to list managed instances", &habitat.project, e))?;
+    instances.sort_by(|a, b| a.name.cmp(&b.name));
+    print!(
+        "{}",
+        bcls::output::mig_detail_text(&mig, &autoscalers, &instances, &config.output.null.table)
+    );
+    Ok(())
+}
+
+fn disks_report(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &DisksReportArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, api_version)?;
+    let disks = c
+        .list_all_disks()
+        .map_err(|e| api_error("Failed to list disks", &habitat.project, e))?;
+    let policies = c
+        .list_all_resource_policies()
+        .map_err(|e| api_error("Failed to list resource policies", &habitat.project, e))?;
+
+    let rows = bcls::report::disk_schedules(disks, &policies)
+        .into_iter()
+        .filter(|row| !args.unscheduled || row.snapshot_schedules.is_empty())
+        .collect::<Vec<_>>();
+    print!(
+        "{}",
+        bcls::report::render_disk_schedules(&rows, args.format)
+    );
+    Ok(())
+}
+
+fn plan_zone_move(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    args: &PlanZoneMoveArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let c = compute_for(config, habitat, habitat.api_version.unwrap_or_default())?;
+    let instances = c
+        .list_all_instances()
+        .map_err(|e| api_error("Failed to list instances", &habitat.project, e))
+        .map(|listed| report_incomplete(&habitat.project, listed))?
+        .into_iter()
+        .filter(|inst| bcls::query::matches_pattern(inst, args.pattern.as_deref()))
+        .collect::<Vec<_>>();
+    let disks = c
+        .list_all_disks()
+        .map_err(|e| api_error("Failed to list disks", &habitat.project, e))?;
+    let reservations = c
+        .list_all_reservations()
+        .map_err(|e| api_error("Failed to list reservations", &habitat.project, e))?;
+
+    let plans = bcls::plan::plan_zone_move(&instances, &disks, &reservations, &args.from, &args.to);
+
+    let mut table = bcls::output::Table::new(["Name", "Machine Type", "Blockers"]);
+    for plan in &plans {
+        let blockers = match plan.blockers.is_empty() {
+            true => "-".to_string(),
+            false => plan
+                .blockers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        };
+        table.add_row([plan.instance.clone(), plan.machine_type.clone(), blockers]);
+    }
+    table.printstd();
+
+    let movable = plans.iter().filter(|p| p.blockers.is_empty()).count();
+    println!(
+        "\n{} of {} instances in {} can move to {} without blockers",
+        movable,
+        plans.len(),
+        args.from,
+        args.to
+    );
+    Ok(())
+}
+
+/// Treats a closed stdout as a clean exit, e.g. when the output of `bcls prd -o jsonl` is
+/// piped into `head -1`.
+fn ignore_broken_pipe(
+    result: Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn show_instances(
+    config: &bcls::config::FileConfig,
+    habitat: &bcls::config::Habitat,
+    api_version: bcls::compute::ApiVersion,
+    args: &EnvArgs,
+    timezone: bcls::output::Timezone,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.group_by.is_some() && !args.summary && args.output != bcls::output::Format::Table {
+        return Err("--group-by only works with table output".into());
+    }
+    if args.summary
+        && matches!(
+            args.output,
+            bcls::output::Format::Selflink | bcls::output::Format::Hosts
+        )
+    {
+        return Err("--summary only works with table, json and csv output".into());
+    }
+    if args.watch.is_some() && args.output != bcls::output::Format::Table {
+        return Err("--watch only works with table output".into());
+    }
+    if args.watch == Some(0) {
+        return Err("--watch needs an interval of at least 1 second".into());
+    }
+    let client = client_for(config, habitat, api_version)?;
+    let c = client.compute()?;
+    let mut labels = args.labels.clone();
+    if let Some(cell) = &args.cell {
+        labels.push(bcls::query::LabelFilter {
+            key: "cell".to_string(),
+            value: cell.clone(),
+        });
+    }
+    let filter = bcls::query::InstanceFilter {
+        labels,
+        statuses: args.status.clone(),
+        excluded_statuses: args.exclude_status.clone(),
+        resource_policies: args.resource_policies.clone(),
+        machine_types: args.machine_type.clone(),
+        created_before: args.older_than.map(|age| chrono::Utc::now() - age),
+        created_after: args.newer_than.map(|age| chrono::Utc::now() - age),
+        spot: match (args.spot_only, args.no_spot) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        },
+        service_accounts: args.service_account.clone(),
+    };
+    let name_pattern = match (&args.pattern, args.regex) {
+        (Some(pattern), true) => Some(bcls::query::NamePattern::regex(pattern)?),
+        (Some(pattern), false) => Some(bcls::query::NamePattern::plain(pattern)),
+        (None, _) => None,
+    };
+    let query = bcls::InstanceQuery {
+        filter,
+        name_pattern,
+        limit: args.limit,
+        cache: bcls::CacheMode::Use,
+    };
+
+    // The enrichment stages to run over the instances that will be shown
+    let machine_types = bcls::enrichment::MachineTypeStage::new(&c);
+    let dns = bcls::enrichment::DnsStage::new(c.project());
+    let schedules = bcls::enrichment::ScheduleStage::new(&c);
+    let stage = |kind: &bcls::enrichment::StageKind| match kind {
+        bcls::enrichment::StageKind::MachineType => &machine_types as &dyn bcls::enrichment::Stage,
+        bcls::enrichment::StageKind::Dns => &dns,
+        bcls::enrichment::StageKind::Schedule => &schedules,
+    };
+    // The columns picked with --columns or used by the --format template
+    let selected = match &args.format {
+        Some(template) => template.keys(),
+        None => args.columns.clone(),
+    };
+    // Stages are also run for the enrichment columns picked with --columns
+    let mut stages = <bcls::enrichment::StageKind as clap::ValueEnum>::value_variants()
+        .iter()
+        .filter(|kind| {
+            args.enrich.contains(kind)
+                || (args.resources && **kind == bcls::enrichment::StageKind::MachineType)
+                || stage(kind)
+                    .columns()
+                    .iter()
+                    .any(|column| selected.contains(&column.key))
+        })
+        .map(stage)
+        .collect::<Vec<_>>();
+    let metrics = <bcls::monitoring::Metric as clap::ValueEnum>::value_variants()
+        .iter()
+        .filter(|metric| {
+            args.metrics.contains(metric) || selected.iter().any(|key| key == metric.key())
+        })
+        .copied()
+        .collect::<Vec<_>>();
+    // The Monitoring API is only set up when metrics are shown
+    let monitoring = match metrics.is_empty() {
+        true => None,
+        false => Some(client_for(config, habitat, api_version)?.monitoring()?),
+    };
+    let metrics_stage = monitoring
+        .as_ref()
+        .map(|monitoring| bcls::enrichment::MetricsStage::new(monitoring, &metrics));
+    if let Some(metrics_stage) = &metrics_stage {
+        stages.push(metrics_stage);
+    }
+
+    // Lists, filters, samples, sorts and enriches the instances to show. Watch mode calls
+    // this on every refresh, always bypassing the cache. The instances matching the search
+    // pattern, labels and status are passed to `on_page` as each page arrives, and listing
+    // stops if it fails, e.g. because stdout was closed.
+    let list = |use_cache: bool,
+                on_page: &mut dyn FnMut(&[bcls::compute::Instance]) -> std::io::Result<()>|
+     -> Result<Vec<bcls::compute::Instance>, Box<dyn std::error::Error>> {
+        let query = bcls::InstanceQuery {
+            cache: cache_mode(args.no_cache, !use_cache),
+            ..query.clone()
+        };
+        let listed = client
+            .list_streaming(&query, on_page)
+            // Output errors, e.g. from a closed stdout, are passed on as they are
+            .map_err(|e| match e.is::<std::io::Error>() {
+                true => e,
+                false => api_error("Failed to list instances", &habitat.project, e).into(),
+            })?;
+        let instances = report_incomplete(&habitat.project, listed);
+
+        // Sample from the matches
+        let mut instances = match args.sample {
+            Some(count) => {
+                let total = instances.len();
+                let instances = bcls::query::sample(instances, count, args.seed);
+                eprintln!(
+                    "Showing {} of {} matching instances",
+                    instances.len(),
+                    total
+                );
+                instances
+            }
+            None => instances,
+        };
+        if let Some(key) = args.sort {
+            bcls::query::sort(&mut instances, key, args.reverse);
+        }
+        if !args.ip {
+            bcls::enrichment::run(&stages, &mut instances)?;
+        }
+        Ok(instances)
+    };
+
+    if let Some(version) = args.porcelain {
+        let instances = list(!args.no_cache && !args.refresh, &mut |_| Ok(()))?;
+        print!("{}", bcls::output::porcelain(version, &instances));
+        return Ok(());
+    }
+    if args.ip {
+        let instances = list(!args.no_cache && !args.refresh, &mut |_| Ok(()))?;
+        print!(
+            "{}",
+            bcls::output::ip_lines(&instances, args.external, args.all_ips)
+        );
+        return Ok(());
+    }
+
+    let columns = match selected.is_empty() {
+        true => {
+            let mut columns = match args.long {
+                true => bcls::output::long_columns(),
+                false => bcls::output::default_columns(),
+            };
+            if args.long && api_version == bcls::compute::ApiVersion::Beta {
+                columns.extend(bcls::output::beta_columns());
+            }
+            // JSON exports carry the creation time, in UTC too, so they can be compared across
+            // timezones
+            if matches!(
+                args.output,
+                bcls::output::Format::Json | bcls::output::Format::Jsonl
+            ) {
+                columns.push(bcls::output::created_column(timezone));
+                columns.push(bcls::output::created_utc_column());
+            }
+            for stage in &stages {
+                columns.extend(stage.columns());
+            }
+            columns
+        }
+        false => {
+            let mut registry = bcls::output::column_registry(timezone);
+            for kind in <bcls::enrichment::StageKind as clap::ValueEnum>::value_variants() {
+                registry.extend(stage(kind).columns());
+            }
+            registry.extend(bcls::enrichment::metric_columns(
+                <bcls::monitoring::Metric as clap::ValueEnum>::value_variants(),
+            ));
+            let mut columns = bcls::output::select_columns(registry, &selected)?;
+            // JSON exports carry the creation time in UTC too, so they can be compared across
+            // timezones
+            let created = columns.iter().position(|column| column.key == "created");
+            if let Some(position) = created.filter(|_| {
+                matches!(
+                    args.output,
+                    bcls::output::Format::Json | bcls::output::Format::Jsonl
+                )
+            }) {
+                if !args.columns.iter().any(|key| key == "created_utc") {
+                    columns.insert(position + 1, bcls::output::created_utc_column());
+                }
+            }
+            columns
+        }
+    };
+    let columns = match args.all_ips {
+        true => bcls::output::with_all_ips(columns),
+        false => columns,
+    };
+    if let Some(template) = &args.format {
+        let instances = list(!args.no_cache && !args.refresh, &mut |_| Ok(()))?;
+        print!(
+            "{}",
+            template.render(&columns, &instances, &config.output.null.table)
+        );
+        return Ok(());
+    }
+    if let Some(interval) = args.watch {
+        return watch(
+            || list(false, &mut |_| Ok(())),
+            &columns,
+            &config.output,
+            std::time::Duration::from_secs(interval),
+        );
+    }
+    if let (true, Some(group_by)) = (args.summary, &args.group_by) {
+        let instances = list(!args.no_cache && !args.refresh, &mut |_| Ok(()))?;
+        let counts = bcls::query::count_by(&instances, group_by);
+        print!(
+            "{}",
+            bcls::output::render_counts(args.output, group_by, &counts, &config.output.null)
+        );
+        return Ok(());
+    }
+    // Without sorting, sampling or enrichment the rows can be shown as the pages arrive,
+    // rather than after the whole fleet has been listed
+    let streaming = args.group_by.is_none()
+        && args.sort.is_none()
+        && args.sample.is_none()
+        && stages.is_empty();
+    if streaming && args.output == bcls::output::Format::Table {
+        let mut table = bcls::output::StreamingTable::new(&columns, &config.output.null)
+            .configure(&config.output);
+        let mut stdout = std::io::stdout().lock();
+        let listed = list(!args.no_cache && !args.refresh, &mut |page| {
+            stdout.write_all(table.page(page).as_bytes())
+        });
+        return ignore_broken_pipe(listed.and_then(|_| {
+            stdout.write_all(table.finish().as_bytes())?;
+            Ok(())
+        }));
+    }
+    // JSON Lines need no widths, so each page is written as soon as it has been parsed
+    if streaming && args.output == bcls::output::Format::Jsonl {
+        let nulls = &config.output.null;
+        let mut stdout = std::io::stdout().lock();
+        let listed = list(!args.no_cache && !args.refresh, &mut |page| {
+            stdout.write_all(bcls::output::instances_jsonl(&columns, page, nulls).as_bytes())
+        });
+        return ignore_broken_pipe(listed.map(|_| ()));
+    }
+    let instances = list(!args.no_cache && !args.refresh, &mut |_| Ok(()))?;
+    // The totals follow a table, but mustn't get into machine-readable output
+    let totals = args
+        .resources
+        .then(|| bcls::enrichment::ResourceTotals::of(&instances));
+    let print_totals = || match (&totals, args.output) {
+        (Some(totals), bcls::output::Format::Table) => println!("\n{}", totals),
+        (Some(totals), _) => eprintln!("{}", totals),
+        (None, _) => {}
+    };
+    if let Some(group_by) = &args.group_by {
+        let groups = bcls::query::group_by(instances, group_by)
+            .into_iter()
+            .map(|(value, instances)| {
+                let name =
+                    value.unwrap_or_else(|| format!("no {}", group_by.title().to_lowercase()));
+                (name, instances)
+            })
+            .collect::<Vec<_>>();
+        print!(
+            "{}",
+            bcls::output::grouped_tables(&columns, &groups, &config.output)
+        );
+        print_totals();
+        return Ok(());
+    }
+    print!(
+        "{}",
+        bcls::output::render_instances(args.output, &columns, &instances, &config.output)
+    );
+    print_totals();
+    //print_instances(instances);
+    Ok(())
+}
+
+/// Redraws the instance table every `interval` until interrupted, marking the instances
+/// whose status changed since the previous refresh.
+///
+/// # Arguments
+///
+/// * `list` - Lists the instances to show.
+/// * `columns` - The columns to show.
+/// * `output` - The `[output]` config section, with the placeholders for missing values,
+///   whether to highlight the changed rows in color rather than with a `*` and whether to fit
+///   the table into the terminal. The header is always shown.
+/// * `interval` - The time between refreshes.
+fn watch(
+    list: impl Fn() -> Result<Vec<bcls::compute::Instance>, Box<dyn std::error::Error>>,
+    columns: &[bcls::output::Column],
+    output: &bcls::config::OutputConfig,
+    interval: std::time::Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = output.color.enabled();
+    let mut previous = None;
+    let mut rows = String::new();
+    loop {
+        let time = chrono::Local::now().format("%H:%M:%S");
+        let status = match list() {
+            Ok(instances) => {
+                let changed = match &previous {
+                    Some(previous) => bcls::query::status_changes(previous, &instances),
+                    None => vec![false; instances.len()],
+                };
+                let table = bcls::output::instances_table(columns, &instances, &output.null)
+                    .max_width(bcls::output::table_width(output))
+                    .render();
+                rows = bcls::output::highlight_rows(&table, &changed, color);
+                previous = Some(bcls::query::statuses(&instances));
+                format!(
+                    "{} instances, {} changed",
+                    instances.len(),
+                    changed.iter().filter(|changed| **changed).count()
+                )
+            }
+            // A failed refresh, e.g. a dropped connection, keeps the last table on screen and
+            // is tried again at the next one
+            Err(e) => format!("listing failed: {}", e),
+        };
+        if std::io::stdout().is_terminal() {
+            // Clear the screen and move the cursor to the top left
+            print!("\x1b[2J\x1b[H");
+        }
+        println!("Every {}s, {}: {}", interval.as_secs(), time, status);
+        print!("{}", rows);
+        std::io::stdout().flush()?;
+        std::thread::sleep(interval);
+    }
+}
+
+#[allow(dead_code)]
+fn print_instances(instances: Vec<bcls::compute::Instance>) {
+    // Print each instance as a string
+    for inst in instances {
+        println!("{}", inst.as_string());
+    }
 }
@@ -0,0 +1,327 @@
+//! This module provides a client for the Cloud Monitoring API, querying recent metrics of
+//! instances, such as their CPU utilization.
+//!
+//! Metrics are queried for many instances at once: each request asks for the time series of
+//! up to `BATCH_SIZE` instances by name, aligned to a single mean over the query window, so a
+//! listing costs one request per metric and batch rather than one per instance.
+
+use crate::compute::TokenSource;
+use crate::http;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+
+/// The root of all Cloud Monitoring API URLs.
+const BASE_URL: &str = "https://monitoring.googleapis.com/v3";
+
+/// The number of instances whose time series are requested at once. It keeps the filter, and
+/// so the URL, well below the length limit of the API.
+pub const BATCH_SIZE: usize = 100;
+
+/// A metric of an instance that can be shown as a column, selected with `--metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Metric {
+    /// The CPU utilization of the instance, as reported by the hypervisor.
+    Cpu,
+    /// The memory in use, as reported by the Ops Agent. Instances without the agent have
+    /// no value.
+    Mem,
+}
+
+impl Metric {
+    /// Returns the key of the metric's column and enrichment field, e.g. `cpu_percent`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Metric::Cpu => "cpu_percent",
+            Metric::Mem => "memory_percent",
+        }
+    }
+
+    /// Returns the title of the metric's column.
+    pub fn title(self) -> &'static str {
+        match self {
+            Metric::Cpu => "CPU %",
+            Metric::Mem => "Memory %",
+        }
+    }
+
+    /// Returns the Cloud Monitoring filter selecting the metric's time series, without the
+    /// instances.
+    fn filter(self) -> &'static str {
+        match self {
+            Metric::Cpu => concat!(
+                r#"metric.type = "compute.googleapis.com/instance/cpu/utilization""#,
+                r#" AND resource.type = "gce_instance""#,
+            ),
+            Metric::Mem => concat!(
+                r#"metric.type = "agent.googleapis.com/memory/percent_used""#,
+                r#" AND metric.labels.state = "used""#,
+                r#" AND resource.type = "gce_instance""#,
+            ),
+        }
+    }
+
+    /// Converts a value of the metric to a percentage. CPU utilization is a fraction.
+    fn percent(self, value: f64) -> f64 {
+        match self {
+            Metric::Cpu => value * 100.0,
+            Metric::Mem => value,
+        }
+    }
+}
+
+/// Configuration for the `Monitoring` service.
+pub struct MonitoringConfig<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The HTTP client implementation.
+    pub client: H,
+    /// The token source.
+    pub token_source: T,
+    /// The maximum number of time series per page. `None` leaves it to the API.
+    pub page_size: Option<u32>,
+}
+
+/// A client for the Cloud Monitoring API of a project.
+pub struct Monitoring<H: http::HttpClient, T: TokenSource> {
+    config: MonitoringConfig<H, T>,
+}
+
+impl<H: http::HttpClient, T: TokenSource> Monitoring<H, T> {
+    /// Creates a new `Monitoring` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the `Monitoring` service.
+    pub fn new(config: MonitoringConfig<H, T>) -> Self {
+        Self { config }
+    }
+
+    /// Fetches the mean of a metric over a recent window for each of a set of instances.
+    ///
+    /// # Arguments
+    ///
+    /// * `metric` - The metric to fetch.
+    /// * `names` - The names of the instances. Instances with the same name in different zones
+    ///   are all fetched.
+    /// * `window` - How far back from `now` to average the metric over.
+    /// * `now` - The end of the window.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(HashMap)` - The metric as a percentage, keyed by the zone and name of the
+    ///   instance. Instances without data in the window are missing.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if an API call fails.
+    pub fn instance_metric(
+        &self,
+        metric: Metric,
+        names: &[&str],
+        window: chrono::Duration,
+        now: DateTime<Utc>,
+    ) -> Result<HashMap<(String, String), f64>, Box<dyn std::error::Error>> {
+        let mut names = names.to_vec();
+        names.sort();
+        names.dedup();
+        let mut values = HashMap::new();
+        for batch in names.chunks(BATCH_SIZE) {
+            let quoted = batch
+                .iter()
+                .map(|name| format!("\"{}\"", name))
+                .collect::<Vec<_>>();
+            let filter = format!(
+                "{} AND metadata.system_labels.name = one_of({})",
+                metric.filter(),
+                quoted.join(",")
+            );
+            for series in self.list_time_series(&filter, window, now)? {
+                if let Some((instance, value)) = latest_value(&series) {
+                    values.insert(instance, metric.percent(value));
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Fetches every page of the time series matching a filter, each aligned to its mean over
+    /// the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Cloud Monitoring filter, e.g. selecting a metric type.
+    /// * `window` - How far back from `now` to query.
+    /// * `now` - The end of the window.
+    fn list_time_series(
+        &self,
+        filter: &str,
+        window: chrono::Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<JsonValue>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/monitoring/api/ref_v3/rest/v3/projects.timeSeries/list>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let path = format!(
+            "{}/projects/{}/timeSeries",
+            BASE_URL,
+            urlencoding::encode(&self.config.project)
+        );
+        let timestamp = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let mut series = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let params = [
+                ("filter", filter.to_string()),
+                ("interval.startTime", timestamp(now - window)),
+                ("interval.endTime", timestamp(now)),
+                (
+                    "aggregation.alignmentPeriod",
+                    format!("{}s", window.num_seconds()),
+                ),
+                ("aggregation.perSeriesAligner", "ALIGN_MEAN".to_string()),
+            ]
+            .into_iter()
+            .chain(
+                self.config
+                    .page_size
+                    .map(|size| ("pageSize", size.to_string())),
+            )
+            .chain(page_token.map(|token| ("pageToken", token)))
+            .map(|(key, value)| format!("{}={}", key, urlencoding::encode(&value)))
+            .collect::<Vec<_>>();
+            let url = format!("{}?{}", path, params.join("&"));
+            let resp = self.config.client.get(&token, &url)?;
+            // No matching series at all means no timeSeries key
+            series.extend(resp["timeSeries"].as_array().into_iter().flatten().cloned());
+            match resp["nextPageToken"]
+                .as_str()
+                .filter(|token| !token.is_empty())
+            {
+                Some(token) => page_token = Some(token.to_string()),
+                None => return Ok(series),
+            }
+        }
+    }
+}
+
+/// Extracts the instance and the most recent value of a time series.
+///
+/// # Returns
+///
+/// The zone and name of the instance with the value, or `None` if the series lacks any of
+/// them.
+fn latest_value(series: &JsonValue) -> Option<((String, String), f64)> {
+    let zone = series.pointer("/resource/labels/zone")?.as_str()?;
+    let name = series.pointer("/metadata/systemLabels/name")?.as_str()?;
+    // Points are returned newest first
+    let value = series.pointer("/points/0/value")?;
+    let value = value
+        .get("doubleValue")
+        .and_then(JsonValue::as_f64)
+        // int64 values are encoded as strings
+        .or_else(|| value.get("int64Value")?.as_str()?.parse().ok())?;
+    Some(((zone.to_string(), name.to_string()), value))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::MockHttpClient;
+    use serde_json::json;
+
+    fn monitoring(mock_http: MockHttpClient) -> Monitoring<MockHttpClient, MockTokenSource> {
+        Monitoring::new(MonitoringConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+            page_size: None,
+        })
+    }
+
+    fn series(zone: &str, name: &str, value: JsonValue) -> JsonValue {
+        json!({
+            "resource": {"type": "gce_instance", "labels": {"zone": zone, "instance_id": "1"}},
+            "metadata": {"systemLabels": {"name": name}},
+            "points": [{"value": value}]
+        })
+    }
+
+    #[test]
+    fn test_instance_metric() {
+        let mut mock_http = MockHttpClient::new();
+        let mut seq = mockall::Sequence::new();
+        mock_http
+            .expect_get()
+            .withf(|_, url| {
+                url.starts_with(
+                    "https://monitoring.googleapis.com/v3/projects/test-project/timeSeries?filter=",
+                ) && url.contains(&*urlencoding::encode(r#"one_of("a","b")"#))
+                    && url.contains("interval.startTime=2024-03-01T11%3A50%3A00Z")
+                    && url.contains("aggregation.alignmentPeriod=600s")
+            })
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(json!({
+                    "timeSeries": [series("us-east1-b", "a", json!({"doubleValue": 0.425}))],
+                    "nextPageToken": "page2"
+                }))
+            });
+        mock_http
+            .expect_get()
+            .withf(|_, url| url.ends_with("pageToken=page2"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(json!({
+                    "timeSeries": [series("us-east1-c", "b", json!({"doubleValue": 0.05}))]
+                }))
+            });
+
+        let now = "2024-03-01T12:00:00Z".parse().unwrap();
+        let values = monitoring(mock_http)
+            .instance_metric(
+                Metric::Cpu,
+                &["b", "a", "a"],
+                chrono::Duration::minutes(10),
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[&("us-east1-b".to_string(), "a".to_string())], 42.5);
+        assert_eq!(values[&("us-east1-c".to_string(), "b".to_string())], 5.0);
+    }
+
+    #[test]
+    fn test_instance_metric_batches_names() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .times(2)
+            .returning(|_, _| Ok(json!({})));
+
+        let names = (0..BATCH_SIZE + 1)
+            .map(|i| format!("web-{}", i))
+            .collect::<Vec<_>>();
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let values = monitoring(mock_http)
+            .instance_metric(
+                Metric::Mem,
+                &names,
+                chrono::Duration::minutes(10),
+                Utc::now(),
+            )
+            .unwrap();
+
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_latest_value() {
+        assert_eq!(
+            latest_value(&series("z", "a", json!({"int64Value": "7"}))),
+            Some((("z".to_string(), "a".to_string()), 7.0))
+        );
+        assert_eq!(latest_value(&json!({"points": []})), None);
+    }
+}
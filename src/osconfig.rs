@@ -0,0 +1,142 @@
+//! This module provides a client for the OS Config API, fetching the OS inventory that the
+//! OS Config agent of an instance reports, such as its OS version, kernel and pending package
+//! updates.
+
+mod inventory;
+
+use crate::compute::TokenSource;
+use crate::http;
+
+pub use inventory::Inventory;
+
+/// The root of all OS Config API URLs.
+const BASE_URL: &str = "https://osconfig.googleapis.com/v1";
+
+/// Configuration for the `OsConfig` service.
+pub struct OsConfigConfig<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID.
+    pub project: String,
+    /// The HTTP client implementation.
+    pub client: H,
+    /// The token source.
+    pub token_source: T,
+}
+
+/// A client for the OS Config API of a project.
+pub struct OsConfig<H: http::HttpClient, T: TokenSource> {
+    config: OsConfigConfig<H, T>,
+}
+
+impl<H: http::HttpClient, T: TokenSource> OsConfig<H, T> {
+    /// Creates a new `OsConfig` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration for the `OsConfig` service.
+    pub fn new(config: OsConfigConfig<H, T>) -> Self {
+        Self { config }
+    }
+
+    /// Fetches the OS inventory of an instance, including its packages.
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone of the instance.
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Inventory))` - The inventory last reported by the instance.
+    /// * `Ok(None)` - If the instance has never reported an inventory, e.g. because it runs
+    ///   without the OS Config agent.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the API call fails or if there's an
+    ///   issue parsing the response.
+    pub fn get_inventory(
+        &self,
+        zone: &str,
+        name: &str,
+    ) -> Result<Option<Inventory>, Box<dyn std::error::Error>> {
+        // <https://cloud.google.com/compute/docs/osconfig/rest/v1/projects.locations.instances.inventories/get>
+        let token = self.config.token_source.get_token(&self.config.project)?;
+        let url = format!(
+            "{}/projects/{}/locations/{}/instances/{}/inventory?view=FULL",
+            BASE_URL,
+            urlencoding::encode(&self.config.project),
+            urlencoding::encode(zone),
+            urlencoding::encode(name)
+        );
+        match self.config.client.get(&token, &url) {
+            Ok(resp) => Ok(Some(Inventory::try_from(resp)?)),
+            Err(e)
+                if e.downcast_ref::<http::HttpError>()
+                    .is_some_and(http::HttpError::is_not_found) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::{HttpError, MockHttpClient};
+    use serde_json::json;
+
+    fn osconfig(mock_http: MockHttpClient) -> OsConfig<MockHttpClient, MockTokenSource> {
+        OsConfig::new(OsConfigConfig {
+            project: "test-project".to_string(),
+            client: mock_http,
+            token_source: MockTokenSource::new("mock_token"),
+        })
+    }
+
+    fn http_error(status: u16) -> Box<dyn std::error::Error> {
+        Box::new(HttpError {
+            status: Some(status),
+            message: "error".to_string(),
+            api_status: None,
+            client_request_id: "c-1".to_string(),
+            server_request_id: None,
+            retry_after: None,
+        })
+    }
+
+    #[test]
+    fn test_get_inventory() {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .withf(|token, url| {
+                token == "mock_token"
+                    && url
+                        == "https://osconfig.googleapis.com/v1/projects/test-project/locations/\
+                            us-east1-b/instances/web-1/inventory?view=FULL"
+            })
+            .times(1)
+            .returning(|_, _| Ok(json!({"osInfo": {"kernelRelease": "6.1.0-18-cloud-amd64"}})));
+        mock_http
+            .expect_get()
+            .withf(|_, url| url.contains("/instances/web-2/"))
+            .times(1)
+            .returning(|_, _| Err(http_error(404)));
+        mock_http
+            .expect_get()
+            .withf(|_, url| url.contains("/instances/web-3/"))
+            .times(1)
+            .returning(|_, _| Err(http_error(403)));
+
+        let osconfig = osconfig(mock_http);
+
+        let inventory = osconfig.get_inventory("us-east1-b", "web-1").unwrap();
+        assert_eq!(
+            inventory.unwrap().kernel,
+            Some("6.1.0-18-cloud-amd64".to_string())
+        );
+        assert_eq!(osconfig.get_inventory("us-east1-b", "web-2").unwrap(), None);
+        assert!(osconfig.get_inventory("us-east1-b", "web-3").is_err());
+    }
+}
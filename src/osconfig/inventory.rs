@@ -0,0 +1,109 @@
+//! This module defines the `Inventory` struct, which represents the OS inventory an instance's
+//! OS Config agent reports, and provides a `TryFrom` implementation for creating it from JSON
+//! data.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::error::Error;
+
+/// Represents the OS inventory of an instance, as last reported by its OS Config agent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inventory {
+    /// The name of the operating system, e.g. `Debian GNU/Linux 12 (bookworm)`.
+    pub os_name: Option<String>,
+    /// The version of the operating system, e.g. `12`.
+    pub os_version: Option<String>,
+    /// The release of the running kernel, e.g. `6.1.0-18-cloud-amd64`.
+    pub kernel: Option<String>,
+    /// The number of package updates available to the instance.
+    pub pending_updates: usize,
+    /// When the agent last reported the inventory.
+    pub update_time: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<JsonValue> for Inventory {
+    type Error = Box<dyn Error>;
+
+    /// Attempts to create an `Inventory` from a `JsonValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The `JsonValue` containing the inventory data, fetched with the `FULL` view
+    ///   so it includes the packages.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Inventory)` - The created `Inventory` on success.
+    /// * `Err(Box<dyn Error>)` - An error if the JSON data is not an object.
+    fn try_from(json: JsonValue) -> Result<Self, Self::Error> {
+        if !json.is_object() {
+            return Err("Invalid inventory: not an object".into());
+        }
+        let str_field = |pointer: &str| json.pointer(pointer).and_then(JsonValue::as_str);
+
+        // Packages are keyed by an ID; installed ones are listed alongside available updates
+        let pending_updates = json
+            .get("items")
+            .and_then(JsonValue::as_object)
+            .into_iter()
+            .flat_map(|items| items.values())
+            .filter(|item| item["type"].as_str() == Some("AVAILABLE_PACKAGE"))
+            .count();
+
+        Ok(Inventory {
+            os_name: str_field("/osInfo/longName")
+                .or(str_field("/osInfo/shortName"))
+                .map(str::to_string),
+            os_version: str_field("/osInfo/version").map(str::to_string),
+            kernel: str_field("/osInfo/kernelRelease").map(str::to_string),
+            pending_updates,
+            update_time: str_field("/updateTime")
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(|time| time.with_timezone(&Utc)),
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_inventory_from_json() {
+        let json = json!({
+            "name": "projects/123/locations/us-east1-b/instances/456/inventory",
+            "osInfo": {
+                "shortName": "debian",
+                "longName": "Debian GNU/Linux 12 (bookworm)",
+                "version": "12",
+                "kernelVersion": "#1 SMP PREEMPT_DYNAMIC Debian 6.1.76-1 (2024-02-01)",
+                "kernelRelease": "6.1.0-18-cloud-amd64"
+            },
+            "items": {
+                "installedPackage-curl": {"type": "INSTALLED_PACKAGE"},
+                "availablePackage-curl": {"type": "AVAILABLE_PACKAGE"},
+                "availablePackage-linux-image-cloud-amd64": {"type": "AVAILABLE_PACKAGE"}
+            },
+            "updateTime": "2024-03-01T12:00:00.123Z"
+        });
+
+        let inventory = Inventory::try_from(json).unwrap();
+
+        assert_eq!(
+            inventory.os_name,
+            Some("Debian GNU/Linux 12 (bookworm)".to_string())
+        );
+        assert_eq!(inventory.os_version, Some("12".to_string()));
+        assert_eq!(inventory.kernel, Some("6.1.0-18-cloud-amd64".to_string()));
+        assert_eq!(inventory.pending_updates, 2);
+        assert!(inventory.update_time.is_some());
+
+        // The basic view has no packages
+        let basic = Inventory::try_from(json!({"osInfo": {"shortName": "rhel"}})).unwrap();
+        assert_eq!(basic.os_name, Some("rhel".to_string()));
+        assert_eq!(basic.pending_updates, 0);
+        assert!(Inventory::try_from(json!([])).is_err());
+    }
+}
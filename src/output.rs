@@ -0,0 +1,1699 @@
+//! This module renders instance listings as tables, JSON or CSV.
+//! Table column widths are measured in terminal cells (using `unicode-width`) rather than bytes or
+//! chars, so names and labels containing CJK characters or emoji stay aligned.
+
+mod listing;
+mod porcelain;
+mod template;
+
+use crate::compute::{last_segment, Instance, InstanceDetail, ListWarning};
+use crate::config::{NullValues, OutputConfig};
+use crate::query::GroupBy;
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde_json::{Map, Value as JsonValue};
+use std::io::IsTerminal;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub use listing::{
+    addresses_listing, clusters_listing, disks_listing, dns_zones_listing, history_listing,
+    images_listing, metadata_listing, mig_detail_text, migs_listing, networks_listing,
+    node_pools_listing, operations_listing, os_listing, record_sets_listing, snapshots_listing,
+    subnets_listing, templates_listing, Listing,
+};
+pub use porcelain::{porcelain, PorcelainVersion};
+pub use template::Template;
+
+/// A plain-text table whose columns are aligned by display width.
+#[derive(Debug, Default)]
+pub struct Table {
+    /// The header row.
+    header: Vec<String>,
+    /// The data rows.
+    rows: Vec<Vec<String>>,
+    /// The style of each column's data cells, if any.
+    styles: Vec<Option<CellStyle>>,
+    /// Whether cells are colored by their column's style.
+    color: bool,
+    /// The width to fit the table into, in terminal cells, or `None` to never truncate.
+    max_width: Option<usize>,
+    /// Whether to leave out the header row.
+    no_header: bool,
+}
+
+/// Columns aren't narrowed below this many cells, or their title if it is wider, to fit a
+/// table into the terminal.
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// Returns the width of the terminal stdout is connected to, in cells, or `None` if stdout is
+/// not a terminal, e.g. when piping into another command.
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    match crossterm::terminal::size() {
+        Ok((columns, _)) if columns > 0 => return Some(columns as usize),
+        _ => {}
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
+
+/// Returns the width to fit tables into: the terminal's, unless `wide` is set in the config or
+/// with `--wide`.
+pub fn table_width(config: &OutputConfig) -> Option<usize> {
+    match config.wide {
+        true => None,
+        false => terminal_width(),
+    }
+}
+
+/// Chooses the color of a cell from its text, as the parameter of an ANSI SGR escape, e.g.
+/// `32` for green, or `None` to leave the cell as it is.
+pub type CellStyle = fn(&str) -> Option<&'static str>;
+
+/// When output is colored, set with `--color`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// When stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Always, e.g. when piping into `less -R`.
+    Always,
+    /// Never.
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output to stdout is colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => crate::doctor::use_color(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Colors an instance status: green when running, red when terminated and yellow while it
+/// is changing state. Stopped and suspended instances are left as they are.
+pub fn status_color(status: &str) -> Option<&'static str> {
+    match status {
+        "RUNNING" => Some("32"),
+        "TERMINATED" => Some("31"),
+        "PROVISIONING" | "STAGING" | "STOPPING" | "SUSPENDING" | "REPAIRING" => Some("33"),
+        _ => None,
+    }
+}
+
+impl Table {
+    /// Creates a new `Table` with the given header row.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The column titles.
+    pub fn new<S: Into<String>>(header: impl IntoIterator<Item = S>) -> Self {
+        Table {
+            header: header.into_iter().map(Into::into).collect(),
+            ..Table::default()
+        }
+    }
+
+    /// Sets whether data cells are colored by the style of their column.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the width to fit the table into, in terminal cells. The widest columns are
+    /// narrowed first and cells that don't fit are cut short with `…`. With `None` the table
+    /// is as wide as its content.
+    pub fn max_width(mut self, max_width: Option<usize>) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Sets whether to leave out the header row, e.g. for scripts.
+    pub fn no_header(mut self, no_header: bool) -> Self {
+        self.no_header = no_header;
+        self
+    }
+
+    /// Applies the table settings of the `[output]` config section: color, the width to fit
+    /// into and the header.
+    pub fn configure(self, config: &OutputConfig) -> Self {
+        self.color(config.color.enabled())
+            .max_width(table_width(config))
+            .no_header(config.no_header)
+    }
+
+    /// Returns the styles to render data rows with, none if they aren't colored.
+    fn row_styles(&self) -> &[Option<CellStyle>] {
+        match self.color {
+            true => &self.styles,
+            false => &[],
+        }
+    }
+
+    /// Appends a data row. Rows shorter than the header are padded with empty cells.
+    pub fn add_row<S: Into<String>>(&mut self, row: impl IntoIterator<Item = S>) {
+        self.rows.push(row.into_iter().map(Into::into).collect());
+    }
+
+    /// Computes the display width of each column over the header and all rows.
+    fn column_widths(&self) -> Vec<usize> {
+        let columns = std::iter::once(&self.header)
+            .chain(&self.rows)
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(UnicodeWidthStr::width(cell.as_str()));
+            }
+        }
+        widths
+    }
+
+    /// Narrows column widths, as computed by `column_widths`, to fit the table into its
+    /// maximum width. The widest column is narrowed a cell at a time, but no column below
+    /// `MIN_COLUMN_WIDTH` or its title, so a table with many columns may still not fit.
+    fn fit(&self, mut widths: Vec<usize>) -> Vec<usize> {
+        let Some(max_width) = self.max_width else {
+            return widths;
+        };
+        let minimums = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let title = self.header.get(i).map_or(0, |title| title.width());
+                (*width).min(title.max(MIN_COLUMN_WIDTH))
+            })
+            .collect::<Vec<_>>();
+        // Every cell has a space either side, and the line is indented by one more space
+        while widths.iter().map(|width| width + 2).sum::<usize>() > max_width {
+            let widest = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, width)| **width > minimums[*i])
+                .max_by_key(|(i, width)| (**width, std::cmp::Reverse(*i)))
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+        widths
+    }
+
+    /// Renders the table to a string, one line per row with the header first.
+    ///
+    /// Each cell is padded to the width of its column with one space either side. Trailing
+    /// whitespace is trimmed from each line.
+    pub fn render(&self) -> String {
+        let widths = self.fit(self.column_widths());
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| render_row(row, &widths, self.row_styles()));
+        let header = (!self.no_header).then(|| render_row(&self.header, &widths, &[]));
+        header.into_iter().chain(rows).collect()
+    }
+
+    /// Prints the rendered table to stdout.
+    pub fn printstd(&self) {
+        print!("{}", self.render());
+    }
+}
+
+/// Renders a table row as a line, padding each cell to the width of its column with one space
+/// either side and trimming trailing whitespace. Cells wider than their column are truncated.
+/// Cells are colored by the style of their column, leaving the padding uncolored.
+fn render_row(row: &[String], widths: &[usize], styles: &[Option<CellStyle>]) -> String {
+    let mut line = String::from(" ");
+    for (i, width) in widths.iter().enumerate() {
+        let cell = row.get(i).map(String::as_str).unwrap_or("");
+        let style = styles.get(i).copied().flatten();
+        let color = style.and_then(|style| style(cell));
+        let cell = truncate(cell, *width);
+        let padding = width.saturating_sub(UnicodeWidthStr::width(cell.as_ref()));
+        line.push(' ');
+        match color {
+            Some(color) => line.push_str(&format!("\x1b[{}m{}\x1b[0m", color, cell)),
+            None => line.push_str(&cell),
+        }
+        line.push_str(&" ".repeat(padding + 1));
+    }
+    let mut line = line.trim_end().to_string();
+    line.push('\n');
+    line
+}
+
+/// Cuts a cell short to fit into `width` terminal cells, ending it with `…` to show that
+/// something is missing. Cells that fit are returned as they are.
+fn truncate(cell: &str, width: usize) -> std::borrow::Cow<'_, str> {
+    if cell.width() <= width {
+        return cell.into();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in cell.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if used + c_width + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += c_width;
+    }
+    out.push('…');
+    out.into()
+}
+
+/// The output formats supported for instance listings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// An aligned, human-readable table.
+    #[default]
+    Table,
+    /// A JSON array with one object per instance.
+    Json,
+    /// One JSON object per line (JSON Lines), written as the pages of the listing arrive.
+    Jsonl,
+    /// Comma-separated values with a header line.
+    Csv,
+    /// The self link of each instance, one per line, e.g. for tools that take self links as
+    /// input.
+    Selflink,
+    /// `IP<TAB>name` lines for `/etc/hosts` or dnsmasq.
+    Hosts,
+}
+
+/// Extracts a column's value from an instance, or `None` if the instance has no value.
+type ValueFn = Box<dyn Fn(&Instance) -> Option<JsonValue>>;
+
+/// The timezone timestamps are rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timezone {
+    /// The timezone of the machine bcls runs on.
+    #[default]
+    Local,
+    /// UTC, e.g. to compare timestamps with colleagues in other timezones.
+    Utc,
+}
+
+impl Timezone {
+    /// Renders a timestamp as RFC3339 to the second, with the offset of the timezone, e.g.
+    /// `2024-03-01T18:30:00+01:00`.
+    pub fn format(self, time: DateTime<Utc>) -> String {
+        match self {
+            Timezone::Local => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+            Timezone::Utc => time.to_rfc3339_opts(SecondsFormat::Secs, false),
+        }
+    }
+}
+
+/// A column in an instance listing.
+pub struct Column {
+    /// The key used for JSON objects and the CSV header, and to select the column with
+    /// `--columns`.
+    pub key: String,
+    /// The title used for the table header.
+    pub title: String,
+    /// Extracts the column's value from an instance.
+    pub value: ValueFn,
+    /// How the column's cells are colored in tables, if at all.
+    pub style: Option<CellStyle>,
+}
+
+impl Column {
+    /// Creates a new `Column`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key used for JSON objects and the CSV header.
+    /// * `title` - The title used for the table header.
+    /// * `value` - Extracts the column's value from an instance, or `None` if the instance has
+    ///   no value.
+    pub fn new(
+        key: impl Into<String>,
+        title: impl Into<String>,
+        value: impl Fn(&Instance) -> Option<JsonValue> + 'static,
+    ) -> Self {
+        Column {
+            key: key.into(),
+            title: title.into(),
+            value: Box::new(value),
+            style: None,
+        }
+    }
+
+    /// Colors the column's cells in tables.
+    pub fn with_style(mut self, style: CellStyle) -> Self {
+        self.style = Some(style);
+        self
+    }
+}
+
+/// The columns shown in a default instance listing: name and IP.
+pub fn default_columns() -> Vec<Column> {
+    vec![
+        Column::new("name", "Name", |i| Some(i.name.clone().into())),
+        Column::new("ip", "IP", |i| i.primary_ip().map(Into::into)),
+    ]
+}
+
+/// The columns shown in a long instance listing, in display order.
+pub fn long_columns() -> Vec<Column> {
+    let mut columns = default_columns();
+    columns.extend([
+        Column::new("external_ip", "External IP", |i| {
+            i.external_ip().map(Into::into)
+        }),
+        Column::new("zone", "Zone", |i| Some(i.zone.clone().into())),
+        Column::new("machine_type", "Machine Type", |i| {
+            Some(i.machine_type.clone().into())
+        }),
+        Column::new("cpu_platform", "CPU Platform", |i| {
+            (!i.cpu_platform.is_empty()).then(|| i.cpu_platform.clone().into())
+        }),
+        Column::new("disks", "Disks", |i| i.disk_summary().map(Into::into)),
+        Column::new("status", "Status", |i| Some(i.status.clone().into())).with_style(status_color),
+        Column::new("cell", "Cell", |i| i.cell.clone().map(Into::into)),
+        Column::new("labels", "Labels", |i| {
+            i.labels.as_ref().map(|labels| {
+                labels
+                    .iter()
+                    .map(|(k, v)| (k.clone(), JsonValue::from(v.clone())))
+                    .collect::<Map<String, JsonValue>>()
+                    .into()
+            })
+        }),
+    ]);
+    columns
+}
+
+/// Columns for fields that are only returned by the beta API.
+pub fn beta_columns() -> Vec<Column> {
+    vec![
+        Column::new("host_error_timeout_seconds", "Host Error Timeout", |i| {
+            i.beta
+                .as_ref()
+                .and_then(|b| b.host_error_timeout_seconds)
+                .map(|t| format!("{}s", t).into())
+        }),
+        Column::new("graceful_shutdown", "Graceful Shutdown", |i| {
+            i.beta
+                .as_ref()
+                .and_then(|b| b.graceful_shutdown)
+                .map(|g| g.to_string().into())
+        }),
+    ]
+}
+
+/// The columns listing the internal and external addresses of every network interface.
+fn all_ips_columns() -> [Column; 2] {
+    [
+        Column::new("ips", "IPs", |i| Some(i.ips().into())),
+        Column::new("external_ips", "External IPs", |i| {
+            Some(i.external_ips().into())
+        }),
+    ]
+}
+
+/// Replaces the `ip` and `external_ip` columns with columns listing the addresses of every
+/// network interface rather than just the first.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to update.
+pub fn with_all_ips(columns: Vec<Column>) -> Vec<Column> {
+    columns
+        .into_iter()
+        .map(|column| {
+            let [ips, external_ips] = all_ips_columns();
+            match column.key.as_str() {
+                "ip" => ips,
+                "external_ip" => external_ips,
+                _ => column,
+            }
+        })
+        .collect()
+}
+
+/// The column showing when an instance was created, in the given timezone.
+pub fn created_column(timezone: Timezone) -> Column {
+    Column::new("created", "Created", move |i| {
+        i.creation_timestamp
+            .map(|created| timezone.format(created).into())
+    })
+}
+
+/// The column showing when an instance was created, always in UTC.
+pub fn created_utc_column() -> Column {
+    Column::new("created_utc", "Created (UTC)", |i| {
+        i.creation_timestamp
+            .map(|created| Timezone::Utc.format(created).into())
+    })
+}
+
+/// Formats a length of time compactly in its two largest units, e.g. `3d 4h` or `25m`.
+pub fn format_elapsed(elapsed: chrono::Duration) -> String {
+    let minutes = elapsed.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+/// The columns showing how long ago an instance was created and how long it has been running
+/// since it was last started, as of `now`.
+pub fn age_columns(now: DateTime<Utc>) -> [Column; 2] {
+    [
+        Column::new("age", "Age", move |i| {
+            i.creation_timestamp
+                .map(|created| format_elapsed(now - created).into())
+        }),
+        Column::new("uptime", "Uptime", move |i| {
+            let started = i.last_start_timestamp.or(i.creation_timestamp);
+            match i.status.as_str() {
+                "RUNNING" => started.map(|started| format_elapsed(now - started).into()),
+                _ => None,
+            }
+        }),
+    ]
+}
+
+/// The column marking instances that run on spot capacity: `SPOT` for spot VMs and
+/// `PREEMPTIBLE` for legacy preemptible VMs.
+pub fn spot_column() -> Column {
+    Column::new("spot", "Spot", |i| {
+        match (i.provisioning_model.as_deref(), i.preemptible) {
+            (Some("SPOT"), _) => Some("SPOT".into()),
+            (_, true) => Some("PREEMPTIBLE".into()),
+            _ => None,
+        }
+    })
+}
+
+/// The column showing the environment an instance was listed in, for listings across
+/// environments. Reads the `environment` field set on each instance by the caller.
+pub fn environment_column() -> Column {
+    Column::new("environment", "Environment", |i| {
+        i.enrichments.get("environment").map(|v| v.clone().into())
+    })
+}
+
+/// Every built-in column that can be selected with `--columns`: the long listing, the
+/// beta-only fields, the all-addresses columns, the creation time, age and uptime, the spot
+/// marker, the service account and the resource policies.
+///
+/// # Arguments
+///
+/// * `timezone` - The timezone of the `created` column.
+pub fn column_registry(timezone: Timezone) -> Vec<Column> {
+    let mut columns = long_columns();
+    columns.extend(beta_columns());
+    columns.extend(all_ips_columns());
+    columns.extend([created_column(timezone), created_utc_column()]);
+    columns.extend(age_columns(Utc::now()));
+    columns.push(spot_column());
+    columns.push(Column::new("service_account", "Service Account", |i| {
+        Some(i.service_accounts.join(",").into())
+    }));
+    columns.push(Column::new("resource_policies", "Resource Policies", |i| {
+        Some(i.resource_policies.clone().into())
+    }));
+    columns.push(Column::new("self_link", "Self Link", |i| {
+        i.self_link.clone().map(Into::into)
+    }));
+    columns
+}
+
+/// Builds a column showing the value of a single label, e.g. `labels.team`.
+fn label_column(key: &str) -> Column {
+    let label = key.to_string();
+    Column::new(format!("labels.{}", key), key, move |i| {
+        i.labels
+            .as_ref()
+            .and_then(|labels| labels.get(&label))
+            .map(|value| value.clone().into())
+    })
+}
+
+/// Picks columns by key, in the order given, for `--columns`.
+///
+/// # Arguments
+///
+/// * `registry` - The columns that can be selected, e.g. `column_registry()` plus the columns
+///   of the enrichment stages.
+/// * `keys` - The keys of the columns to show. `labels.KEY` selects the value of a single
+///   label.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Column>)` - The selected columns.
+/// * `Err(String)` - An error listing the known keys if a key is unknown, or if a key is
+///   selected twice.
+pub fn select_columns(registry: Vec<Column>, keys: &[String]) -> Result<Vec<Column>, String> {
+    let known = registry.iter().map(|c| c.key.clone()).collect::<Vec<_>>();
+    let mut registry = registry.into_iter().map(Some).collect::<Vec<_>>();
+    let mut columns = Vec::new();
+    for key in keys {
+        if columns.iter().any(|c: &Column| c.key == *key) {
+            return Err(format!("Column {} is selected more than once", key));
+        }
+        let column = match key.strip_prefix("labels.") {
+            Some(label) if !label.is_empty() => label_column(label),
+            _ => registry
+                .iter_mut()
+                .find(|c| c.as_ref().is_some_and(|c| c.key == *key))
+                .and_then(Option::take)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown column {}. Known columns: {}, labels.KEY",
+                        key,
+                        known.join(", ")
+                    )
+                })?,
+        };
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// Formats a column value as plain text. Arrays (such as IP lists) become a comma-separated
+/// list and objects (such as labels) a comma-separated `key: value` list, sorted by key so the
+/// output is stable between runs. Empty arrays are treated as missing.
+///
+/// # Arguments
+///
+/// * `value` - The column value.
+/// * `null` - The placeholder for a missing value.
+fn value_to_text(value: Option<JsonValue>, null: &str) -> String {
+    match value {
+        None | Some(JsonValue::Null) => null.to_string(),
+        Some(JsonValue::String(s)) => s,
+        Some(JsonValue::Array(values)) if values.is_empty() => null.to_string(),
+        Some(JsonValue::Array(values)) => values
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| v.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        Some(JsonValue::Object(map)) => {
+            let mut entries = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v.as_str().unwrap_or_default()))
+                .collect::<Vec<String>>();
+            entries.sort();
+            entries.join(", ")
+        }
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Builds a table with one row per instance.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to show.
+/// * `instances` - The instances to include in the table.
+/// * `nulls` - The placeholders for missing values.
+pub fn instances_table(columns: &[Column], instances: &[Instance], nulls: &NullValues) -> Table {
+    let mut table = Table::new(columns.iter().map(|c| c.title.as_str()));
+    table.styles = columns.iter().map(|c| c.style).collect();
+    for inst in instances {
+        table.add_row(
+            columns
+                .iter()
+                .map(|c| value_to_text((c.value)(inst), &nulls.table)),
+        );
+    }
+    table
+}
+
+/// An instance table rendered a page of instances at a time, so rows can be shown while the
+/// rest of the listing is still being fetched.
+///
+/// The column widths are measured on the first page. Later rows widen their columns when they
+/// don't fit, so only rows wider than everything before them are out of line.
+pub struct StreamingTable<'a> {
+    /// The columns to show.
+    columns: &'a [Column],
+    /// The placeholders for missing values.
+    nulls: &'a NullValues,
+    /// The current column widths, or `None` until the header has been rendered.
+    widths: Option<Vec<usize>>,
+    /// Whether cells are colored by their column's style.
+    color: bool,
+    /// The width to fit the table into, or `None` to never truncate.
+    max_width: Option<usize>,
+    /// Whether to leave out the header row.
+    no_header: bool,
+}
+
+impl<'a> StreamingTable<'a> {
+    /// Creates a new `StreamingTable`.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The columns to show.
+    /// * `nulls` - The placeholders for missing values.
+    pub fn new(columns: &'a [Column], nulls: &'a NullValues) -> Self {
+        StreamingTable {
+            columns,
+            nulls,
+            widths: None,
+            color: false,
+            max_width: None,
+            no_header: false,
+        }
+    }
+
+    /// Applies the table settings of the `[output]` config section, as `Table::configure`.
+    pub fn configure(mut self, config: &OutputConfig) -> Self {
+        self.color = config.color.enabled();
+        self.max_width = table_width(config);
+        self.no_header = config.no_header;
+        self
+    }
+
+    /// Builds the table of a page of instances with the settings of this table.
+    fn table(&self, instances: &[Instance]) -> Table {
+        instances_table(self.columns, instances, self.nulls)
+            .color(self.color)
+            .max_width(self.max_width)
+            .no_header(self.no_header)
+    }
+
+    /// Renders a page of instances, preceded by the header if this is the first page.
+    pub fn page(&mut self, instances: &[Instance]) -> String {
+        let table = self.table(instances);
+        let page_widths = table.column_widths();
+        let mut out = String::new();
+        let widths = match &mut self.widths {
+            Some(widths) => {
+                for (width, page_width) in widths.iter_mut().zip(page_widths) {
+                    *width = (*width).max(page_width);
+                }
+                table.fit(widths.clone())
+            }
+            None => {
+                let widths = table.fit(self.widths.insert(page_widths).clone());
+                if !self.no_header {
+                    out += &render_row(&table.header, &widths, &[]);
+                }
+                widths
+            }
+        };
+        for row in &table.rows {
+            out += &render_row(row, &widths, table.row_styles());
+        }
+        out
+    }
+
+    /// Renders whatever is still missing once the listing is complete: the header, if no page
+    /// was rendered.
+    pub fn finish(self) -> String {
+        match self.widths {
+            Some(_) => String::new(),
+            None => self.table(&[]).render(),
+        }
+    }
+}
+
+/// Renders instances as a pretty-printed JSON array of objects.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to show.
+/// * `instances` - The instances to render.
+/// * `nulls` - The placeholders for missing values.
+pub fn instances_json(columns: &[Column], instances: &[Instance], nulls: &NullValues) -> String {
+    let objects = instances
+        .iter()
+        .map(|inst| instance_object(columns, inst, nulls))
+        .collect::<Vec<JsonValue>>();
+    serde_json::to_string_pretty(&objects).expect("JSON values always serialize") + "\n"
+}
+
+/// Renders instances as JSON Lines: one compact JSON object per line, so a listing can be
+/// written a page at a time.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to show.
+/// * `instances` - The instances to render.
+/// * `nulls` - The placeholders for missing values.
+pub fn instances_jsonl(columns: &[Column], instances: &[Instance], nulls: &NullValues) -> String {
+    let objects = instances
+        .iter()
+        .map(|inst| instance_object(columns, inst, nulls))
+        .collect::<Vec<JsonValue>>();
+    json_lines(&objects)
+}
+
+/// Builds the JSON object of an instance, with a key per column.
+fn instance_object(columns: &[Column], inst: &Instance, nulls: &NullValues) -> JsonValue {
+    columns
+        .iter()
+        .map(|c| {
+            let value = (c.value)(inst).unwrap_or_else(|| match &nulls.json {
+                Some(null) => null.clone().into(),
+                None => JsonValue::Null,
+            });
+            (c.key.clone(), value)
+        })
+        .collect::<Map<String, JsonValue>>()
+        .into()
+}
+
+/// Renders JSON values as JSON Lines, one compact value per line.
+pub(crate) fn json_lines(values: &[JsonValue]) -> String {
+    values
+        .iter()
+        .map(|value| serde_json::to_string(value).expect("JSON values always serialize") + "\n")
+        .collect()
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote, or line break.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Renders instances as CSV with a header line.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to show.
+/// * `instances` - The instances to render.
+/// * `nulls` - The placeholders for missing values.
+pub fn instances_csv(columns: &[Column], instances: &[Instance], nulls: &NullValues) -> String {
+    let mut out = columns
+        .iter()
+        .map(|c| c.key.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+        + "\n";
+    for inst in instances {
+        let line = columns
+            .iter()
+            .map(|c| csv_field(&value_to_text((c.value)(inst), &nulls.csv)))
+            .collect::<Vec<_>>()
+            .join(",");
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders instances in the requested format.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `columns` - The columns to show.
+/// * `instances` - The instances to render.
+/// * `config` - The `[output]` config section, with the placeholders for missing values and
+///   the table settings.
+pub fn render_instances(
+    format: Format,
+    columns: &[Column],
+    instances: &[Instance],
+    config: &OutputConfig,
+) -> String {
+    let nulls = &config.null;
+    match format {
+        Format::Table => instances_table(columns, instances, nulls)
+            .configure(config)
+            .render(),
+        Format::Json => instances_json(columns, instances, nulls),
+        Format::Jsonl => instances_jsonl(columns, instances, nulls),
+        Format::Csv => instances_csv(columns, instances, nulls),
+        Format::Selflink => selflink_lines(instances),
+        Format::Hosts => hosts_lines(instances, config.hosts_domain.as_deref()),
+    }
+}
+
+/// Renders the self link of each instance on its own line. Instances without a self link are
+/// skipped.
+pub fn selflink_lines(instances: &[Instance]) -> String {
+    instances
+        .iter()
+        .filter_map(|instance| instance.self_link.as_deref())
+        .map(|link| format!("{}\n", link))
+        .collect()
+}
+
+/// Renders the primary IP and name of each instance as an `/etc/hosts` line. With a domain,
+/// the fully qualified name comes first and the bare name follows as an alias, e.g.
+/// `10.0.0.1\tstore-lb-1.corp.example store-lb-1`. Instances without an IP are skipped.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to render.
+/// * `domain` - The domain to append to the names, if any.
+pub fn hosts_lines(instances: &[Instance], domain: Option<&str>) -> String {
+    instances
+        .iter()
+        .filter_map(|instance| {
+            let ip = instance.primary_ip()?;
+            Some(match domain {
+                Some(domain) => format!(
+                    "{}\t{}.{} {}\n",
+                    ip,
+                    instance.name,
+                    domain.trim_start_matches('.'),
+                    instance.name
+                ),
+                None => format!("{}\t{}\n", ip, instance.name),
+            })
+        })
+        .collect()
+}
+
+/// Renders instance counts per value of a field, for `--summary`. Tables end with the total.
+/// Counts have no self links or IPs, so the `Selflink` and `Hosts` formats render a table too.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `group_by` - The field the instances were counted by.
+/// * `counts` - The number of instances per value, in display order, as from
+///   `query::count_by`.
+/// * `nulls` - The placeholders for missing values.
+pub fn render_counts(
+    format: Format,
+    group_by: &GroupBy,
+    counts: &[(Option<String>, usize)],
+    nulls: &NullValues,
+) -> String {
+    match format {
+        Format::Table | Format::Selflink | Format::Hosts => {
+            let mut table = Table::new([group_by.title(), "Instances".to_string()]);
+            for (value, count) in counts {
+                let value = value.clone().unwrap_or_else(|| nulls.table.clone());
+                table.add_row([value, count.to_string()]);
+            }
+            let total = counts.iter().map(|(_, count)| count).sum::<usize>();
+            table.add_row(["Total".to_string(), total.to_string()]);
+            table.render()
+        }
+        Format::Json | Format::Jsonl => {
+            let objects = counts
+                .iter()
+                .map(|(value, count)| {
+                    let value = match (value, &nulls.json) {
+                        (Some(value), _) | (None, Some(value)) => value.clone().into(),
+                        (None, None) => JsonValue::Null,
+                    };
+                    serde_json::json!({ group_by.key(): value, "instances": count })
+                })
+                .collect::<Vec<_>>();
+            match format {
+                Format::Jsonl => json_lines(&objects),
+                _ => {
+                    serde_json::to_string_pretty(&objects).expect("JSON values always serialize")
+                        + "\n"
+                }
+            }
+        }
+        Format::Csv => {
+            let mut out = format!("{},instances\n", csv_field(&group_by.key()));
+            for (value, count) in counts {
+                let value = value.as_deref().unwrap_or(&nulls.csv);
+                out += &format!("{},{}\n", csv_field(value), count);
+            }
+            out
+        }
+    }
+}
+
+/// Renders groups of instances as tables, each under a heading with the group's name and
+/// instance count, e.g. `cell-a (3 instances)`.
+///
+/// # Arguments
+///
+/// * `columns` - The columns to show.
+/// * `groups` - The name and instances of each group, in display order.
+/// * `config` - The `[output]` config section, with the placeholders for missing values and
+///   the table settings.
+pub fn grouped_tables(
+    columns: &[Column],
+    groups: &[(String, Vec<Instance>)],
+    config: &OutputConfig,
+) -> String {
+    groups
+        .iter()
+        .map(|(name, instances)| {
+            let noun = match instances.len() {
+                1 => "instance",
+                _ => "instances",
+            };
+            format!(
+                "{} ({} {})\n{}",
+                name,
+                instances.len(),
+                noun,
+                instances_table(columns, instances, &config.null)
+                    .configure(config)
+                    .render()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the full details of an instance as text, for `bcls <env> describe`: a list of
+/// fields followed by tables of disks, network interfaces and service accounts.
+///
+/// # Arguments
+///
+/// * `detail` - The instance details.
+/// * `null` - The placeholder for missing values.
+/// * `timezone` - The timezone of the creation time.
+pub fn instance_detail_text(detail: &InstanceDetail, null: &str, timezone: Timezone) -> String {
+    let inst = &detail.instance;
+    let scheduling = &detail.scheduling;
+    let model = match (scheduling.is_spot(), scheduling.preemptible) {
+        (true, _) => "spot",
+        (false, true) => "preemptible",
+        (false, false) => "standard",
+    };
+    let mut scheduling_text = vec![model.to_string()];
+    if let Some(policy) = &scheduling.on_host_maintenance {
+        scheduling_text.push(format!("on host maintenance: {}", policy));
+    }
+    if let Some(restart) = scheduling.automatic_restart {
+        scheduling_text.push(format!("automatic restart: {}", restart));
+    }
+    let labels = inst.labels.as_ref().map(|labels| {
+        labels
+            .iter()
+            .map(|(k, v)| (k.clone(), JsonValue::from(v.clone())))
+            .collect::<Map<String, JsonValue>>()
+            .into()
+    });
+    let metadata_keys = Some(detail.metadata_keys.clone().into());
+    let resource_policies = Some(inst.resource_policies.clone().into());
+    let fields = [
+        ("Name", inst.name.clone()),
+        ("Zone", inst.zone.clone()),
+        ("Machine type", inst.machine_type.clone()),
+        ("CPU platform", inst.cpu_platform.clone()),
+        ("Status", inst.status.clone()),
+        (
+            "Created",
+            inst.creation_timestamp
+                .map(|created| timezone.format(created))
+                .unwrap_or_else(|| null.to_string()),
+        ),
+        ("Scheduling", scheduling_text.join(", ")),
+        ("Labels", value_to_text(labels, null)),
+        ("Metadata keys", value_to_text(metadata_keys, null)),
+        ("Resource policies", value_to_text(resource_policies, null)),
+    ];
+    let mut out = String::new();
+    for (name, value) in fields {
+        out += &format!("{:14} {}\n", format!("{}:", name), value);
+    }
+
+    let optional = |value: Option<String>| value.unwrap_or_else(|| null.to_string());
+    let mut disks = Table::new(["Device", "Boot", "Type", "Interface", "Size (GB)", "Source"]);
+    for disk in &inst.disks {
+        disks.add_row([
+            disk.device_name.clone(),
+            disk.boot.to_string(),
+            disk.kind.clone(),
+            optional(disk.interface.clone()),
+            optional(disk.size_gb.map(|size| size.to_string())),
+            optional(disk.source_name()),
+        ]);
+    }
+    let mut nics = Table::new(["Name", "IP", "External IP", "Network", "Subnetwork"]);
+    for nic in &inst.network_interfaces {
+        nics.add_row([
+            nic.name.clone(),
+            optional(nic.ip.clone()),
+            optional(nic.external_ip.clone()),
+            optional(nic.network.clone()),
+            optional(nic.subnetwork.clone()),
+        ]);
+    }
+    let mut accounts = Table::new(["Email", "Scopes"]);
+    for account in &detail.service_accounts {
+        let scopes = account
+            .scopes
+            .iter()
+            .map(|scope| last_segment(scope))
+            .collect::<Vec<_>>();
+        accounts.add_row([account.email.clone(), scopes.join(", ")]);
+    }
+    for (title, table, empty) in [
+        ("Disks", disks, inst.disks.is_empty()),
+        (
+            "Network interfaces",
+            nics,
+            inst.network_interfaces.is_empty(),
+        ),
+        (
+            "Service accounts",
+            accounts,
+            detail.service_accounts.is_empty(),
+        ),
+    ] {
+        out += &format!("\n{}:\n", title);
+        out += &match empty {
+            true => format!("  {}\n", null),
+            false => table.render(),
+        };
+    }
+    out
+}
+
+/// Highlights rows of a rendered table: in bold yellow with `color`, otherwise with a `*` in
+/// the margin.
+///
+/// # Arguments
+///
+/// * `table` - The table, as rendered by `Table::render`.
+/// * `highlighted` - Whether each data row is highlighted, not counting the header.
+/// * `color` - Whether to highlight with ANSI escapes.
+pub fn highlight_rows(table: &str, highlighted: &[bool], color: bool) -> String {
+    let mut out = String::new();
+    for (i, line) in table.lines().enumerate() {
+        match i.checked_sub(1).and_then(|row| highlighted.get(row)) {
+            Some(true) if color => out += &format!("\x1b[1;33m{}\x1b[0m", line),
+            Some(true) => out += &format!("*{}", &line[1..]),
+            _ => out += line,
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders one IP address per line, e.g. for piping into other tools.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to render.
+/// * `external` - Print external (NAT) IPs instead of internal ones.
+/// * `all` - Print the addresses of every network interface rather than just the first.
+///
+/// Instances without a matching address are skipped.
+pub fn ip_lines(instances: &[Instance], external: bool, all: bool) -> String {
+    instances
+        .iter()
+        .flat_map(|inst| match (external, all) {
+            (false, false) => inst.primary_ip().into_iter().collect(),
+            (true, false) => inst.external_ip().into_iter().collect(),
+            (false, true) => inst.ips(),
+            (true, true) => inst.external_ips(),
+        })
+        .map(|ip| format!("{}\n", ip))
+        .collect()
+}
+
+/// Summarises what is missing from a listing of a project, to print to stderr after the
+/// results.
+///
+/// # Arguments
+///
+/// * `project` - The project listed.
+/// * `warnings` - The warnings of the listing.
+///
+/// # Returns
+///
+/// The summary, one line per warning after a heading, or `None` if nothing is missing.
+pub fn incomplete_listing(project: &str, warnings: &[ListWarning]) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let mut summary = format!("warning: the listing of {} may be incomplete:\n", project);
+    for warning in warnings {
+        summary.push_str(&format!("  {}\n", warning));
+    }
+    Some(summary)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::NetworkInterface;
+    use chrono::Timelike;
+    use std::collections::HashMap;
+
+    fn interface(ip: &str, external_ip: Option<&str>) -> NetworkInterface {
+        NetworkInterface {
+            name: "nic0".to_string(),
+            ip: Some(ip.to_string()),
+            external_ip: external_ip.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn instance(name: &str, labels: &[(&str, &str)]) -> Instance {
+        Instance {
+            name: name.to_string(),
+            network_interfaces: vec![interface("10.0.0.1", None)],
+            zone: "us-east1-b".to_string(),
+            machine_type: "e2-medium".to_string(),
+            cpu_platform: "Intel Broadwell".to_string(),
+            status: "RUNNING".to_string(),
+            labels: Some(
+                labels
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<HashMap<_, _>>(),
+            ),
+            region: "us-east1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the display column at which `needle` starts in `line`.
+    fn display_column(line: &str, needle: &str) -> usize {
+        let byte_offset = line.find(needle).expect("needle not found in line");
+        UnicodeWidthStr::width(&line[..byte_offset])
+    }
+
+    #[test]
+    fn test_table_aligns_ascii() {
+        let mut table = Table::new(["A", "B"]);
+        table.add_row(["abc", "x"]);
+        table.add_row(["a", "y"]);
+        assert_eq!(table.render(), "  A    B\n  abc  x\n  a    y\n");
+    }
+
+    #[test]
+    fn test_table_aligns_wide_characters() {
+        let instances = vec![
+            instance("ascii-instance", &[("team", "storage")]),
+            instance("存储-实例", &[("team", "ストレージ")]),
+            instance("rocket-🚀🚀", &[("team", "🦀")]),
+        ];
+        let rendered =
+            instances_table(&long_columns(), &instances, &NullValues::default()).render();
+        let lines = rendered.lines().collect::<Vec<_>>();
+
+        // Every row's IP column must start at the same display column as the header's.
+        let ip_column = display_column(lines[0], "IP");
+        for line in &lines[1..] {
+            assert_eq!(display_column(line, "10.0.0.1"), ip_column, "{}", line);
+        }
+
+        // Same for the Labels column, which comes after the wide names.
+        let labels_column = display_column(lines[0], "Labels");
+        for line in &lines[1..] {
+            assert_eq!(display_column(line, "team: "), labels_column, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_table_pads_short_rows() {
+        let mut table = Table::new(["A", "B", "C"]);
+        table.add_row(["日本"]);
+        assert_eq!(table.render(), "  A     B  C\n  日本\n");
+    }
+
+    #[test]
+    fn test_status_color() {
+        assert_eq!(status_color("RUNNING"), Some("32"));
+        assert_eq!(status_color("TERMINATED"), Some("31"));
+        assert_eq!(status_color("STOPPING"), Some("33"));
+        assert_eq!(status_color("-"), None);
+    }
+
+    #[test]
+    fn test_table_colors_styled_columns() {
+        let table = |color| {
+            let mut table = Table::new(["Name", "Status", "Zone"]).color(color);
+            table.add_row(["a", "RUNNING", "us-east1-b"]);
+            table.add_row(["b", "SUSPENDED", "us-east1-c"]);
+            table.styles = vec![None, Some(status_color as CellStyle)];
+            table
+        };
+
+        // The padding stays outside the escapes, so the columns still line up
+        assert_eq!(
+            table(true).render(),
+            "  Name  Status     Zone\n  a     \x1b[32mRUNNING\x1b[0m    us-east1-b\n  b     SUSPENDED  us-east1-c\n"
+        );
+        assert_eq!(
+            table(false).render(),
+            "  Name  Status     Zone\n  a     RUNNING    us-east1-b\n  b     SUSPENDED  us-east1-c\n"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("store-lb-1", 10), "store-lb-1");
+        assert_eq!(truncate("store-lb-1", 6), "store…");
+        // Wide characters are never split, so the cell may end up a cell short
+        assert_eq!(truncate("存储-实例", 6), "存储-…");
+        assert_eq!(truncate("存储-实例", 5), "存储…");
+    }
+
+    #[test]
+    fn test_table_fits_max_width() {
+        let table = |max_width| {
+            let mut table = Table::new(["Name", "Labels"]).max_width(max_width);
+            table.add_row(["store-lb-1", "cell: a, team: storage, tier: frontend"]);
+            table.add_row(["db", "-"]);
+            table.render()
+        };
+        assert_eq!(
+            table(Some(30)),
+            "  Name        Labels\n  store-lb-1  cell: a, team: …\n  db          -\n"
+        );
+        for line in table(Some(30)).lines() {
+            assert!(line.width() <= 30, "{}", line);
+        }
+        // Columns aren't narrowed below their minimum, even if the table doesn't fit
+        assert_eq!(
+            table(Some(10)),
+            "  Name      Labels\n  store-l…  cell: a…\n  db        -\n"
+        );
+        assert_eq!(table(None), table(Some(80)));
+    }
+
+    #[test]
+    fn test_table_without_header() {
+        let mut table = Table::new(["A", "B"]).no_header(true);
+        table.add_row(["abc", "x"]);
+        assert_eq!(table.render(), "  abc  x\n");
+    }
+
+    #[test]
+    fn test_labels_are_sorted() {
+        let inst = instance("i", &[("b", "2"), ("a", "1"), ("c", "🚀")]);
+        let table = instances_table(&long_columns(), &[inst], &NullValues::default()).render();
+        assert!(table.contains("a: 1, b: 2, c: 🚀"), "{}", table);
+    }
+
+    #[test]
+    fn test_missing_values_use_default_placeholders() {
+        let mut inst = instance("i", &[]);
+        inst.labels = None;
+        let nulls = NullValues::default();
+
+        let table = instances_table(&long_columns(), &[inst.clone()], &nulls).render();
+        assert!(table.lines().nth(1).unwrap().ends_with(" -"), "{}", table);
+
+        let json: JsonValue =
+            serde_json::from_str(&instances_json(&long_columns(), &[inst.clone()], &nulls))
+                .unwrap();
+        assert_eq!(json[0]["labels"], JsonValue::Null);
+
+        let csv = instances_csv(&long_columns(), &[inst], &nulls);
+        assert!(
+            csv.lines().nth(1).unwrap().ends_with("RUNNING,,"),
+            "{}",
+            csv
+        );
+    }
+
+    #[test]
+    fn test_missing_values_use_configured_placeholders() {
+        let mut inst = instance("i", &[]);
+        inst.labels = None;
+        let nulls = NullValues {
+            table: "<none>".to_string(),
+            json: Some("n/a".to_string()),
+            csv: "NULL".to_string(),
+        };
+
+        let table = instances_table(&long_columns(), &[inst.clone()], &nulls).render();
+        assert!(
+            table.lines().nth(1).unwrap().ends_with(" <none>"),
+            "{}",
+            table
+        );
+
+        let json: JsonValue =
+            serde_json::from_str(&instances_json(&long_columns(), &[inst.clone()], &nulls))
+                .unwrap();
+        assert_eq!(json[0]["labels"], "n/a");
+
+        let csv = instances_csv(&long_columns(), &[inst], &nulls);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",NULL"), "{}", csv);
+    }
+
+    #[test]
+    fn test_ip_lines() {
+        let mut with_external = instance("a", &[]);
+        with_external.network_interfaces = vec![
+            interface("10.0.0.1", Some("34.1.2.3")),
+            interface("10.1.0.1", Some("34.1.2.4")),
+        ];
+        let mut without_external = instance("b", &[]);
+        without_external.network_interfaces = vec![interface("10.0.0.2", None)];
+        let mut without_interfaces = instance("c", &[]);
+        without_interfaces.network_interfaces = vec![];
+        let instances = [with_external, without_external, without_interfaces];
+
+        assert_eq!(ip_lines(&instances, false, false), "10.0.0.1\n10.0.0.2\n");
+        assert_eq!(ip_lines(&instances, true, false), "34.1.2.3\n");
+        assert_eq!(
+            ip_lines(&instances, false, true),
+            "10.0.0.1\n10.1.0.1\n10.0.0.2\n"
+        );
+        assert_eq!(ip_lines(&instances, true, true), "34.1.2.3\n34.1.2.4\n");
+    }
+
+    #[test]
+    fn test_all_ips_columns() {
+        let mut inst = instance("a", &[]);
+        inst.network_interfaces = vec![
+            interface("10.0.0.1", Some("34.1.2.3")),
+            interface("10.1.0.1", None),
+        ];
+        let columns = with_all_ips(long_columns());
+
+        let csv = instances_csv(&columns, &[inst.clone()], &NullValues::default());
+        assert!(csv.starts_with("name,ips,external_ips,zone,"), "{}", csv);
+        assert!(csv.contains(",\"10.0.0.1, 10.1.0.1\",34.1.2.3,"), "{}", csv);
+
+        let json: JsonValue =
+            serde_json::from_str(&instances_json(&columns, &[inst], &NullValues::default()))
+                .unwrap();
+        assert_eq!(json[0]["ips"], serde_json::json!(["10.0.0.1", "10.1.0.1"]));
+    }
+
+    #[test]
+    fn test_environment_column() {
+        let mut prd = instance("a", &[]);
+        prd.enrichments
+            .insert("environment".to_string(), "prd".to_string());
+        let mut columns = vec![environment_column()];
+        columns.extend(default_columns());
+
+        let csv = instances_csv(&columns, &[prd, instance("b", &[])], &NullValues::default());
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>()[..2],
+            ["environment,name,ip", "prd,a,10.0.0.1"]
+        );
+    }
+
+    #[test]
+    fn test_age_columns() {
+        let now = Utc::now();
+        let mut inst = instance("a", &[]);
+        inst.creation_timestamp = Some(now - chrono::Duration::days(40));
+        inst.last_start_timestamp = Some(now - chrono::Duration::minutes(135));
+        let columns = age_columns(now);
+
+        let csv = |inst: &Instance| {
+            instances_csv(&columns, std::slice::from_ref(inst), &NullValues::default())
+        };
+        inst.status = "RUNNING".to_string();
+        assert_eq!(csv(&inst).lines().nth(1), Some("40d 0h,2h 15m"));
+        // Uptime only counts while the instance is running
+        inst.status = "TERMINATED".to_string();
+        assert_eq!(csv(&inst).lines().nth(1), Some("40d 0h,"));
+
+        assert_eq!(format_elapsed(chrono::Duration::seconds(90)), "1m");
+        assert_eq!(format_elapsed(chrono::Duration::seconds(-5)), "0m");
+    }
+
+    #[test]
+    fn test_streaming_table() {
+        let columns = default_columns();
+        let nulls = NullValues::default();
+        let mut wide = instance("a-much-longer-name", &[]);
+        wide.network_interfaces = vec![];
+
+        let mut table = StreamingTable::new(&columns, &nulls);
+        let first = table.page(&[instance("a", &[])]);
+        let second = table.page(&[wide]);
+        assert_eq!(first, "  Name  IP\n  a     10.0.0.1\n");
+        // Later pages keep the widths where they fit and widen columns where they don't
+        assert_eq!(second, "  a-much-longer-name  -\n");
+        assert_eq!(table.finish(), "");
+
+        let empty = StreamingTable::new(&columns, &nulls);
+        assert_eq!(empty.finish(), "  Name  IP\n");
+
+        let config = OutputConfig {
+            no_header: true,
+            ..OutputConfig::default()
+        };
+        let mut table = StreamingTable::new(&columns, &nulls).configure(&config);
+        // The titles still count towards the widths
+        assert_eq!(table.page(&[instance("a", &[])]), "  a     10.0.0.1\n");
+        let empty = StreamingTable::new(&columns, &nulls).configure(&config);
+        assert_eq!(empty.finish(), "");
+    }
+
+    #[test]
+    fn test_render_counts() {
+        let counts = [(Some("e2-medium".to_string()), 3), (None, 1)];
+        let group_by = GroupBy::MachineType;
+        let nulls = NullValues::default();
+
+        assert_eq!(
+            render_counts(Format::Table, &group_by, &counts, &nulls),
+            "  Machine Type  Instances\n  e2-medium     3\n  -             1\n  Total         4\n"
+        );
+        assert_eq!(
+            render_counts(Format::Csv, &group_by, &counts, &nulls),
+            "machine_type,instances\ne2-medium,3\n,1\n"
+        );
+        let json: JsonValue =
+            serde_json::from_str(&render_counts(Format::Json, &group_by, &counts, &nulls)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"machine_type": "e2-medium", "instances": 3},
+                {"machine_type": null, "instances": 1}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_jsonl_output() {
+        let mut unreachable = instance("b", &[]);
+        unreachable.network_interfaces.clear();
+        let instances = [instance("a", &[]), unreachable];
+        let nulls = NullValues::default();
+
+        assert_eq!(
+            instances_jsonl(&default_columns(), &instances, &nulls),
+            "{\"ip\":\"10.0.0.1\",\"name\":\"a\"}\n{\"ip\":null,\"name\":\"b\"}\n"
+        );
+        assert_eq!(instances_jsonl(&default_columns(), &[], &nulls), "");
+        // Every line parses on its own as the object of the pretty-printed array
+        let json: JsonValue =
+            serde_json::from_str(&instances_json(&default_columns(), &instances, &nulls)).unwrap();
+        let lines = instances_jsonl(&default_columns(), &instances, &nulls);
+        for (line, object) in lines.lines().zip(json.as_array().unwrap()) {
+            assert_eq!(&serde_json::from_str::<JsonValue>(line).unwrap(), object);
+        }
+
+        let counts = [(Some("e2-medium".to_string()), 3)];
+        assert_eq!(
+            render_counts(Format::Jsonl, &GroupBy::MachineType, &counts, &nulls),
+            "{\"instances\":3,\"machine_type\":\"e2-medium\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_hosts_output() {
+        let mut unreachable = instance("b", &[]);
+        unreachable.network_interfaces.clear();
+        let instances = [instance("a", &[]), unreachable];
+
+        assert_eq!(
+            render_instances(
+                Format::Hosts,
+                &default_columns(),
+                &instances,
+                &OutputConfig::default()
+            ),
+            "10.0.0.1\ta\n"
+        );
+        assert_eq!(
+            hosts_lines(&instances, Some(".corp.example")),
+            "10.0.0.1\ta.corp.example a\n"
+        );
+    }
+
+    #[test]
+    fn test_selflink_output() {
+        let mut linked = instance("a", &[]);
+        linked.self_link = Some(
+            "https://www.googleapis.com/compute/v1/projects/p/zones/z/instances/a".to_string(),
+        );
+        let instances = [linked, instance("b", &[])];
+
+        assert_eq!(
+            render_instances(
+                Format::Selflink,
+                &default_columns(),
+                &instances,
+                &OutputConfig::default()
+            ),
+            "https://www.googleapis.com/compute/v1/projects/p/zones/z/instances/a\n"
+        );
+        let columns =
+            select_columns(column_registry(Timezone::Utc), &["self_link".to_string()]).unwrap();
+        let csv = instances_csv(&columns, &instances, &NullValues::default());
+        assert_eq!(
+            csv,
+            "self_link\nhttps://www.googleapis.com/compute/v1/projects/p/zones/z/instances/a\n\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let inst = instance("i", &[("a", "1"), ("b", "say \"hi\"")]);
+        let csv = instances_csv(&long_columns(), &[inst], &NullValues::default());
+        assert!(csv.ends_with(",\"a: 1, b: say \"\"hi\"\"\"\n"), "{}", csv);
+    }
+
+    #[test]
+    fn test_grouped_tables() {
+        let instance = |name: &str| Instance {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let groups = vec![
+            ("cell-a".to_string(), vec![instance("a-1"), instance("a-2")]),
+            ("no cell".to_string(), vec![instance("x")]),
+        ];
+        let out = grouped_tables(&default_columns(), &groups, &OutputConfig::default());
+        assert_eq!(
+            out,
+            "cell-a (2 instances)\n  Name  IP\n  a-1   -\n  a-2   -\n\n\
+             no cell (1 instance)\n  Name  IP\n  x     -\n"
+        );
+    }
+
+    #[test]
+    fn test_timezone_format() {
+        let time = DateTime::parse_from_rfc3339("2024-03-01T09:30:00.123-08:00")
+            .unwrap()
+            .to_utc();
+        assert_eq!(Timezone::Utc.format(time), "2024-03-01T17:30:00+00:00");
+        // The local offset depends on the machine, but always denotes the same instant
+        let local = Timezone::Local.format(time);
+        assert_eq!(
+            DateTime::parse_from_rfc3339(&local).unwrap().to_utc(),
+            time.with_nanosecond(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let keys = ["labels.team", "zone", "name", "labels.missing"].map(str::to_string);
+        let columns = select_columns(column_registry(Timezone::Utc), &keys).unwrap();
+        let instances = vec![instance("db-1", &[("team", "storage")])];
+        assert_eq!(
+            instances_csv(&columns, &instances, &NullValues::default()),
+            "labels.team,zone,name,labels.missing\nstorage,us-east1-b,db-1,\n"
+        );
+        assert!(
+            instances_table(&columns, &instances, &NullValues::default())
+                .render()
+                .starts_with("  team     Zone        Name  missing\n")
+        );
+
+        let error = select_columns(column_registry(Timezone::Utc), &["nmae".to_string()])
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Unknown column nmae. Known columns: name, ip,"));
+        assert!(select_columns(
+            column_registry(Timezone::Utc),
+            &["ip".to_string(), "ip".to_string()]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_highlight_rows() {
+        let mut table = Table::new(["Name", "Status"]);
+        table.add_row(["a", "RUNNING"]);
+        table.add_row(["b", "STOPPING"]);
+        let table = table.render();
+        assert_eq!(
+            highlight_rows(&table, &[false, true], false),
+            "  Name  Status\n  a     RUNNING\n* b     STOPPING\n"
+        );
+        assert!(highlight_rows(&table, &[false, true], true)
+            .ends_with("\x1b[1;33m  b     STOPPING\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_instance_detail_text() {
+        let detail = InstanceDetail {
+            instance: Instance {
+                name: "db-1".to_string(),
+                zone: "us-east1-b".to_string(),
+                status: "RUNNING".to_string(),
+                network_interfaces: vec![NetworkInterface {
+                    name: "nic0".to_string(),
+                    ip: Some("10.0.0.2".to_string()),
+                    subnetwork: Some("db".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            service_accounts: vec![crate::compute::ServiceAccount {
+                email: "db@p.iam.gserviceaccount.com".to_string(),
+                scopes: vec!["https://www.googleapis.com/auth/cloud-platform".to_string()],
+            }],
+            scheduling: crate::compute::Scheduling {
+                preemptible: true,
+                provisioning_model: Some("SPOT".to_string()),
+                ..Default::default()
+            },
+            metadata_keys: vec!["ssh-keys".to_string()],
+        };
+
+        let text = instance_detail_text(&detail, "-", Timezone::Utc);
+        assert!(text.starts_with("Name:          db-1\nZone:          us-east1-b\n"));
+        assert!(text.contains("Created:       -\n"));
+        assert!(text.contains("Scheduling:    spot\n"));
+        assert!(text.contains("Metadata keys: ssh-keys\n"));
+        assert!(text.contains("\nDisks:\n  -\n"));
+        assert!(text.contains("  nic0  10.0.0.2  -            -        db\n"));
+        assert!(text.contains("  db@p.iam.gserviceaccount.com  cloud-platform\n"));
+    }
+
+    #[test]
+    fn test_incomplete_listing() {
+        assert_eq!(incomplete_listing("acme-prd", &[]), None);
+        let warnings = [
+            ListWarning::Scope(crate::compute::ScopeWarning {
+                scope: "zones/europe-west4-a".to_string(),
+                code: "UNREACHABLE".to_string(),
+                message: String::new(),
+            }),
+            ListWarning::Skipped(crate::compute::RecordError {
+                scope: "zones/us-east1-b".to_string(),
+                resource: "instances".to_string(),
+                index: 3,
+                name: Some("web-1".to_string()),
+                error: "missing field `machineType`".to_string(),
+            }),
+        ];
+        assert_eq!(
+            incomplete_listing("acme-prd", &warnings).unwrap(),
+            "warning: the listing of acme-prd may be incomplete:\n  \
+             zone europe-west4-a unreachable\n  \
+             zone us-east1-b: skipped web-1: missing field `machineType`\n"
+        );
+    }
+}
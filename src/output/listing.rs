@@ -0,0 +1,1104 @@
+//! This module renders listings of resources other than instances, e.g. disks, as tables,
+//! JSON or CSV, the same way instance listings are rendered.
+
+use super::{csv_field, json_lines, value_to_text, Format, Table, Timezone};
+use crate::cloud_logging::AuditEntry;
+use crate::compute::{
+    Address, Autoscaler, Disk, Image, Instance, InstanceGroupManager, InstanceTemplate,
+    ManagedInstance, Metadata, Network, Operation, Snapshot, Subnetwork,
+};
+use crate::config::NullValues;
+use crate::container::{Cluster, NodePool};
+use crate::dns::{ManagedZone, RecordSet};
+use crate::osconfig::Inventory;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value as JsonValue};
+
+/// A listing of resources with a fixed set of columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    /// The key of each column, used in JSON and CSV, and its title, used in tables.
+    columns: Vec<(&'static str, &'static str)>,
+    /// The values of each row, in column order. `None` is a missing value.
+    rows: Vec<Vec<Option<JsonValue>>>,
+}
+
+impl Listing {
+    /// Creates an empty listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The key and title of each column, e.g. `("size_gb", "Size (GB)")`.
+    pub fn new(columns: &[(&'static str, &'static str)]) -> Self {
+        Listing {
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    /// Appends a row, with a value per column.
+    pub fn add_row(&mut self, row: Vec<Option<JsonValue>>) {
+        self.rows.push(row);
+    }
+
+    /// Renders the listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The output format: table, JSON, JSON Lines or CSV.
+    /// * `nulls` - The placeholders for missing values.
+    ///
+    /// # Returns
+    ///
+    /// The rendered listing, or an error for formats that only apply to instances.
+    pub fn render(&self, format: Format, nulls: &NullValues) -> Result<String, String> {
+        match format {
+            Format::Table => {
+                let mut table = Table::new(self.columns.iter().map(|(_, title)| *title));
+                for row in &self.rows {
+                    table.add_row(row.iter().map(|v| value_to_text(v.clone(), &nulls.table)));
+                }
+                Ok(table.render())
+            }
+            Format::Json | Format::Jsonl => {
+                let objects = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let object = self
+                            .columns
+                            .iter()
+                            .zip(row)
+                            .map(|((key, _), value)| {
+                                let value = match (value, &nulls.json) {
+                                    (Some(value), _) => value.clone(),
+                                    (None, Some(null)) => null.clone().into(),
+                                    (None, None) => JsonValue::Null,
+                                };
+                                (key.to_string(), value)
+                            })
+                            .collect::<Map<_, _>>();
+                        JsonValue::Object(object)
+                    })
+                    .collect::<Vec<_>>();
+                Ok(match format {
+                    Format::Jsonl => json_lines(&objects),
+                    _ => {
+                        serde_json::to_string_pretty(&objects)
+                            .expect("JSON values always serialize")
+                            + "\n"
+                    }
+                })
+            }
+            Format::Csv => {
+                let header = self.columns.iter().map(|(key, _)| *key);
+                let mut out = header.collect::<Vec<_>>().join(",") + "\n";
+                for row in &self.rows {
+                    let fields = row
+                        .iter()
+                        .map(|v| csv_field(&value_to_text(v.clone(), &nulls.csv)));
+                    out.push_str(&fields.collect::<Vec<_>>().join(","));
+                    out.push('\n');
+                }
+                Ok(out)
+            }
+            Format::Selflink | Format::Hosts => {
+                Err("This listing only supports table, json and csv output".to_string())
+            }
+        }
+    }
+}
+
+/// Lists disks with their name, size, type, location, the instances they're attached to and
+/// their status.
+pub fn disks_listing(disks: &[Disk]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("size_gb", "Size (GB)"),
+        ("type", "Type"),
+        ("location", "Zone/Region"),
+        ("attached_to", "Attached To"),
+        ("status", "Status"),
+    ]);
+    for disk in disks {
+        let location = disk.zone.as_ref().or(disk.region.as_ref());
+        listing.add_row(vec![
+            Some(disk.name.clone().into()),
+            Some(disk.size_gb.into()),
+            Some(disk.disk_type.clone().into()),
+            location.map(|location| location.clone().into()),
+            Some(disk.users.clone().into()),
+            Some(disk.status.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists static IP addresses with their name, IP, type, region, status and the resources
+/// using them. Global addresses are shown in the `global` region.
+pub fn addresses_listing(addresses: &[Address]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("address", "Address"),
+        ("type", "Type"),
+        ("region", "Region"),
+        ("status", "Status"),
+        ("used_by", "Used By"),
+    ]);
+    for address in addresses {
+        listing.add_row(vec![
+            Some(address.name.clone().into()),
+            Some(address.address.clone().into()),
+            Some(address.address_type.clone().into()),
+            Some(address.region.as_deref().unwrap_or("global").into()),
+            Some(address.status.clone().into()),
+            Some(address.users.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists VPC networks with their subnet mode, routing mode, MTU and number of subnets.
+pub fn networks_listing(networks: &[Network]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("mode", "Mode"),
+        ("routing_mode", "Routing"),
+        ("mtu", "MTU"),
+        ("subnets", "Subnets"),
+    ]);
+    for network in networks {
+        listing.add_row(vec![
+            Some(network.name.clone().into()),
+            Some(network.mode.clone().into()),
+            network.routing_mode.clone().map(JsonValue::from),
+            network.mtu.map(JsonValue::from),
+            Some(network.subnet_count.into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists subnets with their network, region, primary range and secondary ranges. Secondary
+/// ranges are shown as `NAME=CIDR`.
+pub fn subnets_listing(subnets: &[Subnetwork]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("network", "Network"),
+        ("region", "Region"),
+        ("range", "Range"),
+        ("secondary_ranges", "Secondary Ranges"),
+    ]);
+    for subnet in subnets {
+        let secondary_ranges = subnet
+            .secondary_ranges
+            .iter()
+            .map(|range| format!("{}={}", range.name, range.cidr))
+            .collect::<Vec<_>>();
+        listing.add_row(vec![
+            Some(subnet.name.clone().into()),
+            Some(subnet.network.clone().into()),
+            Some(subnet.region.clone().into()),
+            Some(subnet.ip_cidr_range.clone().into()),
+            Some(secondary_ranges.into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists GKE clusters with their location, control plane version, node count, number of node
+/// pools and status.
+pub fn clusters_listing(clusters: &[Cluster]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("location", "Location"),
+        ("version", "Version"),
+        ("nodes", "Nodes"),
+        ("node_pools", "Node Pools"),
+        ("status", "Status"),
+    ]);
+    for cluster in clusters {
+        listing.add_row(vec![
+            Some(cluster.name.clone().into()),
+            Some(cluster.location.clone().into()),
+            Some(cluster.master_version.clone().into()),
+            Some(cluster.node_count.into()),
+            Some(cluster.node_pools.len().into()),
+            Some(cluster.status.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists the node pools of a cluster with their machine type, node version, initial node
+/// count, autoscaling limits as `MIN-MAX`, whether they run Spot VMs, and status. Node counts
+/// and limits are per zone.
+pub fn node_pools_listing(pools: &[NodePool]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("machine_type", "Machine Type"),
+        ("version", "Version"),
+        ("initial_nodes", "Initial Nodes"),
+        ("autoscaling", "Autoscaling"),
+        ("spot", "Spot"),
+        ("status", "Status"),
+    ]);
+    for pool in pools {
+        let autoscaling = pool.autoscaling.map(|autoscaling| {
+            format!(
+                "{}-{}",
+                autoscaling.min_node_count, autoscaling.max_node_count
+            )
+        });
+        listing.add_row(vec![
+            Some(pool.name.clone().into()),
+            Some(pool.machine_type.clone().into()),
+            Some(pool.version.clone().into()),
+            Some(pool.initial_node_count.into()),
+            autoscaling.map(JsonValue::from),
+            Some(pool.spot.into()),
+            Some(pool.status.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists Cloud DNS managed zones with their DNS name, visibility and description.
+pub fn dns_zones_listing(zones: &[ManagedZone]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("dns_name", "DNS Name"),
+        ("visibility", "Visibility"),
+        ("description", "Description"),
+    ]);
+    for zone in zones {
+        listing.add_row(vec![
+            Some(zone.name.clone().into()),
+            Some(zone.dns_name.clone().into()),
+            Some(zone.visibility.clone().into()),
+            Some(zone.description.clone())
+                .filter(|d| !d.is_empty())
+                .map(JsonValue::from),
+        ]);
+    }
+    listing
+}
+
+/// Lists DNS record sets with their name, type, TTL, data and the managed zone they're in.
+///
+/// # Arguments
+///
+/// * `record_sets` - The record sets, each with the name of its managed zone.
+pub fn record_sets_listing(record_sets: &[(String, RecordSet)]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("type", "Type"),
+        ("ttl", "TTL"),
+        ("data", "Data"),
+        ("zone", "Zone"),
+    ]);
+    for (zone, record_set) in record_sets {
+        listing.add_row(vec![
+            Some(record_set.name.clone().into()),
+            Some(record_set.record_type.clone().into()),
+            record_set.ttl.map(JsonValue::from),
+            Some(record_set.rrdatas.clone().into()),
+            Some(zone.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists snapshots with their name, source disk, sizes, creation time and age, storage
+/// locations and status. The storage size is what is billed, in GB to one decimal.
+///
+/// # Arguments
+///
+/// * `snapshots` - The snapshots to list.
+/// * `timezone` - The timezone creation times are shown in.
+/// * `now` - The time ages are measured to.
+pub fn snapshots_listing(
+    snapshots: &[Snapshot],
+    timezone: Timezone,
+    now: DateTime<Utc>,
+) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("source_disk", "Source Disk"),
+        ("disk_size_gb", "Disk Size (GB)"),
+        ("storage_gb", "Storage (GB)"),
+        ("created", "Created"),
+        ("age_days", "Age (days)"),
+        ("storage_locations", "Locations"),
+        ("status", "Status"),
+    ]);
+    for snapshot in snapshots {
+        let storage_gb = snapshot
+            .storage_bytes
+            .map(|bytes| (bytes as f64 / (1u64 << 30) as f64 * 10.0).round() / 10.0);
+        let created = snapshot.creation_timestamp;
+        listing.add_row(vec![
+            Some(snapshot.name.clone().into()),
+            snapshot.source_disk.clone().map(Into::into),
+            Some(snapshot.disk_size_gb.into()),
+            storage_gb.map(Into::into),
+            created.map(|time| timezone.format(time).into()),
+            created.map(|time| (now - time).num_days().into()),
+            Some(snapshot.storage_locations.clone().into()),
+            Some(snapshot.status.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists custom images with their family, creation time, source disk, size, status and
+/// deprecation state.
+///
+/// # Arguments
+///
+/// * `images` - The images to list.
+/// * `timezone` - The timezone creation times are shown in.
+pub fn images_listing(images: &[Image], timezone: Timezone) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("family", "Family"),
+        ("created", "Created"),
+        ("source_disk", "Source Disk"),
+        ("disk_size_gb", "Size (GB)"),
+        ("status", "Status"),
+        ("deprecation", "Deprecation"),
+        ("replacement", "Replacement"),
+    ]);
+    for image in images {
+        listing.add_row(vec![
+            Some(image.name.clone().into()),
+            image.family.clone().map(Into::into),
+            image
+                .creation_timestamp
+                .map(|time| timezone.format(time).into()),
+            image.source_disk.clone().map(Into::into),
+            image.disk_size_gb.map(Into::into),
+            Some(image.status.clone().into()),
+            image.deprecation.clone().map(Into::into),
+            image.replacement.clone().map(Into::into),
+        ]);
+    }
+    listing
+}
+
+/// Lists instance templates with their machine type, source image and creation time, and
+/// the managed instance groups creating instances from each.
+///
+/// # Arguments
+///
+/// * `templates` - The templates to list.
+/// * `migs` - The managed instance groups of the project, matched to their templates by name.
+/// * `timezone` - The timezone creation times are shown in.
+pub fn templates_listing(
+    templates: &[InstanceTemplate],
+    migs: &[InstanceGroupManager],
+    timezone: Timezone,
+) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("machine_type", "Machine Type"),
+        ("source_image", "Source Image"),
+        ("created", "Created"),
+        ("migs", "Used By"),
+    ]);
+    for template in templates {
+        let users = migs
+            .iter()
+            .filter(|mig| mig.instance_template.as_deref() == Some(template.name.as_str()))
+            .map(|mig| mig.name.clone())
+            .collect::<Vec<_>>();
+        listing.add_row(vec![
+            Some(template.name.clone().into()),
+            Some(template.machine_type.clone().into()),
+            template.source_image.clone().map(Into::into),
+            template
+                .creation_timestamp
+                .map(|time| timezone.format(time).into()),
+            Some(users.into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists instances with the OS, kernel and number of pending package updates from their OS
+/// inventory, e.g. to find the instances still on an old kernel after patching.
+///
+/// # Arguments
+///
+/// * `instances` - The instances, each with its inventory, or `None` if it has never reported
+///   one.
+/// * `timezone` - The timezone report times are shown in.
+pub fn os_listing(instances: &[(Instance, Option<Inventory>)], timezone: Timezone) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("zone", "Zone"),
+        ("status", "Status"),
+        ("os", "OS"),
+        ("os_version", "Version"),
+        ("kernel", "Kernel"),
+        ("pending_updates", "Updates"),
+        ("reported", "Reported"),
+    ]);
+    for (instance, inventory) in instances {
+        let inventory = inventory.as_ref();
+        listing.add_row(vec![
+            Some(instance.name.clone().into()),
+            Some(instance.zone.clone().into()),
+            Some(instance.status.clone().into()),
+            inventory.and_then(|i| i.os_name.clone()).map(Into::into),
+            inventory.and_then(|i| i.os_version.clone()).map(Into::into),
+            inventory.and_then(|i| i.kernel.clone()).map(Into::into),
+            inventory.map(|i| i.pending_updates.into()),
+            inventory
+                .and_then(|i| i.update_time)
+                .map(|time| timezone.format(time).into()),
+        ]);
+    }
+    listing
+}
+
+/// Finds the autoscaler that resizes a managed instance group, if any.
+fn autoscaler_of<'a>(
+    mig: &InstanceGroupManager,
+    autoscalers: &'a [Autoscaler],
+) -> Option<&'a Autoscaler> {
+    autoscalers
+        .iter()
+        .find(|autoscaler| autoscaler.target == mig.name && autoscaler.location == mig.location)
+}
+
+/// Lists the metadata entries of an instance with their values.
+pub fn metadata_listing(metadata: &Metadata) -> Listing {
+    let mut listing = Listing::new(&[("key", "Key"), ("value", "Value")]);
+    for item in &metadata.items {
+        listing.add_row(vec![
+            Some(item.key.clone().into()),
+            Some(item.value.clone().into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists operations with their type, target, location, status, the user who requested them,
+/// when they were requested and finished, and the errors of failed ones.
+///
+/// # Arguments
+///
+/// * `operations` - The operations to list.
+/// * `timezone` - The timezone times are shown in.
+pub fn operations_listing(operations: &[Operation], timezone: Timezone) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("type", "Type"),
+        ("target", "Target"),
+        ("location", "Location"),
+        ("status", "Status"),
+        ("user", "User"),
+        ("inserted", "Inserted"),
+        ("ended", "Ended"),
+        ("errors", "Errors"),
+    ]);
+    let time = |time: &Option<String>| {
+        let time = time.as_deref()?;
+        Some(match DateTime::parse_from_rfc3339(time) {
+            Ok(time) => timezone.format(time.to_utc()).into(),
+            Err(_) => time.into(),
+        })
+    };
+    for operation in operations {
+        let location = operation
+            .zone
+            .clone()
+            .or_else(|| operation.region.clone())
+            .unwrap_or_else(|| "global".to_string());
+        listing.add_row(vec![
+            Some(operation.name.clone().into()),
+            Some(operation.operation_type.clone().into()),
+            Some(operation.target.clone().into()),
+            Some(location.into()),
+            Some(operation.status.clone().into()),
+            operation.user.clone().map(Into::into),
+            time(&operation.insert_time),
+            time(&operation.end_time),
+            (!operation.errors.is_empty()).then(|| operation.errors.join("; ").into()),
+        ]);
+    }
+    listing
+}
+
+/// Lists audit log entries with the method called, who called it, from where and whether it
+/// failed.
+///
+/// # Arguments
+///
+/// * `entries` - The entries to list, e.g. the most recent first.
+/// * `timezone` - The timezone entry times are shown in.
+pub fn history_listing(entries: &[AuditEntry], timezone: Timezone) -> Listing {
+    let mut listing = Listing::new(&[
+        ("time", "Time"),
+        ("method", "Method"),
+        ("principal", "Principal"),
+        ("caller_ip", "Caller IP"),
+        ("error", "Error"),
+    ]);
+    for entry in entries {
+        listing.add_row(vec![
+            entry.timestamp.map(|time| timezone.format(time).into()),
+            Some(entry.method.clone().into()),
+            entry.principal.clone().map(Into::into),
+            entry.caller_ip.clone().map(Into::into),
+            entry.error.clone().map(Into::into),
+        ]);
+    }
+    listing
+}
+
+/// Lists managed instance groups with their location, target and current size, instance
+/// template, autoscaler status and whether they are stable.
+///
+/// # Arguments
+///
+/// * `migs` - The groups to list.
+/// * `autoscalers` - The autoscalers of the project, matched to the groups they resize.
+pub fn migs_listing(migs: &[InstanceGroupManager], autoscalers: &[Autoscaler]) -> Listing {
+    let mut listing = Listing::new(&[
+        ("name", "Name"),
+        ("location", "Zone/Region"),
+        ("target_size", "Target"),
+        ("current_size", "Current"),
+        ("instance_template", "Template"),
+        ("autoscaler", "Autoscaler"),
+        ("stable", "Stable"),
+    ]);
+    for mig in migs {
+        let autoscaler = autoscaler_of(mig, autoscalers);
+        listing.add_row(vec![
+            Some(mig.name.clone().into()),
+            Some(mig.location.to_string().into()),
+            Some(mig.target_size.into()),
+            Some(mig.current_size.into()),
+            mig.instance_template.clone().map(Into::into),
+            autoscaler.map(|autoscaler| autoscaler.status.clone().into()),
+            Some(mig.is_stable.into()),
+        ]);
+    }
+    listing
+}
+
+/// Renders the details of a managed instance group, followed by a table of its instances
+/// with their status, current action and health.
+///
+/// # Arguments
+///
+/// * `mig` - The group.
+/// * `autoscalers` - The autoscalers of the project, matched to the group.
+/// * `instances` - The instances of the group.
+/// * `null` - The placeholder for missing values.
+pub fn mig_detail_text(
+    mig: &InstanceGroupManager,
+    autoscalers: &[Autoscaler],
+    instances: &[ManagedInstance],
+    null: &str,
+) -> String {
+    let autoscaler = autoscaler_of(mig, autoscalers)
+        .map(|autoscaler| format!("{} ({})", autoscaler.name, autoscaler.status));
+    let fields = [
+        ("Name", mig.name.clone()),
+        ("Zone/Region", mig.location.to_string()),
+        (
+            "Template",
+            mig.instance_template
+                .clone()
+                .unwrap_or_else(|| null.to_string()),
+        ),
+        ("Target size", mig.target_size.to_string()),
+        ("Current size", mig.current_size.to_string()),
+        ("Autoscaler", autoscaler.unwrap_or_else(|| null.to_string())),
+        ("Stable", mig.is_stable.to_string()),
+    ];
+    let mut out = String::new();
+    for (name, value) in fields {
+        out += &format!("{:14} {}\n", format!("{}:", name), value);
+    }
+
+    out += "\nInstances:\n";
+    if instances.is_empty() {
+        out += &format!("  {}\n", null);
+        return out;
+    }
+    let mut table = Table::new(["Name", "Zone", "Status", "Action", "Health"]);
+    for instance in instances {
+        let health = match instance.health.is_empty() {
+            true => null.to_string(),
+            false => instance.health.join(", "),
+        };
+        table.add_row([
+            instance.name.clone(),
+            instance.zone.clone(),
+            instance.status.clone().unwrap_or_else(|| null.to_string()),
+            instance.current_action.clone(),
+            health,
+        ]);
+    }
+    out + &table.render()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::{MigLocation, SecondaryRange};
+    use crate::container::NodePoolAutoscaling;
+    use serde_json::json;
+
+    fn disks() -> Vec<Disk> {
+        vec![
+            Disk {
+                name: "data-1".to_string(),
+                size_gb: 500,
+                disk_type: "pd-ssd".to_string(),
+                zone: Some("us-east1-b".to_string()),
+                users: vec!["db-1".to_string(), "db-2".to_string()],
+                status: "READY".to_string(),
+                ..Default::default()
+            },
+            Disk {
+                name: "orphan".to_string(),
+                size_gb: 10,
+                disk_type: "pd-balanced".to_string(),
+                status: "READY".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_disks_table() {
+        let table = disks_listing(&disks())
+            .render(Format::Table, &NullValues::default())
+            .unwrap();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "  Name    Size (GB)  Type         Zone/Region  Attached To  Status"
+        );
+        assert_eq!(
+            lines[1],
+            "  data-1  500        pd-ssd       us-east1-b   db-1, db-2   READY"
+        );
+        assert_eq!(
+            lines[2],
+            "  orphan  10         pd-balanced  -            -            READY"
+        );
+    }
+
+    #[test]
+    fn test_disks_json_and_csv() {
+        let listing = disks_listing(&disks());
+        let json = listing
+            .render(Format::Json, &NullValues::default())
+            .unwrap();
+        let json = serde_json::from_str::<JsonValue>(&json).unwrap();
+        assert_eq!(
+            json[0],
+            json!({
+                "name": "data-1",
+                "size_gb": 500,
+                "type": "pd-ssd",
+                "location": "us-east1-b",
+                "attached_to": ["db-1", "db-2"],
+                "status": "READY"
+            })
+        );
+        assert_eq!(json[1]["location"], JsonValue::Null);
+
+        let csv = listing.render(Format::Csv, &NullValues::default()).unwrap();
+        assert_eq!(
+            csv,
+            "name,size_gb,type,location,attached_to,status\n\
+             data-1,500,pd-ssd,us-east1-b,\"db-1, db-2\",READY\n\
+             orphan,10,pd-balanced,,,READY\n"
+        );
+        assert!(listing
+            .render(Format::Hosts, &NullValues::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_addresses_listing() {
+        let addresses = [
+            Address {
+                name: "web-ip".to_string(),
+                address: "34.1.2.3".to_string(),
+                address_type: "EXTERNAL".to_string(),
+                status: "IN_USE".to_string(),
+                region: Some("us-east1".to_string()),
+                users: vec!["forwardingRules/web".to_string()],
+            },
+            Address {
+                name: "old-ip".to_string(),
+                address: "34.9.9.9".to_string(),
+                address_type: "EXTERNAL".to_string(),
+                status: "RESERVED".to_string(),
+                region: None,
+                users: vec![],
+            },
+        ];
+        let listing = addresses_listing(&addresses);
+
+        let table = listing
+            .render(Format::Table, &NullValues::default())
+            .unwrap();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "  Name    Address   Type      Region    Status    Used By"
+        );
+        assert_eq!(
+            lines[2],
+            "  old-ip  34.9.9.9  EXTERNAL  global    RESERVED  -"
+        );
+
+        let csv = listing.render(Format::Csv, &NullValues::default()).unwrap();
+        assert_eq!(
+            csv,
+            "name,address,type,region,status,used_by\n\
+             web-ip,34.1.2.3,EXTERNAL,us-east1,IN_USE,forwardingRules/web\n\
+             old-ip,34.9.9.9,EXTERNAL,global,RESERVED,\n"
+        );
+    }
+
+    #[test]
+    fn test_subnets_listing() {
+        let subnets = [Subnetwork {
+            name: "gke-east".to_string(),
+            network: "shared".to_string(),
+            region: "us-east1".to_string(),
+            ip_cidr_range: "10.12.0.0/20".to_string(),
+            secondary_ranges: vec![
+                SecondaryRange {
+                    name: "pods".to_string(),
+                    cidr: "10.64.0.0/14".to_string(),
+                },
+                SecondaryRange {
+                    name: "services".to_string(),
+                    cidr: "10.68.0.0/20".to_string(),
+                },
+            ],
+        }];
+
+        let csv = subnets_listing(&subnets)
+            .render(Format::Csv, &NullValues::default())
+            .unwrap();
+        assert_eq!(
+            csv,
+            "name,network,region,range,secondary_ranges\n\
+             gke-east,shared,us-east1,10.12.0.0/20,\"pods=10.64.0.0/14, services=10.68.0.0/20\"\n"
+        );
+    }
+
+    #[test]
+    fn test_node_pools_listing() {
+        let pools = [
+            NodePool {
+                name: "default".to_string(),
+                machine_type: "e2-standard-4".to_string(),
+                version: "1.29.4".to_string(),
+                initial_node_count: 1,
+                autoscaling: Some(NodePoolAutoscaling {
+                    min_node_count: 1,
+                    max_node_count: 5,
+                }),
+                spot: false,
+                status: "RUNNING".to_string(),
+            },
+            NodePool {
+                name: "batch".to_string(),
+                machine_type: "n2-highmem-8".to_string(),
+                version: "1.29.4".to_string(),
+                initial_node_count: 2,
+                autoscaling: None,
+                spot: true,
+                status: "RUNNING".to_string(),
+            },
+        ];
+
+        let csv = node_pools_listing(&pools)
+            .render(Format::Csv, &NullValues::default())
+            .unwrap();
+        assert_eq!(
+            csv,
+            "name,machine_type,version,initial_nodes,autoscaling,spot,status\n\
+             default,e2-standard-4,1.29.4,1,1-5,false,RUNNING\n\
+             batch,n2-highmem-8,1.29.4,2,,true,RUNNING\n"
+        );
+    }
+
+    #[test]
+    fn test_record_sets_listing() {
+        let record_sets = [(
+            "internal".to_string(),
+            RecordSet {
+                name: "db.corp.internal.".to_string(),
+                record_type: "A".to_string(),
+                ttl: Some(300),
+                rrdatas: vec!["10.12.0.5".to_string(), "10.12.0.6".to_string()],
+            },
+        )];
+        let listing = record_sets_listing(&record_sets);
+
+        let table = listing
+            .render(Format::Table, &NullValues::default())
+            .unwrap();
+        assert_eq!(
+            table.lines().nth(1).unwrap(),
+            "  db.corp.internal.  A     300  10.12.0.5, 10.12.0.6  internal"
+        );
+
+        let json = listing
+            .render(Format::Json, &NullValues::default())
+            .unwrap();
+        let json = serde_json::from_str::<JsonValue>(&json).unwrap();
+        assert_eq!(json[0]["data"], json!(["10.12.0.5", "10.12.0.6"]));
+    }
+
+    #[test]
+    fn test_snapshots_listing() {
+        let time = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let snapshots = [
+            Snapshot {
+                name: "data-1-20240301".to_string(),
+                source_disk: Some("data-1".to_string()),
+                disk_size_gb: 500,
+                storage_bytes: Some(3 << 29),
+                creation_timestamp: Some(time("2024-03-01T12:00:00Z")),
+                storage_locations: vec!["us".to_string()],
+                status: "READY".to_string(),
+            },
+            Snapshot {
+                name: "orphan".to_string(),
+                disk_size_gb: 10,
+                ..Default::default()
+            },
+        ];
+        let listing = snapshots_listing(&snapshots, Timezone::Utc, time("2024-04-01T00:00:00Z"));
+        let json = listing
+            .render(Format::Json, &NullValues::default())
+            .unwrap();
+        let json = serde_json::from_str::<JsonValue>(&json).unwrap();
+        assert_eq!(
+            json[0],
+            json!({
+                "name": "data-1-20240301",
+                "source_disk": "data-1",
+                "disk_size_gb": 500,
+                "storage_gb": 1.5,
+                "created": "2024-03-01T12:00:00+00:00",
+                "age_days": 30,
+                "storage_locations": ["us"],
+                "status": "READY"
+            })
+        );
+        assert_eq!(json[1]["age_days"], JsonValue::Null);
+        assert_eq!(json[1]["source_disk"], JsonValue::Null);
+    }
+
+    #[test]
+    fn test_operations_listing() {
+        let operations = [
+            Operation {
+                name: "op-1".to_string(),
+                operation_type: "stop".to_string(),
+                target: "db-1".to_string(),
+                status: "DONE".to_string(),
+                zone: Some("us-east1-b".to_string()),
+                user: Some("jdoe@example.com".to_string()),
+                insert_time: Some("2024-03-01T04:00:00.000-08:00".to_string()),
+                errors: vec!["The resource is not ready".to_string()],
+                ..Default::default()
+            },
+            Operation {
+                name: "op-2".to_string(),
+                operation_type: "insert".to_string(),
+                target: "fw-allow-ssh".to_string(),
+                status: "RUNNING".to_string(),
+                ..Default::default()
+            },
+        ];
+        let json = operations_listing(&operations, Timezone::Utc)
+            .render(Format::Json, &NullValues::default())
+            .unwrap();
+        let json = serde_json::from_str::<JsonValue>(&json).unwrap();
+        assert_eq!(json[0]["location"], "us-east1-b");
+        assert_eq!(json[0]["inserted"], "2024-03-01T12:00:00+00:00");
+        assert_eq!(json[0]["errors"], "The resource is not ready");
+        assert_eq!(json[1]["location"], "global");
+        assert_eq!(json[1]["ended"], JsonValue::Null);
+    }
+
+    fn mig() -> InstanceGroupManager {
+        InstanceGroupManager {
+            name: "web".to_string(),
+            location: MigLocation::Zone("us-east1-b".to_string()),
+            target_size: 2,
+            current_size: 2,
+            instance_template: Some("web-v7".to_string()),
+            is_stable: true,
+        }
+    }
+
+    fn autoscalers() -> Vec<Autoscaler> {
+        let autoscaler = |location: MigLocation, status: &str| Autoscaler {
+            name: "web-autoscaler".to_string(),
+            target: "web".to_string(),
+            location,
+            status: status.to_string(),
+        };
+        vec![
+            autoscaler(MigLocation::Region("us-east1".to_string()), "ERROR"),
+            autoscaler(MigLocation::Zone("us-east1-b".to_string()), "ACTIVE"),
+        ]
+    }
+
+    #[test]
+    fn test_migs_listing() {
+        let csv = migs_listing(&[mig()], &autoscalers())
+            .render(Format::Csv, &NullValues::default())
+            .unwrap();
+        assert_eq!(
+            csv,
+            "name,location,target_size,current_size,instance_template,autoscaler,stable\n\
+             web,us-east1-b,2,2,web-v7,ACTIVE,true\n"
+        );
+        let csv = migs_listing(&[mig()], &[])
+            .render(Format::Csv, &NullValues::default())
+            .unwrap();
+        assert!(csv.ends_with("web,us-east1-b,2,2,web-v7,,true\n"));
+    }
+
+    #[test]
+    fn test_templates_listing() {
+        let templates = [
+            InstanceTemplate {
+                name: "web-v7".to_string(),
+                machine_type: "n2-standard-4".to_string(),
+                source_image: Some("p/base-v3".to_string()),
+                ..Default::default()
+            },
+            InstanceTemplate {
+                name: "web-v6".to_string(),
+                machine_type: "n2-standard-2".to_string(),
+                ..Default::default()
+            },
+        ];
+        let csv = templates_listing(&templates, &[mig()], Timezone::Utc)
+            .render(Format::Csv, &NullValues::default())
+            .unwrap();
+        assert_eq!(
+            csv,
+            "name,machine_type,source_image,created,migs\n\
+             web-v7,n2-standard-4,p/base-v3,,web\n\
+             web-v6,n2-standard-2,,,\n"
+        );
+    }
+
+    #[test]
+    fn test_images_listing() {
+        let image = Image {
+            name: "base-v3".to_string(),
+            family: Some("base".to_string()),
+            status: "READY".to_string(),
+            deprecation: Some("DEPRECATED".to_string()),
+            replacement: Some("p/base-v4".to_string()),
+            ..Default::default()
+        };
+        let table = images_listing(&[image], Timezone::Utc)
+            .render(Format::Table, &NullValues::default())
+            .unwrap();
+        assert!(
+            table.ends_with("base-v3  base    -        -            -          READY   DEPRECATED   p/base-v4\n"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn test_history_listing() {
+        let entry = AuditEntry {
+            method: "stop".to_string(),
+            principal: Some("alice@example.com".to_string()),
+            ..Default::default()
+        };
+        let table = history_listing(&[entry], Timezone::Utc)
+            .render(Format::Table, &NullValues::default())
+            .unwrap();
+        assert!(
+            table.ends_with("-     stop    alice@example.com  -          -\n"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn test_os_listing() {
+        let instance = |name: &str| Instance {
+            name: name.to_string(),
+            zone: "us-east1-b".to_string(),
+            status: "RUNNING".to_string(),
+            ..Default::default()
+        };
+        let inventory = Inventory {
+            os_name: Some("Debian GNU/Linux 12 (bookworm)".to_string()),
+            os_version: Some("12".to_string()),
+            kernel: Some("6.1.0-18-cloud-amd64".to_string()),
+            pending_updates: 3,
+            update_time: None,
+        };
+        let listing = os_listing(
+            &[
+                (instance("web-1"), Some(inventory)),
+                (instance("web-2"), None),
+            ],
+            Timezone::Utc,
+        );
+        let json = listing
+            .render(Format::Json, &NullValues::default())
+            .unwrap();
+        let json: JsonValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(json[0]["kernel"], "6.1.0-18-cloud-amd64");
+        assert_eq!(json[0]["pending_updates"], 3);
+        assert_eq!(json[1]["kernel"], JsonValue::Null);
+        assert_eq!(json[1]["status"], "RUNNING");
+    }
+
+    #[test]
+    fn test_mig_detail_text() {
+        let instances = [
+            ManagedInstance {
+                name: "web-a1".to_string(),
+                zone: "us-east1-b".to_string(),
+                status: Some("RUNNING".to_string()),
+                current_action: "NONE".to_string(),
+                health: vec!["HEALTHY".to_string()],
+            },
+            ManagedInstance {
+                name: "web-b2".to_string(),
+                zone: "us-east1-b".to_string(),
+                status: None,
+                current_action: "CREATING".to_string(),
+                health: vec![],
+            },
+        ];
+        let text = mig_detail_text(&mig(), &autoscalers(), &instances, "-");
+        assert!(text.contains("Autoscaler:    web-autoscaler (ACTIVE)\n"));
+        assert!(text.contains("Template:      web-v7\n"));
+        assert!(text.contains("\nInstances:\n  Name    Zone        Status   Action    Health\n"));
+        assert!(text.contains("  web-b2  us-east1-b  -        CREATING  -\n"));
+        assert!(mig_detail_text(&mig(), &[], &[], "-").ends_with("Instances:\n  -\n"));
+    }
+}
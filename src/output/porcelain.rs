@@ -0,0 +1,99 @@
+//! This module renders the porcelain output format: a tab-separated listing for scripts whose
+//! layout only changes with a new version, independent of the human-readable formats.
+//!
+//! Version 1 prints one line per instance, sorted by name and then zone, with these fields:
+//!
+//! 1. name
+//! 2. zone
+//! 3. status, e.g. `RUNNING`
+//! 4. internal IP of the first network interface
+//! 5. external IP of the first network interface
+//! 6. machine type, e.g. `n2-standard-4`
+//! 7. cell
+//!
+//! Missing values are empty fields. There is no header and no color, and tabs or line breaks
+//! within values are replaced by spaces.
+
+use crate::compute::Instance;
+
+/// The versions of the porcelain format. New fields or changes to existing ones only ever go
+/// into a new version.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PorcelainVersion {
+    /// Name, zone, status, internal IP, external IP, machine type and cell.
+    #[default]
+    V1,
+}
+
+/// Replaces the characters that would break the line and field structure.
+fn field(value: Option<&str>) -> String {
+    value.unwrap_or_default().replace(['\t', '\n', '\r'], " ")
+}
+
+/// Renders instances in the porcelain format.
+///
+/// # Arguments
+///
+/// * `version` - The version of the format.
+/// * `instances` - The instances, in any order.
+pub fn porcelain(version: PorcelainVersion, instances: &[Instance]) -> String {
+    let mut instances = instances.iter().collect::<Vec<_>>();
+    instances.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.zone.cmp(&b.zone)));
+    match version {
+        PorcelainVersion::V1 => instances
+            .into_iter()
+            .map(|instance| {
+                let fields = [
+                    field(Some(&instance.name)),
+                    field(Some(&instance.zone)),
+                    field(Some(&instance.status)),
+                    field(instance.primary_ip()),
+                    field(instance.external_ip()),
+                    field(Some(&instance.machine_type)),
+                    field(instance.cell.as_deref()),
+                ];
+                fields.join("\t") + "\n"
+            })
+            .collect(),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::NetworkInterface;
+
+    fn instance(name: &str, zone: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            zone: zone.to_string(),
+            status: "RUNNING".to_string(),
+            machine_type: "n2-standard-4".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Version 1 is a contract with scripts: if this test needs changing, the change belongs
+    /// in a new version instead.
+    #[test]
+    fn test_v1_contract() {
+        let mut db = instance("db-1", "us-east1-c");
+        db.network_interfaces = vec![NetworkInterface {
+            ip: Some("10.0.0.2".to_string()),
+            external_ip: Some("34.1.2.3".to_string()),
+            ..Default::default()
+        }];
+        db.cell = Some("cell\ta".to_string());
+        let mut web = instance("web-1", "us-east1-b");
+        web.status = "TERMINATED".to_string();
+        let instances = [web, db, instance("db-1", "us-east1-b")];
+
+        assert_eq!(
+            porcelain(PorcelainVersion::V1, &instances),
+            "db-1\tus-east1-b\tRUNNING\t\t\tn2-standard-4\t\n\
+             db-1\tus-east1-c\tRUNNING\t10.0.0.2\t34.1.2.3\tn2-standard-4\tcell a\n\
+             web-1\tus-east1-b\tTERMINATED\t\t\tn2-standard-4\t\n"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! This module renders instances through a user-supplied line template, for `--format`.
+//!
+//! A template is plain text with fields in double braces, in the style of Go templates, e.g.
+//! `{{.name}} {{.ip}} {{.labels.cell}}`. Each field is the key of a column, as for
+//! `--columns`, and renders the value the column would show in a table. `\t` and `\n` in the
+//! text stand for a tab and a line break. Every instance gets one rendering of the template,
+//! followed by a line break.
+
+use super::{value_to_text, Column};
+use crate::compute::Instance;
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    /// Text copied to the output as it is.
+    Text(String),
+    /// The key of the column whose value is inserted, e.g. `labels.cell`.
+    Field(String),
+}
+
+/// A parsed `--format` template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    /// The text and fields, in order.
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template, e.g. `{{.name}}\t{{.ip}}`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Template)` - The parsed template.
+    /// * `Err(String)` - An error if a field isn't closed, or isn't a `.` followed by a
+    ///   column key.
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            push_text(&mut parts, &rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("Unclosed field in template: {}", &rest[start..]))?;
+            let field = rest[start + 2..start + end].trim();
+            match field.strip_prefix('.') {
+                Some(key) if !key.is_empty() => parts.push(Part::Field(key.to_string())),
+                _ => {
+                    return Err(format!(
+                        "Invalid field {{{{{}}}}} in template, expected e.g. {{{{.name}}}}",
+                        field
+                    ))
+                }
+            }
+            rest = &rest[start + end + 2..];
+        }
+        push_text(&mut parts, rest);
+        Ok(Template { parts })
+    }
+
+    /// Returns the keys of the columns the template uses, in order of first use, e.g. to
+    /// select them with `select_columns`.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for part in &self.parts {
+            if let Part::Field(key) = part {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        keys
+    }
+
+    /// Renders the template once per instance, each followed by a line break.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The columns the fields refer to, e.g. selected with `keys`. Fields
+    ///   without a column render as `null`.
+    /// * `instances` - The instances to render.
+    /// * `null` - The placeholder for missing values.
+    pub fn render(&self, columns: &[Column], instances: &[Instance], null: &str) -> String {
+        let mut out = String::new();
+        for instance in instances {
+            for part in &self.parts {
+                match part {
+                    Part::Text(text) => out.push_str(text),
+                    Part::Field(key) => {
+                        let value = columns
+                            .iter()
+                            .find(|column| column.key == *key)
+                            .and_then(|column| (column.value)(instance));
+                        out.push_str(&value_to_text(value, null));
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Appends the text between fields, with its `\t` and `\n` escapes replaced.
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    if !text.is_empty() {
+        parts.push(Part::Text(text.replace("\\t", "\t").replace("\\n", "\n")));
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::select_columns;
+    use std::collections::HashMap;
+
+    fn instance(name: &str, cell: Option<&str>) -> Instance {
+        Instance {
+            name: name.to_string(),
+            labels: cell.map(|cell| HashMap::from([("cell".to_string(), cell.to_string())])),
+            ..Instance::default()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let template = Template::parse("{{.name}}\\t{{ .labels.cell }} ({{.name}})").unwrap();
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Field("name".to_string()),
+                Part::Text("\t".to_string()),
+                Part::Field("labels.cell".to_string()),
+                Part::Text(" (".to_string()),
+                Part::Field("name".to_string()),
+                Part::Text(")".to_string()),
+            ]
+        );
+        assert_eq!(template.keys(), vec!["name", "labels.cell"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Template::parse("{{.name").unwrap_err(),
+            "Unclosed field in template: {{.name"
+        );
+        assert_eq!(
+            Template::parse("{{name}}").unwrap_err(),
+            "Invalid field {{name}} in template, expected e.g. {{.name}}"
+        );
+        assert!(Template::parse("{{.}}").is_err());
+        assert_eq!(
+            Template::parse("plain").unwrap().keys(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse("{{.name}} cell={{.labels.cell}}").unwrap();
+        let mut columns = select_columns(vec![], &["labels.cell".to_string()]).unwrap();
+        columns.push(Column::new("name", "Name", |i| Some(i.name.clone().into())));
+        let instances = [instance("a", Some("c1")), instance("b", None)];
+        assert_eq!(
+            template.render(&columns, &instances, "-"),
+            "a cell=c1\nb cell=-\n"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! This module decides where bcls reads its config file from and where it keeps its caches,
+//! following the XDG base directory conventions while still honouring `~/.bcls`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The directory bcls used before it followed the XDG conventions, relative to the home
+/// directory.
+const LEGACY_DIR: &str = ".bcls";
+
+/// The environment variable holding the path of the config file.
+pub const CONFIG_PATH_VAR: &str = "BCLS_CONFIG";
+
+/// The files and directories bcls uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// The config file. It need not exist.
+    pub config_file: PathBuf,
+    /// The directory of cached instance listings.
+    pub cache_dir: PathBuf,
+    /// The directory of cached access tokens, if plain-file token caching is enabled.
+    pub token_cache_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the paths from the process environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_file` - The config file given with `--config`, which takes precedence over
+    ///   everything else.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Paths)` - The paths.
+    /// * `Err(String)` - An error if a path is needed under the home directory and it isn't
+    ///   known.
+    pub fn resolve(config_file: Option<&Path>) -> Result<Self, String> {
+        Self::resolve_with(
+            config_file,
+            dirs::home_dir(),
+            |name| std::env::var_os(name),
+            Path::exists,
+        )
+    }
+
+    /// Resolves the paths from the given environment, so the rules can be tested.
+    ///
+    /// The config file is the first of:
+    ///
+    /// 1. `config_file`, from `--config`.
+    /// 2. `$BCLS_CONFIG`.
+    /// 3. `$XDG_CONFIG_HOME/bcls/config.toml`, or `~/.config/bcls/config.toml` if
+    ///    `XDG_CONFIG_HOME` isn't set, if it exists.
+    /// 4. `~/.bcls/config.toml` if it exists.
+    /// 5. `$XDG_CONFIG_HOME/bcls/config.toml` if `XDG_CONFIG_HOME` is set, else
+    ///    `~/.bcls/config.toml`, as where a new config file goes.
+    ///
+    /// Caches go to `$XDG_CACHE_HOME/bcls/` if `XDG_CACHE_HOME` is set, and to `~/.bcls/`
+    /// otherwise. Relative XDG paths are ignored, as the XDG specification asks.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_file` - The config file given with `--config`.
+    /// * `home` - The home directory, if known.
+    /// * `var` - Looks up an environment variable.
+    /// * `exists` - Checks whether a file exists.
+    pub fn resolve_with(
+        config_file: Option<&Path>,
+        home: Option<PathBuf>,
+        var: impl Fn(&str) -> Option<OsString>,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Result<Self, String> {
+        let home = || home.clone().ok_or("Homedir not found");
+        let xdg = |name: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+        };
+        let legacy_dir = || home().map(|home| home.join(LEGACY_DIR));
+        let xdg_config_home = xdg("XDG_CONFIG_HOME");
+        let xdg_cache_home = xdg("XDG_CACHE_HOME");
+
+        let config_file = match config_file
+            .map(Path::to_path_buf)
+            .or_else(|| var(CONFIG_PATH_VAR).map(PathBuf::from))
+        {
+            Some(path) => path,
+            None => {
+                let xdg_config_file = match &xdg_config_home {
+                    Some(dir) => Some(dir.join("bcls/config.toml")),
+                    None => home()
+                        .ok()
+                        .map(|home| home.join(".config/bcls/config.toml")),
+                };
+                let legacy_config_file = legacy_dir()?.join("config.toml");
+                match xdg_config_file {
+                    Some(path) if exists(&path) => path,
+                    _ if exists(&legacy_config_file) => legacy_config_file,
+                    Some(path) if xdg_config_home.is_some() => path,
+                    _ => legacy_config_file,
+                }
+            }
+        };
+
+        let (cache_dir, token_cache_dir) = match xdg_cache_home {
+            Some(dir) => (dir.join("bcls/instances"), dir.join("bcls/token-cache")),
+            None => (
+                legacy_dir()?.join("cache"),
+                legacy_dir()?.join("token-cache"),
+            ),
+        };
+
+        Ok(Paths {
+            config_file,
+            cache_dir,
+            token_cache_dir,
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Resolves the paths with the home directory `/home/u`, the given environment variables
+    /// and the given existing files.
+    fn resolve(
+        config_file: Option<&str>,
+        vars: &[(&str, &str)],
+        existing: &[&str],
+    ) -> Result<Paths, String> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect::<HashMap<_, _>>();
+        let existing = existing.iter().map(PathBuf::from).collect::<Vec<_>>();
+        Paths::resolve_with(
+            config_file.map(Path::new),
+            Some(PathBuf::from("/home/u")),
+            |name| vars.get(name).cloned(),
+            |path| existing.iter().any(|existing| existing == path),
+        )
+    }
+
+    #[test]
+    fn test_defaults_to_legacy_dir() {
+        let paths = resolve(None, &[], &[]).unwrap();
+        assert_eq!(
+            paths,
+            Paths {
+                config_file: PathBuf::from("/home/u/.bcls/config.toml"),
+                cache_dir: PathBuf::from("/home/u/.bcls/cache"),
+                token_cache_dir: PathBuf::from("/home/u/.bcls/token-cache"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_file_precedence() {
+        let both = [
+            "/home/u/.config/bcls/config.toml",
+            "/home/u/.bcls/config.toml",
+        ];
+        let config_file = |config: Option<&str>, vars: &[(&str, &str)], existing: &[&str]| {
+            resolve(config, vars, existing).unwrap().config_file
+        };
+
+        assert_eq!(
+            config_file(Some("/etc/bcls.toml"), &[("BCLS_CONFIG", "/x.toml")], &both),
+            PathBuf::from("/etc/bcls.toml")
+        );
+        assert_eq!(
+            config_file(None, &[("BCLS_CONFIG", "/x.toml")], &both),
+            PathBuf::from("/x.toml")
+        );
+        assert_eq!(
+            config_file(None, &[], &both),
+            PathBuf::from("/home/u/.config/bcls/config.toml")
+        );
+        assert_eq!(
+            config_file(None, &[], &both[1..]),
+            PathBuf::from("/home/u/.bcls/config.toml")
+        );
+        assert_eq!(
+            config_file(
+                None,
+                &[("XDG_CONFIG_HOME", "/cfg")],
+                &["/cfg/bcls/config.toml"]
+            ),
+            PathBuf::from("/cfg/bcls/config.toml")
+        );
+    }
+
+    #[test]
+    fn test_new_config_file_goes_to_xdg_dir_only_if_set() {
+        assert_eq!(
+            resolve(None, &[("XDG_CONFIG_HOME", "/cfg")], &[])
+                .unwrap()
+                .config_file,
+            PathBuf::from("/cfg/bcls/config.toml")
+        );
+        // A relative XDG_CONFIG_HOME is ignored
+        assert_eq!(
+            resolve(None, &[("XDG_CONFIG_HOME", "cfg")], &[])
+                .unwrap()
+                .config_file,
+            PathBuf::from("/home/u/.bcls/config.toml")
+        );
+    }
+
+    #[test]
+    fn test_xdg_cache_home() {
+        let paths = resolve(None, &[("XDG_CACHE_HOME", "/var/cache/u")], &[]).unwrap();
+        assert_eq!(
+            paths.cache_dir,
+            PathBuf::from("/var/cache/u/bcls/instances")
+        );
+        assert_eq!(
+            paths.token_cache_dir,
+            PathBuf::from("/var/cache/u/bcls/token-cache")
+        );
+    }
+
+    #[test]
+    fn test_without_home() {
+        assert!(Paths::resolve_with(None, None, |_| None, |_| false).is_err());
+        // Everything can still be given explicitly
+        let paths = Paths::resolve_with(
+            Some(Path::new("/etc/bcls.toml")),
+            None,
+            |name| (name == "XDG_CACHE_HOME").then(|| OsString::from("/cache")),
+            |_| false,
+        )
+        .unwrap();
+        assert_eq!(paths.config_file, PathBuf::from("/etc/bcls.toml"));
+    }
+}
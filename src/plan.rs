@@ -0,0 +1,320 @@
+//! This module analyses whether instances can be moved from one zone to another by combining
+//! instance, disk and reservation data, and reports the zone-pinned resources that block a move.
+
+use crate::compute::{last_segment, Disk, Instance, Reservation};
+use std::fmt;
+
+/// A zone-pinned resource that prevents an instance from simply being moved to another zone.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Blocker {
+    /// A local SSD, whose data does not survive moving the instance.
+    LocalSsd {
+        /// The device name of the local SSD.
+        device_name: String,
+    },
+    /// A zonal persistent disk, which must be snapshotted and recreated in the target zone.
+    ZonalDisk {
+        /// The name of the disk.
+        name: String,
+        /// The size of the disk in GB, if known.
+        size_gb: Option<u64>,
+        /// Other instances the disk is attached to, which must move together.
+        shared_with: Vec<String>,
+    },
+    /// A regional disk that is not replicated to the target zone.
+    RegionalDisk {
+        /// The name of the disk.
+        name: String,
+    },
+    /// The instance consumes specific, named reservations, which are zonal.
+    Reservation {
+        /// The reservations the instance targets.
+        names: Vec<String>,
+        /// A reservation in the target zone with free capacity for the instance's machine type.
+        alternative: Option<String>,
+    },
+}
+
+impl fmt::Display for Blocker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Blocker::LocalSsd { device_name } => write!(f, "local SSD {}", device_name),
+            Blocker::ZonalDisk {
+                name,
+                size_gb,
+                shared_with,
+            } => {
+                write!(f, "zonal disk {}", name)?;
+                if let Some(size) = size_gb {
+                    write!(f, " ({}GB)", size)?;
+                }
+                if !shared_with.is_empty() {
+                    write!(f, " shared with {}", shared_with.join(", "))?;
+                }
+                Ok(())
+            }
+            Blocker::RegionalDisk { name } => {
+                write!(f, "regional disk {} not replicated to target zone", name)
+            }
+            Blocker::Reservation { names, alternative } => {
+                write!(f, "specific reservation {}", names.join(", "))?;
+                match alternative {
+                    Some(alternative) => write!(f, " (target zone has {})", alternative),
+                    None => write!(f, " (no matching reservation in target zone)"),
+                }
+            }
+        }
+    }
+}
+
+/// The result of analysing a single instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovePlan {
+    /// The name of the instance.
+    pub instance: String,
+    /// The machine type of the instance.
+    pub machine_type: String,
+    /// The resources blocking the move. Empty if the instance can move freely.
+    pub blockers: Vec<Blocker>,
+}
+
+/// Analyses which instances in the source zone have resources pinned to that zone.
+///
+/// # Arguments
+///
+/// * `instances` - The candidate instances. Only those in `from` are analysed.
+/// * `disks` - All disks in the project, used for sizes, sharing and replica zones.
+/// * `reservations` - All reservations in the project, used to find alternatives in `to`.
+/// * `from` - The zone the instances are currently in.
+/// * `to` - The zone the instances would move to.
+///
+/// # Returns
+///
+/// One `MovePlan` per instance in the source zone, in input order.
+pub fn plan_zone_move(
+    instances: &[Instance],
+    disks: &[Disk],
+    reservations: &[Reservation],
+    from: &str,
+    to: &str,
+) -> Vec<MovePlan> {
+    instances
+        .iter()
+        .filter(|inst| inst.zone == from)
+        .map(|inst| MovePlan {
+            instance: inst.name.clone(),
+            machine_type: inst.machine_type.clone(),
+            blockers: instance_blockers(inst, disks, reservations, from, to),
+        })
+        .collect()
+}
+
+/// Collects the blockers for a single instance.
+fn instance_blockers(
+    instance: &Instance,
+    disks: &[Disk],
+    reservations: &[Reservation],
+    from: &str,
+    to: &str,
+) -> Vec<Blocker> {
+    let mut blockers = vec![];
+
+    for attached in &instance.disks {
+        if attached.is_local_ssd() {
+            blockers.push(Blocker::LocalSsd {
+                device_name: attached.device_name.clone(),
+            });
+            continue;
+        }
+        let Some(name) = attached.source_name() else {
+            continue;
+        };
+        if attached.is_zonal() {
+            let disk = disks
+                .iter()
+                .find(|d| d.name == name && d.zone.as_deref() == Some(from));
+            blockers.push(Blocker::ZonalDisk {
+                name,
+                size_gb: disk.map(|d| d.size_gb).or(attached.size_gb),
+                shared_with: disk
+                    .map(|d| {
+                        d.users
+                            .iter()
+                            .filter(|user| **user != instance.name)
+                            .cloned()
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        } else {
+            let replicated = disks
+                .iter()
+                .find(|d| d.name == name && d.region.is_some())
+                .is_some_and(|d| d.replica_zones.iter().any(|zone| zone == to));
+            if !replicated {
+                blockers.push(Blocker::RegionalDisk { name });
+            }
+        }
+    }
+
+    if let Some(affinity) = instance
+        .reservation_affinity
+        .as_ref()
+        .filter(|a| a.is_specific())
+    {
+        let alternative = reservations
+            .iter()
+            .find(|r| {
+                r.zone == to
+                    && r.machine_type.as_deref() == Some(instance.machine_type.as_str())
+                    && r.in_use_count < r.count
+            })
+            .map(|r| r.name.clone());
+        blockers.push(Blocker::Reservation {
+            names: affinity
+                .values
+                .iter()
+                .map(|value| last_segment(value))
+                .collect(),
+            alternative,
+        });
+    }
+
+    blockers
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::{AttachedDisk, ReservationAffinity};
+
+    fn zonal_disk(name: &str) -> AttachedDisk {
+        AttachedDisk {
+            device_name: name.to_string(),
+            kind: "PERSISTENT".to_string(),
+            source: Some(format!("projects/p/zones/us-east1-b/disks/{}", name)),
+            ..Default::default()
+        }
+    }
+
+    fn instance(name: &str, zone: &str, disks: Vec<AttachedDisk>) -> Instance {
+        Instance {
+            name: name.to_string(),
+            zone: zone.to_string(),
+            machine_type: "n2-standard-16".to_string(),
+            disks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_zone_move() {
+        let mut reserved = instance("reserved", "us-east1-b", vec![]);
+        reserved.reservation_affinity = Some(ReservationAffinity {
+            consume_type: "SPECIFIC_RESERVATION".to_string(),
+            values: vec!["projects/p/zones/us-east1-b/reservations/res-b".to_string()],
+        });
+        let instances = vec![
+            instance(
+                "db",
+                "us-east1-b",
+                vec![
+                    zonal_disk("db-data"),
+                    AttachedDisk {
+                        device_name: "local-ssd-0".to_string(),
+                        kind: "SCRATCH".to_string(),
+                        ..Default::default()
+                    },
+                    AttachedDisk {
+                        device_name: "shared".to_string(),
+                        kind: "PERSISTENT".to_string(),
+                        source: Some("projects/p/regions/us-east1/disks/shared".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            ),
+            reserved,
+            instance("free", "us-east1-b", vec![]),
+            instance("elsewhere", "us-east1-d", vec![zonal_disk("other")]),
+        ];
+        let disks = vec![
+            Disk {
+                name: "db-data".to_string(),
+                size_gb: 500,
+                zone: Some("us-east1-b".to_string()),
+                users: vec!["db".to_string(), "db-replica".to_string()],
+                ..Default::default()
+            },
+            Disk {
+                name: "shared".to_string(),
+                region: Some("us-east1".to_string()),
+                replica_zones: vec!["us-east1-b".to_string(), "us-east1-d".to_string()],
+                ..Default::default()
+            },
+        ];
+        let reservations = vec![Reservation {
+            name: "res-c".to_string(),
+            zone: "us-east1-c".to_string(),
+            machine_type: Some("n2-standard-16".to_string()),
+            count: 2,
+            in_use_count: 1,
+            ..Default::default()
+        }];
+
+        let plans = plan_zone_move(
+            &instances,
+            &disks,
+            &reservations,
+            "us-east1-b",
+            "us-east1-c",
+        );
+
+        assert_eq!(plans.len(), 3);
+        assert_eq!(
+            plans[0].blockers,
+            vec![
+                Blocker::ZonalDisk {
+                    name: "db-data".to_string(),
+                    size_gb: Some(500),
+                    shared_with: vec!["db-replica".to_string()],
+                },
+                Blocker::LocalSsd {
+                    device_name: "local-ssd-0".to_string()
+                },
+                Blocker::RegionalDisk {
+                    name: "shared".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            plans[1].blockers,
+            vec![Blocker::Reservation {
+                names: vec!["res-b".to_string()],
+                alternative: Some("res-c".to_string()),
+            }]
+        );
+        assert!(plans[2].blockers.is_empty());
+    }
+
+    #[test]
+    fn test_blocker_display() {
+        let blocker = Blocker::ZonalDisk {
+            name: "db-data".to_string(),
+            size_gb: Some(500),
+            shared_with: vec!["db-replica".to_string()],
+        };
+        assert_eq!(
+            blocker.to_string(),
+            "zonal disk db-data (500GB) shared with db-replica"
+        );
+        let blocker = Blocker::Reservation {
+            names: vec!["res-b".to_string()],
+            alternative: None,
+        };
+        assert_eq!(
+            blocker.to_string(),
+            "specific reservation res-b (no matching reservation in target zone)"
+        );
+    }
+}
@@ -0,0 +1,931 @@
+//! This module provides selection of instances: matching them against the user's search
+//! pattern, labels and status, sampling from the matches and sorting them. Label and status
+//! filters are also translated into server-side filter expressions, so only matching instances
+//! are fetched.
+
+mod filter;
+mod instance_set;
+mod name_pattern;
+mod regex;
+
+use crate::compute::Instance;
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+pub use filter::{Comparison, Filter, Literal};
+pub use instance_set::{InstanceSet, Selection};
+pub use name_pattern::NamePattern;
+pub use regex::Regex;
+
+/// Checks whether an instance's name matches a search pattern.
+///
+/// # Arguments
+///
+/// * `instance` - The instance to check.
+/// * `pattern` - A substring to look for in the instance name, or a glob such as
+///   `store-lb-*` matching the whole name. `None` matches everything.
+pub fn matches_pattern(instance: &Instance, pattern: Option<&str>) -> bool {
+    pattern.is_none_or(|pattern| NamePattern::plain(pattern).matches(&instance.name))
+}
+
+/// A `key=value` label an instance must have, e.g. from `--label env=prod`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelFilter {
+    /// The label key.
+    pub key: String,
+    /// The label value. Empty to match instances that have the label set to an empty value.
+    pub value: String,
+}
+
+impl FromStr for LabelFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(LabelFilter {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected key=value, got `{}`", s)),
+        }
+    }
+}
+
+impl LabelFilter {
+    /// Checks whether an instance has the label with this value.
+    pub fn matches(&self, instance: &Instance) -> bool {
+        instance
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(&self.key))
+            .is_some_and(|value| *value == self.value)
+    }
+
+    /// Returns the filter as a Compute Engine filter, e.g. `labels.env = "prod"`.
+    ///
+    /// Returns `None` if the key or value contain characters that labels can't contain, such
+    /// as quotes, or a key the API can't name, such as one with non-ASCII letters. Such
+    /// filters are left to `matches`.
+    fn to_filter(&self) -> Option<Filter> {
+        let valid = |s: &str| {
+            s.chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        };
+        // The API only takes ASCII field names, while values are quoted
+        let valid_key = self
+            .key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        (valid_key && valid(&self.value)).then(|| Filter::label_eq(&self.key, &self.value))
+    }
+}
+
+/// The statuses an instance can have.
+/// <https://cloud.google.com/compute/docs/instances/instance-life-cycle>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[value(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Status {
+    Provisioning,
+    Staging,
+    Running,
+    Stopping,
+    Stopped,
+    Suspending,
+    Suspended,
+    Repairing,
+    Terminated,
+}
+
+impl Status {
+    /// Returns the status as the API reports it, e.g. `RUNNING`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Provisioning => "PROVISIONING",
+            Status::Staging => "STAGING",
+            Status::Running => "RUNNING",
+            Status::Stopping => "STOPPING",
+            Status::Stopped => "STOPPED",
+            Status::Suspending => "SUSPENDING",
+            Status::Suspended => "SUSPENDED",
+            Status::Repairing => "REPAIRING",
+            Status::Terminated => "TERMINATED",
+        }
+    }
+}
+
+/// Filters on instance fields, from `--label`, `--status` and `--resource-policy`. Most of
+/// them can be applied by the API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceFilter {
+    /// Labels an instance must all have.
+    pub labels: Vec<LabelFilter>,
+    /// Statuses an instance must have one of. Empty to allow any status.
+    pub statuses: Vec<Status>,
+    /// Statuses an instance must not have.
+    pub excluded_statuses: Vec<Status>,
+    /// Names of resource policies an instance must all have attached. The API can't filter
+    /// on these, so they are only checked by `matches`.
+    pub resource_policies: Vec<String>,
+    /// Machine types an instance must have one of, e.g. `n2-standard-8`. Empty to allow any.
+    /// The API only knows the full URL of the machine type, so these are only checked by
+    /// `matches`.
+    pub machine_types: Vec<String>,
+    /// Only instances created before this time, e.g. from `--older-than`. Instances without a
+    /// creation time don't match. Only checked by `matches`.
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only instances created after this time, e.g. from `--newer-than`. Instances without a
+    /// creation time don't match. Only checked by `matches`.
+    pub created_after: Option<DateTime<Utc>>,
+    /// Whether instances must run on spot capacity (`Some(true)`) or must not
+    /// (`Some(false)`), e.g. from `--spot-only` and `--no-spot`. Only checked by `matches`.
+    pub spot: Option<bool>,
+    /// Service account emails an instance must run as one of, e.g. from `--service-account`.
+    /// `default` stands for the project's default Compute Engine service account. Only
+    /// checked by `matches`.
+    pub service_accounts: Vec<String>,
+}
+
+impl InstanceFilter {
+    /// Adds a label an instance must have, e.g. `.label("team", "payments")`.
+    pub fn label(mut self, key: &str, value: &str) -> Self {
+        self.labels.push(LabelFilter {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    /// Adds a status an instance may have. Instances must have one of the statuses added.
+    pub fn status(mut self, status: Status) -> Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Adds a status an instance must not have.
+    pub fn exclude_status(mut self, status: Status) -> Self {
+        self.excluded_statuses.push(status);
+        self
+    }
+
+    /// Builds a Compute Engine `filter` expression for the parts of the filter the API can
+    /// apply.
+    ///
+    /// Filters that can't be translated are left out: labels with characters labels can't
+    /// contain, and a choice between several statuses, which would need `OR` and with it an
+    /// explicit `AND` between all expressions. The result must therefore still be checked
+    /// with `matches`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - The expression, e.g. `(labels.env = "prod") (status != "RUNNING")`.
+    /// * `Ok(None)` - If no part of the filter can be applied on the server.
+    /// * `Err(String)` - Why the filter can't be expressed, as for `Filter::expression`.
+    pub fn expression(&self) -> Result<Option<String>, String> {
+        self.to_filter()
+            .map(|filter| filter.expression())
+            .transpose()
+    }
+
+    /// Returns the parts of the filter the API can apply as a `Filter`, as for `expression`.
+    pub fn to_filter(&self) -> Option<Filter> {
+        let mut filters = self
+            .labels
+            .iter()
+            .filter_map(LabelFilter::to_filter)
+            .collect::<Vec<_>>();
+        if let [status] = self.statuses.as_slice() {
+            filters.push(Filter::eq("status", status.as_str()));
+        }
+        filters.extend(
+            self.excluded_statuses
+                .iter()
+                .map(|status| Filter::ne("status", status.as_str())),
+        );
+        Filter::all(filters)
+    }
+
+    /// Checks whether an instance matches every part of the filter.
+    pub fn matches(&self, instance: &Instance) -> bool {
+        let has_status = |status: &Status| status.as_str() == instance.status;
+        self.labels.iter().all(|label| label.matches(instance))
+            && (self.statuses.is_empty() || self.statuses.iter().any(has_status))
+            && !self.excluded_statuses.iter().any(has_status)
+            && self
+                .resource_policies
+                .iter()
+                .all(|policy| instance.resource_policies.contains(policy))
+            && (self.machine_types.is_empty()
+                || self.machine_types.contains(&instance.machine_type))
+            && self.created_before.is_none_or(|before| {
+                instance
+                    .creation_timestamp
+                    .is_some_and(|created| created < before)
+            })
+            && self.created_after.is_none_or(|after| {
+                instance
+                    .creation_timestamp
+                    .is_some_and(|created| created > after)
+            })
+            && self.spot.is_none_or(|spot| instance.is_spot() == spot)
+            && (self.service_accounts.is_empty()
+                || self
+                    .service_accounts
+                    .iter()
+                    .any(|account| match account.as_str() {
+                        "default" => instance.uses_default_service_account(),
+                        email => instance.service_accounts.iter().any(|sa| sa == email),
+                    }))
+    }
+}
+
+/// Records the status of each instance by name, to spot changes between two listings.
+pub fn statuses(instances: &[Instance]) -> HashMap<String, String> {
+    instances
+        .iter()
+        .map(|inst| (inst.name.clone(), inst.status.clone()))
+        .collect()
+}
+
+/// Finds the instances whose status changed since a previous listing.
+///
+/// # Arguments
+///
+/// * `previous` - The statuses from the previous listing, from `statuses`.
+/// * `instances` - The current instances.
+///
+/// # Returns
+///
+/// Whether each instance's status changed, in the same order as `instances`. Instances that
+/// weren't in the previous listing don't count as changed.
+pub fn status_changes(previous: &HashMap<String, String>, instances: &[Instance]) -> Vec<bool> {
+    instances
+        .iter()
+        .map(|inst| {
+            previous
+                .get(&inst.name)
+                .is_some_and(|status| *status != inst.status)
+        })
+        .collect()
+}
+
+/// The fields instances can be grouped by, in table output or for `--summary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupBy {
+    /// The cell, from the instance's `cell` label.
+    Cell,
+    /// The zone.
+    Zone,
+    /// The machine type.
+    MachineType,
+    /// The status, e.g. `RUNNING`.
+    Status,
+    /// The value of a label, given as `labels.KEY` like in `--columns`.
+    Label(String),
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cell" => Ok(GroupBy::Cell),
+            "zone" => Ok(GroupBy::Zone),
+            "machine-type" => Ok(GroupBy::MachineType),
+            "status" => Ok(GroupBy::Status),
+            _ => match s.strip_prefix("labels.") {
+                Some(key) if !key.is_empty() => Ok(GroupBy::Label(key.to_string())),
+                _ => Err(format!(
+                    "expected cell, zone, machine-type, status or labels.KEY, got `{}`",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl GroupBy {
+    /// Returns the key of the field, as used for JSON objects and CSV headers, e.g.
+    /// `machine_type` or `labels.team`.
+    pub fn key(&self) -> String {
+        match self {
+            GroupBy::Cell => "cell".to_string(),
+            GroupBy::Zone => "zone".to_string(),
+            GroupBy::MachineType => "machine_type".to_string(),
+            GroupBy::Status => "status".to_string(),
+            GroupBy::Label(key) => format!("labels.{}", key),
+        }
+    }
+
+    /// Returns the title of the field for table headers, e.g. `Machine Type` or `team`.
+    pub fn title(&self) -> String {
+        match self {
+            GroupBy::Cell => "Cell".to_string(),
+            GroupBy::Zone => "Zone".to_string(),
+            GroupBy::MachineType => "Machine Type".to_string(),
+            GroupBy::Status => "Status".to_string(),
+            GroupBy::Label(key) => key.clone(),
+        }
+    }
+
+    /// Returns the value of the field for an instance, or `None` if the instance has no cell or
+    /// doesn't have the label.
+    pub fn value(&self, instance: &Instance) -> Option<String> {
+        match self {
+            GroupBy::Cell => instance.cell.clone(),
+            GroupBy::Zone => Some(instance.zone.clone()),
+            GroupBy::MachineType => Some(instance.machine_type.clone()),
+            GroupBy::Status => Some(instance.status.clone()),
+            GroupBy::Label(key) => instance
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(key))
+                .cloned(),
+        }
+    }
+}
+
+/// Groups instances by a field.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to group.
+/// * `group_by` - The field to group by.
+///
+/// # Returns
+///
+/// The groups sorted by value, with the instances without a value last. Instances keep their
+/// relative order within each group.
+pub fn group_by(
+    instances: Vec<Instance>,
+    group_by: &GroupBy,
+) -> Vec<(Option<String>, Vec<Instance>)> {
+    let mut groups = std::collections::BTreeMap::<Option<String>, Vec<Instance>>::new();
+    for instance in instances {
+        groups
+            .entry(group_by.value(&instance))
+            .or_default()
+            .push(instance);
+    }
+    // `None` sorts first in a map, but reads better last
+    let without_value = groups.remove(&None);
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    groups.extend(without_value.map(|instances| (None, instances)));
+    groups
+}
+
+/// Groups instances by cell.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to group.
+///
+/// # Returns
+///
+/// The groups sorted by cell name, with the instances without a cell last. Instances keep
+/// their relative order within each group.
+pub fn group_by_cell(instances: Vec<Instance>) -> Vec<(Option<String>, Vec<Instance>)> {
+    group_by(instances, &GroupBy::Cell)
+}
+
+/// Counts instances per value of a field, for `--summary`.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to count.
+/// * `group_by` - The field to count by.
+///
+/// # Returns
+///
+/// The number of instances per value, most common first, ties by value. The count of
+/// instances without a value comes last.
+pub fn count_by(instances: &[Instance], group_by: &GroupBy) -> Vec<(Option<String>, usize)> {
+    let mut counts = HashMap::<Option<String>, usize>::new();
+    for instance in instances {
+        *counts.entry(group_by.value(instance)).or_default() += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a, a_count), (b, b_count)| match (a, b) {
+        (Some(a), Some(b)) => b_count.cmp(a_count).then_with(|| a.cmp(b)),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    });
+    counts
+}
+
+/// The default for `max_results_warning`: how many instances a mutation may target before it
+/// needs explicit confirmation.
+pub const DEFAULT_MAX_RESULTS_WARNING: usize = 2000;
+
+/// Guards against a pattern accidentally matching a large part of the fleet before acting on
+/// every match.
+///
+/// # Arguments
+///
+/// * `count` - The number of instances the operation would act on.
+/// * `max` - The `max_results_warning` threshold from the config.
+/// * `confirmed` - Whether the user passed `--i-know-what-im-doing`.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the operation may go ahead.
+/// * `Err(String)` - An explanation if the count exceeds the threshold without confirmation.
+pub fn check_result_budget(count: usize, max: usize, confirmed: bool) -> Result<(), String> {
+    match count > max && !confirmed {
+        true => Err(format!(
+            "The pattern matches {} instances, more than max_results_warning ({}). \
+             Pass --i-know-what-im-doing if this is intended",
+            count, max
+        )),
+        false => Ok(()),
+    }
+}
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// It is not suitable for anything security related, but unlike a library RNG its output for a
+/// given seed is guaranteed not to change between releases, so `--seed` reproduces the same
+/// sample.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `0..bound`. The modulo bias is negligible for fleet-sized bounds.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// Picks a random subset of instances.
+///
+/// The sampled instances keep their relative order from the input.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to sample from.
+/// * `count` - The number of instances to pick. If there are fewer instances, all are returned.
+/// * `seed` - A seed for a deterministic sample. If `None`, the current time is used.
+pub fn sample(instances: Vec<Instance>, count: usize, seed: Option<u64>) -> Vec<Instance> {
+    if count >= instances.len() {
+        return instances;
+    }
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut rng = SplitMix64(seed);
+
+    // Partial Fisher-Yates shuffle of the indices, then restore the original order
+    let mut indices = (0..instances.len()).collect::<Vec<_>>();
+    for i in 0..count {
+        let j = i + rng.below(indices.len() - i);
+        indices.swap(i, j);
+    }
+    let mut picked = indices[..count].to_vec();
+    picked.sort_unstable();
+
+    let mut instances = instances.into_iter().map(Some).collect::<Vec<_>>();
+    picked
+        .into_iter()
+        .filter_map(|i| instances[i].take())
+        .collect()
+}
+
+/// The fields instances can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    /// The instance name.
+    Name,
+    /// The primary internal IP, compared numerically.
+    Ip,
+    /// The zone.
+    Zone,
+    /// The status, e.g. `RUNNING`.
+    Status,
+    /// The machine type.
+    MachineType,
+    /// The cell.
+    Cell,
+}
+
+/// Sorts instances by a field, breaking ties by name. Instances without a value for the field
+/// (e.g. no IP or cell) come last, also when reversed.
+///
+/// # Arguments
+///
+/// * `instances` - The instances to sort.
+/// * `key` - The field to sort by.
+/// * `reverse` - Whether to sort in descending order.
+pub fn sort(instances: &mut [Instance], key: SortKey, reverse: bool) {
+    instances.sort_by(|a, b| match (sort_value(a, key), sort_value(b, key)) {
+        (Some(x), Some(y)) => {
+            let ordering = x.cmp(&y).then_with(|| a.name.cmp(&b.name));
+            match reverse {
+                true => ordering.reverse(),
+                false => ordering,
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.name.cmp(&b.name),
+    });
+}
+
+/// A value instances are sorted by.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue<'a> {
+    Text(&'a str),
+    /// IPs compare numerically, so that e.g. `10.0.0.9` sorts before `10.0.0.10`.
+    Ip(IpAddr),
+}
+
+/// Returns the value of an instance for a sort key, if it has one.
+fn sort_value(instance: &Instance, key: SortKey) -> Option<SortValue<'_>> {
+    match key {
+        SortKey::Name => Some(SortValue::Text(&instance.name)),
+        SortKey::Ip => instance
+            .primary_ip()
+            .and_then(|ip| ip.parse().ok())
+            .map(SortValue::Ip),
+        SortKey::Zone => Some(SortValue::Text(&instance.zone)),
+        SortKey::Status => Some(SortValue::Text(&instance.status)),
+        SortKey::MachineType => Some(SortValue::Text(&instance.machine_type)),
+        SortKey::Cell => instance.cell.as_deref().map(SortValue::Text),
+    }
+}
+
+/// Parses an age such as `30d` into a duration. The units are `m` (minutes), `h` (hours),
+/// `d` (days) and `w` (weeks).
+///
+/// # Arguments
+///
+/// * `s` - A whole number followed by a unit, e.g. `30d` or `12h`. Ages reaching back before
+///   the earliest time chrono can represent are rejected.
+pub fn parse_age(s: &str) -> Result<chrono::Duration, String> {
+    let invalid = || {
+        format!(
+            "expected a number and a unit (m, h, d or w), e.g. 30d, got `{}`",
+            s
+        )
+    };
+    let split = s.len().saturating_sub(1);
+    let (number, unit) = (s.get(..split).ok_or_else(invalid)?, &s[split..]);
+    let number = number.parse::<i64>().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => chrono::Duration::try_minutes(number),
+        "h" => chrono::Duration::try_hours(number),
+        "d" => chrono::Duration::try_days(number),
+        "w" => chrono::Duration::try_weeks(number),
+        _ => None,
+    };
+    let duration = duration
+        .filter(|duration| *duration >= chrono::Duration::zero())
+        .ok_or_else(invalid)?;
+    match chrono::Utc::now().checked_sub_signed(duration) {
+        Some(_) => Ok(duration),
+        None => Err(format!("`{}` reaches back too far", s)),
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instances(count: usize) -> Vec<Instance> {
+        (0..count)
+            .map(|i| Instance {
+                name: format!("instance-{}", i),
+                zone: "us-east1-b".to_string(),
+                status: "RUNNING".to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn names(instances: &[Instance]) -> Vec<&str> {
+        instances.iter().map(|i| i.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        let inst = &instances(1)[0];
+        assert!(matches_pattern(inst, None));
+        assert!(matches_pattern(inst, Some("stance-")));
+        assert!(!matches_pattern(inst, Some("store-lb")));
+    }
+
+    #[test]
+    fn test_sample_is_deterministic_with_seed() {
+        let first = sample(instances(100), 5, Some(42));
+        let second = sample(instances(100), 5, Some(42));
+        assert_eq!(first.len(), 5);
+        assert_eq!(names(&first), names(&second));
+
+        // The sample keeps the input order and has no duplicates
+        let mut sorted = names(&first);
+        sorted.sort_by_key(|name| name[9..].parse::<usize>().unwrap());
+        sorted.dedup();
+        assert_eq!(sorted, names(&first));
+    }
+
+    #[test]
+    fn test_sample_larger_than_input_returns_everything() {
+        let picked = sample(instances(3), 10, None);
+        assert_eq!(
+            names(&picked),
+            vec!["instance-0", "instance-1", "instance-2"]
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut instances = instances(4);
+        let ips = [Some("10.0.0.10"), Some("10.0.0.9"), None, Some("10.0.1.1")];
+        for (inst, ip) in instances.iter_mut().zip(ips) {
+            inst.network_interfaces = vec![crate::compute::NetworkInterface {
+                ip: ip.map(str::to_string),
+                ..Default::default()
+            }];
+        }
+
+        sort(&mut instances, SortKey::Ip, false);
+        assert_eq!(
+            names(&instances),
+            vec!["instance-1", "instance-0", "instance-3", "instance-2"]
+        );
+        // Instances without an IP stay last when reversed
+        sort(&mut instances, SortKey::Ip, true);
+        assert_eq!(
+            names(&instances),
+            vec!["instance-3", "instance-0", "instance-1", "instance-2"]
+        );
+        // Ties are broken by name
+        sort(&mut instances, SortKey::Zone, false);
+        assert_eq!(
+            names(&instances),
+            vec!["instance-0", "instance-1", "instance-2", "instance-3"]
+        );
+    }
+
+    #[test]
+    fn test_label_filter() {
+        assert_eq!(
+            "env=prod".parse::<LabelFilter>().unwrap(),
+            LabelFilter {
+                key: "env".to_string(),
+                value: "prod".to_string()
+            }
+        );
+        assert_eq!("env=".parse::<LabelFilter>().unwrap().value, "");
+        assert!("env".parse::<LabelFilter>().is_err());
+        assert!("=prod".parse::<LabelFilter>().is_err());
+
+        let mut inst = instances(1).remove(0);
+        let env_prod = "env=prod".parse::<LabelFilter>().unwrap();
+        let team = "team=storage".parse::<LabelFilter>().unwrap();
+        assert!(!env_prod.matches(&inst));
+        inst.labels = Some([("env".to_string(), "prod".to_string())].into());
+        assert!(env_prod.matches(&inst));
+        assert!(!team.matches(&inst));
+    }
+
+    #[test]
+    fn test_filter_expression() {
+        let labels = ["env=prod", "team=storage", "bad=\"quoted\""]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<LabelFilter>>();
+        let filter = InstanceFilter {
+            labels: labels.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.expression(),
+            Ok(Some(
+                r#"(labels.env = "prod") (labels.team = "storage")"#.to_string()
+            ))
+        );
+        let filter = InstanceFilter {
+            labels: labels[2..].to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(filter.expression(), Ok(None));
+        assert_eq!(InstanceFilter::default().expression(), Ok(None));
+        // Label keys with non-ASCII letters are only checked locally
+        let filter = InstanceFilter {
+            labels: vec!["café=x".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(filter.expression(), Ok(None));
+
+        let filter = InstanceFilter {
+            statuses: vec![Status::Terminated],
+            excluded_statuses: vec![Status::Running],
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.expression(),
+            Ok(Some(
+                r#"(status = "TERMINATED") (status != "RUNNING")"#.to_string()
+            ))
+        );
+        // A choice between statuses is only checked locally
+        let filter = InstanceFilter {
+            statuses: vec![Status::Stopping, Status::Terminated],
+            ..Default::default()
+        };
+        assert_eq!(filter.expression(), Ok(None));
+    }
+
+    #[test]
+    fn test_instance_filter_matches() {
+        let mut inst = instances(1).remove(0);
+        assert!(InstanceFilter::default().matches(&inst));
+
+        let not_running = InstanceFilter {
+            excluded_statuses: vec![Status::Running],
+            ..Default::default()
+        };
+        assert!(!not_running.matches(&inst));
+        inst.status = "TERMINATED".to_string();
+        assert!(not_running.matches(&inst));
+
+        let stopping_or_terminated = InstanceFilter {
+            statuses: vec![Status::Stopping, Status::Terminated],
+            labels: vec!["env=prod".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(!stopping_or_terminated.matches(&inst));
+        inst.labels = Some([("env".to_string(), "prod".to_string())].into());
+        assert!(stopping_or_terminated.matches(&inst));
+
+        let scheduled = InstanceFilter {
+            resource_policies: vec!["nightly-stop".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(scheduled.expression(), Ok(None));
+        assert!(!scheduled.matches(&inst));
+        inst.resource_policies = vec!["backups".to_string(), "nightly-stop".to_string()];
+        assert!(scheduled.matches(&inst));
+
+        let machine_types = InstanceFilter {
+            machine_types: vec!["n2-standard-8".to_string(), "e2-small".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(machine_types.expression(), Ok(None));
+        assert!(!machine_types.matches(&inst));
+        inst.machine_type = "e2-small".to_string();
+        assert!(machine_types.matches(&inst));
+
+        let now = Utc::now();
+        let old = InstanceFilter {
+            created_before: Some(now - chrono::Duration::days(30)),
+            ..Default::default()
+        };
+        let new = InstanceFilter {
+            created_after: Some(now - chrono::Duration::days(30)),
+            ..Default::default()
+        };
+        assert!(!old.matches(&inst) && !new.matches(&inst));
+        inst.creation_timestamp = Some(now - chrono::Duration::days(40));
+        assert!(old.matches(&inst) && !new.matches(&inst));
+        inst.creation_timestamp = Some(now - chrono::Duration::days(2));
+        assert!(!old.matches(&inst) && new.matches(&inst));
+
+        let spot_only = InstanceFilter {
+            spot: Some(true),
+            ..Default::default()
+        };
+        assert!(!spot_only.matches(&inst));
+        inst.provisioning_model = Some("SPOT".to_string());
+        assert!(spot_only.matches(&inst));
+
+        let default_account = InstanceFilter {
+            service_accounts: vec!["default".to_string()],
+            ..Default::default()
+        };
+        let app_account = InstanceFilter {
+            service_accounts: vec!["app@p.iam.gserviceaccount.com".to_string()],
+            ..Default::default()
+        };
+        assert!(!default_account.matches(&inst) && !app_account.matches(&inst));
+        inst.service_accounts = vec!["123-compute@developer.gserviceaccount.com".to_string()];
+        assert!(default_account.matches(&inst) && !app_account.matches(&inst));
+        inst.service_accounts = vec!["app@p.iam.gserviceaccount.com".to_string()];
+        assert!(!default_account.matches(&inst) && app_account.matches(&inst));
+    }
+
+    #[test]
+    fn test_status_changes() {
+        let mut instances = instances(3);
+        let previous = statuses(&instances[..2]);
+        instances[1].status = "STOPPING".to_string();
+        instances[2].status = "STOPPING".to_string();
+        assert_eq!(
+            status_changes(&previous, &instances),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_check_result_budget() {
+        assert!(check_result_budget(2000, 2000, false).is_ok());
+        assert!(check_result_budget(5, 2000, false).is_ok());
+        let err = check_result_budget(2001, 2000, false).unwrap_err();
+        assert!(err.starts_with("The pattern matches 2001 instances"));
+        assert!(check_result_budget(2001, 2000, true).is_ok());
+    }
+
+    #[test]
+    fn test_group_by_cell() {
+        let mut instances = instances(4);
+        let cells = [Some("b"), None, Some("a"), Some("b")];
+        for (inst, cell) in instances.iter_mut().zip(cells) {
+            inst.cell = cell.map(str::to_string);
+        }
+
+        let groups = group_by_cell(instances);
+        let summary = groups
+            .iter()
+            .map(|(cell, instances)| (cell.as_deref(), names(instances)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("a"), vec!["instance-2"]),
+                (Some("b"), vec!["instance-0", "instance-3"]),
+                (None, vec!["instance-1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        assert_eq!("machine-type".parse(), Ok(GroupBy::MachineType));
+        assert_eq!(
+            "labels.team".parse(),
+            Ok(GroupBy::Label("team".to_string()))
+        );
+        assert!("labels.".parse::<GroupBy>().is_err());
+        assert!("name".parse::<GroupBy>().is_err());
+    }
+
+    #[test]
+    fn test_count_by() {
+        let mut instances = instances(5);
+        let types = [
+            "e2-medium",
+            "n2-standard-16",
+            "e2-medium",
+            "n2-standard-16",
+            "c3",
+        ];
+        for (inst, machine_type) in instances.iter_mut().zip(types) {
+            inst.machine_type = machine_type.to_string();
+        }
+        instances[4].labels = Some([("team".to_string(), "db".to_string())].into());
+
+        assert_eq!(
+            count_by(&instances, &GroupBy::MachineType),
+            vec![
+                (Some("e2-medium".to_string()), 2),
+                (Some("n2-standard-16".to_string()), 2),
+                (Some("c3".to_string()), 1),
+            ]
+        );
+        assert_eq!(
+            count_by(&instances, &GroupBy::Label("team".to_string())),
+            vec![(Some("db".to_string()), 1), (None, 4)]
+        );
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d"), Ok(chrono::Duration::days(30)));
+        assert_eq!(parse_age("12h"), Ok(chrono::Duration::hours(12)));
+        assert_eq!(parse_age("2w"), Ok(chrono::Duration::weeks(2)));
+        assert_eq!(parse_age("90m"), Ok(chrono::Duration::minutes(90)));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("-1d").is_err());
+        assert!(parse_age("1y").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("日").is_err());
+        // Representable as a duration, but not as a time that long ago
+        assert_eq!(
+            parse_age("20000000w"),
+            Err("`20000000w` reaches back too far".to_string())
+        );
+    }
+}
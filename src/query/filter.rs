@@ -0,0 +1,379 @@
+//! This module builds Compute Engine `filter` expressions, so that values are always quoted
+//! and escaped the same way and combinations follow the filter grammar.
+//! <https://cloud.google.com/compute/docs/reference/rest/v1/instances/aggregatedList#body.QUERY_PARAMETERS.filter>
+
+use super::Status;
+use std::fmt;
+
+/// A value a field is compared with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    /// A string, quoted in the expression.
+    String(String),
+    /// A boolean, e.g. for `scheduling.preemptible`.
+    Bool(bool),
+    /// A number, e.g. for `cpuCount`.
+    Number(i64),
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::String(value.to_string())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::String(value)
+    }
+}
+
+impl From<bool> for Literal {
+    fn from(value: bool) -> Self {
+        Literal::Bool(value)
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(value: i64) -> Self {
+        Literal::Number(value)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::String(value) => {
+                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "\"{}\"", escaped)
+            }
+            Literal::Bool(value) => write!(f, "{}", value),
+            Literal::Number(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// How a field is compared with a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::Greater => ">",
+        }
+    }
+}
+
+/// A Compute Engine filter, e.g. `Filter::label_eq("env", "prod").and(Filter::ne("status",
+/// "RUNNING"))`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter(Node);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Compare {
+        field: String,
+        comparison: Comparison,
+        value: Literal,
+    },
+    /// A regular expression the whole field must match, in the older `eq` syntax.
+    Matches { field: String, regex: String },
+    /// Expressions that must all hold. Never empty.
+    All(Vec<Node>),
+    /// Expressions of which one must hold. Never empty.
+    Any(Vec<Node>),
+}
+
+impl Filter {
+    /// Compares a field with a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field, e.g. `status` or `scheduling.preemptible`.
+    /// * `comparison` - How to compare.
+    /// * `value` - The value, e.g. `"RUNNING"` or `true`.
+    pub fn compare(field: &str, comparison: Comparison, value: impl Into<Literal>) -> Self {
+        Filter(Node::Compare {
+            field: field.to_string(),
+            comparison,
+            value: value.into(),
+        })
+    }
+
+    /// Requires a field to equal a value, e.g. `status = "RUNNING"`.
+    pub fn eq(field: &str, value: impl Into<Literal>) -> Self {
+        Filter::compare(field, Comparison::Equal, value)
+    }
+
+    /// Requires a field to differ from a value, e.g. `status != "RUNNING"`.
+    pub fn ne(field: &str, value: impl Into<Literal>) -> Self {
+        Filter::compare(field, Comparison::NotEqual, value)
+    }
+
+    /// Requires a label to have a value, e.g. `labels.env = "prod"`.
+    pub fn label_eq(key: &str, value: &str) -> Self {
+        Filter::eq(&format!("labels.{}", key), value)
+    }
+
+    /// Requires the status to be one of several, e.g. `(status = "STOPPING") OR (status =
+    /// "TERMINATED")`.
+    ///
+    /// # Returns
+    ///
+    /// The filter, or `None` if no status is given.
+    pub fn status_in(statuses: &[Status]) -> Option<Self> {
+        Filter::any(
+            statuses
+                .iter()
+                .map(|status| Filter::eq("status", status.as_str())),
+        )
+    }
+
+    /// Requires the name to contain some text, in the `eq` syntax, e.g.
+    /// `name eq ".*store\.lb.*"`. Such a filter can't be combined with others.
+    pub fn name_contains(text: &str) -> Self {
+        let escaped = text
+            .chars()
+            .map(|c| match c {
+                c if "\\.+*?()|[]{}^$".contains(c) => format!("\\{}", c),
+                c => c.to_string(),
+            })
+            .collect::<String>();
+        Filter::name_matches(&format!(".*{}.*", escaped))
+    }
+
+    /// Requires the whole name to match a regular expression, in the `eq` syntax, e.g.
+    /// `name eq "store-lb-.*"`. Such a filter can't be combined with others.
+    pub fn name_matches(regex: &str) -> Self {
+        Filter(Node::Matches {
+            field: "name".to_string(),
+            regex: regex.to_string(),
+        })
+    }
+
+    /// Requires every filter to hold.
+    ///
+    /// # Returns
+    ///
+    /// The filter, or `None` if no filter is given.
+    pub fn all(filters: impl IntoIterator<Item = Filter>) -> Option<Self> {
+        let nodes = filters
+            .into_iter()
+            .map(|filter| filter.0)
+            .collect::<Vec<_>>();
+        (!nodes.is_empty()).then_some(Filter(Node::All(nodes)))
+    }
+
+    /// Requires one of the filters to hold. A single filter is returned as it is.
+    ///
+    /// # Returns
+    ///
+    /// The filter, or `None` if no filter is given.
+    pub fn any(filters: impl IntoIterator<Item = Filter>) -> Option<Self> {
+        let mut nodes = filters
+            .into_iter()
+            .map(|filter| filter.0)
+            .collect::<Vec<_>>();
+        match nodes.len() {
+            0 => None,
+            1 => nodes.pop().map(Filter),
+            _ => Some(Filter(Node::Any(nodes))),
+        }
+    }
+
+    /// Requires this filter and another to hold.
+    pub fn and(self, other: Filter) -> Self {
+        let mut nodes = match self.0 {
+            Node::All(nodes) => nodes,
+            node => vec![node],
+        };
+        match other.0 {
+            Node::All(others) => nodes.extend(others),
+            node => nodes.push(node),
+        }
+        Filter(Node::All(nodes))
+    }
+
+    /// Requires this filter or another to hold.
+    pub fn or(self, other: Filter) -> Self {
+        let mut nodes = match self.0 {
+            Node::Any(nodes) => nodes,
+            node => vec![node],
+        };
+        match other.0 {
+            Node::Any(others) => nodes.extend(others),
+            node => nodes.push(node),
+        }
+        Filter(Node::Any(nodes))
+    }
+
+    /// Serializes the filter as the value of the API's `filter` parameter.
+    ///
+    /// Expressions that must all hold are separated by spaces, unless the filter also has a
+    /// choice, as `OR` can't be mixed with that implicit `AND`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The expression, e.g. `(labels.env = "prod") (status != "RUNNING")`.
+    /// * `Err(String)` - Why the filter can't be expressed: a field name that would need
+    ///   quoting, a regular expression with a quote, or an `eq` filter combined with others.
+    pub fn expression(&self) -> Result<String, String> {
+        if self.0.has_regex() && self.0.leaves() > 1 {
+            return Err("A name regex filter can't be combined with other filters".to_string());
+        }
+        self.0.render(self.0.has_choice())
+    }
+}
+
+impl Node {
+    fn has_regex(&self) -> bool {
+        match self {
+            Node::Compare { .. } => false,
+            Node::Matches { .. } => true,
+            Node::All(nodes) | Node::Any(nodes) => nodes.iter().any(Node::has_regex),
+        }
+    }
+
+    fn leaves(&self) -> usize {
+        match self {
+            Node::Compare { .. } | Node::Matches { .. } => 1,
+            Node::All(nodes) | Node::Any(nodes) => nodes.iter().map(Node::leaves).sum(),
+        }
+    }
+
+    fn has_choice(&self) -> bool {
+        match self {
+            Node::Compare { .. } | Node::Matches { .. } => false,
+            Node::Any(_) => true,
+            Node::All(nodes) => nodes.iter().any(Node::has_choice),
+        }
+    }
+
+    /// Renders the node, joining `All` with an explicit `AND` if `explicit_and` is set.
+    fn render(&self, explicit_and: bool) -> Result<String, String> {
+        let field_name = |field: &str| match field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        {
+            true => Ok(field.to_string()),
+            false => Err(format!("Invalid field name in filter: {}", field)),
+        };
+        let join = |nodes: &[Node], separator: &str| {
+            nodes
+                .iter()
+                .map(|node| node.render(explicit_and).map(|s| format!("({})", s)))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|parts| parts.join(separator))
+        };
+        match self {
+            Node::Compare {
+                field,
+                comparison,
+                value,
+            } => Ok(format!(
+                "{} {} {}",
+                field_name(field)?,
+                comparison.as_str(),
+                value
+            )),
+            // The `eq` syntax takes the expression as it is, with no escapes of its own
+            Node::Matches { field, regex } if !regex.contains('"') => {
+                Ok(format!("{} eq \"{}\"", field_name(field)?, regex))
+            }
+            Node::Matches { regex, .. } => {
+                Err(format!("A name regex can't contain quotes: {}", regex))
+            }
+            Node::All(nodes) => join(nodes, if explicit_and { " AND " } else { " " }),
+            Node::Any(nodes) => join(nodes, " OR "),
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(
+            Filter::eq("status", "RUNNING").expression().unwrap(),
+            r#"status = "RUNNING""#
+        );
+        assert_eq!(
+            Filter::eq("scheduling.preemptible", true)
+                .expression()
+                .unwrap(),
+            "scheduling.preemptible = true"
+        );
+        assert_eq!(
+            Filter::compare("cpuCount", Comparison::Greater, 8)
+                .expression()
+                .unwrap(),
+            "cpuCount > 8"
+        );
+    }
+
+    #[test]
+    fn test_values_are_escaped() {
+        assert_eq!(
+            Filter::label_eq("note", r#"say "hi" \o/"#)
+                .expression()
+                .unwrap(),
+            r#"labels.note = "say \"hi\" \\o/""#
+        );
+        assert!(Filter::label_eq("bad key", "x").expression().is_err());
+        assert!(Filter::eq("name) OR (name", "x").expression().is_err());
+    }
+
+    #[test]
+    fn test_combinations() {
+        let filter = Filter::label_eq("env", "prod").and(Filter::ne("status", "RUNNING"));
+        assert_eq!(
+            filter.expression().unwrap(),
+            r#"(labels.env = "prod") (status != "RUNNING")"#
+        );
+
+        let statuses = Filter::status_in(&[Status::Stopping, Status::Terminated]).unwrap();
+        assert_eq!(
+            statuses.expression().unwrap(),
+            r#"(status = "STOPPING") OR (status = "TERMINATED")"#
+        );
+        // A choice within a conjunction needs the explicit AND
+        assert_eq!(
+            Filter::label_eq("env", "prod")
+                .and(statuses)
+                .expression()
+                .unwrap(),
+            r#"(labels.env = "prod") AND ((status = "STOPPING") OR (status = "TERMINATED"))"#
+        );
+        assert_eq!(
+            Filter::status_in(&[Status::Running]).unwrap(),
+            Filter::eq("status", "RUNNING")
+        );
+        assert_eq!(Filter::status_in(&[]), None);
+        assert_eq!(Filter::all([]), None);
+    }
+
+    #[test]
+    fn test_name_regex() {
+        assert_eq!(
+            Filter::name_contains("store.lb").expression().unwrap(),
+            r#"name eq ".*store\.lb.*""#
+        );
+        assert!(Filter::name_matches(r#"a"b"#).expression().is_err());
+        let alone = Filter::all([Filter::name_matches("lb-.*")]).unwrap();
+        assert_eq!(alone.expression().unwrap(), r#"(name eq "lb-.*")"#);
+        let combined = Filter::name_contains("lb").and(Filter::eq("status", "RUNNING"));
+        assert!(combined.expression().is_err());
+    }
+}
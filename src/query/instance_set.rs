@@ -0,0 +1,379 @@
+//! This module provides `InstanceSet`, a listing of instances indexed by name, label, zone,
+//! status and IP address, so that several filters can be applied to a large listing without
+//! scanning every instance for each of them.
+//!
+//! Queries return a `Selection` of instances, which can be combined with `and`, `or` and
+//! `refine` before the instances are read out, always in the order of the listing.
+
+use super::{InstanceFilter, NamePattern};
+use crate::compute::Instance;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Instances with indices over the fields they are most often looked up by.
+#[derive(Debug, Clone, Default)]
+pub struct InstanceSet {
+    /// The instances, in the order of the listing.
+    instances: Vec<Instance>,
+    /// The positions of the instances with each name. Names are only unique within a zone.
+    by_name: HashMap<String, Vec<usize>>,
+    /// The positions of the instances with each label, keyed by label key and value.
+    by_label: HashMap<(String, String), Vec<usize>>,
+    /// The positions of the instances in each zone.
+    by_zone: HashMap<String, Vec<usize>>,
+    /// The positions of the instances with each status, e.g. `RUNNING`.
+    by_status: HashMap<String, Vec<usize>>,
+    /// The positions of the instances with each internal or external IP address.
+    by_ip: HashMap<IpAddr, Vec<usize>>,
+}
+
+/// Some of the instances of an `InstanceSet`, as their positions in the listing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// The positions, ascending and without duplicates.
+    indices: Vec<usize>,
+}
+
+impl InstanceSet {
+    /// Indexes a listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `instances` - The instances, e.g. a cached listing of a project.
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let mut set = InstanceSet::default();
+        for (i, instance) in instances.iter().enumerate() {
+            set.by_name
+                .entry(instance.name.clone())
+                .or_default()
+                .push(i);
+            for (key, value) in instance.labels.iter().flatten() {
+                set.by_label
+                    .entry((key.clone(), value.clone()))
+                    .or_default()
+                    .push(i);
+            }
+            set.by_zone
+                .entry(instance.zone.clone())
+                .or_default()
+                .push(i);
+            set.by_status
+                .entry(instance.status.clone())
+                .or_default()
+                .push(i);
+            let ips = instance.ips().into_iter().chain(instance.external_ips());
+            for ip in ips.filter_map(|ip| ip.parse::<IpAddr>().ok()) {
+                let positions = set.by_ip.entry(ip).or_default();
+                // An address may be both internal and external, e.g. in tests
+                if positions.last() != Some(&i) {
+                    positions.push(i);
+                }
+            }
+        }
+        set.instances = instances;
+        set
+    }
+
+    /// Returns the number of instances.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns whether there are no instances.
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Selects every instance.
+    pub fn all(&self) -> Selection {
+        Selection {
+            indices: (0..self.instances.len()).collect(),
+        }
+    }
+
+    /// Selects the instances with a name, in any zone.
+    pub fn name(&self, name: &str) -> Selection {
+        Selection::from_index(self.by_name.get(name))
+    }
+
+    /// Selects the instances with a label set to a value.
+    pub fn label(&self, key: &str, value: &str) -> Selection {
+        Selection::from_index(self.by_label.get(&(key.to_string(), value.to_string())))
+    }
+
+    /// Selects the instances in a zone, e.g. `us-east1-b`.
+    pub fn zone(&self, zone: &str) -> Selection {
+        Selection::from_index(self.by_zone.get(zone))
+    }
+
+    /// Selects the instances with a status as the API reports it, e.g. `RUNNING`.
+    pub fn status(&self, status: &str) -> Selection {
+        Selection::from_index(self.by_status.get(status))
+    }
+
+    /// Selects the instances with an internal or external IP address on any interface.
+    pub fn ip(&self, ip: IpAddr) -> Selection {
+        Selection::from_index(self.by_ip.get(&ip))
+    }
+
+    /// Narrows a selection to the instances a predicate holds for, checking only the selected
+    /// instances. This is how conditions without an index are applied, e.g. a name pattern.
+    pub fn refine(&self, selection: Selection, predicate: impl Fn(&Instance) -> bool) -> Selection {
+        Selection {
+            indices: selection
+                .indices
+                .into_iter()
+                .filter(|&i| predicate(&self.instances[i]))
+                .collect(),
+        }
+    }
+
+    /// Selects the instances matching a filter and a name pattern, as the `bcls` command
+    /// filters a listing.
+    ///
+    /// Labels and statuses are looked up in the indices, and only the instances found there
+    /// are checked against the rest of the filter and the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter, e.g. from `--label` and `--status`.
+    /// * `pattern` - The pattern the names must match. `None` matches every name.
+    pub fn matching(&self, filter: &InstanceFilter, pattern: Option<&NamePattern>) -> Selection {
+        let mut selection = match filter.statuses.as_slice() {
+            [] => self.all(),
+            statuses => statuses
+                .iter()
+                .map(|status| self.status(status.as_str()))
+                .fold(Selection::default(), |union, status| union.or(&status)),
+        };
+        for label in &filter.labels {
+            selection = selection.and(&self.label(&label.key, &label.value));
+        }
+        self.refine(selection, |instance| {
+            pattern.is_none_or(|pattern| pattern.matches(&instance.name))
+                && filter.matches(instance)
+        })
+    }
+
+    /// Returns the selected instances, in the order of the listing.
+    pub fn get<'a>(&'a self, selection: &'a Selection) -> impl Iterator<Item = &'a Instance> + 'a {
+        selection.indices.iter().map(|&i| &self.instances[i])
+    }
+
+    /// Takes the selected instances out of the set, in the order of the listing.
+    pub fn into_selected(self, selection: &Selection) -> Vec<Instance> {
+        let mut selected = vec![false; self.instances.len()];
+        for &i in &selection.indices {
+            selected[i] = true;
+        }
+        self.instances
+            .into_iter()
+            .zip(selected)
+            .filter_map(|(instance, selected)| selected.then_some(instance))
+            .collect()
+    }
+}
+
+impl Selection {
+    /// Creates a selection from the positions stored in an index, if the key was found.
+    fn from_index(indices: Option<&Vec<usize>>) -> Self {
+        Selection {
+            indices: indices.cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the number of selected instances.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns whether no instance is selected.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Selects the instances in both selections.
+    pub fn and(&self, other: &Selection) -> Selection {
+        let (mut a, mut b) = (
+            self.indices.iter().peekable(),
+            other.indices.iter().peekable(),
+        );
+        let mut indices = vec![];
+        while let (Some(&&x), Some(&&y)) = (a.peek(), b.peek()) {
+            match x.cmp(&y) {
+                std::cmp::Ordering::Less => {
+                    a.next();
+                }
+                std::cmp::Ordering::Greater => {
+                    b.next();
+                }
+                std::cmp::Ordering::Equal => {
+                    indices.push(x);
+                    a.next();
+                    b.next();
+                }
+            }
+        }
+        Selection { indices }
+    }
+
+    /// Selects the instances in either selection.
+    pub fn or(&self, other: &Selection) -> Selection {
+        let mut indices = [self.indices.as_slice(), other.indices.as_slice()].concat();
+        indices.sort_unstable();
+        indices.dedup();
+        Selection { indices }
+    }
+
+    /// Selects the instances of a set that aren't in this selection.
+    pub fn not(&self, set: &InstanceSet) -> Selection {
+        set.all().minus(self)
+    }
+
+    /// Selects the instances in this selection that aren't in another.
+    pub fn minus(&self, other: &Selection) -> Selection {
+        Selection {
+            indices: self
+                .indices
+                .iter()
+                .copied()
+                .filter(|i| other.indices.binary_search(i).is_err())
+                .collect(),
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::NetworkInterface;
+    use crate::query::Status;
+
+    fn instance(
+        name: &str,
+        zone: &str,
+        status: &str,
+        labels: &[(&str, &str)],
+        ip: &str,
+    ) -> Instance {
+        Instance {
+            name: name.to_string(),
+            zone: zone.to_string(),
+            status: status.to_string(),
+            labels: Some(
+                labels
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            ),
+            network_interfaces: vec![NetworkInterface {
+                ip: Some(ip.to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn set() -> InstanceSet {
+        InstanceSet::new(vec![
+            instance(
+                "web-1",
+                "us-east1-b",
+                "RUNNING",
+                &[("team", "web")],
+                "10.0.0.1",
+            ),
+            instance(
+                "db-1",
+                "us-east1-b",
+                "RUNNING",
+                &[("team", "data")],
+                "10.0.0.2",
+            ),
+            instance(
+                "web-2",
+                "us-east1-c",
+                "TERMINATED",
+                &[("team", "web")],
+                "10.0.0.3",
+            ),
+            instance(
+                "web-1",
+                "us-east1-c",
+                "RUNNING",
+                &[("team", "web")],
+                "10.0.0.4",
+            ),
+        ])
+    }
+
+    fn names<'a>(set: &'a InstanceSet, selection: &'a Selection) -> Vec<(&'a str, &'a str)> {
+        set.get(selection)
+            .map(|instance| (instance.name.as_str(), instance.zone.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lookups() {
+        let set = set();
+        assert_eq!(set.len(), 4);
+        assert_eq!(
+            names(&set, &set.name("web-1")),
+            [("web-1", "us-east1-b"), ("web-1", "us-east1-c")]
+        );
+        assert_eq!(set.label("team", "web").len(), 3);
+        assert_eq!(set.zone("us-east1-b").len(), 2);
+        assert_eq!(set.status("TERMINATED").len(), 1);
+        assert_eq!(
+            names(&set, &set.ip("10.0.0.2".parse().unwrap())),
+            [("db-1", "us-east1-b")]
+        );
+        assert!(set.name("missing").is_empty());
+        assert!(set.label("team", "missing").is_empty());
+    }
+
+    #[test]
+    fn test_combinators() {
+        let set = set();
+        let web = set.label("team", "web");
+        let running = set.status("RUNNING");
+
+        assert_eq!(
+            names(&set, &web.and(&running)),
+            [("web-1", "us-east1-b"), ("web-1", "us-east1-c")]
+        );
+        assert_eq!(web.or(&running).len(), 4);
+        assert_eq!(names(&set, &running.not(&set)), [("web-2", "us-east1-c")]);
+        assert_eq!(
+            names(&set, &web.minus(&set.zone("us-east1-c"))),
+            [("web-1", "us-east1-b")]
+        );
+        let refined = set.refine(set.all(), |instance| instance.name.starts_with("db"));
+        assert_eq!(names(&set, &refined), [("db-1", "us-east1-b")]);
+    }
+
+    #[test]
+    fn test_matching() {
+        let set = set();
+        let filter = InstanceFilter::default()
+            .label("team", "web")
+            .status(Status::Running)
+            .status(Status::Terminated);
+        let pattern = NamePattern::plain("web-*");
+
+        // The same instances as a linear scan, in the same order
+        let selection = set.matching(&filter, Some(&pattern));
+        let all = set.all();
+        let expected = set
+            .get(&all)
+            .filter(|instance| pattern.matches(&instance.name) && filter.matches(instance))
+            .map(|instance| (instance.name.as_str(), instance.zone.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(names(&set, &selection), expected);
+        assert_eq!(selection.len(), 3);
+
+        let selection = set.matching(&InstanceFilter::default(), None);
+        assert_eq!(set.into_selected(&selection).len(), 4);
+    }
+}
@@ -0,0 +1,135 @@
+//! This module interprets the search pattern matched against instance names: a substring, a
+//! glob such as `store-lb-*`, or a regular expression with `--regex`.
+
+use super::regex::Regex;
+use super::Filter;
+
+/// A pattern an instance name must match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NamePattern {
+    /// The name must contain the text.
+    Substring(String),
+    /// The whole name must match, where `*` matches any text and `?` any one character.
+    Glob(String),
+    /// The name must match the regular expression somewhere.
+    Regex(Regex),
+}
+
+impl NamePattern {
+    /// Interprets a pattern given without `--regex`. Instance names can't contain `*` or `?`,
+    /// so a pattern with either is a glob, and any other is a substring.
+    pub fn plain(pattern: &str) -> Self {
+        match pattern.contains(['*', '?']) {
+            true => NamePattern::Glob(pattern.to_string()),
+            false => NamePattern::Substring(pattern.to_string()),
+        }
+    }
+
+    /// Interprets a pattern given with `--regex`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(NamePattern)` - The pattern.
+    /// * `Err(String)` - What is wrong with the regular expression.
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        Regex::new(pattern).map(NamePattern::Regex)
+    }
+
+    /// Checks whether an instance name matches the pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Substring(text) => name.contains(text.as_str()),
+            NamePattern::Glob(glob) => glob_matches(glob, name),
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Builds a Compute Engine `filter` expression for the pattern, so only matching instances
+    /// are fetched. Only globs are translated, into the `eq` syntax whose value is a regular
+    /// expression matching the whole name, e.g. `name eq "store-lb-.*"`. That syntax can't be
+    /// combined with other kinds of expressions, so callers only use it on its own.
+    ///
+    /// # Returns
+    ///
+    /// The expression, or `None` if the pattern is only matched locally.
+    pub fn expression(&self) -> Option<String> {
+        let NamePattern::Glob(glob) = self else {
+            return None;
+        };
+        let regex = glob
+            .chars()
+            .map(|c| match c {
+                '*' => Some(".*".to_string()),
+                '?' => Some(".".to_string()),
+                c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => Some(c.to_string()),
+                // Quotes and backslashes would need escaping twice over, so aren't sent
+                '"' | '\\' => None,
+                c if c.is_ascii_punctuation() => Some(format!("\\{}", c)),
+                _ => None,
+            })
+            .collect::<Option<String>>();
+        regex.and_then(|regex| Filter::name_matches(&regex).expression().ok())
+    }
+}
+
+/// Matches a whole name against a glob, where `*` matches any text and `?` any one character.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // matched[j] holds whether the glob so far matches the first j characters of the name
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for g in glob {
+        let previous = std::mem::replace(&mut matched, vec![false; name.len() + 1]);
+        for j in 0..=name.len() {
+            matched[j] = match g {
+                '*' => previous[j] || (j > 0 && matched[j - 1]),
+                '?' => j > 0 && previous[j - 1],
+                c => j > 0 && previous[j - 1] && name[j - 1] == c,
+            };
+        }
+    }
+    matched[name.len()]
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_patterns() {
+        let substring = NamePattern::plain("lb");
+        assert!(substring.matches("store-lb-1"));
+        assert_eq!(substring.expression(), None);
+
+        let glob = NamePattern::plain("store-lb-*");
+        assert!(glob.matches("store-lb-1"));
+        assert!(glob.matches("store-lb-"));
+        // Globs match the whole name
+        assert!(!glob.matches("old-store-lb-1"));
+        assert!(NamePattern::plain("*-lb-?").matches("store-lb-2"));
+        assert!(!NamePattern::plain("*-lb-?").matches("store-lb-12"));
+    }
+
+    #[test]
+    fn test_glob_expression() {
+        assert_eq!(
+            NamePattern::plain("store-lb-*").expression(),
+            Some("name eq \"store-lb-.*\"".to_string())
+        );
+        assert_eq!(
+            NamePattern::plain("*.lb?").expression(),
+            Some("name eq \".*\\.lb.\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_regex_pattern() {
+        let regex = NamePattern::regex("^store-lb-[0-9]+$").unwrap();
+        assert!(regex.matches("store-lb-12"));
+        assert!(!regex.matches("store-lb-a"));
+        assert_eq!(regex.expression(), None);
+        assert!(NamePattern::regex("store-(lb").is_err());
+    }
+}
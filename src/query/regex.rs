@@ -0,0 +1,93 @@
+//! Regular expressions for name patterns, in RE2 syntax. Matching is done by the `regex`
+//! crate, which runs in time linear in the name, so no pattern can make a listing hang.
+
+/// A compiled regular expression.
+#[derive(Debug, Clone)]
+pub struct Regex(regex::Regex);
+
+impl Regex {
+    /// Compiles a regular expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The expression, e.g. `^store-lb-\d+$`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Regex)` - The compiled expression.
+    /// * `Err(String)` - What is wrong with the expression and where.
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        regex::Regex::new(pattern)
+            .map(Regex)
+            .map_err(|e| format!("Invalid regex '{}': {}", pattern, e))
+    }
+
+    /// Returns the expression as it was written.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Checks whether the expression matches somewhere in a text.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Regex {
+    /// Expressions are equal if they were written the same.
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_matching() {
+        assert!(is_match("lb", "store-lb-1"));
+        assert!(is_match("^store-lb-\\d+$", "store-lb-12"));
+        assert!(!is_match("^store-lb-\\d+$", "store-lb-12a"));
+        assert!(is_match("^(web|api)-[a-c]$", "api-b"));
+        assert!(!is_match("^(web|api)-[a-c]$", "api-d"));
+        assert!(is_match("^a.?b*c+$", "abbcc"));
+        assert!(is_match("^x{2,3}$", "xxx"));
+        assert!(!is_match("^x{2,3}$", "xxxx"));
+        assert!(is_match("^[^0-9]+$", "abc"));
+        assert!(is_match("^[\\w.-]+$", "a_b.c-d"));
+        assert!(is_match("^(?:ab)*$", ""));
+        assert!(is_match("(a*)*b", "aaab"));
+        assert!(is_match("a\\.b", "a.b"));
+        assert!(!is_match("a\\.b", "axb"));
+        assert!(is_match("(?i)^LB$", "lb"));
+    }
+
+    #[test]
+    fn test_linear_time() {
+        // Exponential for a backtracking matcher
+        let regex = Regex::new("^(a|a)*b$").unwrap();
+        let text = "a".repeat(10_000);
+        let start = std::time::Instant::now();
+        assert!(!regex.is_match(&text));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in [
+            "(store", "store)", "*lb", "a{3,2}", "[z-a]", "[abc", "a\\", "\\q",
+        ] {
+            let error = Regex::new(pattern).unwrap_err();
+            assert!(error.starts_with("Invalid regex"), "{}", error);
+        }
+        assert!(Regex::new("store)")
+            .unwrap_err()
+            .starts_with("Invalid regex 'store)': "));
+    }
+}
@@ -0,0 +1,371 @@
+//! This module builds rollup reports over a listing, such as the per-cell summary used for
+//! capacity reviews or the snapshot schedules of disks, and renders them as Markdown or CSV.
+
+use crate::compute::{Disk, Instance, PolicyKind, ResourcePolicy};
+use crate::output::csv_field;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// The formats reports can be rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A Markdown table, e.g. for pasting into a review document.
+    #[default]
+    Markdown,
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+/// A summary of the instances in one cell.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellRollup {
+    /// The cell, or `None` for instances without a cell.
+    pub cell: Option<String>,
+    /// The number of instances.
+    pub instances: usize,
+    /// The number of instances per machine family, e.g. `n2`.
+    pub machine_families: BTreeMap<String, usize>,
+    /// The number of instances per status, e.g. `RUNNING`.
+    pub statuses: BTreeMap<String, usize>,
+    /// The total vCPUs, from the `vcpus` field of the machine-type enrichment. Instances that
+    /// weren't enriched count as zero.
+    pub vcpus: u64,
+    /// When the oldest instance was created, if any instance reports it.
+    pub oldest_created: Option<DateTime<Utc>>,
+}
+
+/// Returns the family of a machine type, e.g. `n2` for `n2-standard-4`.
+fn machine_family(machine_type: &str) -> &str {
+    machine_type.split('-').next().unwrap_or(machine_type)
+}
+
+/// Summarises instances per cell.
+///
+/// # Arguments
+///
+/// * `instances` - The instances, enriched with machine types for vCPU totals.
+///
+/// # Returns
+///
+/// One rollup per cell, sorted by cell name, with the instances without a cell last.
+pub fn cell_rollups(instances: &[Instance]) -> Vec<CellRollup> {
+    crate::query::group_by_cell(instances.to_vec())
+        .into_iter()
+        .map(|(cell, instances)| {
+            let mut rollup = CellRollup {
+                cell,
+                instances: instances.len(),
+                ..Default::default()
+            };
+            for instance in &instances {
+                *rollup
+                    .machine_families
+                    .entry(machine_family(&instance.machine_type).to_string())
+                    .or_default() += 1;
+                *rollup.statuses.entry(instance.status.clone()).or_default() += 1;
+                rollup.vcpus += instance
+                    .enrichments
+                    .get("vcpus")
+                    .and_then(|vcpus| vcpus.parse::<u64>().ok())
+                    .unwrap_or(0);
+            }
+            rollup.oldest_created = instances
+                .iter()
+                .filter_map(|instance| instance.creation_timestamp)
+                .min();
+            rollup
+        })
+        .collect()
+}
+
+/// Joins counts as `key: count` pairs, e.g. `n2: 3, e2: 1`.
+fn counts(counts: &BTreeMap<String, usize>) -> String {
+    counts
+        .iter()
+        .map(|(key, count)| format!("{}: {}", key, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders a report table.
+///
+/// # Arguments
+///
+/// * `header` - The title of each column for Markdown, and its key for the CSV header.
+/// * `rows` - The rows, with one field per column.
+/// * `format` - The report format.
+fn render(header: &[(&str, &str)], rows: Vec<Vec<String>>, format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Markdown => {
+            let titles = header.iter().map(|(title, _)| *title).collect::<Vec<_>>();
+            let mut out = format!("| {} |\n", titles.join(" | "));
+            out += &format!("|{}\n", "---|".repeat(header.len()));
+            for row in rows {
+                out += &format!("| {} |\n", row.join(" | "));
+            }
+            out
+        }
+        ReportFormat::Csv => {
+            let keys = header.iter().map(|(_, key)| *key).collect::<Vec<_>>();
+            let mut out = keys.join(",") + "\n";
+            for row in rows {
+                let fields = row.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+                out += &fields.join(",");
+                out.push('\n');
+            }
+            out
+        }
+    }
+}
+
+/// Renders per-cell rollups as a report.
+///
+/// # Arguments
+///
+/// * `rollups` - The rollups, in display order.
+/// * `format` - The report format.
+/// * `now` - The time instance ages are computed against.
+pub fn render_cells(rollups: &[CellRollup], format: ReportFormat, now: DateTime<Utc>) -> String {
+    let header = [
+        ("Cell", "cell"),
+        ("Instances", "instances"),
+        ("Machine families", "machine_families"),
+        ("Statuses", "statuses"),
+        ("vCPUs", "vcpus"),
+        ("Oldest (days)", "oldest_days"),
+    ];
+    let rows = rollups
+        .iter()
+        .map(|rollup| {
+            vec![
+                rollup.cell.clone().unwrap_or_else(|| "no cell".to_string()),
+                rollup.instances.to_string(),
+                counts(&rollup.machine_families),
+                counts(&rollup.statuses),
+                rollup.vcpus.to_string(),
+                rollup
+                    .oldest_created
+                    .map(|created| (now - created).num_days().to_string())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect();
+    render(&header, rows, format)
+}
+
+/// A persistent disk and the snapshot schedules attached to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiskSchedules {
+    /// The disk.
+    pub disk: Disk,
+    /// The attached snapshot schedule policies, as `name (schedule)`.
+    pub snapshot_schedules: Vec<String>,
+}
+
+/// Looks up the snapshot schedules attached to each disk.
+///
+/// # Arguments
+///
+/// * `disks` - The disks.
+/// * `policies` - The resource policies of the project. Policies other than snapshot
+///   schedules are ignored.
+pub fn disk_schedules(disks: Vec<Disk>, policies: &[ResourcePolicy]) -> Vec<DiskSchedules> {
+    disks
+        .into_iter()
+        .map(|disk| {
+            // Policies are regional; a zonal disk can only use those of its zone's region
+            let region = disk.region.clone().or_else(|| {
+                disk.zone
+                    .as_ref()
+                    .map(|zone| zone.rsplit_once('-').map_or(zone.as_str(), |(r, _)| r))
+                    .map(str::to_string)
+            });
+            let snapshot_schedules = disk
+                .resource_policies
+                .iter()
+                .filter_map(|name| {
+                    policies.iter().find(|policy| {
+                        policy.kind == PolicyKind::SnapshotSchedule
+                            && policy.name == *name
+                            && Some(&policy.region) == region.as_ref()
+                    })
+                })
+                .map(|policy| {
+                    format!(
+                        "{} ({})",
+                        policy.name,
+                        policy.schedule.as_deref().unwrap_or_default()
+                    )
+                })
+                .collect();
+            DiskSchedules {
+                disk,
+                snapshot_schedules,
+            }
+        })
+        .collect()
+}
+
+/// Renders disks and their snapshot schedules as a report.
+///
+/// # Arguments
+///
+/// * `disks` - The disks, in display order.
+/// * `format` - The report format.
+pub fn render_disk_schedules(disks: &[DiskSchedules], format: ReportFormat) -> String {
+    let header = [
+        ("Disk", "disk"),
+        ("Location", "location"),
+        ("Size (GB)", "size_gb"),
+        ("Users", "users"),
+        ("Snapshot schedules", "snapshot_schedules"),
+    ];
+    let rows = disks
+        .iter()
+        .map(|row| {
+            vec![
+                row.disk.name.clone(),
+                row.disk
+                    .zone
+                    .clone()
+                    .or_else(|| row.disk.region.clone())
+                    .unwrap_or_default(),
+                row.disk.size_gb.to_string(),
+                row.disk.users.join(", "),
+                match row.snapshot_schedules.is_empty() {
+                    true => "none".to_string(),
+                    false => row.snapshot_schedules.join("; "),
+                },
+            ]
+        })
+        .collect();
+    render(&header, rows, format)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(cell: Option<&str>, machine_type: &str, status: &str, created: &str) -> Instance {
+        Instance {
+            name: "instance".to_string(),
+            machine_type: machine_type.to_string(),
+            status: status.to_string(),
+            cell: cell.map(str::to_string),
+            creation_timestamp: DateTime::parse_from_rfc3339(created)
+                .ok()
+                .map(|created| created.with_timezone(&Utc)),
+            enrichments: BTreeMap::from([("vcpus".to_string(), "4".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    fn rollups() -> Vec<CellRollup> {
+        cell_rollups(&[
+            instance(
+                Some("a"),
+                "n2-standard-4",
+                "RUNNING",
+                "2024-01-01T00:00:00Z",
+            ),
+            instance(None, "e2-medium", "RUNNING", ""),
+            instance(
+                Some("a"),
+                "e2-standard-4",
+                "TERMINATED",
+                "2024-03-01T00:00:00Z",
+            ),
+            instance(Some("a"), "n2-highmem-4", "RUNNING", "2024-02-01T00:00:00Z"),
+        ])
+    }
+
+    #[test]
+    fn test_cell_rollups() {
+        let rollups = rollups();
+        assert_eq!(rollups.len(), 2);
+        let a = &rollups[0];
+        assert_eq!(a.cell.as_deref(), Some("a"));
+        assert_eq!(a.instances, 3);
+        assert_eq!(
+            a.machine_families,
+            BTreeMap::from([("e2".to_string(), 1), ("n2".to_string(), 2)])
+        );
+        assert_eq!(
+            a.statuses,
+            BTreeMap::from([("RUNNING".to_string(), 2), ("TERMINATED".to_string(), 1)])
+        );
+        assert_eq!(a.vcpus, 12);
+        assert_eq!(
+            a.oldest_created.unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(rollups[1].cell, None);
+        assert_eq!(rollups[1].oldest_created, None);
+    }
+
+    #[test]
+    fn test_render_cells() {
+        let now = DateTime::parse_from_rfc3339("2024-04-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            render_cells(&rollups(), ReportFormat::Markdown, now),
+            "| Cell | Instances | Machine families | Statuses | vCPUs | Oldest (days) |\n\
+             |---|---|---|---|---|---|\n\
+             | a | 3 | e2: 1, n2: 2 | RUNNING: 2, TERMINATED: 1 | 12 | 100 |\n\
+             | no cell | 1 | e2: 1 | RUNNING: 1 | 4 |  |\n"
+        );
+        assert_eq!(
+            render_cells(&rollups(), ReportFormat::Csv, now),
+            "cell,instances,machine_families,statuses,vcpus,oldest_days\n\
+             a,3,\"e2: 1, n2: 2\",\"RUNNING: 2, TERMINATED: 1\",12,100\n\
+             no cell,1,e2: 1,RUNNING: 1,4,\n"
+        );
+    }
+
+    #[test]
+    fn test_disk_schedules() {
+        let disk = |name: &str, zone: &str, policies: &[&str]| Disk {
+            name: name.to_string(),
+            size_gb: 100,
+            zone: Some(zone.to_string()),
+            users: vec!["db-1".to_string()],
+            resource_policies: policies.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        let policies = [
+            ResourcePolicy {
+                name: "daily".to_string(),
+                region: "us-east1".to_string(),
+                kind: PolicyKind::SnapshotSchedule,
+                schedule: Some("daily at 04:00".to_string()),
+            },
+            ResourcePolicy {
+                name: "nightly-stop".to_string(),
+                region: "us-east1".to_string(),
+                kind: PolicyKind::InstanceSchedule,
+                schedule: Some("stop 0 20 * * *".to_string()),
+            },
+        ];
+        let rows = disk_schedules(
+            vec![
+                disk("data", "us-east1-b", &["daily"]),
+                disk("boot", "us-east1-c", &["nightly-stop"]),
+                // Same name, but in another region
+                disk("other", "europe-west1-b", &["daily"]),
+            ],
+            &policies,
+        );
+
+        assert_eq!(rows[0].snapshot_schedules, vec!["daily (daily at 04:00)"]);
+        assert!(rows[1].snapshot_schedules.is_empty());
+        assert!(rows[2].snapshot_schedules.is_empty());
+        assert_eq!(
+            render_disk_schedules(&rows[..2], ReportFormat::Csv),
+            "disk,location,size_gb,users,snapshot_schedules\n\
+             data,us-east1-b,100,db-1,daily (daily at 04:00)\n\
+             boot,us-east1-c,100,db-1,none\n"
+        );
+    }
+}
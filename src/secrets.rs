@@ -0,0 +1,217 @@
+//! This module resolves references in config values, such as `env:MY_PROJECT`,
+//! `file:~/keys/prd.json` or `cmd:vault read -field=token ...`, when the config is loaded. This
+//! keeps credentials and machine-specific values out of a shared config file.
+
+use serde_json::Value as JsonValue;
+use std::error::Error;
+use std::process::Command;
+
+/// Resolves config values of the form `<scheme>:<reference>`.
+pub trait SecretResolver {
+    /// The scheme this resolver handles, without the colon, e.g. `env`.
+    fn scheme(&self) -> &'static str;
+
+    /// Resolves a reference to its value.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - The part of the config value after `<scheme>:`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The resolved value on success.
+    /// * `Err(Box<dyn Error>)` - An error if the reference can't be resolved.
+    fn resolve(&self, reference: &str) -> Result<String, Box<dyn Error>>;
+}
+
+/// Resolves `env:NAME` to the value of the environment variable `NAME`.
+pub struct EnvResolver;
+
+impl SecretResolver for EnvResolver {
+    fn scheme(&self) -> &'static str {
+        "env"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+        std::env::var(reference)
+            .map_err(|e| format!("environment variable {}: {}", reference, e).into())
+    }
+}
+
+/// Resolves `file:PATH` to the contents of the file, without trailing whitespace. A leading
+/// `~/` refers to the home directory.
+pub struct FileResolver;
+
+impl SecretResolver for FileResolver {
+    fn scheme(&self) -> &'static str {
+        "file"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+        let path = match reference.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir().ok_or("Homedir not found")?.join(rest),
+            None => reference.into(),
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("file {}: {}", path.display(), e))?;
+        Ok(contents.trim_end().to_string())
+    }
+}
+
+/// Resolves `cmd:COMMAND` to the output of running the command with the shell, without
+/// trailing whitespace.
+pub struct CommandResolver;
+
+impl SecretResolver for CommandResolver {
+    fn scheme(&self) -> &'static str {
+        "cmd"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+        let output = match cfg!(windows) {
+            true => Command::new("cmd").args(["/C", reference]).output(),
+            false => Command::new("sh").args(["-c", reference]).output(),
+        }
+        .map_err(|e| format!("command `{}`: {}", reference, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "command `{}` failed: {}",
+                reference,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+    }
+}
+
+/// Returns the resolvers for the `env:`, `file:` and `cmd:` schemes.
+pub fn default_resolvers() -> Vec<Box<dyn SecretResolver>> {
+    vec![
+        Box::new(EnvResolver),
+        Box::new(FileResolver),
+        Box::new(CommandResolver),
+    ]
+}
+
+/// Replaces every string in a config tree that starts with a resolver's scheme by the resolved
+/// value. Strings with other or no prefixes, e.g. URLs, are left alone.
+///
+/// # Arguments
+///
+/// * `value` - The config, e.g. as deserialized from the config file.
+/// * `resolvers` - The resolvers to use.
+///
+/// # Returns
+///
+/// * `Ok(())` - If every reference was resolved.
+/// * `Err(Box<dyn Error>)` - An error naming the config key whose reference failed.
+pub fn resolve_secrets(
+    value: &mut JsonValue,
+    resolvers: &[Box<dyn SecretResolver>],
+) -> Result<(), Box<dyn Error>> {
+    resolve_at(value, resolvers, "")
+}
+
+/// Resolves references in a subtree, tracking the key path for error messages.
+fn resolve_at(
+    value: &mut JsonValue,
+    resolvers: &[Box<dyn SecretResolver>],
+    path: &str,
+) -> Result<(), Box<dyn Error>> {
+    match value {
+        JsonValue::String(s) => {
+            let resolved = resolvers.iter().find_map(|resolver| {
+                s.strip_prefix(resolver.scheme())
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .map(|reference| resolver.resolve(reference))
+            });
+            if let Some(resolved) = resolved {
+                *s = resolved.map_err(|e| format!("Failed to resolve {}: {}", path, e))?;
+            }
+        }
+        JsonValue::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let path = match path.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}.{}", path, key),
+                };
+                resolve_at(value, resolvers, &path)?;
+            }
+        }
+        JsonValue::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                resolve_at(value, resolvers, &format!("{}[{}]", path, i))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Resolves `test:X` to `resolved-X`, and fails for `test:fail`.
+    struct TestResolver;
+
+    impl SecretResolver for TestResolver {
+        fn scheme(&self) -> &'static str {
+            "test"
+        }
+
+        fn resolve(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+            match reference {
+                "fail" => Err("no such secret".into()),
+                _ => Ok(format!("resolved-{}", reference)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_secrets() {
+        let mut config = json!({
+            "int": {"project": "test:int"},
+            "ssh": {"options": ["-o", "test:opt"]},
+            "webhook": "https://example.com/hook",
+            "plain": "testing",
+            "ttl_secs": 300
+        });
+        resolve_secrets(&mut config, &[Box::new(TestResolver)]).unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "int": {"project": "resolved-int"},
+                "ssh": {"options": ["-o", "resolved-opt"]},
+                "webhook": "https://example.com/hook",
+                "plain": "testing",
+                "ttl_secs": 300
+            })
+        );
+
+        let mut config = json!({"prd": {"project": "test:fail"}});
+        let err = resolve_secrets(&mut config, &[Box::new(TestResolver)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to resolve prd.project: no such secret"
+        );
+    }
+
+    #[test]
+    fn test_default_resolvers() {
+        let resolvers = default_resolvers();
+        let mut config = json!({
+            "path": "env:PATH",
+            "cmd": "cmd:echo hello",
+        });
+        resolve_secrets(&mut config, &resolvers).unwrap();
+        assert_eq!(config["path"], std::env::var("PATH").unwrap());
+        assert_eq!(config["cmd"], "hello");
+
+        let mut config = json!({"key": "file:/nonexistent/bcls-key.json"});
+        assert!(resolve_secrets(&mut config, &resolvers).is_err());
+    }
+}
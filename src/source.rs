@@ -0,0 +1,129 @@
+//! This module abstracts where instance listings come from, so that listing, filtering and
+//! output work the same over the Compute API and over exported inventory snapshots.
+
+mod snapshot;
+
+use crate::compute::{flatten_pages, Compute, Instance, Listed, Page, ResultSet, TokenSource};
+use crate::http;
+use serde::Deserialize;
+
+pub use snapshot::{GcsObject, SnapshotSource};
+
+/// Where the instances of a habitat are listed from, set per habitat in the config file.
+///
+/// ```toml
+/// [prd]
+/// project = "my-prd-project"
+/// source = { snapshot = "gs://fleet-exports/prd/instances.json" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// List instances with the Compute Engine API. The default.
+    #[default]
+    Api,
+    /// Read instances from a JSON export in Cloud Storage, as a `gs://BUCKET/OBJECT` URL.
+    /// Only read access to the bucket is needed, not to the Compute API.
+    Snapshot(String),
+}
+
+/// A provider of instance listings.
+pub trait InstanceSource {
+    /// Returns the Google Cloud project ID the instances belong to.
+    fn project(&self) -> &str;
+
+    /// Lists the instances a page at a time, so they can be shown before the whole listing
+    /// has arrived.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Compute Engine filter expression, as built by
+    ///   `query::InstanceFilter::expression`. It only narrows the listing where the source can
+    ///   evaluate it, so callers must still match the instances themselves.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Box<dyn Iterator>)` - An iterator over the pages of instances. The iteration
+    ///   ends after the first error.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the listing couldn't be started.
+    fn list_instance_pages<'a>(
+        &'a self,
+        filter: Option<&str>,
+    ) -> Result<Box<dyn Iterator<Item = Page<Instance>> + 'a>, Box<dyn std::error::Error>>;
+
+    /// Lists the instances one at a time, fetching each page only when the iterator reaches
+    /// it, so the caller can stop early, e.g. after the first match.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - A Compute Engine filter expression, as for `list_instance_pages`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Box<dyn Iterator>)` - An iterator over the instances. The iteration ends after
+    ///   the first error.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if the listing couldn't be started.
+    fn instances_iter<'a>(
+        &'a self,
+        filter: Option<&str>,
+    ) -> Result<Box<dyn Iterator<Item = Listed<Instance>> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(flatten_pages(self.list_instance_pages(filter)?)))
+    }
+
+    /// Lists the instances matching a filter expression, as for `list_instance_pages`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The instances, with warnings about what is missing.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if listing failed.
+    fn list_instances(
+        &self,
+        filter: Option<&str>,
+    ) -> Result<ResultSet<Instance>, Box<dyn std::error::Error>> {
+        ResultSet::from_pages(self.list_instance_pages(filter)?)
+    }
+
+    /// Lists every instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ResultSet<Instance>)` - The instances, with warnings about what is missing.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if listing failed.
+    fn list_all_instances(&self) -> Result<ResultSet<Instance>, Box<dyn std::error::Error>> {
+        self.list_instances(None)
+    }
+
+    /// Finds an instance by name, in any zone.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Instance))` - The instance, if it exists.
+    /// * `Ok(None)` - If no instance has that name.
+    /// * `Err(Box<dyn std::error::Error>)` - An error if listing failed.
+    fn find_instance(&self, name: &str) -> Result<Option<Instance>, Box<dyn std::error::Error>> {
+        for instance in self.instances_iter(None)? {
+            let instance = instance?;
+            if instance.name == name {
+                return Ok(Some(instance));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<H: http::HttpClient + Sync, T: TokenSource + Sync> InstanceSource for Compute<H, T> {
+    fn project(&self) -> &str {
+        Compute::project(self)
+    }
+
+    fn list_instance_pages<'a>(
+        &'a self,
+        filter: Option<&str>,
+    ) -> Result<Box<dyn Iterator<Item = Page<Instance>> + 'a>, Box<dyn std::error::Error>> {
+        Ok(Box::new(Compute::list_instance_pages(self, filter)?))
+    }
+}
@@ -0,0 +1,222 @@
+//! This module reads instances from inventory snapshots exported to Cloud Storage, for users
+//! who can read the export bucket but have no access to the Compute API.
+
+use super::InstanceSource;
+use crate::compute::{self, ApiVersion, Instance, Page, ResultSet, TokenSource};
+use crate::http;
+use serde_json::Value as JsonValue;
+
+/// An object in a Cloud Storage bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcsObject {
+    /// The name of the bucket.
+    pub bucket: String,
+    /// The name of the object within the bucket, e.g. `prd/instances.json`.
+    pub object: String,
+}
+
+impl GcsObject {
+    /// Parses a `gs://BUCKET/OBJECT` URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL, e.g. `gs://fleet-exports/prd/instances.json`.
+    ///
+    /// # Returns
+    ///
+    /// The object, or an error if the URL isn't a `gs://` URL with both a bucket and an object.
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (bucket, object) = url
+            .strip_prefix("gs://")
+            .and_then(|path| path.split_once('/'))
+            .filter(|(bucket, object)| !bucket.is_empty() && !object.is_empty())
+            .ok_or_else(|| format!("'{}' is not a gs://BUCKET/OBJECT URL", url))?;
+        Ok(GcsObject {
+            bucket: bucket.to_string(),
+            object: object.to_string(),
+        })
+    }
+
+    /// Returns the JSON API URL that downloads the object's contents.
+    pub fn media_url(&self) -> String {
+        format!(
+            "https://storage.googleapis.com/storage/v1/b/{}/o/{}?alt=media",
+            urlencoding::encode(&self.bucket),
+            urlencoding::encode(&self.object)
+        )
+    }
+}
+
+/// Lists instances from a JSON export in Cloud Storage. The export is either an array of
+/// instance resources, as printed by `gcloud compute instances list --format=json`, or an
+/// aggregated list response of the Compute API.
+pub struct SnapshotSource<H: http::HttpClient, T: TokenSource> {
+    /// The Google Cloud project ID the instances belong to.
+    project: String,
+    /// Where the export is stored.
+    location: GcsObject,
+    /// The HTTP client implementation.
+    client: H,
+    /// The token source.
+    token_source: T,
+    /// The API version the export was made with, which decides whether beta-only fields are
+    /// parsed.
+    api_version: ApiVersion,
+}
+
+impl<H: http::HttpClient, T: TokenSource> SnapshotSource<H, T> {
+    /// Creates a new `SnapshotSource`.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - The Google Cloud project ID the instances belong to.
+    /// * `location` - Where the export is stored.
+    /// * `client` - The HTTP client implementation.
+    /// * `token_source` - The token source.
+    /// * `api_version` - The API version the export was made with.
+    pub fn new(
+        project: &str,
+        location: GcsObject,
+        client: H,
+        token_source: T,
+        api_version: ApiVersion,
+    ) -> Self {
+        SnapshotSource {
+            project: project.to_string(),
+            location,
+            client,
+            token_source,
+            api_version,
+        }
+    }
+}
+
+/// Collects the instance resources of an export.
+///
+/// # Arguments
+///
+/// * `export` - An array of instances, or an aggregated list whose `items` map each zone to an
+///   object holding its `instances`.
+fn export_instances(export: &JsonValue) -> Result<Vec<&JsonValue>, Box<dyn std::error::Error>> {
+    match (export, &export["items"]) {
+        (JsonValue::Array(instances), _) => Ok(instances.iter().collect()),
+        (_, JsonValue::Object(scopes)) => Ok(scopes
+            .values()
+            .filter_map(|scope| scope["instances"].as_array())
+            .flatten()
+            .collect()),
+        (_, JsonValue::Null) if export.is_object() => Ok(vec![]),
+        _ => Err("The snapshot is neither a list of instances nor an aggregated list".into()),
+    }
+}
+
+impl<H: http::HttpClient, T: TokenSource> InstanceSource for SnapshotSource<H, T> {
+    fn project(&self) -> &str {
+        &self.project
+    }
+
+    /// Downloads the whole export as a single page. Snapshots can't evaluate filter
+    /// expressions, so the filter is ignored.
+    fn list_instance_pages<'a>(
+        &'a self,
+        _filter: Option<&str>,
+    ) -> Result<Box<dyn Iterator<Item = Page<Instance>> + 'a>, Box<dyn std::error::Error>> {
+        let token = self.token_source.get_token(&self.project)?;
+        let export = self.client.get(&token, &self.location.media_url())?;
+        let instances = export_instances(&export)?
+            .into_iter()
+            .map(|json| compute::parse_instance(json, self.api_version))
+            .collect::<Result<Vec<_>, _>>()
+            .map(ResultSet::new);
+        Ok(Box::new(std::iter::once(instances)))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::MockTokenSource;
+    use crate::http::MockHttpClient;
+    use mockall::predicate;
+    use serde_json::json;
+
+    fn instance_json(name: &str) -> JsonValue {
+        json!({
+            "name": name,
+            "zone": "https://www.googleapis.com/compute/v1/projects/p/zones/us-east1-b",
+            "machineType": "zones/us-east1-b/machineTypes/e2-medium",
+            "cpuPlatform": "Intel Broadwell",
+            "status": "RUNNING",
+        })
+    }
+
+    fn source(export: JsonValue) -> SnapshotSource<MockHttpClient, MockTokenSource> {
+        let mut mock_http = MockHttpClient::new();
+        mock_http
+            .expect_get()
+            .with(
+                predicate::eq("mock_token"),
+                predicate::eq(
+                    "https://storage.googleapis.com/storage/v1/b/fleet-exports/o/prd%2Finstances.json?alt=media",
+                ),
+            )
+            .times(1)
+            .returning(move |_, _| Ok(export.clone()));
+        SnapshotSource::new(
+            "p",
+            GcsObject::parse("gs://fleet-exports/prd/instances.json").unwrap(),
+            mock_http,
+            MockTokenSource::new("mock_token"),
+            ApiVersion::V1,
+        )
+    }
+
+    #[test]
+    fn test_parse_gcs_object() {
+        assert_eq!(
+            GcsObject::parse("gs://fleet-exports/prd/instances.json"),
+            Ok(GcsObject {
+                bucket: "fleet-exports".to_string(),
+                object: "prd/instances.json".to_string(),
+            })
+        );
+        assert!(GcsObject::parse("gs://fleet-exports").is_err());
+        assert!(GcsObject::parse("gs:///instances.json").is_err());
+        assert!(GcsObject::parse("https://example.com/instances.json").is_err());
+    }
+
+    #[test]
+    fn test_list_instances_from_gcloud_export() {
+        let source = source(json!([instance_json("a"), instance_json("b")]));
+        let instances = source
+            .list_instances(Some("status = \"RUNNING\""))
+            .unwrap()
+            .items;
+        assert_eq!(
+            instances
+                .iter()
+                .map(|i| i.name.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(instances[0].zone, "us-east1-b");
+        assert_eq!(instances[0].machine_type, "e2-medium");
+    }
+
+    #[test]
+    fn test_find_instance_in_aggregated_export() {
+        let source = source(json!({"items": {
+            "zones/us-east1-b": {"instances": [instance_json("a")]},
+            "zones/us-east1-c": {"warning": {"code": "NO_RESULTS_ON_PAGE"}},
+            "zones/us-east1-d": {"instances": [instance_json("b")]}
+        }}));
+        assert_eq!(source.find_instance("b").unwrap().unwrap().name, "b");
+    }
+
+    #[test]
+    fn test_invalid_export() {
+        let source = source(json!("not an export"));
+        assert!(source.list_all_instances().is_err());
+    }
+}
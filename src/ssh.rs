@@ -0,0 +1,338 @@
+//! This module builds `ssh` invocations for connecting to instances.
+
+use crate::compute::Instance;
+use crate::config::{Environment, SshConfig};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+/// Builds an `ssh` command for connecting to a host.
+///
+/// # Arguments
+///
+/// * `host` - The IP address or hostname to connect to.
+/// * `config` - The user and options from the `[ssh]` config section.
+/// * `proxy_jump` - The bastion to connect through, as from `proxy_jump`.
+/// * `extra_args` - Arguments appended after the destination, e.g. a remote command.
+///
+/// # Returns
+///
+/// * `Command` - The `ssh` command, ready to be spawned or exec'd.
+pub fn ssh_command(
+    host: &str,
+    config: &SshConfig,
+    proxy_jump: Option<&str>,
+    extra_args: &[String],
+) -> Command {
+    let destination = match &config.user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    };
+    let mut command = Command::new("ssh");
+    command.args(&config.options);
+    if let Some(bastion) = proxy_jump {
+        command.arg("-J").arg(bastion);
+    }
+    command.arg(destination).args(extra_args);
+    command
+}
+
+/// Chooses the bastion to reach an instance through: the `[bastions]` entry for its cell,
+/// else the one for its region, else the `proxy_jump` template of the `[ssh]` config section.
+///
+/// # Arguments
+///
+/// * `instance` - The instance to connect to.
+/// * `env` - The environment the instance is in, for the template.
+/// * `config` - The `[ssh]` config section.
+/// * `bastions` - The `[bastions]` config section.
+///
+/// # Returns
+///
+/// The bastion, `None` to connect directly, or an error if the template can't be expanded.
+pub fn proxy_jump(
+    instance: &Instance,
+    env: Environment,
+    config: &SshConfig,
+    bastions: &BTreeMap<String, String>,
+) -> Result<Option<String>, String> {
+    let mapped = instance
+        .cell
+        .as_ref()
+        .and_then(|cell| bastions.get(cell))
+        .or_else(|| bastions.get(&instance.region));
+    match (mapped, &config.proxy_jump) {
+        (Some(bastion), _) => Ok(Some(bastion.clone())),
+        (None, Some(template)) => expand_template(template, instance, env).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Replaces the current process with the given command.
+///
+/// On Unix this uses `exec`, so it only returns if the command could not be started. Elsewhere
+/// the command is run to completion and the process exits with its status code.
+pub fn exec(mut command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(command.exec().into())
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status()?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+/// Fills in the placeholders of an `[ssh]` template for an instance.
+///
+/// # Arguments
+///
+/// * `template` - The template, e.g. `bastion-{region}.corp`.
+/// * `instance` - The instance to take the values from.
+/// * `env` - The environment the instance is in, for `{env}`.
+///
+/// # Returns
+///
+/// The expanded template, or an error naming a placeholder that is unknown, unterminated or
+/// has no value for the instance, e.g. `{cell}` for an instance outside any cell.
+pub fn expand_template(
+    template: &str,
+    instance: &Instance,
+    env: Environment,
+) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("unterminated placeholder in '{}'", template))?;
+        let placeholder = &rest[start + 1..end];
+        let value = match placeholder {
+            "name" => Some(instance.name.as_str()),
+            "zone" => Some(instance.zone.as_str()),
+            "region" => Some(instance.region.as_str()),
+            "cell" => instance.cell.as_deref(),
+            "env" => Some(env.as_str()),
+            _ => match placeholder.strip_prefix("labels.") {
+                Some(key) => instance
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(key))
+                    .map(String::as_str),
+                None => return Err(format!("unknown placeholder {{{}}}", placeholder)),
+            },
+        };
+        let value = value.ok_or_else(|| format!("no value for {{{}}}", placeholder))?;
+        expanded.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Builds an ssh config `Host` block for an instance, with the user and `IdentityFile`
+/// expanded from the `[ssh]` config section and the `ProxyJump` chosen by `proxy_jump`.
+///
+/// # Arguments
+///
+/// * `instance` - The instance to connect to, aliased by its name.
+/// * `env` - The environment the instance is in.
+/// * `config` - The `[ssh]` config section.
+/// * `bastions` - The `[bastions]` config section.
+/// * `external` - Whether to connect to the external (NAT) IP instead of the internal one.
+///
+/// # Returns
+///
+/// The block, ending with a newline, or an error if the instance has no suitable IP address
+/// or a template can't be expanded for it.
+pub fn host_block(
+    instance: &Instance,
+    env: Environment,
+    config: &SshConfig,
+    bastions: &BTreeMap<String, String>,
+    external: bool,
+) -> Result<String, String> {
+    let ip = match external {
+        true => instance.external_ip(),
+        false => instance.primary_ip(),
+    }
+    .ok_or("no suitable IP address")?;
+    let mut block = format!("Host {}\n    HostName {}\n", instance.name, ip);
+    if let Some(user) = &config.user {
+        let user = expand_template(user, instance, env)?;
+        block.push_str(&format!("    User {}\n", user));
+    }
+    if let Some(bastion) = proxy_jump(instance, env, config, bastions)? {
+        block.push_str(&format!("    ProxyJump {}\n", bastion));
+    }
+    if let Some(identity_file) = &config.identity_file {
+        let identity_file = expand_template(identity_file, instance, env)?;
+        block.push_str(&format!("    IdentityFile {}\n", identity_file));
+    }
+    Ok(block)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_ssh_command_defaults() {
+        let command = ssh_command("10.0.0.1", &SshConfig::default(), None, &[]);
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(args(&command), vec!["10.0.0.1"]);
+    }
+
+    #[test]
+    fn test_ssh_command_with_config_and_extra_args() {
+        let config = SshConfig {
+            user: Some("jdoe".to_string()),
+            options: vec!["-o".to_string(), "StrictHostKeyChecking=no".to_string()],
+            ..SshConfig::default()
+        };
+        let command = ssh_command("10.0.0.1", &config, None, &["uptime".to_string()]);
+        assert_eq!(
+            args(&command),
+            vec!["-o", "StrictHostKeyChecking=no", "jdoe@10.0.0.1", "uptime"]
+        );
+        let command = ssh_command("10.0.0.1", &config, Some("bastion-e1.corp"), &[]);
+        assert_eq!(
+            args(&command),
+            vec![
+                "-o",
+                "StrictHostKeyChecking=no",
+                "-J",
+                "bastion-e1.corp",
+                "jdoe@10.0.0.1"
+            ]
+        );
+    }
+
+    fn instance() -> Instance {
+        Instance {
+            name: "store-lb-1".to_string(),
+            zone: "us-east1-b".to_string(),
+            region: "us-east1".to_string(),
+            cell: Some("c1".to_string()),
+            labels: Some([("team".to_string(), "payments".to_string())].into()),
+            network_interfaces: vec![crate::compute::NetworkInterface {
+                ip: Some("10.0.0.1".to_string()),
+                external_ip: Some("34.1.2.3".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let instance = instance();
+        assert_eq!(
+            expand_template("bastion-{region}-{cell}.corp", &instance, Environment::Prd),
+            Ok("bastion-us-east1-c1.corp".to_string())
+        );
+        assert_eq!(
+            expand_template("~/.ssh/{env}_{labels.team}", &instance, Environment::Stg),
+            Ok("~/.ssh/stg_payments".to_string())
+        );
+        assert_eq!(
+            expand_template("jdoe", &instance, Environment::Prd),
+            Ok("jdoe".to_string())
+        );
+        assert!(expand_template("{labels.owner}", &instance, Environment::Prd).is_err());
+        assert!(expand_template("{host}", &instance, Environment::Prd).is_err());
+        assert!(expand_template("{name", &instance, Environment::Prd).is_err());
+    }
+
+    #[test]
+    fn test_host_block() {
+        let config = SshConfig {
+            user: Some("jdoe".to_string()),
+            proxy_jump: Some("bastion-{region}.corp".to_string()),
+            identity_file: Some("~/.ssh/{env}".to_string()),
+            ..SshConfig::default()
+        };
+        assert_eq!(
+            host_block(
+                &instance(),
+                Environment::Prd,
+                &config,
+                &BTreeMap::new(),
+                false
+            )
+            .unwrap(),
+            "Host store-lb-1\n    HostName 10.0.0.1\n    User jdoe\n    \
+             ProxyJump bastion-us-east1.corp\n    IdentityFile ~/.ssh/prd\n"
+        );
+        assert_eq!(
+            host_block(
+                &instance(),
+                Environment::Prd,
+                &SshConfig::default(),
+                &BTreeMap::new(),
+                true
+            )
+            .unwrap(),
+            "Host store-lb-1\n    HostName 34.1.2.3\n"
+        );
+
+        let mut cell_less = instance();
+        cell_less.cell = None;
+        let config = SshConfig {
+            proxy_jump: Some("bastion-{cell}".to_string()),
+            ..SshConfig::default()
+        };
+        assert!(host_block(
+            &cell_less,
+            Environment::Prd,
+            &config,
+            &BTreeMap::new(),
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_proxy_jump_prefers_cell_then_region_then_template() {
+        let bastions = BTreeMap::from([
+            ("c1".to_string(), "bastion-c1.corp".to_string()),
+            ("us-east1".to_string(), "bastion-e1.corp".to_string()),
+        ]);
+        let config = SshConfig {
+            proxy_jump: Some("bastion-{region}.fallback".to_string()),
+            ..SshConfig::default()
+        };
+        let jump = |instance: &Instance| proxy_jump(instance, Environment::Prd, &config, &bastions);
+
+        assert_eq!(jump(&instance()), Ok(Some("bastion-c1.corp".to_string())));
+        let mut other_cell = instance();
+        other_cell.cell = Some("c2".to_string());
+        assert_eq!(jump(&other_cell), Ok(Some("bastion-e1.corp".to_string())));
+        let mut other_region = other_cell.clone();
+        other_region.region = "europe-west1".to_string();
+        assert_eq!(
+            jump(&other_region),
+            Ok(Some("bastion-europe-west1.fallback".to_string()))
+        );
+        assert_eq!(
+            proxy_jump(
+                &other_region,
+                Environment::Prd,
+                &SshConfig::default(),
+                &bastions
+            ),
+            Ok(None)
+        );
+    }
+}
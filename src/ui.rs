@@ -0,0 +1,517 @@
+//! This module provides the interactive browser started with `bcls tui <env>`: a filterable,
+//! scrollable instance list with a detail pane, and keys to copy an instance's IP, SSH to it,
+//! or start and stop it.
+//!
+//! `App` holds the state and draws it onto a ratatui frame, independently of the terminal,
+//! which `Terminal` handles with crossterm. The caller lists the instances and carries out the
+//! actions `App` asks for, so the compute module stays the only data source.
+
+mod terminal;
+
+use crate::compute::Instance;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, Borders, List, ListState, Padding, Paragraph};
+use ratatui::Frame;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub use terminal::Terminal;
+
+/// A key press, decoded from the terminal's input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A printable character.
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Backspace,
+    /// Ctrl-C, which the terminal delivers as a key in raw mode.
+    CtrlC,
+}
+
+impl Key {
+    /// Decodes a key event from the terminal. Keys the browser doesn't use, and keys held
+    /// with Ctrl or Alt other than Ctrl-C, are dropped.
+    pub fn from_event(event: KeyEvent) -> Option<Self> {
+        if event.modifiers.contains(KeyModifiers::CONTROL) {
+            return (event.code == KeyCode::Char('c')).then_some(Key::CtrlC);
+        }
+        if event.modifiers.contains(KeyModifiers::ALT) {
+            return None;
+        }
+        let key = match event.code {
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::Home => Key::Home,
+            KeyCode::End => Key::End,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Esc => Key::Esc,
+            KeyCode::Backspace => Key::Backspace,
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+/// Something the caller must do in response to a key.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Leave the browser.
+    Quit,
+    /// List the instances again.
+    Refresh,
+    /// Copy an IP address to the clipboard.
+    CopyIp(String),
+    /// SSH to an instance.
+    Ssh(Instance),
+    /// Start an instance. Only returned once the user confirmed.
+    Start(Instance),
+    /// Stop an instance. Only returned once the user confirmed.
+    Stop(Instance),
+}
+
+/// What keys currently do.
+#[derive(Debug, Clone)]
+enum Mode {
+    /// Moving through the list.
+    Browse,
+    /// Typing a filter after `/`.
+    Filter,
+    /// Waiting for `y` to confirm a start or stop.
+    Confirm(Box<Action>),
+}
+
+/// The browser's state: the instances, the filter and the selection.
+#[derive(Debug, Clone)]
+pub struct App {
+    /// Shown in the title bar, e.g. the environment and project.
+    title: String,
+    /// Every listed instance.
+    instances: Vec<Instance>,
+    /// Only instances whose name contains this are shown.
+    filter: String,
+    /// What keys currently do.
+    mode: Mode,
+    /// The index of the selected instance among the shown ones.
+    selected: usize,
+    /// The index of the first shown instance on screen.
+    offset: usize,
+    /// A message for the status line, e.g. the result of the last action.
+    message: Option<String>,
+}
+
+/// The help shown in the status line while browsing.
+const HELP: &str = "j/k move  / filter  c copy IP  s ssh  S start  X stop  r refresh  q quit";
+
+impl App {
+    /// Creates a new `App` showing the given instances.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - Shown in the title bar, e.g. the environment and project.
+    /// * `instances` - The instances to browse.
+    pub fn new(title: &str, instances: Vec<Instance>) -> Self {
+        App {
+            title: title.to_string(),
+            instances,
+            filter: String::new(),
+            mode: Mode::Browse,
+            selected: 0,
+            offset: 0,
+            message: None,
+        }
+    }
+
+    /// Replaces the instances after a refresh, keeping the same instance selected if it is
+    /// still shown.
+    pub fn set_instances(&mut self, instances: Vec<Instance>) {
+        let selected = self.selected().map(|inst| inst.name.clone());
+        self.instances = instances;
+        self.selected = selected
+            .and_then(|name| self.visible().iter().position(|inst| inst.name == name))
+            .unwrap_or(0);
+        self.clamp_selection();
+    }
+
+    /// Sets the message shown in the status line until the next key.
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    /// The instances matching the filter, in listing order.
+    pub fn visible(&self) -> Vec<&Instance> {
+        self.instances
+            .iter()
+            .filter(|inst| inst.name.contains(&self.filter))
+            .collect()
+    }
+
+    /// The selected instance, if any instance is shown.
+    pub fn selected(&self) -> Option<&Instance> {
+        self.visible().get(self.selected).copied()
+    }
+
+    /// Keeps the selection within the shown instances.
+    fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+    }
+
+    /// Moves the selection by `delta` rows, stopping at either end.
+    fn move_selection(&mut self, delta: isize) {
+        self.selected = self.selected.saturating_add_signed(delta);
+        self.clamp_selection();
+    }
+
+    /// Updates the state for a key press.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key pressed.
+    /// * `height` - The height of the terminal, for paging.
+    ///
+    /// # Returns
+    ///
+    /// The action the caller must carry out, if any.
+    pub fn handle_key(&mut self, key: Key, height: usize) -> Option<Action> {
+        self.message = None;
+        let page = list_height(height).max(1) as isize;
+        match (&self.mode, key) {
+            (_, Key::CtrlC) => return Some(Action::Quit),
+            (Mode::Confirm(action), Key::Char('y')) => {
+                let action = *action.clone();
+                self.mode = Mode::Browse;
+                return Some(action);
+            }
+            (Mode::Confirm(_), _) => {
+                self.mode = Mode::Browse;
+                self.message = Some("Cancelled".to_string());
+            }
+            (Mode::Filter, Key::Enter) => self.mode = Mode::Browse,
+            (Mode::Filter, Key::Esc) => {
+                self.filter.clear();
+                self.mode = Mode::Browse;
+            }
+            (Mode::Filter, Key::Backspace) => {
+                self.filter.pop();
+            }
+            (Mode::Filter, Key::Char(c)) => {
+                self.filter.push(c);
+                self.selected = 0;
+            }
+            (Mode::Filter, _) => {}
+            (Mode::Browse, Key::Char('q') | Key::Esc) => return Some(Action::Quit),
+            (Mode::Browse, Key::Char('j') | Key::Down) => self.move_selection(1),
+            (Mode::Browse, Key::Char('k') | Key::Up) => self.move_selection(-1),
+            (Mode::Browse, Key::PageDown) => self.move_selection(page),
+            (Mode::Browse, Key::PageUp) => self.move_selection(-page),
+            (Mode::Browse, Key::Char('g') | Key::Home) => self.selected = 0,
+            (Mode::Browse, Key::Char('G') | Key::End) => {
+                self.selected = usize::MAX;
+                self.clamp_selection();
+            }
+            (Mode::Browse, Key::Char('/')) => self.mode = Mode::Filter,
+            (Mode::Browse, Key::Char('r')) => return Some(Action::Refresh),
+            (Mode::Browse, Key::Char(c @ ('c' | 's' | 'S' | 'X'))) => {
+                let instance = self.selected()?.clone();
+                match c {
+                    'c' | 's' if instance.primary_ip().is_none() => {
+                        self.message = Some(format!("{} has no IP address", instance.name));
+                    }
+                    'c' => return Some(Action::CopyIp(instance.primary_ip()?.to_string())),
+                    's' => return Some(Action::Ssh(instance)),
+                    'S' => self.mode = Mode::Confirm(Box::new(Action::Start(instance))),
+                    _ => self.mode = Mode::Confirm(Box::new(Action::Stop(instance))),
+                }
+            }
+            (Mode::Browse, _) => {}
+        }
+        None
+    }
+
+    /// Draws the browser: a title bar, the list with the detail pane beside it, and a status
+    /// line. The selected row is in reverse video.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to draw on, covering the whole terminal.
+    pub fn render(&mut self, frame: &mut Frame) {
+        let [title_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let width = main_area.width as usize;
+        let list_width = (width / 2).clamp(width.min(20), 60);
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Length(list_width as u16), Constraint::Fill(1)])
+                .areas(main_area);
+
+        let visible = self.visible();
+        let mut title = format!(
+            " {} — {}/{} instances",
+            self.title,
+            visible.len(),
+            self.instances.len()
+        );
+        if !self.filter.is_empty() {
+            title += &format!(", filter: {}", self.filter);
+        }
+        frame.render_widget(Paragraph::new(title).reversed(), title_area);
+
+        let rows = visible.iter().map(|inst| {
+            let status = format!(" {}", inst.status);
+            let name_width = list_width.saturating_sub(status.len() + 1);
+            format!(" {}{}", fit(&inst.name, name_width), status)
+        });
+        // The list scrolls just enough to keep the selection on screen
+        let mut state = ListState::default()
+            .with_offset(self.offset)
+            .with_selected((!visible.is_empty()).then_some(self.selected));
+        frame.render_stateful_widget(
+            List::new(rows).highlight_style(Style::new().reversed()),
+            list_area,
+            &mut state,
+        );
+
+        let detail = visible
+            .get(self.selected)
+            .map(|inst| detail_lines(inst))
+            .unwrap_or_default();
+        frame.render_widget(
+            Paragraph::new(detail.join("\n")).block(
+                Block::new()
+                    .borders(Borders::LEFT)
+                    .padding(Padding::left(1)),
+            ),
+            detail_area,
+        );
+
+        let status = match (&self.mode, &self.message) {
+            (Mode::Filter, _) => format!("/{}", self.filter),
+            (Mode::Confirm(action), _) => match action.as_ref() {
+                Action::Start(inst) => format!("Start {}? [y/N]", inst.name),
+                Action::Stop(inst) => format!("Stop {}? [y/N]", inst.name),
+                _ => String::new(),
+            },
+            (_, Some(message)) => message.clone(),
+            _ => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+        self.offset = state.offset();
+    }
+}
+
+/// The number of list rows that fit between the title bar and the status line.
+fn list_height(height: usize) -> usize {
+    height.saturating_sub(2)
+}
+
+/// The lines of the detail pane for an instance.
+fn detail_lines(inst: &Instance) -> Vec<String> {
+    let optional = |value: Option<&str>| value.unwrap_or("-").to_string();
+    let mut lines = vec![
+        format!("Name:         {}", inst.name),
+        format!("Status:       {}", inst.status),
+        format!("Zone:         {}", inst.zone),
+        format!("Machine type: {}", inst.machine_type),
+        format!(
+            "CPU platform: {}",
+            optional(Some(inst.cpu_platform.as_str()).filter(|platform| !platform.is_empty()))
+        ),
+        format!("IP:           {}", optional(inst.primary_ip())),
+        format!("External IP:  {}", optional(inst.external_ip())),
+        format!("Cell:         {}", optional(inst.cell.as_deref())),
+        format!(
+            "Created:      {}",
+            optional(
+                inst.creation_timestamp
+                    .map(|created| created.to_rfc3339())
+                    .as_deref()
+            )
+        ),
+    ];
+    let mut labels = inst
+        .labels
+        .iter()
+        .flatten()
+        .map(|(k, v)| format!("  {}: {}", k, v))
+        .collect::<Vec<_>>();
+    labels.sort();
+    if !labels.is_empty() {
+        lines.push("Labels:".to_string());
+        lines.extend(labels);
+    }
+    lines
+}
+
+/// Pads or truncates text to exactly `width` terminal cells.
+fn fit(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    let padding = width - UnicodeWidthStr::width(out.as_str()).min(width);
+    out + &" ".repeat(padding)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::NetworkInterface;
+    use ratatui::backend::TestBackend;
+    use ratatui::style::Modifier;
+
+    fn instance(name: &str, status: &str) -> Instance {
+        Instance {
+            name: name.to_string(),
+            status: status.to_string(),
+            network_interfaces: vec![NetworkInterface {
+                ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn app() -> App {
+        App::new(
+            "prd",
+            vec![
+                instance("db-1", "RUNNING"),
+                instance("web-1", "RUNNING"),
+                instance("web-2", "TERMINATED"),
+            ],
+        )
+    }
+
+    /// Draws the browser on a terminal of the given size.
+    fn draw(app: &mut App, width: u16, height: u16) -> ratatui::buffer::Buffer {
+        let mut terminal = ratatui::Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app.render(frame)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    /// The text of a row of the terminal.
+    fn row(buffer: &ratatui::buffer::Buffer, y: u16) -> String {
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_event() {
+        let key = |code| Key::from_event(KeyEvent::new(code, KeyModifiers::NONE));
+        assert_eq!(key(KeyCode::Char('j')), Some(Key::Char('j')));
+        assert_eq!(key(KeyCode::PageDown), Some(Key::PageDown));
+        assert_eq!(key(KeyCode::Esc), Some(Key::Esc));
+        assert_eq!(key(KeyCode::F(1)), None);
+        assert_eq!(
+            Key::from_event(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Key::CtrlC)
+        );
+        assert_eq!(
+            Key::from_event(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT)),
+            None
+        );
+        // Shifted letters arrive as the capital with Shift held
+        assert_eq!(
+            Key::from_event(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            Some(Key::Char('G'))
+        );
+    }
+
+    #[test]
+    fn test_filter_and_move() {
+        let mut app = app();
+        app.handle_key(Key::Char('G'), 10);
+        assert_eq!(app.selected().unwrap().name, "web-2");
+        app.handle_key(Key::Char('j'), 10);
+        assert_eq!(app.selected().unwrap().name, "web-2");
+
+        for key in [Key::Char('/'), Key::Char('w'), Key::Char('e'), Key::Enter] {
+            assert!(app.handle_key(key, 10).is_none());
+        }
+        assert_eq!(app.visible().len(), 2);
+        assert_eq!(app.selected().unwrap().name, "web-1");
+
+        // A refresh keeps the selection on the same instance
+        app.handle_key(Key::Down, 10);
+        app.set_instances(vec![
+            instance("web-0", "RUNNING"),
+            instance("web-2", "RUNNING"),
+        ]);
+        assert_eq!(app.selected().unwrap().name, "web-2");
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut app = app();
+        assert!(matches!(
+            app.handle_key(Key::Char('c'), 10),
+            Some(Action::CopyIp(ip)) if ip == "10.0.0.1"
+        ));
+        assert!(matches!(
+            app.handle_key(Key::Char('s'), 10),
+            Some(Action::Ssh(_))
+        ));
+
+        // Stopping needs confirmation, and anything but `y` cancels
+        assert!(app.handle_key(Key::Char('X'), 10).is_none());
+        assert!(app.handle_key(Key::Char('n'), 10).is_none());
+        assert!(app.handle_key(Key::Char('X'), 10).is_none());
+        assert!(matches!(
+            app.handle_key(Key::Char('y'), 10),
+            Some(Action::Stop(inst)) if inst.name == "db-1"
+        ));
+        assert!(matches!(
+            app.handle_key(Key::Char('q'), 10),
+            Some(Action::Quit)
+        ));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut app = app();
+        app.handle_key(Key::Char('X'), 6);
+        let buffer = draw(&mut app, 60, 6);
+        assert!(row(&buffer, 0).starts_with(" prd — 3/3 instances"));
+        assert!(row(&buffer, 1).starts_with(" db-1"));
+        assert!(buffer[(0, 1)].modifier.contains(Modifier::REVERSED));
+        assert!(row(&buffer, 1).ends_with(&format!("│ {}", fit("Name:         db-1", 28))));
+        assert!(row(&buffer, 2).starts_with(" web-1"));
+        assert!(!buffer[(0, 2)].modifier.contains(Modifier::REVERSED));
+        assert_eq!(row(&buffer, 5), fit("Stop db-1? [y/N]", 60));
+
+        // The list scrolls to keep the selection on screen
+        app.handle_key(Key::Esc, 4);
+        app.handle_key(Key::Char('G'), 4);
+        let buffer = draw(&mut app, 60, 4);
+        assert!(row(&buffer, 2).starts_with(" web-2"));
+        assert!(buffer[(0, 2)].modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("abc", 5), "abc  ");
+        assert_eq!(fit("abcdef", 3), "abc");
+        assert_eq!(fit("存储", 3), "存 ");
+    }
+}
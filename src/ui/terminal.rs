@@ -0,0 +1,96 @@
+//! This module runs the browser in raw mode on the alternate screen with crossterm, draws it
+//! with ratatui, and restores the terminal when dropped.
+
+use super::{App, Key};
+use base64::Engine;
+use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, terminal};
+use ratatui::backend::CrosstermBackend;
+use std::io::{Stdout, Write};
+use std::time::Duration;
+
+/// The terminal in raw mode.
+pub struct Terminal {
+    terminal: ratatui::Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Terminal {
+    /// Switches the terminal to raw mode and the alternate screen.
+    pub fn enter() -> std::io::Result<Self> {
+        let terminal = ratatui::Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+        let mut terminal = Terminal { terminal };
+        terminal.resume()?;
+        Ok(terminal)
+    }
+
+    /// Enters raw mode and the alternate screen, e.g. again after `suspend`.
+    pub fn resume(&mut self) -> std::io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            self.terminal.backend_mut(),
+            EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        // Whatever ran in between drew over the screen, so the next frame is drawn in full
+        self.terminal.clear()
+    }
+
+    /// Restores the terminal as it was, e.g. to hand it to `ssh`. `resume` undoes this.
+    pub fn suspend(&mut self) -> std::io::Result<()> {
+        execute!(
+            self.terminal.backend_mut(),
+            cursor::Show,
+            LeaveAlternateScreen
+        )?;
+        terminal::disable_raw_mode()
+    }
+
+    /// Returns the height of the terminal, which `App::handle_key` pages by.
+    pub fn height(&self) -> std::io::Result<usize> {
+        Ok(self.terminal.size()?.height as usize)
+    }
+
+    /// Draws the browser.
+    pub fn draw(&mut self, app: &mut App) -> std::io::Result<()> {
+        self.terminal.draw(|frame| app.render(frame))?;
+        Ok(())
+    }
+
+    /// Waits briefly for key presses.
+    ///
+    /// # Returns
+    ///
+    /// The keys pressed, or nothing if none was pressed within half a second, so the caller
+    /// can redraw after a resize.
+    pub fn read_keys(&mut self) -> std::io::Result<Vec<Key>> {
+        let mut keys = Vec::new();
+        let mut timeout = Duration::from_millis(500);
+        while event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release {
+                    keys.extend(Key::from_event(key));
+                }
+            }
+            // Only take the events already waiting once something has arrived
+            timeout = Duration::ZERO;
+        }
+        Ok(keys)
+    }
+
+    /// Copies text to the clipboard with the OSC 52 escape sequence, which most terminals
+    /// support, including over SSH.
+    pub fn copy(&mut self, text: &str) -> std::io::Result<()> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+        let backend = self.terminal.backend_mut();
+        write!(backend, "\x1b]52;c;{}\x07", encoded)?;
+        backend.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // Nothing more can be done if the terminal is gone
+        let _ = self.suspend();
+    }
+}
@@ -0,0 +1,172 @@
+//! This module describes the running build of bcls for the `version` subcommand, so wrappers
+//! can check which capabilities are available before relying on them.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Build and environment metadata reported by `bcls version`.
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    /// The crate version, e.g. `0.2.0`.
+    pub version: &'static str,
+    /// The git commit the binary was built from, or `unknown`.
+    pub git_sha: &'static str,
+    /// The UTC date the binary was built, as `YYYY-MM-DD`.
+    pub build_date: &'static str,
+    /// The cargo features the binary was built with.
+    pub features: Vec<&'static str>,
+    /// The config files bcls reads, in the order they are applied.
+    pub config_paths: Vec<ConfigPath>,
+    /// The authentication methods available on this machine.
+    pub auth_methods: Vec<String>,
+}
+
+/// A config file location and whether it exists.
+#[derive(Debug, Serialize)]
+pub struct ConfigPath {
+    /// The path of the config file.
+    pub path: PathBuf,
+    /// Whether the file exists.
+    pub exists: bool,
+}
+
+impl VersionInfo {
+    /// Collects the build metadata and inspects the environment.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_paths` - The config file locations bcls searches.
+    pub fn detect(config_paths: &[PathBuf]) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("BCLS_GIT_SHA"),
+            build_date: env!("BCLS_BUILD_DATE"),
+            features: env!("BCLS_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .collect(),
+            config_paths: config_paths
+                .iter()
+                .map(|path| ConfigPath {
+                    path: path.clone(),
+                    exists: path.is_file(),
+                })
+                .collect(),
+            auth_methods: detect_auth_methods(),
+        }
+    }
+
+    /// Renders the metadata as human-readable text.
+    pub fn to_text(&self) -> String {
+        let short_sha = self.git_sha.get(..12).unwrap_or(self.git_sha);
+        let mut text = format!(
+            "bcls {} ({} {})\n",
+            self.version, short_sha, self.build_date
+        );
+        text += &format!("features: {}\n", list_or_none(&self.features));
+        text += &format!("auth methods: {}\n", list_or_none(&self.auth_methods));
+        text += "config paths:\n";
+        for config in &self.config_paths {
+            let state = match config.exists {
+                true => "found",
+                false => "missing",
+            };
+            text += &format!("  {} ({})\n", config.path.display(), state);
+        }
+        text
+    }
+}
+
+/// Joins a list with commas, or returns `none` if it is empty.
+fn list_or_none<S: AsRef<str>>(items: &[S]) -> String {
+    match items.is_empty() {
+        true => "none".to_string(),
+        false => items
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Returns the authentication methods that can be used on this machine.
+pub fn detect_auth_methods() -> Vec<String> {
+    let mut methods = vec![];
+    if on_gce() {
+        methods.push("metadata".to_string());
+    }
+    if find_on_path("gcloud").is_some() {
+        methods.push("gcloud".to_string());
+    }
+    methods
+}
+
+/// Whether bcls appears to run on a GCE VM, where the metadata server provides tokens.
+///
+/// This checks the DMI product name like Google's client libraries do, rather than probing
+/// the metadata server over the network.
+fn on_gce() -> bool {
+    std::env::var_os("GCE_METADATA_HOST").is_some()
+        || std::fs::read_to_string("/sys/class/dmi/id/product_name")
+            .is_ok_and(|name| name.trim().starts_with("Google"))
+}
+
+/// Looks for an executable in the directories listed in `PATH`.
+pub(crate) fn find_on_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| executable_in(&dir, program))
+}
+
+/// Returns the path of `program` in `dir` if it exists there.
+fn executable_in(dir: &Path, program: &str) -> Option<PathBuf> {
+    let candidates = match cfg!(windows) {
+        true => vec![format!("{}.cmd", program), format!("{}.exe", program)],
+        false => vec![program.to_string()],
+    };
+    candidates
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_info() {
+        let missing = std::env::temp_dir().join("bcls-test-missing/config.toml");
+        let info = VersionInfo::detect(std::slice::from_ref(&missing));
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.build_date.len(), "YYYY-MM-DD".len());
+        assert!(!info.config_paths[0].exists);
+
+        // Field order is part of the --json contract
+        let json = serde_json::to_string(&info).unwrap();
+        let keys = [
+            "version",
+            "git_sha",
+            "build_date",
+            "features",
+            "config_paths",
+            "auth_methods",
+        ];
+        let positions = keys
+            .iter()
+            .map(|key| json.find(&format!("\"{}\"", key)).unwrap())
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        let text = info.to_text();
+        assert!(text.starts_with(&format!("bcls {} (", info.version)));
+        assert!(text.contains(&format!("  {} (missing)", missing.display())));
+    }
+
+    #[test]
+    fn test_list_or_none() {
+        assert_eq!(list_or_none::<&str>(&[]), "none");
+        assert_eq!(list_or_none(&["gcloud", "metadata"]), "gcloud, metadata");
+    }
+}